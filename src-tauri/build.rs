@@ -1,62 +1,216 @@
-use std::{env, fs, path::PathBuf};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
 
 fn main() {
     tauri_build::build();
     generate_plugins_registry();
 }
 
+/// Встроенный плагин: файл `foo_plugin.rs` или папка `foo_plugin/mod.rs` с подмодулями.
+struct BuiltinPlugin {
+    module: String,
+    /// Путь к файлу модуля (для папки — к `mod.rs`).
+    path: PathBuf,
+    /// Что отслеживать для пересборки: файл или вся папка.
+    source: PathBuf,
+}
+
+impl BuiltinPlugin {
+    /// Фича, которая включает плагин отдельно: `plugin-temp-cleaner` для `temp_cleaner_plugin`.
+    fn feature(&self) -> String {
+        let name = self.module.strip_suffix("_plugin").unwrap_or(&self.module);
+        format!("plugin-{}", name.replace('_', "-"))
+    }
+
+    /// Плагин собирается, если включена `all-plugins` (по умолчанию) или его фича.
+    fn enabled(&self) -> bool {
+        let variable =
+            |feature: &str| format!("CARGO_FEATURE_{}", feature.to_uppercase().replace('-', "_"));
+        env::var_os(variable("all-plugins")).is_some()
+            || env::var_os(variable(&self.feature())).is_some()
+    }
+}
+
 fn generate_plugins_registry() {
     let manifest_dir =
         PathBuf::from(env::var("CARGO_MANIFEST_DIR").expect("CARGO_MANIFEST_DIR missing"));
     let plugins_dir = manifest_dir.join("src").join("plugins");
     let out_dir = PathBuf::from(env::var("OUT_DIR").expect("OUT_DIR missing"));
 
-    let mut modules = Vec::new();
-    if let Ok(entries) = fs::read_dir(&plugins_dir) {
-        for entry in entries.flatten() {
-            let path = entry.path();
-            let filename = match path.file_name().and_then(|name| name.to_str()) {
-                Some(name) => name,
-                None => continue,
-            };
-            if !filename.ends_with("_plugin.rs") {
-                continue;
-            }
-            let stem = match path.file_stem().and_then(|name| name.to_str()) {
-                Some(stem) => stem.to_string(),
-                None => continue,
-            };
-            modules.push(stem);
+    let plugins = match discover_plugins(&plugins_dir) {
+        Ok(plugins) => plugins,
+        Err(errors) => panic!("Ошибка в src/plugins:\n  - {}", errors.join("\n  - ")),
+    };
+
+    let features = declared_features(&manifest_dir.join("Cargo.toml"));
+    for plugin in &plugins {
+        let feature = plugin.feature();
+        if !features.contains(&feature) {
+            println!(
+                "cargo:warning=Плагин {} нельзя включить отдельно: добавьте фичу {feature} \
+                 в Cargo.toml и в список all-plugins.",
+                plugin.module
+            );
         }
     }
 
-    modules.sort();
+    let enabled: Vec<&BuiltinPlugin> = plugins.iter().filter(|plugin| plugin.enabled()).collect();
 
     let mut output = String::new();
     output.push_str("// @generated by build.rs. Не изменять.\n");
-    for module in &modules {
-        let path = plugins_dir.join(format!("{module}.rs"));
-        let path_str = path.to_string_lossy();
+    for plugin in &enabled {
+        let path_str = plugin.path.to_string_lossy();
         output.push_str(&format!(
             "#[path = r#\"{}\"#]\npub mod {};\n",
-            path_str, module
+            path_str, plugin.module
         ));
     }
-    output.push_str("\n");
-    output.push_str("pub fn all_plugins() -> Vec<Box<dyn Plugin>> {\n");
+    output.push('\n');
+    output.push_str("pub fn builtin_plugins() -> Vec<Box<dyn Plugin>> {\n");
     output.push_str("  vec![\n");
-    for module in &modules {
-        output.push_str(&format!("    {module}::plugin(),\n"));
+    for plugin in &enabled {
+        output.push_str(&format!("    {}::plugin(),\n", plugin.module));
     }
     output.push_str("  ]\n");
     output.push_str("}\n");
 
     fs::write(out_dir.join("plugins.generated.rs"), output)
         .expect("Ошибка создания plugins.generated.rs");
+    fs::write(
+        out_dir.join("plugins_conformance.generated.rs"),
+        conformance_tests(&enabled),
+    )
+    .expect("Ошибка создания plugins_conformance.generated.rs");
 
     println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=Cargo.toml");
     println!("cargo:rerun-if-changed=src/plugins");
-    for module in modules {
-        println!("cargo:rerun-if-changed=src/plugins/{module}.rs");
+    for plugin in plugins {
+        println!("cargo:rerun-if-changed={}", plugin.source.display());
     }
 }
+
+/// Тест согласованности для каждого собранного плагина и общий тест уникальности `id`.
+/// Подключается в конце `core/registry.rs` внутри `#[cfg(test)]`-модуля.
+fn conformance_tests(plugins: &[&BuiltinPlugin]) -> String {
+    let mut output = String::new();
+    output.push_str("// @generated by build.rs. Не изменять.\n");
+    output.push_str("use crate::core::conformance;\n\n");
+    output.push_str("#[test]\n");
+    output.push_str("fn builtin_ids_are_unique() {\n");
+    output.push_str("  let plugins = super::builtin_plugins();\n");
+    output.push_str("  let duplicates = conformance::duplicate_ids(&plugins);\n");
+    output.push_str(
+        "  assert!(duplicates.is_empty(), \"Повторяющиеся id: {}\", duplicates.join(\", \"));\n",
+    );
+    output.push_str("}\n");
+    for plugin in plugins {
+        output.push_str(&format!(
+            "\n#[test]\nfn {module}() {{\n  \
+             conformance::assert_conforms(super::{module}::plugin());\n}}\n",
+            module = plugin.module
+        ));
+    }
+    output
+}
+
+/// Все плагины из `src/plugins`. Ошибки собираются вместе, чтобы сборка сообщила
+/// обо всех конфликтах сразу.
+fn discover_plugins(plugins_dir: &Path) -> Result<Vec<BuiltinPlugin>, Vec<String>> {
+    let mut plugins: Vec<BuiltinPlugin> = Vec::new();
+    let mut errors = Vec::new();
+
+    let Ok(entries) = fs::read_dir(plugins_dir) else {
+        return Ok(plugins);
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let filename = match path.file_name().and_then(|name| name.to_str()) {
+            Some(name) => name.to_string(),
+            None => continue,
+        };
+        let plugin = if path.is_dir() {
+            if !filename.ends_with("_plugin") {
+                continue;
+            }
+            let module_file = path.join("mod.rs");
+            if !module_file.is_file() {
+                errors.push(format!("в папке {filename} нет mod.rs."));
+                continue;
+            }
+            BuiltinPlugin {
+                module: filename.clone(),
+                path: module_file,
+                source: path.clone(),
+            }
+        } else {
+            let Some(module) = filename.strip_suffix(".rs") else {
+                continue;
+            };
+            if !module.ends_with("_plugin") {
+                continue;
+            }
+            BuiltinPlugin {
+                module: module.to_string(),
+                path: path.clone(),
+                source: path.clone(),
+            }
+        };
+
+        if !is_module_name(&plugin.module) {
+            errors.push(format!(
+                "{filename}: имя модуля «{}» должно состоять из строчных латинских букв, \
+                 цифр и _ и начинаться с буквы.",
+                plugin.module
+            ));
+            continue;
+        }
+        if let Some(existing) = plugins.iter().find(|other| other.module == plugin.module) {
+            errors.push(format!(
+                "плагин {} объявлен дважды: {} и {}.",
+                plugin.module,
+                relative(plugins_dir, &existing.source),
+                relative(plugins_dir, &plugin.source)
+            ));
+            continue;
+        }
+        plugins.push(plugin);
+    }
+
+    if !errors.is_empty() {
+        errors.sort();
+        return Err(errors);
+    }
+    plugins.sort_by(|a, b| a.module.cmp(&b.module));
+    Ok(plugins)
+}
+
+fn is_module_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_lowercase())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+/// Имена фич из раздела `[features]` манифеста.
+fn declared_features(manifest: &Path) -> Vec<String> {
+    let content = fs::read_to_string(manifest)
+        .unwrap_or_else(|e| panic!("Не удалось прочитать {}: {e}", manifest.display()));
+    let manifest: toml::Table = content
+        .parse()
+        .unwrap_or_else(|e| panic!("Ошибка в {}: {e}", manifest.display()));
+    manifest
+        .get("features")
+        .and_then(toml::Value::as_table)
+        .map(|features| features.keys().cloned().collect())
+        .unwrap_or_default()
+}
+
+fn relative(base: &Path, path: &Path) -> String {
+    path.strip_prefix(base)
+        .unwrap_or(path)
+        .display()
+        .to_string()
+}
@@ -0,0 +1,4 @@
+fn main() {
+    let args = std::env::args().skip(1).collect();
+    std::process::exit(tauri_app_lib::run_cli(args));
+}
@@ -0,0 +1,690 @@
+use serde_json::{Map, Value};
+use std::{fs, path::PathBuf};
+
+use crate::core::doctor::{CheckStatus, DoctorReport};
+use crate::core::{
+    self, CancelToken, Capability, CoreError, Environment, LogEntry, LogLevel, Logger, PluginInfo,
+    SettingField, SettingKind,
+};
+
+const EXIT_OK: i32 = 0;
+/// Плагин отработал, но вернул ошибку.
+const EXIT_FAILED: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+/// Плагин не найден, не поддерживается или не запустился.
+const EXIT_ERROR: i32 = 3;
+
+const USAGE: &str = "\
+Использование: lamerhelper-cli <команда> [параметры]
+
+Команды:
+  list                      список плагинов
+  info <id>                 описание плагина и его настроек
+  preview <id>              текущее состояние, которое показывает плагин
+  run <id>                  запуск плагина
+      --set ключ=значение   значение настройки (можно повторять)
+      --settings файл.json  настройки из JSON-файла (--set применяется поверх)
+      --post-actions        сразу выполнить то, что попросил плагин: перезапуск
+                            Проводника, выход из системы или перезагрузку
+  doctor                    проверка окружения: программы, права, папка данных,
+                            реестр и свободное место
+
+Общие параметры:
+  --json                    вывод в JSON
+  --data-dir <путь>         папка данных приложения вместо стандартной
+
+Коды выхода: 0 — успех, 1 — плагин завершился с ошибкой
+(у doctor — найдены ошибки окружения), 2 — неверные аргументы,
+3 — плагин не найден или не может быть запущен.";
+
+struct Options {
+    command: String,
+    id: Option<String>,
+    json: bool,
+    data_dir: Option<PathBuf>,
+    settings_file: Option<PathBuf>,
+    set: Vec<(String, String)>,
+    post_actions: bool,
+}
+
+/// Точка входа `lamerhelper-cli`. Возвращает код выхода процесса.
+pub fn run(args: Vec<String>) -> i32 {
+    let options = match parse_args(args) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{err}\n\n{USAGE}");
+            return EXIT_USAGE;
+        }
+    };
+
+    let mut env = Environment::detect();
+    if let Some(dir) = &options.data_dir {
+        env.data_dir = Some(dir.clone());
+    }
+    match options.command.as_str() {
+        "help" => {
+            println!("{USAGE}");
+            EXIT_OK
+        }
+        "list" => list(&options, &env),
+        "info" => info(&options, &env),
+        "preview" => preview(&options, &env),
+        "run" => run_plugin(&options, &env),
+        "doctor" => doctor(&options, &env),
+        other => {
+            eprintln!("Неизвестная команда: {other}\n\n{USAGE}");
+            EXIT_USAGE
+        }
+    }
+}
+
+fn parse_args(args: Vec<String>) -> Result<Options, String> {
+    let mut options = Options {
+        command: String::new(),
+        id: None,
+        json: false,
+        data_dir: None,
+        settings_file: None,
+        set: Vec::new(),
+        post_actions: false,
+    };
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => options.json = true,
+            "--post-actions" => options.post_actions = true,
+            "-h" | "--help" => options.command = "help".to_string(),
+            "--data-dir" => options.data_dir = Some(PathBuf::from(value_for(&arg, args.next())?)),
+            "--settings" => {
+                options.settings_file = Some(PathBuf::from(value_for(&arg, args.next())?))
+            }
+            "--set" => {
+                let pair = value_for(&arg, args.next())?;
+                let (key, value) = pair
+                    .split_once('=')
+                    .ok_or_else(|| format!("Ожидается --set ключ=значение, получено: {pair}"))?;
+                options
+                    .set
+                    .push((key.trim().to_string(), value.to_string()));
+            }
+            flag if flag.starts_with("--") => return Err(format!("Неизвестный параметр: {flag}")),
+            _ if options.command.is_empty() => options.command = arg,
+            _ if options.id.is_none() => options.id = Some(arg),
+            _ => return Err(format!("Лишний аргумент: {arg}")),
+        }
+    }
+
+    if options.command.is_empty() {
+        options.command = "help".to_string();
+    }
+    if matches!(options.command.as_str(), "info" | "preview" | "run") && options.id.is_none() {
+        return Err(format!("Команде {} нужен id плагина.", options.command));
+    }
+    Ok(options)
+}
+
+fn value_for(flag: &str, value: Option<String>) -> Result<String, String> {
+    value.ok_or_else(|| format!("Параметру {flag} нужно значение."))
+}
+
+fn list(options: &Options, env: &Environment) -> i32 {
+    let plugins = core::list_plugins(env);
+    if options.json {
+        return print_json(&plugins);
+    }
+
+    let width = plugins
+        .iter()
+        .map(|plugin| plugin.id.len())
+        .max()
+        .unwrap_or(0);
+    for plugin in &plugins {
+        let mut flags = Vec::new();
+        if plugin.requires_admin {
+            flags.push("админ".to_string());
+        }
+        if let Some(reason) = &plugin.unsupported_reason {
+            flags.push(reason.clone());
+        }
+        if !plugin.missing_tools.is_empty() {
+            flags.push(format!("нет программ: {}", plugin.missing_tools.join(", ")));
+        }
+        let flags = if flags.is_empty() {
+            String::new()
+        } else {
+            format!("  ({})", flags.join("; "))
+        };
+        println!(
+            "{:width$}  {} [{}]{flags}",
+            plugin.id, plugin.name, plugin.category
+        );
+    }
+    EXIT_OK
+}
+
+fn info(options: &Options, env: &Environment) -> i32 {
+    let id = options.id.as_deref().unwrap_or_default();
+    let Some(plugin) = core::list_plugins(env)
+        .into_iter()
+        .find(|plugin| plugin.id == id)
+    else {
+        return report_error(options, CoreError::PluginNotFound { id: id.to_string() });
+    };
+    if options.json {
+        return print_json(&plugin);
+    }
+
+    print_info(&plugin);
+    EXIT_OK
+}
+
+fn print_info(plugin: &PluginInfo) {
+    println!("{} ({})", plugin.name, plugin.id);
+    println!("Категория: {}", plugin.category);
+    if !plugin.description.is_empty() {
+        println!("{}", plugin.description);
+    }
+
+    let platforms = if plugin.platforms.is_empty() {
+        "все".to_string()
+    } else {
+        let labels: Vec<&str> = plugin.platforms.iter().map(|p| p.label()).collect();
+        labels.join(", ")
+    };
+    println!("Платформы: {platforms}");
+    if let Some(reason) = &plugin.unsupported_reason {
+        println!("  {reason}");
+    }
+    if plugin.requires_admin {
+        println!("Требуются права администратора.");
+    }
+    if !plugin.tools.is_empty() {
+        println!("Программы: {}", plugin.tools.join(", "));
+    }
+    if !plugin.missing_tools.is_empty() {
+        println!("  не найдены: {}", plugin.missing_tools.join(", "));
+    }
+
+    if !plugin.capabilities.is_empty() {
+        println!("Разрешения:");
+        for capability in &plugin.capabilities {
+            println!("  {}", describe_capability(capability));
+        }
+    }
+
+    if !plugin.settings.is_empty() {
+        println!("Настройки:");
+        for field in &plugin.settings {
+            let value = plugin.defaults.get(&field.key).unwrap_or(&Value::Null);
+            let mut marks = Vec::new();
+            if field.required {
+                marks.push("обязательно");
+            }
+            if field.requires_admin {
+                marks.push("админ");
+            }
+            let marks = if marks.is_empty() {
+                String::new()
+            } else {
+                format!(" [{}]", marks.join(", "))
+            };
+            println!(
+                "  {} ({}) = {} — {}{marks}",
+                field.key,
+                kind_name(&field.kind),
+                value,
+                field.label
+            );
+            if let Some(description) = &field.description {
+                println!("      {description}");
+            }
+            if let Some(options) = &field.options {
+                let values: Vec<String> = options
+                    .iter()
+                    .map(|option| format!("{} ({})", option.value, option.label))
+                    .collect();
+                println!("      варианты: {}", values.join(", "));
+            }
+        }
+    }
+}
+
+fn describe_capability(capability: &Capability) -> String {
+    match capability {
+        Capability::FsDelete { scopes } => format!("удаление файлов: {}", scopes.join(", ")),
+        Capability::FsWrite { scopes } => format!("запись файлов: {}", scopes.join(", ")),
+        Capability::RegistryRead { keys } => format!("чтение реестра: {}", keys.join(", ")),
+        Capability::RegistryWrite { keys } => format!("запись в реестр: {}", keys.join(", ")),
+        Capability::ProcessSpawn { executables } => {
+            format!("запуск программ: {}", executables.join(", "))
+        }
+        Capability::Network => "сеть".to_string(),
+        Capability::ShellRestart => "перезапуск оболочки".to_string(),
+        Capability::RequiresAdmin => "права администратора".to_string(),
+    }
+}
+
+fn kind_name(kind: &SettingKind) -> String {
+    serde_json::to_value(kind)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+fn preview(options: &Options, env: &Environment) -> i32 {
+    let id = options.id.as_deref().unwrap_or_default();
+    let preview = match core::preview_plugin(env, id) {
+        Ok(preview) => preview,
+        Err(err) => return report_error(options, err),
+    };
+    if options.json {
+        return print_json(&preview);
+    }
+
+    match preview {
+        Some(preview) => {
+            println!("{}: {}", preview.title, preview.value);
+            if let Some(note) = preview.note {
+                println!("{note}");
+            }
+        }
+        None => println!("Плагин не показывает превью."),
+    }
+    EXIT_OK
+}
+
+fn run_plugin(options: &Options, env: &Environment) -> i32 {
+    let id = options.id.as_deref().unwrap_or_default();
+    let Some(plugin) = core::list_plugins(env)
+        .into_iter()
+        .find(|plugin| plugin.id == id)
+    else {
+        return report_error(options, CoreError::PluginNotFound { id: id.to_string() });
+    };
+    let settings = match build_settings(options, &plugin.settings) {
+        Ok(settings) => settings,
+        Err(err) => {
+            eprintln!("{err}");
+            return EXIT_USAGE;
+        }
+    };
+
+    // В человекочитаемом режиме лог печатается по мере выполнения.
+    let logger = if options.json {
+        Logger::new()
+    } else {
+        Logger::with_sink(|entry| println!("{}", render_entry(entry)))
+    };
+    let execution = match core::execute_plugin(env, id, settings, logger, CancelToken::default()) {
+        Ok(execution) => execution,
+        Err(err) => return report_error(options, err),
+    };
+    core::record_execution(env, &execution);
+
+    let mut code = if execution.result.ok {
+        EXIT_OK
+    } else {
+        EXIT_FAILED
+    };
+    if options.json {
+        print_json(&execution);
+    } else if execution.result.ok {
+        println!("\n{}", execution.result.message);
+    } else {
+        eprintln!("\nОшибка: {}", execution.result.message);
+    }
+
+    // Флаг — это и есть подтверждение: без него действия только перечислены в журнале.
+    if options.post_actions
+        && let Err(err) = core::post_action::confirm(&execution.result.post_actions)
+    {
+        eprintln!("{err}");
+        code = EXIT_FAILED;
+    }
+    code
+}
+
+fn doctor(options: &Options, env: &Environment) -> i32 {
+    let report = core::doctor::doctor(env);
+    let code = if report.status == CheckStatus::Error {
+        EXIT_FAILED
+    } else {
+        EXIT_OK
+    };
+    if options.json {
+        print_json(&report);
+    } else {
+        print_doctor(&report);
+    }
+    code
+}
+
+fn print_doctor(report: &DoctorReport) {
+    for check in &report.checks {
+        let mark = match check.status {
+            CheckStatus::Ok => "ок",
+            CheckStatus::Skipped => "--",
+            CheckStatus::Warning => "!!",
+            CheckStatus::Error => "XX",
+        };
+        println!("[{mark}] {}: {}", check.title, check.detail);
+    }
+}
+
+/// Настройки из файла, поверх которых применяются `--set`. Значения `--set`
+/// приводятся к типу поля, чтобы `--set enabled=true` не стал строкой.
+fn build_settings(options: &Options, fields: &[SettingField]) -> Result<Value, String> {
+    let mut settings = match &options.settings_file {
+        Some(path) => {
+            let text = fs::read_to_string(path)
+                .map_err(|e| format!("Ошибка чтения {}: {e}", path.display()))?;
+            match serde_json::from_str(&text) {
+                Ok(Value::Object(map)) => map,
+                Ok(_) => return Err("Файл настроек должен содержать JSON-объект.".to_string()),
+                Err(e) => return Err(format!("Ошибка разбора {}: {e}", path.display())),
+            }
+        }
+        None => Map::new(),
+    };
+
+    for (key, raw) in &options.set {
+        let field = fields
+            .iter()
+            .find(|field| &field.key == key)
+            .ok_or_else(|| format!("У плагина нет настройки '{key}'."))?;
+        settings.insert(key.clone(), parse_value(field, raw)?);
+    }
+    Ok(Value::Object(settings))
+}
+
+fn parse_value(field: &SettingField, raw: &str) -> Result<Value, String> {
+    let invalid = |expected: &str| {
+        format!(
+            "Настройка '{}': ожидается {expected}, получено '{raw}'.",
+            field.key
+        )
+    };
+    match field.kind {
+        SettingKind::Boolean => match raw.to_lowercase().as_str() {
+            "true" | "1" | "yes" | "on" | "да" => Ok(Value::Bool(true)),
+            "false" | "0" | "no" | "off" | "нет" => Ok(Value::Bool(false)),
+            _ => Err(invalid("true или false")),
+        },
+        SettingKind::Number => serde_json::from_str::<serde_json::Number>(raw)
+            .map(Value::Number)
+            .map_err(|_| invalid("число")),
+        SettingKind::Select => {
+            let options = field.options.as_deref().unwrap_or_default();
+            options
+                .iter()
+                .map(|option| &option.value)
+                .find(|value| option_text(value) == raw)
+                .cloned()
+                .ok_or_else(|| {
+                    let values: Vec<String> = options
+                        .iter()
+                        .map(|option| option_text(&option.value))
+                        .collect();
+                    invalid(&format!("одно из: {}", values.join(", ")))
+                })
+        }
+        SettingKind::Text | SettingKind::Color => Ok(Value::String(raw.to_string())),
+    }
+}
+
+/// Значение варианта так, как его пишут в `--set`: строки без кавычек.
+fn option_text(value: &Value) -> String {
+    match value {
+        Value::String(value) => value.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn render_entry(entry: &LogEntry) -> String {
+    let marker = match entry.level {
+        LogLevel::Info => " ",
+        LogLevel::Warn => "!",
+        LogLevel::Error => "✗",
+        LogLevel::Success => "✓",
+    };
+    let indent = "  ".repeat(entry.indent as usize);
+    match &entry.detail {
+        Some(detail) => format!("{marker} {indent}{}: {detail}", entry.message),
+        None => format!("{marker} {indent}{}", entry.message),
+    }
+}
+
+fn report_error(options: &Options, err: CoreError) -> i32 {
+    if options.json {
+        print_json(&err);
+    } else {
+        eprintln!("Ошибка: {err}");
+    }
+    EXIT_ERROR
+}
+
+fn print_json(value: &impl serde::Serialize) -> i32 {
+    match serde_json::to_string_pretty(value) {
+        Ok(json) => {
+            println!("{json}");
+            EXIT_OK
+        }
+        Err(e) => {
+            eprintln!("Ошибка сериализации: {e}");
+            EXIT_ERROR
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Шаг с некорректным DWORD падает без обращения к реестру, если включён `fail`.
+    const PLUGIN: &str = r#"
+id = "cli_sample"
+name = "Пример CLI"
+description = ""
+category = "Тест"
+capabilities = [{ kind = "registry_write", keys = ["Software\\Vendor"] }]
+
+[[settings]]
+key = "fail"
+label = "Упасть"
+kind = "boolean"
+required = false
+default = false
+
+[[steps]]
+action = "after_run"
+kind = "logoff"
+reason = "Проверка"
+
+[[steps]]
+action = "set_registry_value"
+key = "Software\\Vendor"
+name = "Flag"
+kind = "dword"
+value = "не число"
+when = "fail"
+"#;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("lamerhelper-cli-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Папка данных с плагином-описанием `cli_sample`.
+    fn data_dir(name: &str) -> PathBuf {
+        let dir = scratch_dir(name);
+        fs::create_dir_all(dir.join("plugins")).unwrap();
+        fs::write(dir.join("plugins/cli_sample.toml"), PLUGIN).unwrap();
+        fs::write(
+            dir.join("config.json"),
+            r#"{"packages": {"allow_unsigned": true}}"#,
+        )
+        .unwrap();
+        dir
+    }
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn field(value: Value) -> SettingField {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn parses_commands_and_flags() {
+        let options = parse_args(args(&[
+            "run",
+            "temp_cleaner",
+            "--json",
+            "--set",
+            " days = 7=x",
+            "--settings",
+            "s.json",
+            "--data-dir",
+            "data",
+            "--post-actions",
+        ]))
+        .unwrap();
+        assert_eq!(options.command, "run");
+        assert_eq!(options.id.as_deref(), Some("temp_cleaner"));
+        assert!(options.json && options.post_actions);
+        assert_eq!(options.set, vec![("days".to_string(), " 7=x".to_string())]);
+        assert_eq!(options.settings_file, Some(PathBuf::from("s.json")));
+        assert_eq!(options.data_dir, Some(PathBuf::from("data")));
+
+        assert_eq!(parse_args(Vec::new()).unwrap().command, "help");
+        assert_eq!(parse_args(args(&["list", "-h"])).unwrap().command, "help");
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        let error = |list: &[&str]| parse_args(args(list)).err().unwrap();
+        assert_eq!(error(&["list", "--force"]), "Неизвестный параметр: --force");
+        assert_eq!(error(&["run", "a", "b"]), "Лишний аргумент: b");
+        assert_eq!(error(&["run"]), "Команде run нужен id плагина.");
+        assert_eq!(
+            error(&["run", "a", "--set"]),
+            "Параметру --set нужно значение."
+        );
+        assert_eq!(
+            error(&["run", "a", "--set", "days"]),
+            "Ожидается --set ключ=значение, получено: days"
+        );
+    }
+
+    #[test]
+    fn set_values_follow_field_kind() {
+        let boolean = field(json!({
+            "key": "enabled", "label": "", "kind": "boolean", "required": false, "default": false
+        }));
+        for raw in ["true", "1", "Yes", "on", "да"] {
+            assert_eq!(parse_value(&boolean, raw), Ok(json!(true)), "{raw}");
+        }
+        for raw in ["false", "0", "NO", "off", "нет"] {
+            assert_eq!(parse_value(&boolean, raw), Ok(json!(false)), "{raw}");
+        }
+        assert_eq!(
+            parse_value(&boolean, "maybe"),
+            Err("Настройка 'enabled': ожидается true или false, получено 'maybe'.".to_string())
+        );
+
+        let number = field(json!({
+            "key": "days", "label": "", "kind": "number", "required": false, "default": 0
+        }));
+        assert_eq!(parse_value(&number, "7"), Ok(json!(7)));
+        assert_eq!(parse_value(&number, "2.5"), Ok(json!(2.5)));
+        assert!(parse_value(&number, "семь").is_err());
+
+        let select = field(json!({
+            "key": "mode", "label": "", "kind": "select", "required": false, "default": "fast",
+            "options": [{"value": "fast", "label": "Быстро"}, {"value": 2, "label": "Два"}]
+        }));
+        assert_eq!(parse_value(&select, "fast"), Ok(json!("fast")));
+        assert_eq!(parse_value(&select, "2"), Ok(json!(2)));
+        assert_eq!(
+            parse_value(&select, "slow"),
+            Err("Настройка 'mode': ожидается одно из: fast, 2, получено 'slow'.".to_string())
+        );
+
+        let text = field(json!({
+            "key": "name", "label": "", "kind": "text", "required": false, "default": ""
+        }));
+        assert_eq!(parse_value(&text, "true"), Ok(json!("true")));
+    }
+
+    #[test]
+    fn set_overrides_settings_file() {
+        let dir = scratch_dir("settings");
+        let file = dir.join("settings.json");
+        fs::write(&file, r#"{"days": 30, "name": "x"}"#).unwrap();
+        let fields = [
+            field(
+                json!({"key": "days", "label": "", "kind": "number", "required": false, "default": 0}),
+            ),
+            field(
+                json!({"key": "name", "label": "", "kind": "text", "required": false, "default": ""}),
+            ),
+        ];
+        let mut options = parse_args(args(&["run", "a", "--set", "days=7"])).unwrap();
+        options.settings_file = Some(file.clone());
+        assert_eq!(
+            build_settings(&options, &fields),
+            Ok(json!({"days": 7, "name": "x"}))
+        );
+
+        options.set = vec![("missing".to_string(), "1".to_string())];
+        assert_eq!(
+            build_settings(&options, &fields),
+            Err("У плагина нет настройки 'missing'.".to_string())
+        );
+
+        fs::write(&file, "[1, 2]").unwrap();
+        options.set.clear();
+        assert_eq!(
+            build_settings(&options, &fields),
+            Err("Файл настроек должен содержать JSON-объект.".to_string())
+        );
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn exit_codes() {
+        let dir = data_dir("exit");
+        let data = dir.to_str().unwrap();
+        let run_with = |list: &[&str]| {
+            let mut list = args(list);
+            list.extend(args(&["--data-dir", data]));
+            run(list)
+        };
+
+        assert_eq!(run_with(&["help"]), EXIT_OK);
+        assert_eq!(run_with(&["info", "cli_sample"]), EXIT_OK);
+        assert_eq!(run_with(&["run", "cli_sample"]), EXIT_OK);
+        assert_eq!(
+            run_with(&["run", "cli_sample", "--set", "fail=да"]),
+            EXIT_FAILED
+        );
+        assert_eq!(run_with(&["frobnicate"]), EXIT_USAGE);
+        assert_eq!(run_with(&["run", "cli_sample", "--bogus"]), EXIT_USAGE);
+        assert_eq!(
+            run_with(&["run", "cli_sample", "--set", "fail=maybe"]),
+            EXIT_USAGE
+        );
+        assert_eq!(run_with(&["info", "no_such_plugin"]), EXIT_ERROR);
+        assert_eq!(run_with(&["run", "no_such_plugin"]), EXIT_ERROR);
+
+        let history = fs::read_to_string(dir.join("history.jsonl")).unwrap_or_default();
+        assert!(history.contains("cli_sample"), "{history}");
+        let _ = fs::remove_dir_all(dir);
+    }
+}
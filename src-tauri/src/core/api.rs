@@ -1,165 +1,459 @@
 use std::{
-    fs,
+    fmt,
     path::{Component, Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
 };
-use tauri::{AppHandle, Manager};
 
-#[allow(dead_code)]
+use crate::core::backend::{
+    self, Backends, CommandOptions, FileInfo, OutputStream, command_line, status_text,
+};
+use crate::core::capability::{Capability, CapabilityError, CapabilitySet};
+use crate::core::environment::Environment;
+use crate::core::guard::{GuardError, PathGuard};
+use crate::core::hive::{RegistryKey, RegistryValue};
+use crate::core::logger::Logger;
+use crate::core::post_action::{PostAction, PostActionRequest};
+use crate::core::watchdog::Progress;
+
+/// Результат `PluginApi::run_command`: вывод уже раскодирован.
+#[derive(Clone, Debug)]
+pub struct CommandResult {
+    /// `None`, если процесс завершён сигналом.
+    pub code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+    pub duration: Duration,
+}
+
+impl CommandResult {
+    pub fn success(&self) -> bool {
+        self.code == Some(0)
+    }
+
+    /// `код 1` для сообщений об ошибке.
+    pub fn status_text(&self) -> String {
+        status_text(self.code)
+    }
+}
+
+/// Операция, которую `PluginApi` отказался выполнять.
+#[derive(Clone, Debug)]
+pub enum Denial {
+    Path(GuardError),
+    Capability(CapabilityError),
+}
+
+impl fmt::Display for Denial {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Denial::Path(err) => err.fmt(f),
+            Denial::Capability(err) => err.fmt(f),
+        }
+    }
+}
+
+/// Флаг отмены, общий для запуска и того, кто его отменяет.
+#[derive(Clone, Default)]
+pub struct CancelToken {
+    flag: Arc<AtomicBool>,
+    parent: Option<Arc<CancelToken>>,
+}
+
+impl CancelToken {
+    /// Токен, который отменяется вместе с этим, но сам его не отменяет: например, таймаут
+    /// шага рецепта останавливает только шаг.
+    pub fn child(&self) -> CancelToken {
+        CancelToken {
+            flag: Arc::default(),
+            parent: Some(Arc::new(self.clone())),
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.flag.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.flag.load(Ordering::SeqCst)
+            || self
+                .parent
+                .as_ref()
+                .is_some_and(|parent| parent.is_cancelled())
+    }
+}
+
+/// Копии разделяют отмену, прогресс, список заблокированных операций и запрошенные действия.
+#[derive(Clone)]
 pub struct PluginApi {
-    app: AppHandle,
+    env: Environment,
+    guard: PathGuard,
+    capabilities: CapabilitySet,
+    blocked: Arc<Mutex<Vec<Denial>>>,
+    post_actions: Arc<Mutex<Vec<PostActionRequest>>>,
+    cancel: CancelToken,
+    progress: Progress,
+    backends: Backends,
 }
 
 impl PluginApi {
-    pub fn new(app: AppHandle) -> Self {
-        Self { app }
+    pub fn new(env: &Environment, capabilities: Vec<Capability>) -> Self {
+        Self::with_backends(env, capabilities, Backends::default())
+    }
+
+    /// `PluginApi`, который работает с файлами, реестром и программами через `backends`,
+    /// например через подделки из `core::fake` в тестах.
+    pub fn with_backends(
+        env: &Environment,
+        capabilities: Vec<Capability>,
+        backends: Backends,
+    ) -> Self {
+        let capabilities = CapabilitySet::new(capabilities);
+        let mut guard = PathGuard::new(env);
+        guard.set_allowed_roots(capabilities.fs_delete_roots(&env.temp_dir, &*backends.env));
+        Self {
+            env: env.clone(),
+            guard,
+            capabilities,
+            blocked: Arc::default(),
+            post_actions: Arc::default(),
+            cancel: CancelToken::default(),
+            progress: Progress::default(),
+            backends,
+        }
+    }
+
+    pub fn set_cancel_token(&mut self, cancel: CancelToken) {
+        self.cancel = cancel;
+    }
+
+    /// Плагины с долгими циклами должны проверять флаг и завершаться досрочно.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.is_cancelled()
+    }
+
+    /// Останавливает запуск: плагин видит отмену, запущенные через `run_command` процессы убиваются.
+    pub fn abort(&self) {
+        self.cancel.cancel();
+    }
+
+    pub fn progress(&self) -> Progress {
+        self.progress.clone()
+    }
+
+    pub fn take_blocked(&self) -> Vec<Denial> {
+        match self.blocked.lock() {
+            Ok(mut blocked) => std::mem::take(&mut *blocked),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Просит выполнить действие после запуска. Ядро объединит запросы всех плагинов,
+    /// покажет их в `RunResult` и выполнит только после подтверждения пользователем.
+    pub fn request_post_action(
+        &self,
+        action: PostAction,
+        reason: impl Into<String>,
+    ) -> Result<(), String> {
+        if action == PostAction::RestartShell {
+            self.allow(self.capabilities.check_shell_restart())?;
+        }
+        if let Ok(mut requests) = self.post_actions.lock() {
+            requests.push(PostActionRequest {
+                action,
+                reasons: vec![reason.into()],
+            });
+        }
+        Ok(())
+    }
+
+    pub fn take_post_actions(&self) -> Vec<PostActionRequest> {
+        match self.post_actions.lock() {
+            Ok(mut requests) => std::mem::take(&mut *requests),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    fn deny(&self, denial: Denial) -> String {
+        let message = denial.to_string();
+        if let Ok(mut blocked) = self.blocked.lock() {
+            blocked.push(denial);
+        }
+        message
+    }
+
+    fn guard_delete(&self, path: &Path) -> Result<(), String> {
+        self.guard
+            .check_delete(path)
+            .map_err(|err| self.deny(Denial::Path(err)))
+    }
+
+    fn guard_write(&self, path: &Path) -> Result<(), String> {
+        self.guard
+            .check_write(path)
+            .map_err(|err| self.deny(Denial::Path(err)))
+    }
+
+    /// Путь для записи должен быть абсолютным, без `..`, не защищённым и внутри
+    /// папок из `Capability::FsWrite`.
+    fn check_write(&self, path: &Path) -> Result<(), String> {
+        let escapes = path
+            .components()
+            .any(|component| component == Component::ParentDir);
+        if !path.is_absolute() || escapes {
+            return Err(format!("Некорректный путь для записи: {}", path.display()));
+        }
+        self.guard_write(path)?;
+        self.allow(
+            self.capabilities
+                .check_fs_write(path, &self.env.temp_dir, &*self.backends.env),
+        )
+    }
+
+    fn allow(&self, check: Result<(), CapabilityError>) -> Result<(), String> {
+        check.map_err(|err| self.deny(Denial::Capability(err)))
     }
 
-    #[allow(dead_code)]
     pub fn app_data_dir(&self) -> Result<PathBuf, String> {
-        self.app
-            .path()
-            .app_data_dir()
-            .map_err(|e| format!("Ошибка открытия AppData: {e}"))
+        self.env
+            .data_dir
+            .clone()
+            .ok_or_else(|| "Ошибка открытия AppData: папка не определена".to_string())
+    }
+
+    pub fn locale(&self) -> &str {
+        &self.env.locale
+    }
+
+    pub fn is_elevated(&self) -> bool {
+        self.backends.env.is_elevated()
+    }
+
+    /// Переменная окружения; пустая считается незаданной.
+    pub fn env_var(&self, name: &str) -> Option<String> {
+        self.backends
+            .env
+            .var(name)
+            .filter(|value| !value.trim().is_empty())
+    }
+
+    /// Полный путь к внешней программе или `None`, если её нет в системе.
+    pub fn which(&self, program: &str) -> Option<PathBuf> {
+        backend::which(program, &*self.backends.env, &*self.backends.fs)
     }
 
     pub fn temp_dir(&self) -> PathBuf {
-        std::env::temp_dir()
+        self.env.temp_dir.clone()
+    }
+
+    pub fn exists(&self, path: &Path) -> bool {
+        self.backends.fs.exists(path)
     }
 
-    #[allow(dead_code)]
+    /// Пути элементов папки. Ошибки отдаются текстом ОС, чтобы плагин мог их сократить.
+    pub fn read_dir(&self, path: &Path) -> Result<Vec<Result<PathBuf, String>>, String> {
+        let entries = self.backends.fs.read_dir(path).map_err(|e| e.to_string())?;
+        Ok(entries
+            .into_iter()
+            .map(|entry| entry.map_err(|e| e.to_string()))
+            .collect())
+    }
+
+    /// Символическая ссылка описывается сама, без перехода по ней.
+    pub fn metadata(&self, path: &Path) -> Result<FileInfo, String> {
+        self.backends.fs.metadata(path).map_err(|e| e.to_string())
+    }
+
+    /// Создаёт папку вместе с недостающими родительскими; проверки — как у `write_file`.
     pub fn create_dir_all(&self, path: &Path) -> Result<(), String> {
-        fs::create_dir_all(path).map_err(|e| format!("Ошибка создания папки: {e}"))
+        self.check_write(path)?;
+        self.backends
+            .fs
+            .create_dir_all(path)
+            .map_err(|e| format!("Ошибка создания папки: {e}"))
     }
 
     pub fn remove_file(&self, path: &Path) -> Result<(), String> {
-        fs::remove_file(path).map_err(|e| format!("Ошибка удаления файла: {e}"))
+        self.guard_delete(path)?;
+        self.backends
+            .fs
+            .remove_file(path)
+            .map_err(|e| format!("Ошибка удаления файла: {e}"))
     }
 
     pub fn remove_dir_all(&self, path: &Path) -> Result<(), String> {
-        fs::remove_dir_all(path).map_err(|e| format!("Ошибка удаления папки: {e}"))
+        self.guard_delete(path)?;
+        self.backends
+            .fs
+            .remove_dir_all(path)
+            .map_err(|e| format!("Ошибка удаления папки: {e}"))
     }
 
-    #[cfg(windows)]
-    pub fn get_registry_string(&self, key_path: &str, name: &str) -> Result<String, String> {
-        use winreg::RegKey;
-        use winreg::enums::{HKEY_CURRENT_USER, KEY_QUERY_VALUE};
-
-        let hkcu = RegKey::predef(HKEY_CURRENT_USER);
-        let key = hkcu
-            .open_subkey_with_flags(key_path, KEY_QUERY_VALUE)
-            .map_err(|e| format!("Ошибка открытия регистра: {e}"))?;
-        let value: String = key
-            .get_value(name)
-            .map_err(|e| format!("Ошибка прочтения значения в регистре: {e}"))?;
-        Ok(value)
+    /// Создаёт или перезаписывает файл внутри папок из `Capability::FsWrite`.
+    pub fn write_file(&self, path: &Path, contents: &[u8]) -> Result<(), String> {
+        self.check_write(path)?;
+        if let Some(parent) = path.parent() {
+            self.create_dir_all(parent)?;
+        }
+        self.backends
+            .fs
+            .write(path, contents)
+            .map_err(|e| format!("Ошибка записи файла: {e}"))
     }
 
-    #[cfg(not(windows))]
-    pub fn get_registry_string(&self, _key_path: &str, _name: &str) -> Result<String, String> {
-        Err("Registry доступен только на Windows.".to_string())
+    /// Запускает программу и ждёт её завершения. При отмене запуска процесс убивается.
+    /// Кодировка вывода определяется сама.
+    pub fn run_command(&self, program: &str, args: &[&str]) -> Result<CommandResult, String> {
+        self.run_command_with(program, args, &CommandOptions::default(), None)
     }
 
-    #[cfg(windows)]
-    pub fn restart_explorer(&self) -> Result<(), String> {
-        use std::process::Command;
+    /// `run_command` с рабочей папкой, переменными, вводом, таймаутом и кодировкой из `options`.
+    /// С `logger` строки вывода попадают в журнал сразу: stdout — как info, stderr — как warn.
+    pub fn run_command_with(
+        &self,
+        program: &str,
+        args: &[&str],
+        options: &CommandOptions,
+        mut logger: Option<&mut Logger>,
+    ) -> Result<CommandResult, String> {
+        self.allow(self.capabilities.check_process(program))?;
+        let previous_step = self
+            .progress
+            .set_step(Some(format!("Команда: {}", command_line(program, args))));
+        let encoding = options.encoding;
+        let mut on_line = |stream: OutputStream, line: &[u8]| {
+            let Some(logger) = logger.as_deref_mut() else {
+                return;
+            };
+            let text = encoding.decode(line);
+            let text = text.trim_end();
+            if text.is_empty() {
+                return;
+            }
+            match stream {
+                OutputStream::Stdout => logger.info(text),
+                OutputStream::Stderr => logger.warn(text),
+            }
+        };
+        let processes = &self.backends.processes;
+        let output = processes.run(program, args, options, &self.cancel, &mut on_line);
+        self.progress.set_step(previous_step);
+        let output = output?;
+        Ok(CommandResult {
+            code: output.code,
+            stdout: encoding.decode(&output.stdout),
+            stderr: encoding.decode(&output.stderr),
+            duration: output.duration,
+        })
+    }
 
-        let status = Command::new("taskkill")
-            .args(["/F", "/IM", "explorer.exe"])
-            .status()
-            .map_err(|e| format!("Ошибка остановки Explorer: {e}"))?;
+    /// Значение с типом. Ключ задаётся строкой (`HKLM\Software\Vendor`, без раздела — HKCU)
+    /// или `RegistryKey`, если нужен 32- или 64-битный вид.
+    pub fn get_registry_value(
+        &self,
+        key: impl Into<RegistryKey>,
+        name: &str,
+    ) -> Result<RegistryValue, String> {
+        let key = key.into();
+        self.allow(self.capabilities.check_registry_read(&key))?;
+        self.backends.registry.get_value(&key, name)
+    }
 
-        if !status.success() {
-            return Err(format!("Ошибка остановки Explorer (код: {status})"));
+    /// Строковое значение: `REG_SZ` или `REG_EXPAND_SZ` без раскрытия переменных.
+    pub fn get_registry_string(
+        &self,
+        key: impl Into<RegistryKey>,
+        name: &str,
+    ) -> Result<String, String> {
+        let key = key.into();
+        match self.get_registry_value(&key, name)? {
+            RegistryValue::String(text) | RegistryValue::ExpandString(text) => Ok(text),
+            other => Err(format!(
+                "Значение {name} в {key} имеет тип {}, а не строку",
+                other.kind_name()
+            )),
         }
-
-        Command::new("explorer.exe")
-            .spawn()
-            .map_err(|e| format!("Ошибка запуска Explorer: {e}"))?;
-
-        Ok(())
     }
 
-    #[cfg(not(windows))]
-    pub fn restart_explorer(&self) -> Result<(), String> {
-        Err("Перезапуск Explorer доступен только на Windows.".to_string())
+    /// Записывает значение, создавая ключ при необходимости.
+    pub fn set_registry_value(
+        &self,
+        key: impl Into<RegistryKey>,
+        name: &str,
+        value: RegistryValue,
+    ) -> Result<(), String> {
+        let key = key.into();
+        self.allow(self.capabilities.check_registry_write(&key))?;
+        self.backends.registry.create_key(&key)?;
+        self.backends.registry.set_value(&key, name, &value)
     }
 
-    #[cfg(windows)]
     pub fn set_registry_string(
         &self,
-        key_path: &str,
+        key: impl Into<RegistryKey>,
         name: &str,
         value: &str,
     ) -> Result<(), String> {
-        use winreg::RegKey;
-        use winreg::enums::{HKEY_CURRENT_USER, KEY_SET_VALUE};
-
-        let hkcu = RegKey::predef(HKEY_CURRENT_USER);
-        let key = hkcu
-            .open_subkey_with_flags(key_path, KEY_SET_VALUE)
-            .map_err(|e| format!("Ошибка открытия регистра: {e}"))?;
-        key.set_value(name, &value)
-            .map_err(|e| format!("Ошибка записи регистра: {e}"))?;
-        Ok(())
+        self.set_registry_value(key, name, RegistryValue::String(value.to_string()))
     }
 
-    #[cfg(not(windows))]
-    pub fn set_registry_string(
+    pub fn set_registry_dword(
         &self,
-        _key_path: &str,
-        _name: &str,
-        _value: &str,
+        key: impl Into<RegistryKey>,
+        name: &str,
+        value: u32,
     ) -> Result<(), String> {
-        Err("Registry доступен только на Windows.".to_string())
+        self.set_registry_value(key, name, RegistryValue::Dword(value))
     }
 
-    #[cfg(windows)]
-    pub fn registry_key_exists(&self, key_path: &str) -> Result<bool, String> {
-        use winreg::RegKey;
-        use winreg::enums::HKEY_CURRENT_USER;
-
-        let hkcu = RegKey::predef(HKEY_CURRENT_USER);
-        match hkcu.open_subkey(key_path) {
-            Ok(_) => Ok(true),
-            Err(_) => Ok(false),
-        }
+    /// Отсутствующее значение ошибкой не считается.
+    pub fn delete_registry_value(
+        &self,
+        key: impl Into<RegistryKey>,
+        name: &str,
+    ) -> Result<(), String> {
+        let key = key.into();
+        self.allow(self.capabilities.check_registry_write(&key))?;
+        self.backends.registry.delete_value(&key, name)
     }
 
-    #[cfg(not(windows))]
-    pub fn registry_key_exists(&self, _key_path: &str) -> Result<bool, String> {
-        Ok(false)
+    pub fn registry_key_exists(&self, key: impl Into<RegistryKey>) -> Result<bool, String> {
+        let key = key.into();
+        self.allow(self.capabilities.check_registry_read(&key))?;
+        self.backends.registry.key_exists(&key)
     }
 
-    #[cfg(windows)]
-    pub fn create_registry_key(&self, key_path: &str) -> Result<(), String> {
-        use winreg::RegKey;
-        use winreg::enums::HKEY_CURRENT_USER;
-
-        let hkcu = RegKey::predef(HKEY_CURRENT_USER);
-        hkcu.create_subkey(key_path)
-            .map_err(|e| format!("Ошибка создания ключа реестра: {e}"))?;
-        Ok(())
+    pub fn create_registry_key(&self, key: impl Into<RegistryKey>) -> Result<(), String> {
+        let key = key.into();
+        self.allow(self.capabilities.check_registry_write(&key))?;
+        self.backends.registry.create_key(&key)
     }
 
-    #[cfg(not(windows))]
-    pub fn create_registry_key(&self, _key_path: &str) -> Result<(), String> {
-        Err("Registry доступен только на Windows.".to_string())
+    /// Удаляет ключ вместе с подключами.
+    pub fn delete_registry_key(&self, key: impl Into<RegistryKey>) -> Result<(), String> {
+        let key = key.into();
+        self.allow(self.capabilities.check_registry_write(&key))?;
+        self.backends.registry.delete_key(&key)
     }
 
-    #[cfg(windows)]
-    pub fn delete_registry_key(&self, key_path: &str) -> Result<(), String> {
-        use winreg::RegKey;
-        use winreg::enums::HKEY_CURRENT_USER;
-
-        let hkcu = RegKey::predef(HKEY_CURRENT_USER);
-        hkcu.delete_subkey_all(key_path)
-            .map_err(|e| format!("Ошибка удаления ключа реестра: {e}"))?;
-        Ok(())
+    /// Имена подключей (только первого уровня).
+    pub fn registry_subkeys(&self, key: impl Into<RegistryKey>) -> Result<Vec<String>, String> {
+        let key = key.into();
+        self.allow(self.capabilities.check_registry_read(&key))?;
+        self.backends.registry.subkeys(&key)
     }
 
-    #[cfg(not(windows))]
-    pub fn delete_registry_key(&self, _key_path: &str) -> Result<(), String> {
-        Err("Registry доступен только на Windows.".to_string())
+    /// Значения ключа с именами; у значения по умолчанию имя пустое.
+    pub fn registry_values(
+        &self,
+        key: impl Into<RegistryKey>,
+    ) -> Result<Vec<(String, RegistryValue)>, String> {
+        let key = key.into();
+        self.allow(self.capabilities.check_registry_read(&key))?;
+        self.backends.registry.values(&key)
     }
 }
 
@@ -202,3 +496,55 @@ pub fn format_bytes(bytes: u64) -> String {
         format!("{bytes} Байт")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::fake::Fakes;
+
+    fn api(fakes: &Fakes, capabilities: Vec<Capability>) -> PluginApi {
+        PluginApi::with_backends(&fakes.environment(), capabilities, fakes.backends())
+    }
+
+    fn temp_write() -> Vec<Capability> {
+        vec![Capability::FsWrite {
+            scopes: vec!["%TEMP%".to_string()],
+        }]
+    }
+
+    #[test]
+    fn create_dir_all_is_checked_like_write_file() {
+        let fakes = Fakes::new();
+        let dir = fakes.path("temp/reports/2024");
+
+        let error = api(&fakes, Vec::new()).create_dir_all(&dir).unwrap_err();
+        assert!(
+            error.starts_with("Операция не объявлена плагином"),
+            "{error}"
+        );
+        assert!(!fakes.fs.contains(&dir));
+
+        let api = api(&fakes, temp_write());
+        assert_eq!(api.create_dir_all(&dir), Ok(()));
+        assert!(fakes.fs.contains(&dir));
+
+        let relative = api.create_dir_all(Path::new("reports")).unwrap_err();
+        assert_eq!(relative, "Некорректный путь для записи: reports");
+        let escape = fakes.path("temp").join("..").join("data");
+        assert!(api.create_dir_all(&escape).is_err());
+    }
+
+    #[test]
+    fn create_dir_all_respects_the_path_guard() {
+        let fakes = Fakes::new();
+        let api = api(
+            &fakes,
+            vec![Capability::FsWrite {
+                scopes: vec![fakes.path("").display().to_string()],
+            }],
+        );
+        let inside_data = fakes.path("data/plugins");
+        assert!(api.create_dir_all(&inside_data).is_err());
+        assert!(!fakes.fs.contains(&inside_data));
+    }
+}
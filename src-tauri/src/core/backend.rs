@@ -0,0 +1,694 @@
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::{
+        Arc,
+        mpsc::{self, RecvTimeoutError},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::core::api::CancelToken;
+use crate::core::encoding::OutputEncoding;
+#[cfg(windows)]
+use crate::core::hive::{Hive, RegistryView};
+use crate::core::hive::{RegistryKey, RegistryValue};
+#[cfg(not(windows))]
+use crate::core::platform::unsupported_feature;
+use crate::core::privilege;
+
+/// Как часто `run_command` проверяет, не отменён ли запуск и не вышло ли время.
+const PROCESS_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Тип и размер элемента. Символическая ссылка описывается сама, без перехода по ней.
+#[derive(Clone, Copy, Debug)]
+pub struct FileInfo {
+    pub is_dir: bool,
+    /// Размер файла в байтах; у папок 0.
+    pub len: u64,
+}
+
+/// Результат запущенной программы. `code` — `None`, если процесс завершён сигналом.
+#[derive(Clone, Debug, Default)]
+pub struct CommandOutput {
+    pub code: Option<i32>,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    pub duration: Duration,
+}
+
+impl CommandOutput {
+    pub fn success(&self) -> bool {
+        self.code == Some(0)
+    }
+
+    /// `код 1` для сообщений об ошибке.
+    pub fn status_text(&self) -> String {
+        status_text(self.code)
+    }
+}
+
+pub fn status_text(code: Option<i32>) -> String {
+    match code {
+        Some(code) => format!("код {code}"),
+        None => "без кода завершения".to_string(),
+    }
+}
+
+/// Как запускать программу. По умолчанию — в папке приложения, без ввода и без ограничения
+/// по времени.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct CommandOptions {
+    /// Рабочая папка.
+    pub cwd: Option<PathBuf>,
+    /// Переменные окружения в дополнение к унаследованным.
+    pub env: BTreeMap<String, String>,
+    /// Текст, который программа получит на стандартный ввод.
+    pub stdin: Option<String>,
+    /// Через сколько секунд процесс будет убит.
+    pub timeout_secs: Option<u64>,
+    /// Кодировка вывода. Её учитывает `PluginApi`, `ProcessRunner` отдаёт байты как есть.
+    pub encoding: OutputEncoding,
+}
+
+/// Откуда пришла строка вывода.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+/// Обработчик строк вывода: байты строки без перевода строки.
+pub type LineHandler<'a> = dyn FnMut(OutputStream, &[u8]) + 'a;
+
+/// Копит вывод программы и отдаёт строки, как только они завершены.
+#[derive(Default)]
+pub struct LineBuffer {
+    /// Сколько байт stdout и stderr уже отдано.
+    done: [usize; 2],
+}
+
+impl LineBuffer {
+    pub fn push(
+        &mut self,
+        output: &mut CommandOutput,
+        stream: OutputStream,
+        chunk: &[u8],
+        on_line: &mut LineHandler,
+    ) {
+        let (buffer, done) = match stream {
+            OutputStream::Stdout => (&mut output.stdout, &mut self.done[0]),
+            OutputStream::Stderr => (&mut output.stderr, &mut self.done[1]),
+        };
+        buffer.extend_from_slice(chunk);
+        while let Some(end) = buffer[*done..].iter().position(|&byte| byte == b'\n') {
+            let line = &buffer[*done..*done + end];
+            on_line(stream, line.strip_suffix(b"\r").unwrap_or(line));
+            *done += end + 1;
+        }
+    }
+
+    /// Отдаёт последние строки без перевода строки в конце.
+    pub fn finish(&mut self, output: &CommandOutput, on_line: &mut LineHandler) {
+        let streams = [
+            (OutputStream::Stdout, &output.stdout),
+            (OutputStream::Stderr, &output.stderr),
+        ];
+        for ((stream, buffer), done) in streams.into_iter().zip(&mut self.done) {
+            if *done < buffer.len() {
+                on_line(stream, &buffer[*done..]);
+                *done = buffer.len();
+            }
+        }
+    }
+}
+
+/// Файлы и папки, с которыми работает `PluginApi`.
+pub trait FileSystem: Send + Sync {
+    fn exists(&self, path: &Path) -> bool;
+    /// Пути элементов папки. Ошибка одного элемента не прерывает чтение остальных.
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<io::Result<PathBuf>>>;
+    fn metadata(&self, path: &Path) -> io::Result<FileInfo>;
+    fn write(&self, path: &Path, contents: &[u8]) -> io::Result<()>;
+    fn create_dir_all(&self, path: &Path) -> io::Result<()>;
+    fn remove_file(&self, path: &Path) -> io::Result<()>;
+    fn remove_dir_all(&self, path: &Path) -> io::Result<()>;
+}
+
+/// Ключи и значения реестра. Ошибки уже в виде текста для журнала.
+pub trait Registry: Send + Sync {
+    fn get_value(&self, key: &RegistryKey, name: &str) -> Result<RegistryValue, String>;
+    /// Ключ должен существовать.
+    fn set_value(&self, key: &RegistryKey, name: &str, value: &RegistryValue)
+    -> Result<(), String>;
+    /// Отсутствующее значение ошибкой не считается.
+    fn delete_value(&self, key: &RegistryKey, name: &str) -> Result<(), String>;
+    fn key_exists(&self, key: &RegistryKey) -> Result<bool, String>;
+    /// Создаёт ключ вместе с недостающими родителями.
+    fn create_key(&self, key: &RegistryKey) -> Result<(), String>;
+    /// Удаляет ключ вместе с подключами.
+    fn delete_key(&self, key: &RegistryKey) -> Result<(), String>;
+    /// Имена подключей (без пути).
+    fn subkeys(&self, key: &RegistryKey) -> Result<Vec<String>, String>;
+    /// Значения ключа; значение по умолчанию — с пустым именем.
+    fn values(&self, key: &RegistryKey) -> Result<Vec<(String, RegistryValue)>, String>;
+}
+
+/// Запуск внешних программ.
+pub trait ProcessRunner: Send + Sync {
+    /// Ждёт завершения программы и передаёт `on_line` строки вывода по мере появления.
+    /// При отмене запуска или по таймауту процесс убивается.
+    fn run(
+        &self,
+        program: &str,
+        args: &[&str],
+        options: &CommandOptions,
+        cancel: &CancelToken,
+        on_line: &mut LineHandler,
+    ) -> Result<CommandOutput, String>;
+    /// Запускает программу и не ждёт её завершения.
+    fn spawn(&self, program: &str, args: &[&str]) -> Result<(), String>;
+}
+
+/// Переменные окружения и права текущего процесса.
+pub trait SystemEnvironment: Send + Sync {
+    fn var(&self, name: &str) -> Option<String>;
+    fn is_elevated(&self) -> bool;
+}
+
+/// Всё, через что `PluginApi` обращается к системе. По умолчанию — настоящая система,
+/// в тестах — подделки из `core::fake`.
+#[derive(Clone)]
+pub struct Backends {
+    pub fs: Arc<dyn FileSystem>,
+    pub registry: Arc<dyn Registry>,
+    pub processes: Arc<dyn ProcessRunner>,
+    pub env: Arc<dyn SystemEnvironment>,
+}
+
+impl Default for Backends {
+    fn default() -> Self {
+        Self {
+            fs: Arc::new(OsFileSystem),
+            registry: Arc::new(OsRegistry),
+            processes: Arc::new(OsProcessRunner),
+            env: Arc::new(OsEnvironment),
+        }
+    }
+}
+
+pub fn command_line(program: &str, args: &[&str]) -> String {
+    std::iter::once(program)
+        .chain(args.iter().copied())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Где лежит программа, которую запустит `ProcessRunner`. Имя без папки ищется, как это
+/// делает Windows: сначала в `System32` и папке Windows, потом в `PATH`; имя без
+/// расширения дополняется расширениями из `PATHEXT`.
+pub fn which(program: &str, env: &dyn SystemEnvironment, fs: &dyn FileSystem) -> Option<PathBuf> {
+    let is_file = |path: &PathBuf| fs.metadata(path).is_ok_and(|info| !info.is_dir);
+    let path = Path::new(program);
+    if program.is_empty() {
+        return None;
+    }
+    if path.is_absolute() || path.components().count() > 1 {
+        return executable_names(path, env).into_iter().find(is_file);
+    }
+
+    let mut dirs = Vec::new();
+    if cfg!(windows)
+        && let Some(root) = env.var("SystemRoot")
+    {
+        let root = PathBuf::from(root);
+        dirs.push(root.join("System32"));
+        dirs.push(root);
+    }
+    if let Some(paths) = env.var("PATH") {
+        dirs.extend(std::env::split_paths(&paths).filter(|dir| dir.is_absolute()));
+    }
+    dirs.iter()
+        .flat_map(|dir| executable_names(&dir.join(program), env))
+        .find(is_file)
+}
+
+fn executable_names(path: &Path, env: &dyn SystemEnvironment) -> Vec<PathBuf> {
+    if !cfg!(windows) || path.extension().is_some() {
+        return vec![path.to_path_buf()];
+    }
+    let extensions = env
+        .var("PATHEXT")
+        .unwrap_or_else(|| ".COM;.EXE;.BAT;.CMD".to_string());
+    extensions
+        .split(';')
+        .filter(|extension| !extension.is_empty())
+        .map(|extension| {
+            let mut name = path.as_os_str().to_owned();
+            name.push(extension.to_lowercase());
+            PathBuf::from(name)
+        })
+        .collect()
+}
+
+pub struct OsFileSystem;
+
+impl FileSystem for OsFileSystem {
+    fn exists(&self, path: &Path) -> bool {
+        path.exists()
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<io::Result<PathBuf>>> {
+        Ok(fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect())
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<FileInfo> {
+        let metadata = fs::symlink_metadata(path)?;
+        Ok(FileInfo {
+            is_dir: metadata.is_dir(),
+            len: if metadata.is_dir() { 0 } else { metadata.len() },
+        })
+    }
+
+    fn write(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        fs::write(path, contents)
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        fs::create_dir_all(path)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        fs::remove_file(path)
+    }
+
+    fn remove_dir_all(&self, path: &Path) -> io::Result<()> {
+        fs::remove_dir_all(path)
+    }
+}
+
+pub struct OsRegistry;
+
+#[cfg(windows)]
+impl OsRegistry {
+    fn open(key: &RegistryKey, access: u32) -> io::Result<winreg::RegKey> {
+        Self::root(key.hive).open_subkey_with_flags(&key.path, access | Self::view_flags(key))
+    }
+
+    fn root(hive: Hive) -> winreg::RegKey {
+        use winreg::enums::{
+            HKEY_CLASSES_ROOT, HKEY_CURRENT_CONFIG, HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE,
+            HKEY_USERS,
+        };
+
+        winreg::RegKey::predef(match hive {
+            Hive::ClassesRoot => HKEY_CLASSES_ROOT,
+            Hive::CurrentUser => HKEY_CURRENT_USER,
+            Hive::LocalMachine => HKEY_LOCAL_MACHINE,
+            Hive::Users => HKEY_USERS,
+            Hive::CurrentConfig => HKEY_CURRENT_CONFIG,
+        })
+    }
+
+    fn view_flags(key: &RegistryKey) -> u32 {
+        use winreg::enums::{KEY_WOW64_32KEY, KEY_WOW64_64KEY};
+
+        match key.view {
+            RegistryView::Native => 0,
+            RegistryView::Bits32 => KEY_WOW64_32KEY,
+            RegistryView::Bits64 => KEY_WOW64_64KEY,
+        }
+    }
+
+    fn reg_type(kind: u32) -> Option<winreg::enums::RegType> {
+        use winreg::enums::RegType::*;
+
+        [
+            REG_NONE,
+            REG_SZ,
+            REG_EXPAND_SZ,
+            REG_BINARY,
+            REG_DWORD,
+            REG_DWORD_BIG_ENDIAN,
+            REG_LINK,
+            REG_MULTI_SZ,
+            REG_RESOURCE_LIST,
+            REG_FULL_RESOURCE_DESCRIPTOR,
+            REG_RESOURCE_REQUIREMENTS_LIST,
+            REG_QWORD,
+        ]
+        .into_iter()
+        .find(|reg_type| reg_type.clone() as u32 == kind)
+    }
+
+    fn convert(raw: winreg::RegValue) -> RegistryValue {
+        RegistryValue::from_bytes(raw.vtype as u32, &raw.bytes)
+    }
+}
+
+#[cfg(windows)]
+impl Registry for OsRegistry {
+    fn get_value(&self, key: &RegistryKey, name: &str) -> Result<RegistryValue, String> {
+        use winreg::enums::KEY_QUERY_VALUE;
+
+        let handle = Self::open(key, KEY_QUERY_VALUE)
+            .map_err(|e| format!("Ошибка открытия регистра: {e}"))?;
+        handle
+            .get_raw_value(name)
+            .map(Self::convert)
+            .map_err(|e| format!("Ошибка прочтения значения в регистре: {e}"))
+    }
+
+    fn set_value(
+        &self,
+        key: &RegistryKey,
+        name: &str,
+        value: &RegistryValue,
+    ) -> Result<(), String> {
+        use winreg::enums::KEY_SET_VALUE;
+
+        let vtype = Self::reg_type(value.kind())
+            .ok_or_else(|| format!("Неизвестный тип значения реестра: {}", value.kind()))?;
+        let handle =
+            Self::open(key, KEY_SET_VALUE).map_err(|e| format!("Ошибка открытия регистра: {e}"))?;
+        let raw = winreg::RegValue {
+            bytes: value.to_bytes(),
+            vtype,
+        };
+        handle
+            .set_raw_value(name, &raw)
+            .map_err(|e| format!("Ошибка записи регистра: {e}"))
+    }
+
+    fn delete_value(&self, key: &RegistryKey, name: &str) -> Result<(), String> {
+        use winreg::enums::KEY_SET_VALUE;
+
+        let handle = match Self::open(key, KEY_SET_VALUE) {
+            Ok(handle) => handle,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(format!("Ошибка открытия регистра: {e}")),
+        };
+        match handle.delete_value(name) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(format!("Ошибка удаления значения реестра: {e}")),
+        }
+    }
+
+    fn key_exists(&self, key: &RegistryKey) -> Result<bool, String> {
+        use winreg::enums::KEY_READ;
+
+        match Self::open(key, KEY_READ) {
+            Ok(_) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(format!("Ошибка открытия регистра: {e}")),
+        }
+    }
+
+    fn create_key(&self, key: &RegistryKey) -> Result<(), String> {
+        use winreg::enums::KEY_ALL_ACCESS;
+
+        Self::root(key.hive)
+            .create_subkey_with_flags(&key.path, KEY_ALL_ACCESS | Self::view_flags(key))
+            .map_err(|e| format!("Ошибка создания ключа реестра: {e}"))?;
+        Ok(())
+    }
+
+    fn delete_key(&self, key: &RegistryKey) -> Result<(), String> {
+        use winreg::enums::KEY_ALL_ACCESS;
+
+        let Some(parent) = key.parent() else {
+            return Err(format!("Нельзя удалить раздел реестра целиком: {key}"));
+        };
+        // Вид 32/64 бит задаётся при открытии ключа, поэтому поддерево удаляется через родителя.
+        let handle = if key.view == RegistryView::Native {
+            Self::root(key.hive)
+        } else {
+            Self::open(&parent, KEY_ALL_ACCESS)
+                .map_err(|e| format!("Ошибка открытия регистра: {e}"))?
+        };
+        let path = if key.view == RegistryView::Native {
+            key.path.as_str()
+        } else {
+            key.name()
+        };
+        handle
+            .delete_subkey_all(path)
+            .map_err(|e| format!("Ошибка удаления ключа реестра: {e}"))
+    }
+
+    fn subkeys(&self, key: &RegistryKey) -> Result<Vec<String>, String> {
+        use winreg::enums::KEY_ENUMERATE_SUB_KEYS;
+
+        let handle = Self::open(key, KEY_ENUMERATE_SUB_KEYS)
+            .map_err(|e| format!("Ошибка открытия регистра: {e}"))?;
+        handle
+            .enum_keys()
+            .collect::<io::Result<Vec<String>>>()
+            .map_err(|e| format!("Ошибка чтения подключей реестра: {e}"))
+    }
+
+    fn values(&self, key: &RegistryKey) -> Result<Vec<(String, RegistryValue)>, String> {
+        use winreg::enums::KEY_QUERY_VALUE;
+
+        let handle = Self::open(key, KEY_QUERY_VALUE)
+            .map_err(|e| format!("Ошибка открытия регистра: {e}"))?;
+        handle
+            .enum_values()
+            .map(|entry| entry.map(|(name, raw)| (name, Self::convert(raw))))
+            .collect::<io::Result<Vec<_>>>()
+            .map_err(|e| format!("Ошибка чтения значений реестра: {e}"))
+    }
+}
+
+#[cfg(not(windows))]
+impl Registry for OsRegistry {
+    fn get_value(&self, _key: &RegistryKey, _name: &str) -> Result<RegistryValue, String> {
+        Err(unsupported_feature("Реестр"))
+    }
+
+    fn set_value(
+        &self,
+        _key: &RegistryKey,
+        _name: &str,
+        _value: &RegistryValue,
+    ) -> Result<(), String> {
+        Err(unsupported_feature("Реестр"))
+    }
+
+    fn delete_value(&self, _key: &RegistryKey, _name: &str) -> Result<(), String> {
+        Err(unsupported_feature("Реестр"))
+    }
+
+    fn key_exists(&self, _key: &RegistryKey) -> Result<bool, String> {
+        Err(unsupported_feature("Реестр"))
+    }
+
+    fn create_key(&self, _key: &RegistryKey) -> Result<(), String> {
+        Err(unsupported_feature("Реестр"))
+    }
+
+    fn delete_key(&self, _key: &RegistryKey) -> Result<(), String> {
+        Err(unsupported_feature("Реестр"))
+    }
+
+    fn subkeys(&self, _key: &RegistryKey) -> Result<Vec<String>, String> {
+        Err(unsupported_feature("Реестр"))
+    }
+
+    fn values(&self, _key: &RegistryKey) -> Result<Vec<(String, RegistryValue)>, String> {
+        Err(unsupported_feature("Реестр"))
+    }
+}
+
+pub struct OsProcessRunner;
+
+impl ProcessRunner for OsProcessRunner {
+    fn run(
+        &self,
+        program: &str,
+        args: &[&str],
+        options: &CommandOptions,
+        cancel: &CancelToken,
+        on_line: &mut LineHandler,
+    ) -> Result<CommandOutput, String> {
+        let mut command = Command::new(program);
+        command
+            .args(args)
+            .envs(&options.env)
+            .stdin(if options.stdin.is_some() {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        if let Some(cwd) = &options.cwd {
+            command.current_dir(cwd);
+        }
+        let started = Instant::now();
+        let mut child = command
+            .spawn()
+            .map_err(|e| format!("Не удалось запустить {program}: {e}"))?;
+        if let (Some(mut stdin), Some(input)) = (child.stdin.take(), options.stdin.clone()) {
+            // Отдельный поток: программа может не читать ввод, пока не выведет своё.
+            thread::spawn(move || {
+                let _ = stdin.write_all(input.as_bytes());
+            });
+        }
+        let (sender, receiver) = mpsc::channel();
+        read_pipe(child.stdout.take(), OutputStream::Stdout, sender.clone());
+        read_pipe(child.stderr.take(), OutputStream::Stderr, sender);
+
+        let timeout = options.timeout_secs.map(Duration::from_secs);
+        let mut output = CommandOutput::default();
+        let mut lines = LineBuffer::default();
+        let mut pipes_open = true;
+        let status = loop {
+            if pipes_open {
+                match receiver.recv_timeout(PROCESS_POLL_INTERVAL) {
+                    Ok((stream, chunk)) => lines.push(&mut output, stream, &chunk, on_line),
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => pipes_open = false,
+                }
+            } else {
+                thread::sleep(PROCESS_POLL_INTERVAL);
+            }
+            if !pipes_open
+                && let Some(status) = child
+                    .try_wait()
+                    .map_err(|e| format!("Ошибка ожидания {program}: {e}"))?
+            {
+                break status;
+            }
+            let stop = if cancel.is_cancelled() {
+                Some("Команда прервана".to_string())
+            } else {
+                timeout
+                    .filter(|timeout| started.elapsed() >= *timeout)
+                    .map(|timeout| format!("Команда не завершилась за {} с", timeout.as_secs()))
+            };
+            if let Some(reason) = stop {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!("{reason}: {}", command_line(program, args)));
+            }
+        };
+
+        lines.finish(&output, on_line);
+        output.code = status.code();
+        output.duration = started.elapsed();
+        Ok(output)
+    }
+
+    fn spawn(&self, program: &str, args: &[&str]) -> Result<(), String> {
+        Command::new(program)
+            .args(args)
+            .spawn()
+            .map(|_| ())
+            .map_err(|e| format!("Не удалось запустить {program}: {e}"))
+    }
+}
+
+/// Читает поток в отдельном потоке и пересылает куски по мере поступления.
+fn read_pipe(
+    pipe: Option<impl Read + Send + 'static>,
+    stream: OutputStream,
+    sender: mpsc::Sender<(OutputStream, Vec<u8>)>,
+) {
+    let Some(mut pipe) = pipe else {
+        return;
+    };
+    thread::spawn(move || {
+        let mut buffer = [0; 4096];
+        loop {
+            match pipe.read(&mut buffer) {
+                Ok(0) | Err(_) => break,
+                Ok(read) => {
+                    if sender.send((stream, buffer[..read].to_vec())).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+}
+
+pub struct OsEnvironment;
+
+impl SystemEnvironment for OsEnvironment {
+    fn var(&self, name: &str) -> Option<String> {
+        std::env::var(name).ok()
+    }
+
+    fn is_elevated(&self) -> bool {
+        privilege::is_elevated()
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn run(script: &str, options: &CommandOptions) -> (Result<CommandOutput, String>, Vec<String>) {
+        let mut lines = Vec::new();
+        let mut on_line = |stream: OutputStream, line: &[u8]| {
+            lines.push(format!("{stream:?}: {}", String::from_utf8_lossy(line)));
+        };
+        let output = OsProcessRunner.run(
+            "sh",
+            &["-c", script],
+            options,
+            &CancelToken::default(),
+            &mut on_line,
+        );
+        (output, lines)
+    }
+
+    #[test]
+    fn streams_lines_and_passes_stdin_env_and_cwd() {
+        let options = CommandOptions {
+            cwd: Some(PathBuf::from("/")),
+            env: BTreeMap::from([("GREETING".to_string(), "привет".to_string())]),
+            stdin: Some("из stdin".to_string()),
+            ..CommandOptions::default()
+        };
+        let (output, lines) = run("echo $GREETING; pwd; cat; echo fail >&2; exit 3", &options);
+
+        let output = output.unwrap();
+        assert_eq!(output.code, Some(3));
+        assert_eq!(output.stdout, "привет\n/\nиз stdin".as_bytes());
+        // Порядок между stdout и stderr не гарантирован: они читаются в разных потоках.
+        let stdout: Vec<&String> = lines
+            .iter()
+            .filter(|line| line.starts_with("Stdout"))
+            .collect();
+        assert_eq!(stdout, ["Stdout: привет", "Stdout: /", "Stdout: из stdin"]);
+        assert!(lines.contains(&"Stderr: fail".to_string()));
+    }
+
+    #[test]
+    fn timeout_kills_process() {
+        let options = CommandOptions {
+            timeout_secs: Some(1),
+            ..CommandOptions::default()
+        };
+        let started = Instant::now();
+        let (output, _) = run("sleep 10", &options);
+
+        assert!(
+            output
+                .unwrap_err()
+                .starts_with("Команда не завершилась за 1 с")
+        );
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
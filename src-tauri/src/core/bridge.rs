@@ -0,0 +1,276 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::path::PathBuf;
+
+use crate::core::api::PluginApi;
+use crate::core::backend::CommandOptions;
+use crate::core::hive::{RegistryKey, RegistryValue, RegistryView};
+use crate::core::logger::LogLevel;
+use crate::core::post_action::PostAction;
+
+/// Ответ на JSON-запрос внешнего плагина: `{"ok": ...}` или `{"error": "..."}`.
+/// Без `PluginApi` (при чтении метаданных) все запросы отклоняются.
+pub fn respond(api: Option<&PluginApi>, request: &[u8]) -> Vec<u8> {
+    let response = match (api, serde_json::from_slice(request)) {
+        (Some(api), Ok(request)) => match host_call(api, request) {
+            Ok(value) => json!({ "ok": value }),
+            Err(err) => json!({ "error": err }),
+        },
+        (None, _) => json!({ "error": "API недоступно при чтении метаданных." }),
+        (_, Err(e)) => json!({ "error": format!("Некорректный запрос: {e}") }),
+    };
+    response.to_string().into_bytes()
+}
+
+/// Уровень записи журнала по коду из внешнего плагина: 0 — info, 1 — warn,
+/// 2 — error, 3 — success.
+pub fn log_level(level: i32) -> LogLevel {
+    match level {
+        1 => LogLevel::Warn,
+        2 => LogLevel::Error,
+        3 => LogLevel::Success,
+        _ => LogLevel::Info,
+    }
+}
+
+/// Операции `PluginApi`, доступные внешнему плагину через `call`.
+#[derive(Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum HostRequest {
+    TempDir,
+    Locale,
+    IsElevated,
+    /// Необязательные `cwd`, `env`, `stdin`, `timeout_secs` и `encoding` — как у
+    /// `CommandOptions`.
+    RunCommand {
+        program: String,
+        #[serde(default)]
+        args: Vec<String>,
+        #[serde(flatten)]
+        options: CommandOptions,
+    },
+    RemoveFile {
+        path: PathBuf,
+    },
+    RemoveDirAll {
+        path: PathBuf,
+    },
+    WriteFile {
+        path: PathBuf,
+        content: String,
+    },
+    GetRegistryString {
+        key: String,
+        name: String,
+    },
+    SetRegistryString {
+        key: String,
+        name: String,
+        value: String,
+    },
+    SetRegistryDword {
+        key: String,
+        name: String,
+        value: u32,
+    },
+    DeleteRegistryValue {
+        key: String,
+        name: String,
+    },
+    RegistryKeyExists {
+        key: String,
+    },
+    CreateRegistryKey {
+        key: String,
+    },
+    DeleteRegistryKey {
+        key: String,
+    },
+    /// Значение с типом: `{"type": "dword", "data": 1}`.
+    GetRegistryValue {
+        key: String,
+        name: String,
+        #[serde(default)]
+        view: RegistryView,
+    },
+    SetRegistryValue {
+        key: String,
+        name: String,
+        value: RegistryValue,
+        #[serde(default)]
+        view: RegistryView,
+    },
+    RegistrySubkeys {
+        key: String,
+        #[serde(default)]
+        view: RegistryView,
+    },
+    RegistryValues {
+        key: String,
+        #[serde(default)]
+        view: RegistryView,
+    },
+    /// `restart_shell`, `logoff` или `reboot`; выполняется после запуска с подтверждения.
+    RequestPostAction {
+        action: PostAction,
+        reason: String,
+    },
+}
+
+#[derive(Serialize)]
+struct CommandOutput {
+    code: Option<i32>,
+    stdout: String,
+    stderr: String,
+    duration_ms: u64,
+}
+
+fn host_call(api: &PluginApi, request: HostRequest) -> Result<Value, String> {
+    let unit = |result: Result<(), String>| result.map(|()| Value::Null);
+    match request {
+        HostRequest::TempDir => Ok(json!(api.temp_dir())),
+        HostRequest::Locale => Ok(json!(api.locale())),
+        HostRequest::IsElevated => Ok(json!(api.is_elevated())),
+        HostRequest::RunCommand {
+            program,
+            args,
+            options,
+        } => {
+            let args: Vec<&str> = args.iter().map(String::as_str).collect();
+            let output = api.run_command_with(&program, &args, &options, None)?;
+            Ok(json!(CommandOutput {
+                code: output.code,
+                stdout: output.stdout,
+                stderr: output.stderr,
+                duration_ms: output.duration.as_millis() as u64,
+            }))
+        }
+        HostRequest::RemoveFile { path } => unit(api.remove_file(&path)),
+        HostRequest::RemoveDirAll { path } => unit(api.remove_dir_all(&path)),
+        HostRequest::WriteFile { path, content } => unit(api.write_file(&path, content.as_bytes())),
+        HostRequest::GetRegistryString { key, name } => {
+            api.get_registry_string(&key, &name).map(Value::String)
+        }
+        HostRequest::SetRegistryString { key, name, value } => {
+            unit(api.set_registry_string(&key, &name, &value))
+        }
+        HostRequest::SetRegistryDword { key, name, value } => {
+            unit(api.set_registry_dword(&key, &name, value))
+        }
+        HostRequest::DeleteRegistryValue { key, name } => {
+            unit(api.delete_registry_value(&key, &name))
+        }
+        HostRequest::RegistryKeyExists { key } => api.registry_key_exists(&key).map(Value::Bool),
+        HostRequest::CreateRegistryKey { key } => unit(api.create_registry_key(&key)),
+        HostRequest::DeleteRegistryKey { key } => unit(api.delete_registry_key(&key)),
+        HostRequest::RequestPostAction { action, reason } => {
+            unit(api.request_post_action(action, reason))
+        }
+        HostRequest::GetRegistryValue { key, name, view } => {
+            let value = api.get_registry_value(RegistryKey::parse(&key).with_view(view), &name)?;
+            Ok(json!(value))
+        }
+        HostRequest::SetRegistryValue {
+            key,
+            name,
+            value,
+            view,
+        } => unit(api.set_registry_value(RegistryKey::parse(&key).with_view(view), &name, value)),
+        HostRequest::RegistrySubkeys { key, view } => {
+            let subkeys = api.registry_subkeys(RegistryKey::parse(&key).with_view(view))?;
+            Ok(json!(subkeys))
+        }
+        HostRequest::RegistryValues { key, view } => {
+            let values = api.registry_values(RegistryKey::parse(&key).with_view(view))?;
+            Ok(values
+                .into_iter()
+                .map(|(name, value)| json!({ "name": name, "value": value }))
+                .collect())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::capability::Capability;
+    use crate::core::fake::Fakes;
+
+    fn api(fakes: &Fakes, capabilities: Vec<Capability>) -> PluginApi {
+        PluginApi::with_backends(&fakes.environment(), capabilities, fakes.backends())
+    }
+
+    fn call(api: Option<&PluginApi>, request: Value) -> Value {
+        serde_json::from_slice(&respond(api, request.to_string().as_bytes())).unwrap()
+    }
+
+    fn error(response: &Value) -> &str {
+        response["error"].as_str().unwrap_or_default()
+    }
+
+    #[test]
+    fn malformed_requests_are_errors() {
+        let fakes = Fakes::new();
+        let api = api(&fakes, Vec::new());
+        let response: Value = serde_json::from_slice(&respond(Some(&api), b"{not json")).unwrap();
+        assert!(error(&response).starts_with("Некорректный запрос:"));
+
+        let unknown = call(Some(&api), json!({ "op": "format_disk" }));
+        assert!(error(&unknown).starts_with("Некорректный запрос:"));
+
+        let missing = call(Some(&api), json!({ "op": "write_file", "path": "a.txt" }));
+        assert!(error(&missing).starts_with("Некорректный запрос:"));
+
+        let without_api = call(None, json!({ "op": "temp_dir" }));
+        assert_eq!(error(&without_api), "API недоступно при чтении метаданных.");
+    }
+
+    #[test]
+    fn undeclared_operations_are_refused() {
+        let fakes = Fakes::new();
+        let api = api(&fakes, Vec::new());
+        let path = fakes.path("temp/out.txt");
+
+        let write = call(
+            Some(&api),
+            json!({ "op": "write_file", "path": path, "content": "x" }),
+        );
+        assert!(error(&write).starts_with("Операция не объявлена плагином"));
+        assert!(!fakes.fs.contains(&path));
+
+        let registry = call(
+            Some(&api),
+            json!({ "op": "set_registry_dword", "key": "Software\\Vendor", "name": "Mode", "value": 1 }),
+        );
+        assert!(error(&registry).starts_with("Операция не объявлена плагином"));
+
+        let command = call(
+            Some(&api),
+            json!({ "op": "run_command", "program": "netsh" }),
+        );
+        assert!(error(&command).starts_with("Операция не объявлена плагином"));
+        assert!(fakes.processes.calls().is_empty());
+    }
+
+    #[test]
+    fn declared_operations_reach_the_api() {
+        let fakes = Fakes::new();
+        let api = api(
+            &fakes,
+            vec![Capability::FsWrite {
+                scopes: vec!["%TEMP%".to_string()],
+            }],
+        );
+        let path = fakes.path("temp/out.txt");
+
+        let temp = call(Some(&api), json!({ "op": "temp_dir" }));
+        assert_eq!(temp, json!({ "ok": fakes.path("temp") }));
+
+        let write = call(
+            Some(&api),
+            json!({ "op": "write_file", "path": path, "content": "привет" }),
+        );
+        assert_eq!(write, json!({ "ok": null }));
+        assert_eq!(fakes.fs.contents(&path), Some("привет".as_bytes().to_vec()));
+    }
+}
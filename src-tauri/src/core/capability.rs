@@ -0,0 +1,435 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use crate::core::backend::SystemEnvironment;
+use crate::core::guard::{is_within, normalize, same_path};
+use crate::core::hive::RegistryKey;
+
+/// Что плагину разрешено делать. Объявляется в `PluginMeta` и проверяется в `PluginApi`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Capability {
+    /// Удаление файлов внутри папок. Поддерживаются переменные вида `%TEMP%`, `%SystemRoot%\Temp`.
+    FsDelete {
+        scopes: Vec<String>,
+    },
+    /// Создание и перезапись файлов внутри папок. Переменные — как у `FsDelete`.
+    FsWrite {
+        scopes: Vec<String>,
+    },
+    /// Чтение ключей реестра с указанными префиксами. Префикс может начинаться с раздела
+    /// (`HKLM\Software\Vendor`), без раздела это HKCU. Вид 32/64 бит не учитывается.
+    RegistryRead {
+        keys: Vec<String>,
+    },
+    /// Создание, запись и удаление ключей реестра с указанными префиксами, как у `RegistryRead`.
+    RegistryWrite {
+        keys: Vec<String>,
+    },
+    /// Запуск внешних программ из списка. Имя без папки (`netsh`) разрешает только запуск
+    /// по имени; программу по пути можно запустить, только если объявлен этот путь.
+    ProcessSpawn {
+        executables: Vec<String>,
+    },
+    /// Сетевые программы (`ping`, `ipconfig`, `netsh`, `curl`…) и интерпретаторы команд
+    /// (`cmd`, `powershell`, `sh`…), через которые их можно запустить. Нужна вместе
+    /// с `ProcessSpawn`. Сеть распознаётся по списку имён: программа не из списка,
+    /// в том числе объявленная по пути, считается несетевой.
+    Network,
+    ShellRestart,
+    RequiresAdmin,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct CapabilityError {
+    pub capability: &'static str,
+    pub target: String,
+}
+
+impl fmt::Display for CapabilityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Операция не объявлена плагином ({}): {}",
+            self.capability, self.target
+        )
+    }
+}
+
+#[derive(Clone)]
+pub struct CapabilitySet {
+    capabilities: Vec<Capability>,
+}
+
+impl CapabilitySet {
+    pub fn new(capabilities: Vec<Capability>) -> Self {
+        Self { capabilities }
+    }
+
+    pub fn has(&self, capability: &Capability) -> bool {
+        self.capabilities.contains(capability)
+    }
+
+    pub fn fs_delete_roots(&self, temp_dir: &Path, vars: &dyn SystemEnvironment) -> Vec<PathBuf> {
+        self.capabilities
+            .iter()
+            .filter_map(|capability| match capability {
+                Capability::FsDelete { scopes } => Some(scopes),
+                _ => None,
+            })
+            .flatten()
+            .filter_map(|scope| expand_scope(scope, temp_dir, vars))
+            .collect()
+    }
+
+    pub fn check_fs_write(
+        &self,
+        path: &Path,
+        temp_dir: &Path,
+        vars: &dyn SystemEnvironment,
+    ) -> Result<(), CapabilityError> {
+        let target = normalize(path);
+        let allowed = self.capabilities.iter().any(|capability| match capability {
+            Capability::FsWrite { scopes } => scopes
+                .iter()
+                .filter_map(|scope| expand_scope(scope, temp_dir, vars))
+                .any(|root| is_within(&target, &normalize(&root))),
+            _ => false,
+        });
+        self.require(allowed, "запись файлов", &path.display().to_string())
+    }
+
+    pub fn check_registry_read(&self, key: &RegistryKey) -> Result<(), CapabilityError> {
+        let allowed = self.capabilities.iter().any(|capability| match capability {
+            Capability::RegistryRead { keys } | Capability::RegistryWrite { keys } => keys
+                .iter()
+                .any(|scope| key.is_within(&RegistryKey::parse(scope))),
+            _ => false,
+        });
+        self.require(allowed, "чтение реестра", &key.to_string())
+    }
+
+    pub fn check_registry_write(&self, key: &RegistryKey) -> Result<(), CapabilityError> {
+        let allowed = self.capabilities.iter().any(|capability| match capability {
+            Capability::RegistryWrite { keys } => keys
+                .iter()
+                .any(|scope| key.is_within(&RegistryKey::parse(scope))),
+            _ => false,
+        });
+        self.require(allowed, "запись в реестр", &key.to_string())
+    }
+
+    pub fn check_process(&self, program: &str) -> Result<(), CapabilityError> {
+        let allowed = self.capabilities.iter().any(|capability| match capability {
+            Capability::ProcessSpawn { executables } => executables
+                .iter()
+                .any(|allowed| same_program(allowed, program)),
+            _ => false,
+        });
+        self.require(allowed, "запуск программ", program)?;
+        if is_network_program(program) {
+            self.require(self.has(&Capability::Network), "сеть", program)?;
+        }
+        Ok(())
+    }
+
+    pub fn check_shell_restart(&self) -> Result<(), CapabilityError> {
+        let allowed = self.has(&Capability::ShellRestart);
+        self.require(allowed, "перезапуск оболочки", "explorer.exe")
+    }
+
+    fn require(
+        &self,
+        allowed: bool,
+        capability: &'static str,
+        target: &str,
+    ) -> Result<(), CapabilityError> {
+        if allowed {
+            Ok(())
+        } else {
+            Err(CapabilityError {
+                capability,
+                target: target.to_string(),
+            })
+        }
+    }
+}
+
+/// Раскрывает `%VAR%` в шаблоне. Если переменная не задана или пуста, область отбрасывается.
+fn expand_scope(scope: &str, temp_dir: &Path, vars: &dyn SystemEnvironment) -> Option<PathBuf> {
+    let mut path = PathBuf::new();
+    for part in scope.split(['\\', '/']) {
+        if part.is_empty() {
+            if path.as_os_str().is_empty() && scope.starts_with('/') {
+                path.push("/");
+            }
+            continue;
+        }
+        match part
+            .strip_prefix('%')
+            .and_then(|rest| rest.strip_suffix('%'))
+        {
+            Some(var) if var.eq_ignore_ascii_case("TEMP") || var.eq_ignore_ascii_case("TMP") => {
+                path.push(temp_dir)
+            }
+            Some(var) => {
+                let value = vars.var(var).filter(|value| !value.is_empty())?;
+                path.push(value);
+            }
+            None => path.push(part),
+        }
+    }
+
+    if path.is_absolute() { Some(path) } else { None }
+}
+
+/// Программы, которые работают с сетью или сетевыми настройками.
+const NETWORK_PROGRAMS: &[&str] = &[
+    "arp",
+    "bitsadmin",
+    "curl",
+    "ftp",
+    "ipconfig",
+    "nbtstat",
+    "netsh",
+    "netstat",
+    "nslookup",
+    "pathping",
+    "ping",
+    "route",
+    "scp",
+    "ssh",
+    "tftp",
+    "tracert",
+    "traceroute",
+    "wget",
+];
+
+/// Интерпретаторы команд и скриптов: через них запускается что угодно, в том числе
+/// программы из `NETWORK_PROGRAMS`.
+const SHELL_PROGRAMS: &[&str] = &[
+    "bash",
+    "cmd",
+    "cscript",
+    "dash",
+    "mshta",
+    "powershell",
+    "pwsh",
+    "sh",
+    "wscript",
+    "zsh",
+];
+
+fn is_network_program(program: &str) -> bool {
+    let name = executable_name(program);
+    NETWORK_PROGRAMS.contains(&name.as_str()) || SHELL_PROGRAMS.contains(&name.as_str())
+}
+
+/// Имя без папки: `ipconfig`, `netsh.exe`, но не `.\netsh` или `C:\Tools\netsh.exe`.
+fn is_bare_name(program: &str) -> bool {
+    !program.contains(['/', '\\']) && Path::new(program).components().count() == 1
+}
+
+/// Имена сравниваются без регистра и `.exe`, пути — целиком. Имя и путь не совпадают
+/// никогда: иначе `netsh` из объявления разрешил бы `Downloads\netsh.exe`.
+fn same_program(allowed: &str, program: &str) -> bool {
+    match (is_bare_name(allowed), is_bare_name(program)) {
+        (true, true) => executable_name(allowed) == executable_name(program),
+        (false, false) => {
+            let (allowed, program) = (Path::new(allowed), Path::new(program));
+            allowed.is_absolute()
+                && program.is_absolute()
+                && same_path(&normalize(allowed), &normalize(program))
+        }
+        _ => false,
+    }
+}
+
+pub(crate) fn executable_name(program: &str) -> String {
+    let name = Path::new(program)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    name.strip_suffix(".exe")
+        .map(str::to_string)
+        .unwrap_or(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::fake::MemoryEnvironment;
+
+    /// Абсолютный путь для текущей ОС: `/work/...` или `C:\work\...`.
+    fn abs(relative: &str) -> PathBuf {
+        let root = if cfg!(windows) { r"C:\work" } else { "/work" };
+        relative
+            .split('/')
+            .filter(|part| !part.is_empty())
+            .fold(PathBuf::from(root), |path, part| path.join(part))
+    }
+
+    fn vars() -> MemoryEnvironment {
+        let vars = MemoryEnvironment::default();
+        vars.set_var("SystemRoot", abs("windows").to_string_lossy());
+        vars.set_var("EMPTY", "");
+        vars
+    }
+
+    fn write_scope(scope: &str) -> CapabilitySet {
+        CapabilitySet::new(vec![Capability::FsWrite {
+            scopes: vec![scope.to_string()],
+        }])
+    }
+
+    fn spawn(executables: &[&str]) -> CapabilitySet {
+        CapabilitySet::new(vec![Capability::ProcessSpawn {
+            executables: executables.iter().map(|name| name.to_string()).collect(),
+        }])
+    }
+
+    #[test]
+    fn scopes_expand_variables_and_temp() {
+        let temp = abs("temp");
+        let vars = vars();
+
+        assert_eq!(expand_scope("%TEMP%", &temp, &vars), Some(temp.clone()));
+        assert_eq!(
+            expand_scope("%tmp%\\cache", &temp, &vars),
+            Some(temp.join("cache"))
+        );
+        assert_eq!(
+            expand_scope("%SystemRoot%\\Temp", &temp, &vars),
+            Some(abs("windows/Temp"))
+        );
+        assert_eq!(
+            expand_scope("%SystemRoot%/Logs/CBS", &temp, &vars),
+            Some(abs("windows/Logs/CBS"))
+        );
+    }
+
+    #[test]
+    fn scopes_with_missing_variables_or_relative_paths_are_dropped() {
+        let temp = abs("temp");
+        let vars = vars();
+
+        assert_eq!(expand_scope("%UNDEFINED%\\Temp", &temp, &vars), None);
+        assert_eq!(expand_scope("%EMPTY%\\Temp", &temp, &vars), None);
+        assert_eq!(expand_scope("Temp\\cache", &temp, &vars), None);
+
+        let set = CapabilitySet::new(vec![Capability::FsDelete {
+            scopes: vec!["%UNDEFINED%".to_string(), "%TEMP%".to_string()],
+        }]);
+        assert_eq!(set.fs_delete_roots(&temp, &vars), [temp]);
+    }
+
+    #[test]
+    fn fs_write_stays_inside_scope() {
+        let temp = abs("temp");
+        let vars = vars();
+        let set = write_scope("%TEMP%\\reports");
+
+        assert!(
+            set.check_fs_write(&temp.join("reports").join("a.txt"), &temp, &vars)
+                .is_ok()
+        );
+        assert!(
+            set.check_fs_write(&temp.join("reports-old").join("a.txt"), &temp, &vars)
+                .is_err()
+        );
+        assert!(
+            set.check_fs_write(&temp.join("other.txt"), &temp, &vars)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn fs_write_rejects_parent_segments() {
+        let temp = abs("temp");
+        let vars = vars();
+        let set = write_scope("%TEMP%\\reports");
+        let escape = temp.join("reports").join("..").join("..").join("evil.txt");
+
+        assert!(set.check_fs_write(&escape, &temp, &vars).is_err());
+        let inside = temp.join("reports").join("sub").join("..").join("a.txt");
+        assert!(set.check_fs_write(&inside, &temp, &vars).is_ok());
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn fs_write_ignores_case_on_windows() {
+        let temp = PathBuf::from(r"C:\Users\Test\AppData\Local\Temp");
+        let vars = vars();
+        let set = write_scope("%TEMP%\\Reports");
+
+        let path = Path::new(r"c:\users\test\appdata\local\temp\REPORTS\a.txt");
+        assert!(set.check_fs_write(path, &temp, &vars).is_ok());
+    }
+
+    #[test]
+    fn executables_match_by_name_without_case_or_extension() {
+        let set = spawn(&["reg", "SCHTASKS.exe"]);
+
+        assert!(set.check_process("reg").is_ok());
+        assert!(set.check_process("REG.EXE").is_ok());
+        assert!(set.check_process("schtasks").is_ok());
+        assert!(set.check_process("powershell").is_err());
+    }
+
+    #[test]
+    fn path_qualified_executables_need_a_declared_path() {
+        let set = spawn(&["reg"]);
+
+        for program in [
+            r"C:\Users\x\Downloads\reg.exe",
+            "/home/x/Downloads/reg",
+            r".\reg.exe",
+            "./reg",
+            "tools/reg",
+        ] {
+            assert!(set.check_process(program).is_err(), "{program}");
+        }
+
+        let declared = abs("tools/report.exe");
+        let set = spawn(&[&declared.to_string_lossy()]);
+        assert!(set.check_process(&declared.to_string_lossy()).is_ok());
+        assert!(set.check_process("report.exe").is_err());
+        let elsewhere = abs("downloads/report.exe");
+        assert!(set.check_process(&elsewhere.to_string_lossy()).is_err());
+    }
+
+    #[test]
+    fn network_programs_need_network_capability() {
+        let set = spawn(&["ipconfig", "reg"]);
+        assert!(set.check_process("ipconfig").is_err());
+        assert!(set.check_process("reg").is_ok());
+
+        let set = CapabilitySet::new(vec![
+            Capability::ProcessSpawn {
+                executables: vec!["ipconfig".to_string()],
+            },
+            Capability::Network,
+        ]);
+        assert!(set.check_process("ipconfig").is_ok());
+    }
+
+    #[test]
+    fn shells_need_network_capability() {
+        let set = spawn(&["cmd", "powershell.exe", "sh"]);
+        for shell in ["cmd", "CMD.EXE", "powershell", "sh"] {
+            let err = set.check_process(shell).unwrap_err();
+            assert_eq!(err.capability, "сеть", "{shell}");
+        }
+
+        let set = CapabilitySet::new(vec![
+            Capability::ProcessSpawn {
+                executables: vec!["cmd".to_string()],
+            },
+            Capability::Network,
+        ]);
+        assert!(set.check_process("cmd").is_ok());
+    }
+}
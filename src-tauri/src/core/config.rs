@@ -0,0 +1,73 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+const CONFIG_FILE: &str = "config.json";
+
+/// Настройки приложения в `config.json` папки данных. Отсутствующие поля берутся по умолчанию.
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct AppConfig {
+    pub control_api: ControlApiConfig,
+    pub packages: PackagesConfig,
+}
+
+/// Локальный JSON-RPC сервер для скриптов. Слушает только 127.0.0.1.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ControlApiConfig {
+    pub enabled: bool,
+    pub port: u16,
+    /// Передаётся в заголовке `Authorization: Bearer <token>`. Пустой — сгенерируется при запуске.
+    pub token: String,
+}
+
+impl Default for ControlApiConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 47821,
+            token: String::new(),
+        }
+    }
+}
+
+/// Каталог подписанных пакетов плагинов и ключи, которым разрешено их подписывать.
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct PackagesConfig {
+    /// Путь к `index.json` каталога: локальная папка или сетевая (`\\server\share\...`).
+    pub catalog: String,
+    pub trusted_keys: Vec<TrustedKey>,
+    /// Загружать из папки `plugins` любые файлы, а не только установленные из пакетов.
+    pub allow_unsigned: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TrustedKey {
+    /// Кто подписывает пакеты этим ключом; показывается при проверке пакета.
+    pub name: String,
+    /// Открытый ключ Ed25519, 32 байта в hex.
+    pub public_key: String,
+}
+
+fn config_path(data_dir: &Path) -> PathBuf {
+    data_dir.join(CONFIG_FILE)
+}
+
+/// Повреждённый или отсутствующий файл даёт настройки по умолчанию.
+pub fn load(data_dir: &Path) -> AppConfig {
+    fs::read_to_string(config_path(data_dir))
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+pub fn save(data_dir: &Path, config: &AppConfig) -> Result<(), String> {
+    fs::create_dir_all(data_dir).map_err(|e| format!("Ошибка создания папки настроек: {e}"))?;
+    let content =
+        serde_json::to_string_pretty(config).map_err(|e| format!("Ошибка записи настроек: {e}"))?;
+    fs::write(config_path(data_dir), content).map_err(|e| format!("Ошибка записи настроек: {e}"))
+}
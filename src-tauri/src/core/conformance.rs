@@ -0,0 +1,287 @@
+use serde_json::Value;
+use std::collections::HashSet;
+
+use crate::core::capability::{Capability, executable_name};
+use crate::core::fake::Fakes;
+use crate::core::panic;
+use crate::core::plugin::Plugin;
+use crate::core::settings::{SettingKind, defaults_from_fields, merge_settings, validate_settings};
+
+/// Проблемы в метаданных плагина. Пустой список — плагин согласован.
+/// `defaults()` и `preview()` вызываются на подделках из `core::fake`, поэтому
+/// проверяются и плагины для других систем.
+pub fn check_plugin(plugin: &dyn Plugin) -> Vec<String> {
+    let meta = plugin.meta();
+    let mut problems = Vec::new();
+
+    if meta.id.trim().is_empty() {
+        problems.push("пустой id".to_string());
+    }
+    if meta.name.trim().is_empty() {
+        problems.push("пустое название".to_string());
+    }
+    if meta.category.trim().is_empty() {
+        problems.push("пустая категория".to_string());
+    }
+
+    for tool in &meta.tools {
+        if tool.trim().is_empty() {
+            problems.push("пустое имя программы в tools".to_string());
+        }
+    }
+    for capability in &meta.capabilities {
+        let Capability::ProcessSpawn { executables } = capability else {
+            continue;
+        };
+        for program in executables {
+            let name = executable_name(program);
+            if !meta.tools.iter().any(|tool| executable_name(tool) == name) {
+                problems.push(format!(
+                    "программа «{program}» разрешена, но не указана в tools"
+                ));
+            }
+        }
+    }
+
+    let mut keys = HashSet::new();
+    for field in &meta.settings {
+        if !keys.insert(field.key.as_str()) {
+            problems.push(format!("настройка «{}» объявлена дважды", field.key));
+        }
+        if field.required && field.default_value.is_null() {
+            problems.push(format!(
+                "обязательная настройка «{}» без значения по умолчанию",
+                field.key
+            ));
+        }
+        if matches!(field.kind, SettingKind::Select) {
+            let options = field.options.as_deref().unwrap_or_default();
+            if options.is_empty() {
+                problems.push(format!("у списка «{}» нет вариантов", field.key));
+            } else if !field.default_value.is_null()
+                && !options
+                    .iter()
+                    .any(|option| option.value == field.default_value)
+            {
+                problems.push(format!(
+                    "значение по умолчанию {} у «{}» нет среди вариантов",
+                    field.default_value, field.key
+                ));
+            }
+        }
+    }
+
+    let mut defaults = defaults_from_fields(&meta.settings);
+    let api = Fakes::new().api(plugin);
+    match panic::catch(|| plugin.defaults(&api)) {
+        Ok(Some(extra)) => {
+            problems.extend(unknown_keys(&extra, &keys, "defaults()"));
+            defaults = merge_settings(&extra, &defaults);
+        }
+        Ok(None) => {}
+        Err(report) => problems.push(format!("defaults() паникует: {}", report.summary())),
+    }
+    match panic::catch(|| plugin.preview(&api)) {
+        Ok(Ok(_)) => {}
+        Ok(Err(err)) => problems.push(format!("preview() вернул ошибку: {err}")),
+        Err(report) => problems.push(format!("preview() паникует: {}", report.summary())),
+    }
+    problems.extend(
+        validate_settings(&defaults, &meta.settings)
+            .into_iter()
+            .map(|error| format!("значения по умолчанию не проходят проверку: {error}")),
+    );
+    problems
+}
+
+/// Идентификаторы, которые встречаются у нескольких плагинов.
+pub fn duplicate_ids(plugins: &[Box<dyn Plugin>]) -> Vec<String> {
+    let mut seen = HashSet::new();
+    let mut duplicates: Vec<String> = plugins
+        .iter()
+        .map(|plugin| plugin.meta().id)
+        .filter(|id| !seen.insert(id.clone()))
+        .collect();
+    duplicates.sort();
+    duplicates.dedup();
+    duplicates
+}
+
+fn unknown_keys(values: &Value, keys: &HashSet<&str>, source: &str) -> Vec<String> {
+    let Some(values) = values.as_object() else {
+        return vec![format!("{source} вернул не объект")];
+    };
+    values
+        .keys()
+        .filter(|key| !keys.contains(key.as_str()))
+        .map(|key| format!("{source} задаёт «{key}», которого нет в настройках"))
+        .collect()
+}
+
+/// Значения по умолчанию проходят через тип настроек плагина туда и обратно
+/// (`Plugin::settings_round_trip`). Ключ, которого нет в типе, потерялся бы при чтении;
+/// поле типа, которого нет среди настроек, в `run` всегда получает своё значение
+/// по умолчанию, а не выбор пользователя. Плагин без типа настроек не проверяется.
+pub fn check_settings_type(plugin: &dyn Plugin) -> Vec<String> {
+    let meta = plugin.meta();
+    let mut defaults = defaults_from_fields(&meta.settings);
+    let api = Fakes::new().api(plugin);
+    if let Ok(Some(extra)) = panic::catch(|| plugin.defaults(&api)) {
+        defaults = merge_settings(&extra, &defaults);
+    }
+
+    let round_trip = match plugin.settings_round_trip(&defaults) {
+        None => return Vec::new(),
+        Some(Ok(Value::Object(values))) => values,
+        Some(Ok(_)) => return vec!["тип настроек записывается не объектом".to_string()],
+        Some(Err(err)) => {
+            return vec![format!(
+                "значения по умолчанию не читаются типом настроек: {err}"
+            )];
+        }
+    };
+    let declared: HashSet<&str> = meta
+        .settings
+        .iter()
+        .map(|field| field.key.as_str())
+        .collect();
+    let read: HashSet<&str> = round_trip.keys().map(String::as_str).collect();
+
+    let mut problems: Vec<String> = declared
+        .difference(&read)
+        .map(|key| format!("настройки «{key}» нет в типе настроек"))
+        .chain(
+            read.difference(&declared)
+                .map(|key| format!("поле «{key}» типа настроек не объявлено в настройках")),
+        )
+        .collect();
+    problems.sort();
+    problems
+}
+
+/// Общий вывод для сгенерированных тестов: все проблемы одним сообщением.
+pub fn assert_conforms(plugin: Box<dyn Plugin>) {
+    let id = plugin.meta().id;
+    let problems = check_plugin(plugin.as_ref());
+    assert_no_problems(&id, problems);
+}
+
+fn assert_no_problems(id: &str, problems: Vec<String>) {
+    assert!(
+        problems.is_empty(),
+        "Плагин «{id}» не прошёл проверку:\n  - {}",
+        problems.join("\n  - ")
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::api::PluginApi;
+    use crate::core::logger::Logger;
+    use crate::core::registry::builtin_plugins;
+    use crate::core::settings::{PluginMeta, SettingField, round_trip_settings};
+    use serde::{Deserialize, Serialize, de::DeserializeOwned};
+    use std::marker::PhantomData;
+
+    /// Плагин с логическими настройками `keys`, который читает их в тип `T`.
+    struct Declares<T>(&'static [&'static str], PhantomData<T>);
+
+    fn declares<T>(keys: &'static [&'static str]) -> Declares<T> {
+        Declares(keys, PhantomData)
+    }
+
+    impl<T: DeserializeOwned + Serialize + Send + Sync> Plugin for Declares<T> {
+        fn meta(&self) -> PluginMeta {
+            PluginMeta {
+                id: "declares".to_string(),
+                name: "Настройки".to_string(),
+                description: String::new(),
+                category: "Тест".to_string(),
+                settings: self
+                    .0
+                    .iter()
+                    .map(|key| SettingField {
+                        key: key.to_string(),
+                        label: key.to_string(),
+                        kind: SettingKind::Boolean,
+                        description: None,
+                        required: false,
+                        requires_admin: false,
+                        default_value: Value::Bool(true),
+                        options: None,
+                        ui: None,
+                    })
+                    .collect(),
+                capabilities: Vec::new(),
+                platforms: Vec::new(),
+                timeout_secs: None,
+                tools: Vec::new(),
+            }
+        }
+
+        fn run(
+            &self,
+            _api: &PluginApi,
+            _settings: &Value,
+            _logger: &mut Logger,
+        ) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn settings_round_trip(&self, settings: &Value) -> Option<Result<Value, String>> {
+            Some(round_trip_settings::<T>(settings))
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Settings {
+        #[serde(default)]
+        enabled: bool,
+        #[serde(default)]
+        verbose: bool,
+    }
+
+    #[test]
+    fn settings_type_must_match_declared_keys() {
+        assert!(check_settings_type(&declares::<Settings>(&["enabled", "verbose"])).is_empty());
+        assert_eq!(
+            check_settings_type(&declares::<Settings>(&["enabled", "extra"])),
+            [
+                "настройки «extra» нет в типе настроек",
+                "поле «verbose» типа настроек не объявлено в настройках"
+            ]
+        );
+    }
+
+    #[test]
+    fn builtin_settings_types_match_meta() {
+        for plugin in builtin_plugins() {
+            let meta = plugin.meta();
+            if !meta.settings.is_empty() {
+                assert!(
+                    plugin.settings_round_trip(&Value::Null).is_some(),
+                    "Плагин «{}» не сообщает тип настроек",
+                    meta.id
+                );
+            }
+            assert_no_problems(&meta.id, check_settings_type(plugin.as_ref()));
+        }
+    }
+
+    #[test]
+    fn settings_of_wrong_type_are_reported() {
+        #[derive(Serialize, Deserialize)]
+        struct Counts {
+            enabled: u32,
+        }
+
+        let problems = check_settings_type(&declares::<Counts>(&["enabled"]));
+        assert_eq!(problems.len(), 1);
+        assert!(
+            problems[0].starts_with("значения по умолчанию не читаются"),
+            "{}",
+            problems[0]
+        );
+    }
+}
@@ -0,0 +1,400 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::{io::Read, net::SocketAddr, thread};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::core::config::{self, ControlApiConfig};
+use crate::core::doctor;
+use crate::core::environment::Environment;
+use crate::core::error::CoreError;
+use crate::core::jobs::Jobs;
+use crate::core::plugin::{list_plugins, preview_plugin, validate_plugin_settings};
+use crate::core::recipe::find_recipe;
+
+const RPC_PATH: &str = "/rpc";
+const MAX_BODY_BYTES: u64 = 1024 * 1024;
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// Ошибка ядра; `data` содержит `CoreError` (`kind`, `message`).
+const CORE_ERROR: i64 = -32000;
+
+#[derive(Deserialize)]
+struct RpcRequest {
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Serialize)]
+struct RpcError {
+    code: i64,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<Value>,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+}
+
+impl From<CoreError> for RpcError {
+    fn from(err: CoreError) -> Self {
+        Self {
+            code: CORE_ERROR,
+            message: err.to_string(),
+            data: serde_json::to_value(&err).ok(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct PluginParams {
+    id: String,
+    #[serde(default)]
+    settings: Value,
+}
+
+#[derive(Deserialize)]
+struct RecipeParams {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct JobParams {
+    job_id: u64,
+}
+
+#[derive(Serialize)]
+struct Validation {
+    valid: bool,
+    errors: Vec<String>,
+}
+
+/// Поднимает сервер, если он включён в `config.json`. Пустой токен заменяется случайным
+/// и сохраняется, чтобы скрипты могли прочитать его из файла.
+pub fn start(env: Environment, jobs: Jobs) -> Result<Option<SocketAddr>, String> {
+    let Some(data_dir) = env.data_dir.clone() else {
+        return Ok(None);
+    };
+    let mut app_config = config::load(&data_dir);
+    if !app_config.control_api.enabled {
+        return Ok(None);
+    }
+    if app_config.control_api.token.is_empty() {
+        app_config.control_api.token = generate_token()?;
+        config::save(&data_dir, &app_config)?;
+    }
+
+    let ControlApiConfig { port, token, .. } = app_config.control_api;
+    let addr = SocketAddr::from(([127, 0, 0, 1], port));
+    let server = Server::http(addr)
+        .map_err(|e| format!("Не удалось запустить API управления на {addr}: {e}"))?;
+    // С портом 0 система выбирает свободный; вызывающему нужен настоящий.
+    let addr = server.server_addr().to_ip().unwrap_or(addr);
+
+    thread::spawn(move || {
+        for request in server.incoming_requests() {
+            handle(request, &env, &jobs, &token);
+        }
+    });
+    Ok(Some(addr))
+}
+
+fn handle(mut request: Request, env: &Environment, jobs: &Jobs, token: &str) {
+    if request.url() != RPC_PATH {
+        let _ = request.respond(Response::from_string("Not Found").with_status_code(404));
+        return;
+    }
+    if request.method() != &Method::Post {
+        let _ = request.respond(Response::from_string("Method Not Allowed").with_status_code(405));
+        return;
+    }
+    if !authorized(&request, token) {
+        let _ = request.respond(Response::from_string("Unauthorized").with_status_code(401));
+        return;
+    }
+
+    let mut body = String::new();
+    let response = match request
+        .as_reader()
+        .take(MAX_BODY_BYTES)
+        .read_to_string(&mut body)
+    {
+        Ok(_) => respond_to(&body, env, jobs),
+        Err(_) => error_response(
+            Value::Null,
+            RpcError::new(PARSE_ERROR, "Тело запроса не UTF-8."),
+        ),
+    };
+
+    let content_type = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..])
+        .expect("static header is valid");
+    let _ = request.respond(Response::from_string(response.to_string()).with_header(content_type));
+}
+
+fn authorized(request: &Request, token: &str) -> bool {
+    request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv("Authorization"))
+        .and_then(|header| header.value.as_str().strip_prefix("Bearer "))
+        .is_some_and(|provided| constant_time_eq(provided.trim().as_bytes(), token.as_bytes()))
+}
+
+fn respond_to(body: &str, env: &Environment, jobs: &Jobs) -> Value {
+    let value: Value = match serde_json::from_str(body) {
+        Ok(value) => value,
+        Err(e) => return error_response(Value::Null, RpcError::new(PARSE_ERROR, e.to_string())),
+    };
+    let request: RpcRequest = match serde_json::from_value(value) {
+        Ok(request) => request,
+        Err(e) => {
+            return error_response(Value::Null, RpcError::new(INVALID_REQUEST, e.to_string()));
+        }
+    };
+
+    match dispatch(&request.method, request.params, env, jobs) {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": request.id, "result": result }),
+        Err(err) => error_response(request.id, err),
+    }
+}
+
+fn error_response(id: Value, err: RpcError) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": err })
+}
+
+/// Методы называются так же, как команды Tauri, и возвращают те же типы.
+fn dispatch(
+    method: &str,
+    params: Value,
+    env: &Environment,
+    jobs: &Jobs,
+) -> Result<Value, RpcError> {
+    match method {
+        "list_plugins" => to_value(list_plugins(env)),
+        "doctor" => to_value(doctor::doctor(env)),
+        "preview_plugin" => {
+            let params: PluginParams = parse_params(params)?;
+            to_value(preview_plugin(env, &params.id)?)
+        }
+        "validate_settings" => {
+            let params: PluginParams = parse_params(params)?;
+            let errors = validate_plugin_settings(env, &params.id, &params.settings)?;
+            to_value(Validation {
+                valid: errors.is_empty(),
+                errors,
+            })
+        }
+        "run_plugin" => {
+            let params: PluginParams = parse_params(params)?;
+            let job_id = jobs.start(env, &params.id, params.settings);
+            Ok(json!({ "job_id": job_id }))
+        }
+        "run_recipe" => {
+            let params: RecipeParams = parse_params(params)?;
+            let recipe = find_recipe(env, &params.id)?;
+            Ok(json!({ "job_id": jobs.start_recipe(env, recipe) }))
+        }
+        "cancel_run" => {
+            let params: JobParams = parse_params(params)?;
+            Ok(json!({ "cancelled": jobs.cancel(params.job_id) }))
+        }
+        "job_status" => {
+            let params: JobParams = parse_params(params)?;
+            match jobs.status(params.job_id) {
+                Some(status) => to_value(status),
+                None => Err(RpcError::new(
+                    INVALID_PARAMS,
+                    format!("Задание не найдено: {}", params.job_id),
+                )),
+            }
+        }
+        other => Err(RpcError::new(
+            METHOD_NOT_FOUND,
+            format!("Неизвестный метод: {other}"),
+        )),
+    }
+}
+
+fn parse_params<T: for<'de> Deserialize<'de>>(params: Value) -> Result<T, RpcError> {
+    serde_json::from_value(params).map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))
+}
+
+fn to_value(value: impl Serialize) -> Result<Value, RpcError> {
+    serde_json::to_value(value).map_err(|e| RpcError::new(CORE_ERROR, e.to_string()))
+}
+
+fn generate_token() -> Result<String, String> {
+    let mut bytes = [0u8; 24];
+    getrandom::getrandom(&mut bytes).map_err(|e| format!("Не удалось создать токен: {e}"))?;
+    Ok(bytes.iter().map(|byte| format!("{byte:02x}")).collect())
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::config::{AppConfig, PackagesConfig};
+    use std::{
+        fs,
+        io::Write,
+        net::TcpStream,
+        path::PathBuf,
+        time::{Duration, Instant},
+    };
+
+    const TOKEN: &str = "secret";
+
+    /// Крутится, пока запуск не отменят.
+    const SCRIPT: &str = r#"
+fn meta() {
+    #{ id: "rpc_wait", name: "Ожидание", description: "", category: "Тест" }
+}
+
+fn run(settings) {
+    loop {}
+}
+"#;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("lamerhelper-control-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Сервер на свободном порту с плагином `rpc_wait` в папке данных.
+    fn start_server(name: &str) -> (PathBuf, SocketAddr) {
+        let dir = scratch_dir(name);
+        fs::create_dir_all(dir.join("plugins")).unwrap();
+        fs::write(dir.join("plugins/rpc_wait.rhai"), SCRIPT).unwrap();
+        let app_config = AppConfig {
+            control_api: ControlApiConfig {
+                enabled: true,
+                port: 0,
+                token: TOKEN.to_string(),
+            },
+            packages: PackagesConfig {
+                allow_unsigned: true,
+                ..PackagesConfig::default()
+            },
+        };
+        config::save(&dir, &app_config).unwrap();
+
+        let env = Environment::default().with_data_dir(Some(dir.clone()));
+        let addr = start(env, Jobs::default()).unwrap().unwrap();
+        (dir, addr)
+    }
+
+    /// Статус ответа и тело.
+    fn post(addr: SocketAddr, token: &str, body: &str) -> (u16, String) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(
+            stream,
+            "POST {RPC_PATH} HTTP/1.1\r\nHost: {addr}\r\nAuthorization: Bearer {token}\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let status = response[9..12].parse().unwrap();
+        let body = response
+            .split_once("\r\n\r\n")
+            .map(|(_, body)| body.to_string())
+            .unwrap_or_default();
+        (status, body)
+    }
+
+    fn call(addr: SocketAddr, method: &str, params: Value) -> Value {
+        let request = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+        let (status, body) = post(addr, TOKEN, &request.to_string());
+        assert_eq!(status, 200, "{body}");
+        serde_json::from_str(&body).unwrap()
+    }
+
+    #[test]
+    fn disabled_by_default() {
+        let dir = scratch_dir("disabled");
+        let env = Environment::default().with_data_dir(Some(dir.clone()));
+        assert_eq!(start(env, Jobs::default()), Ok(None));
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn rejects_bad_token_and_unknown_method() {
+        let (dir, addr) = start_server("errors");
+        let request = r#"{"jsonrpc": "2.0", "id": 1, "method": "list_plugins"}"#;
+        assert_eq!(post(addr, "wrong", request).0, 401);
+        assert_eq!(post(addr, "", request).0, 401);
+
+        let response = call(addr, "format_disk", Value::Null);
+        assert_eq!(response["error"]["code"], METHOD_NOT_FOUND);
+        assert_eq!(response["id"], 1);
+
+        let (status, body) = post(addr, TOKEN, "{");
+        assert_eq!(status, 200);
+        let response: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(response["error"]["code"], PARSE_ERROR);
+
+        let response = call(addr, "job_status", json!({ "job_id": "first" }));
+        assert_eq!(response["error"]["code"], INVALID_PARAMS);
+        let response = call(addr, "preview_plugin", json!({ "id": "no_such_plugin" }));
+        assert_eq!(response["error"]["code"], CORE_ERROR);
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn runs_and_cancels_a_job() {
+        let (dir, addr) = start_server("jobs");
+        let plugins = call(addr, "list_plugins", Value::Null);
+        assert!(
+            plugins["result"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .any(|plugin| plugin["id"] == "rpc_wait")
+        );
+
+        let started = call(addr, "run_plugin", json!({ "id": "rpc_wait" }));
+        let job_id = started["result"]["job_id"].as_u64().unwrap();
+        let status = call(addr, "job_status", json!({ "job_id": job_id }));
+        assert_eq!(status["result"]["state"], "running");
+        assert_eq!(status["result"]["plugin_id"], "rpc_wait");
+
+        let cancelled = call(addr, "cancel_run", json!({ "job_id": job_id }));
+        assert_eq!(cancelled["result"]["cancelled"], true);
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let status = loop {
+            let status = call(addr, "job_status", json!({ "job_id": job_id }));
+            if status["result"]["state"] != "running" || Instant::now() > deadline {
+                break status;
+            }
+            thread::sleep(Duration::from_millis(20));
+        };
+        assert_eq!(status["result"]["state"], "failed");
+        assert_eq!(status["result"]["result"]["ok"], false);
+
+        let again = call(addr, "cancel_run", json!({ "job_id": job_id }));
+        assert_eq!(again["result"]["cancelled"], false);
+        let missing = call(addr, "job_status", json!({ "job_id": job_id + 100 }));
+        assert_eq!(missing["error"]["code"], INVALID_PARAMS);
+        let _ = fs::remove_dir_all(dir);
+    }
+}
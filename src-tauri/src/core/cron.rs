@@ -0,0 +1,308 @@
+use chrono::{Datelike, Duration, Local, NaiveDate, NaiveDateTime, TimeZone, Timelike};
+
+/// Сколько шагов поиска делать, прежде чем признать, что выражение не срабатывает никогда
+/// (например, `0 0 31 2 *`).
+const SEARCH_LIMIT: usize = 100_000;
+
+/// Выражение cron из пяти полей: минута, час, день месяца, месяц, день недели (0 и 7 — воскресенье).
+/// Поддерживаются `*`, списки, диапазоны, шаги (`*/15`, `1-5/2`) и псевдонимы `@hourly`, `@daily`,
+/// `@weekly`, `@monthly`. Время считается по местному часовому поясу.
+#[derive(Clone, Debug)]
+pub struct CronExpr {
+    minutes: Field,
+    hours: Field,
+    days: Field,
+    months: Field,
+    weekdays: Field,
+}
+
+#[derive(Clone, Debug)]
+struct Field {
+    allowed: Vec<bool>,
+    /// Поле начинается с `*` (`*`, `*/2`): важно для правила «день месяца ИЛИ день недели».
+    any: bool,
+}
+
+impl Field {
+    fn parse(source: &str, name: &str, min: u32, max: u32) -> Result<Self, String> {
+        let mut allowed = vec![false; max as usize + 1];
+        for part in source.split(',') {
+            let (range, step) = match part.split_once('/') {
+                Some((range, step)) => {
+                    let step: u32 = step
+                        .parse()
+                        .ok()
+                        .filter(|step| *step > 0)
+                        .ok_or_else(|| format!("Поле «{name}»: некорректный шаг «{step}»."))?;
+                    (range, step)
+                }
+                None => (part, 1),
+            };
+            let (start, end) = if range == "*" {
+                (min, max)
+            } else if let Some((start, end)) = range.split_once('-') {
+                (parse_number(start, name)?, parse_number(end, name)?)
+            } else {
+                let value = parse_number(range, name)?;
+                // `5/10` означает «с 5 до конца с шагом 10».
+                (value, if step > 1 { max } else { value })
+            };
+            if start < min || end > max || start > end {
+                return Err(format!(
+                    "Поле «{name}»: значение «{part}» вне диапазона {min}-{max}."
+                ));
+            }
+            for value in (start..=end).step_by(step as usize) {
+                allowed[value as usize] = true;
+            }
+        }
+        Ok(Self {
+            allowed,
+            any: source.starts_with('*'),
+        })
+    }
+
+    fn matches(&self, value: u32) -> bool {
+        self.allowed.get(value as usize).copied().unwrap_or(false)
+    }
+}
+
+fn parse_number(source: &str, name: &str) -> Result<u32, String> {
+    source
+        .parse()
+        .map_err(|_| format!("Поле «{name}»: «{source}» не число."))
+}
+
+impl CronExpr {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let source = match source.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            other => other,
+        };
+        let fields: Vec<&str> = source.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            return Err(format!(
+                "Ожидалось 5 полей (минута час день месяц день_недели), получено {}.",
+                fields.len()
+            ));
+        };
+
+        let mut weekdays = Field::parse(weekdays, "день недели", 0, 7)?;
+        if weekdays.allowed[7] {
+            weekdays.allowed[0] = true;
+        }
+        Ok(Self {
+            minutes: Field::parse(minutes, "минута", 0, 59)?,
+            hours: Field::parse(hours, "час", 0, 23)?,
+            days: Field::parse(days, "день месяца", 1, 31)?,
+            months: Field::parse(months, "месяц", 1, 12)?,
+            weekdays,
+        })
+    }
+
+    /// Ближайшее срабатывание строго после `after` (Unix-время в секундах).
+    pub fn next_after(&self, after: u64) -> Option<u64> {
+        let start = Local
+            .timestamp_opt(after as i64, 0)
+            .earliest()?
+            .naive_local();
+        self.next_local(start, |time| {
+            Local
+                .from_local_datetime(time)
+                .earliest()
+                .map(|local| local.timestamp() as u64)
+        })
+    }
+
+    /// Ближайшая подходящая минута после `start` по местным часам. `resolve` переводит её
+    /// во время результата; `None` — такой минуты нет (переход на летнее время).
+    fn next_local<T>(
+        &self,
+        start: NaiveDateTime,
+        resolve: impl Fn(&NaiveDateTime) -> Option<T>,
+    ) -> Option<T> {
+        let mut time = start.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+
+        for _ in 0..SEARCH_LIMIT {
+            if !self.months.matches(time.month()) {
+                time = start_of_next_month(time)?;
+                continue;
+            }
+            if !self.day_matches(time.date()) {
+                time = time.date().succ_opt()?.and_hms_opt(0, 0, 0)?;
+                continue;
+            }
+            if !self.hours.matches(time.hour()) {
+                time = time.with_minute(0)? + Duration::hours(1);
+                continue;
+            }
+            if !self.minutes.matches(time.minute()) {
+                time += Duration::minutes(1);
+                continue;
+            }
+            // Минута, которой нет из-за перехода на летнее время, пропускается.
+            if let Some(resolved) = resolve(&time) {
+                return Some(resolved);
+            }
+            time += Duration::minutes(1);
+        }
+        None
+    }
+
+    /// Как в классическом cron: если заданы оба поля дней, достаточно совпадения одного.
+    fn day_matches(&self, date: NaiveDate) -> bool {
+        let day = self.days.matches(date.day());
+        let weekday = self.weekdays.matches(date.weekday().num_days_from_sunday());
+        match (self.days.any, self.weekdays.any) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        }
+    }
+}
+
+fn start_of_next_month(time: NaiveDateTime) -> Option<NaiveDateTime> {
+    let (year, month) = if time.month() == 12 {
+        (time.year() + 1, 1)
+    } else {
+        (time.year(), time.month() + 1)
+    };
+    NaiveDate::from_ymd_opt(year, month, 1)?.and_hms_opt(0, 0, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(text: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    /// Ближайшие срабатывания после `start` без учёта часового пояса.
+    fn upcoming(expression: &str, start: &str, count: usize) -> Vec<String> {
+        let expr = CronExpr::parse(expression).unwrap();
+        let mut time = at(start);
+        let mut found = Vec::new();
+        for _ in 0..count {
+            time = expr.next_local(time, |time| Some(*time)).unwrap();
+            found.push(time.format("%Y-%m-%d %H:%M").to_string());
+        }
+        found
+    }
+
+    #[test]
+    fn lists_ranges_and_steps() {
+        assert_eq!(
+            upcoming("0,30 9-10 * * *", "2026-03-02 08:00", 5),
+            [
+                "2026-03-02 09:00",
+                "2026-03-02 09:30",
+                "2026-03-02 10:00",
+                "2026-03-02 10:30",
+                "2026-03-03 09:00"
+            ]
+        );
+        assert_eq!(
+            upcoming("*/20 1-5/2 * * *", "2026-03-02 01:30", 4),
+            [
+                "2026-03-02 01:40",
+                "2026-03-02 03:00",
+                "2026-03-02 03:20",
+                "2026-03-02 03:40"
+            ]
+        );
+    }
+
+    #[test]
+    fn single_value_with_step_runs_to_the_end() {
+        assert_eq!(
+            upcoming("5/10 0 * * *", "2026-03-02 00:00", 6),
+            [
+                "2026-03-02 00:05",
+                "2026-03-02 00:15",
+                "2026-03-02 00:25",
+                "2026-03-02 00:35",
+                "2026-03-02 00:45",
+                "2026-03-02 00:55"
+            ]
+        );
+    }
+
+    #[test]
+    fn weekday_seven_is_sunday() {
+        // 2026-03-01 — воскресенье.
+        assert_eq!(
+            upcoming("0 12 * * 7", "2026-02-26 00:00", 2),
+            ["2026-03-01 12:00", "2026-03-08 12:00"]
+        );
+        assert_eq!(
+            upcoming("0 12 * * 0", "2026-02-26 00:00", 1),
+            ["2026-03-01 12:00"]
+        );
+    }
+
+    #[test]
+    fn restricted_day_and_weekday_match_either() {
+        // 13-е число или пятница; 2026-03-06 — пятница.
+        assert_eq!(
+            upcoming("0 0 13 * 5", "2026-03-01 00:00", 4),
+            [
+                "2026-03-06 00:00",
+                "2026-03-13 00:00",
+                "2026-03-20 00:00",
+                "2026-03-27 00:00"
+            ]
+        );
+    }
+
+    #[test]
+    fn starred_step_keeps_day_fields_combined_with_and() {
+        // `*/2` в днях месяца не ограничивает поле: нужны нечётное число и понедельник.
+        assert_eq!(
+            upcoming("0 0 */2 * 1", "2026-03-01 00:00", 3),
+            ["2026-03-09 00:00", "2026-03-23 00:00", "2026-04-13 00:00"]
+        );
+    }
+
+    #[test]
+    fn skips_minutes_missing_after_dst_change() {
+        let expr = CronExpr::parse("30 2 * * *").unwrap();
+        let gap = at("2026-03-29 02:00");
+        let next = expr
+            .next_local(at("2026-03-28 12:00"), |time| {
+                (time.date() != gap.date() || time.hour() != 2).then_some(*time)
+            })
+            .unwrap();
+        assert_eq!(next, at("2026-03-30 02:30"));
+    }
+
+    #[test]
+    fn impossible_date_never_fires() {
+        let expr = CronExpr::parse("0 0 31 2 *").unwrap();
+        assert_eq!(expr.next_after(0), None);
+    }
+
+    #[test]
+    fn next_after_is_strictly_later() {
+        let expr = CronExpr::parse("@hourly").unwrap();
+        let next = expr.next_after(1_800_000_000).unwrap();
+        assert!(next > 1_800_000_000);
+        assert_eq!(expr.next_after(next).map(|after| after - next), Some(3600));
+    }
+
+    #[test]
+    fn rejects_malformed_expressions() {
+        for source in [
+            "* * * *",
+            "60 * * * *",
+            "* * 0 * *",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "a * * * *",
+        ] {
+            assert!(CronExpr::parse(source).is_err(), "{source}");
+        }
+    }
+}
@@ -0,0 +1,706 @@
+use serde::Deserialize;
+use serde_json::Value;
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::core::api::PluginApi;
+use crate::core::backend::CommandOptions;
+use crate::core::capability::Capability;
+use crate::core::hive::{RegistryKey, RegistryValue, RegistryView};
+use crate::core::logger::Logger;
+use crate::core::plugin::Plugin;
+use crate::core::post_action::PostAction;
+use crate::core::registry::Unavailable;
+use crate::core::settings::PluginMeta;
+
+/// Подстановки, доступные в шаблонах помимо ключей настроек.
+const BUILTIN_VARIABLES: [&str; 2] = ["temp_dir", "data_dir"];
+
+/// Плагин, описанный файлом TOML или JSON: метаданные в формате `PluginMeta`
+/// и список шагов. Строки шагов — шаблоны вида `{{ключ_настройки}}`.
+#[derive(Deserialize)]
+struct Definition {
+    #[serde(flatten)]
+    meta: PluginMeta,
+    steps: Vec<Step>,
+}
+
+#[derive(Deserialize, Clone)]
+struct Step {
+    #[serde(flatten)]
+    action: Action,
+    /// Заголовок шага в журнале.
+    #[serde(default)]
+    title: Option<String>,
+    /// Ключ настройки: шаг выполняется, только если она включена.
+    #[serde(default)]
+    when: Option<String>,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(tag = "action", rename_all = "snake_case")]
+enum Action {
+    RunCommand {
+        program: String,
+        #[serde(default)]
+        args: Vec<String>,
+        /// `cwd`, `env`, `stdin`, `timeout_secs`, `encoding`.
+        #[serde(flatten)]
+        options: CommandOptions,
+    },
+    SetRegistryValue {
+        key: String,
+        name: String,
+        value: String,
+        #[serde(default)]
+        kind: RegistryKind,
+        #[serde(default)]
+        view: RegistryView,
+    },
+    DeleteRegistryValue {
+        key: String,
+        name: String,
+        #[serde(default)]
+        view: RegistryView,
+    },
+    /// Удаляет файлы, имена которых подходят под шаблоны с `*` и `?`.
+    DeleteFiles {
+        directory: String,
+        patterns: Vec<String>,
+        #[serde(default)]
+        recursive: bool,
+    },
+    WriteFile {
+        path: String,
+        content: String,
+    },
+    /// Действие после запуска: `restart_shell`, `logoff` или `reboot`.
+    AfterRun {
+        kind: PostAction,
+        reason: String,
+    },
+}
+
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+enum RegistryKind {
+    #[default]
+    String,
+    ExpandString,
+    Dword,
+    Qword,
+}
+
+pub struct DeclarativePlugin {
+    meta: PluginMeta,
+    steps: Vec<Step>,
+}
+
+/// Плагины-описания (`*.toml`, `*.json`) из списка файлов вместе с их именами.
+/// Файл с ошибкой превращается в `Unavailable` с описанием проблемы.
+pub fn load_files(paths: &[PathBuf]) -> Vec<(String, Box<dyn Plugin>)> {
+    paths
+        .iter()
+        .filter(|path| {
+            matches!(
+                path.extension().and_then(|ext| ext.to_str()),
+                Some("toml" | "json")
+            )
+        })
+        .map(|path| {
+            let file = path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let plugin: Box<dyn Plugin> = match load(path) {
+                Ok(plugin) => Box::new(plugin),
+                Err(reason) => Box::new(Unavailable::new(&file, None, reason)),
+            };
+            (file, plugin)
+        })
+        .collect()
+}
+
+pub fn load(path: &Path) -> Result<DeclarativePlugin, String> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("Не удалось прочитать {}: {e}", path.display()))?;
+    let definition: Definition = if path.extension().is_some_and(|ext| ext == "json") {
+        serde_json::from_str(&content).map_err(|e| format!("Ошибка в описании плагина: {e}"))?
+    } else {
+        toml::from_str(&content).map_err(|e| format!("Ошибка в описании плагина: {e}"))?
+    };
+    validate(&definition)?;
+    Ok(DeclarativePlugin {
+        meta: definition.meta,
+        steps: definition.steps,
+    })
+}
+
+/// Общие проверки метаданных внешних плагинов: `id`, название и уникальные ключи настроек.
+pub fn validate_meta(meta: &PluginMeta) -> Result<(), String> {
+    let valid_id = !meta.id.is_empty()
+        && meta
+            .id
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+    if !valid_id {
+        return Err(format!(
+            "Некорректный id «{}»: допустимы строчные латинские буквы, цифры и _.",
+            meta.id
+        ));
+    }
+    if meta.name.trim().is_empty() {
+        return Err("Не указано название плагина.".to_string());
+    }
+    let mut keys = HashSet::new();
+    for field in &meta.settings {
+        if !keys.insert(field.key.as_str()) {
+            return Err(format!("Настройка «{}» объявлена дважды.", field.key));
+        }
+    }
+    Ok(())
+}
+
+fn validate(definition: &Definition) -> Result<(), String> {
+    let meta = &definition.meta;
+    validate_meta(meta)?;
+    if definition.steps.is_empty() {
+        return Err("В плагине нет ни одного шага.".to_string());
+    }
+
+    let keys: HashSet<&str> = meta
+        .settings
+        .iter()
+        .map(|field| field.key.as_str())
+        .collect();
+
+    for (index, step) in definition.steps.iter().enumerate() {
+        let number = index + 1;
+        if let Some(key) = &step.when
+            && !keys.contains(key.as_str())
+        {
+            return Err(format!(
+                "Шаг {number}: условие ссылается на неизвестную настройку «{key}»."
+            ));
+        }
+        let templates = step
+            .action
+            .templates()
+            .into_iter()
+            .chain(step.title.as_deref());
+        for template in templates {
+            for name in placeholders(template)? {
+                if !keys.contains(name) && !BUILTIN_VARIABLES.contains(&name) {
+                    return Err(format!(
+                        "Шаг {number}: неизвестная подстановка «{{{{{name}}}}}»."
+                    ));
+                }
+            }
+        }
+        step.action
+            .check_capabilities(&meta.capabilities)
+            .map_err(|err| format!("Шаг {number}: {err}"))?;
+    }
+    Ok(())
+}
+
+/// Имена подстановок `{{имя}}` в строке.
+fn placeholders(template: &str) -> Result<Vec<&str>, String> {
+    let mut names = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let after = &rest[start + 2..];
+        let end = after
+            .find("}}")
+            .ok_or_else(|| format!("Незакрытая подстановка в «{template}»."))?;
+        names.push(after[..end].trim());
+        rest = &after[end + 2..];
+    }
+    Ok(names)
+}
+
+fn render(template: &str, settings: &Value, api: &PluginApi) -> String {
+    let mut output = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else {
+            output.push_str(&rest[start..]);
+            return output;
+        };
+        let name = after[..end].trim();
+        let value = match name {
+            "temp_dir" => api.temp_dir().display().to_string(),
+            "data_dir" => api
+                .app_data_dir()
+                .map(|dir| dir.display().to_string())
+                .unwrap_or_default(),
+            _ => match settings.get(name) {
+                Some(Value::String(value)) => value.clone(),
+                Some(Value::Null) | None => String::new(),
+                Some(value) => value.to_string(),
+            },
+        };
+        output.push_str(&value);
+        rest = &after[end + 2..];
+    }
+    output.push_str(rest);
+    output
+}
+
+impl Action {
+    fn templates(&self) -> Vec<&str> {
+        match self {
+            Action::RunCommand { program, args, .. } => std::iter::once(program.as_str())
+                .chain(args.iter().map(String::as_str))
+                .collect(),
+            Action::SetRegistryValue {
+                key, name, value, ..
+            } => vec![key, name, value],
+            Action::DeleteRegistryValue { key, name, .. } => vec![key, name],
+            Action::DeleteFiles {
+                directory,
+                patterns,
+                ..
+            } => std::iter::once(directory.as_str())
+                .chain(patterns.iter().map(String::as_str))
+                .collect(),
+            Action::WriteFile { path, content } => vec![path, content],
+            Action::AfterRun { reason, .. } => vec![reason],
+        }
+    }
+
+    /// Проверка при загрузке: шаг должен укладываться в объявленные разрешения.
+    /// Значения с подстановками проверяются уже `PluginApi` при запуске.
+    fn check_capabilities(&self, capabilities: &[Capability]) -> Result<(), String> {
+        let (needed, target) = match self {
+            Action::RunCommand { program, .. } => ("process_spawn", program),
+            Action::SetRegistryValue { key, .. } | Action::DeleteRegistryValue { key, .. } => {
+                ("registry_write", key)
+            }
+            Action::DeleteFiles { directory, .. } => ("fs_delete", directory),
+            Action::WriteFile { path, .. } => ("fs_write", path),
+            Action::AfterRun {
+                kind: PostAction::RestartShell,
+                reason,
+            } => ("shell_restart", reason),
+            Action::AfterRun { .. } => return Ok(()),
+        };
+        let declared = capabilities.iter().any(|capability| {
+            matches!(
+                (needed, capability),
+                ("process_spawn", Capability::ProcessSpawn { .. })
+                    | ("registry_write", Capability::RegistryWrite { .. })
+                    | ("fs_delete", Capability::FsDelete { .. })
+                    | ("fs_write", Capability::FsWrite { .. })
+                    | ("shell_restart", Capability::ShellRestart)
+            )
+        });
+        if declared {
+            Ok(())
+        } else {
+            Err(format!("для «{target}» нужно разрешение {needed}."))
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Action::RunCommand { program, .. } => format!("Команда: {program}"),
+            Action::SetRegistryValue { key, name, .. } => format!("Реестр: {key}\\{name}"),
+            Action::DeleteRegistryValue { key, name, .. } => {
+                format!("Удаление из реестра: {key}\\{name}")
+            }
+            Action::DeleteFiles { directory, .. } => format!("Удаление файлов: {directory}"),
+            Action::WriteFile { path, .. } => format!("Запись файла: {path}"),
+            Action::AfterRun { kind, .. } => format!("Потребуется: {}", kind.label()),
+        }
+    }
+
+    fn execute(
+        &self,
+        api: &PluginApi,
+        settings: &Value,
+        logger: &mut Logger,
+    ) -> Result<(), String> {
+        let render = |template: &str| render(template, settings, api);
+        match self {
+            Action::RunCommand {
+                program,
+                args,
+                options,
+            } => {
+                let program = render(program);
+                let args: Vec<String> = args.iter().map(|arg| render(arg)).collect();
+                let args: Vec<&str> = args.iter().map(String::as_str).collect();
+                let output = api.run_command_with(&program, &args, options, Some(logger))?;
+                if output.success() {
+                    Ok(())
+                } else {
+                    Err(format!(
+                        "{program} завершилась с ошибкой ({})",
+                        output.status_text()
+                    ))
+                }
+            }
+            Action::SetRegistryValue {
+                key,
+                name,
+                value,
+                kind,
+                view,
+            } => {
+                let (name, value) = (render(name), render(value));
+                let key = RegistryKey::parse(&render(key)).with_view(*view);
+                let typed = match kind {
+                    RegistryKind::String => RegistryValue::String(value.clone()),
+                    RegistryKind::ExpandString => RegistryValue::ExpandString(value.clone()),
+                    RegistryKind::Dword => RegistryValue::Dword(
+                        u32::try_from(parse_number(&value)?)
+                            .map_err(|_| format!("Значение «{value}» не подходит для DWORD."))?,
+                    ),
+                    RegistryKind::Qword => RegistryValue::Qword(parse_number(&value)?),
+                };
+                api.set_registry_value(key, &name, typed)?;
+                logger.kv(name, value);
+                Ok(())
+            }
+            Action::DeleteRegistryValue { key, name, view } => {
+                let key = RegistryKey::parse(&render(key)).with_view(*view);
+                api.delete_registry_value(key, &render(name))
+            }
+            Action::DeleteFiles {
+                directory,
+                patterns,
+                recursive,
+            } => {
+                let directory = PathBuf::from(render(directory));
+                let patterns: Vec<String> =
+                    patterns.iter().map(|pattern| render(pattern)).collect();
+                let mut removed = 0;
+                let mut errors = Vec::new();
+                for file in matching_files(api, &directory, &patterns, *recursive)? {
+                    match api.remove_file(&file) {
+                        Ok(()) => removed += 1,
+                        Err(err) => errors.push(err),
+                    }
+                }
+                logger.kv("Удалено файлов", removed.to_string());
+                match errors.first() {
+                    None => Ok(()),
+                    Some(first) => Err(format!(
+                        "Не удалось удалить файлов: {}. {first}",
+                        errors.len()
+                    )),
+                }
+            }
+            Action::WriteFile { path, content } => {
+                let path = PathBuf::from(render(path));
+                api.write_file(&path, render(content).as_bytes())?;
+                logger.kv("Записан файл", path.display().to_string());
+                Ok(())
+            }
+            Action::AfterRun { kind, reason } => api.request_post_action(*kind, render(reason)),
+        }
+    }
+}
+
+/// Для DWORD и QWORD допускаются числа и логические значения настроек.
+fn parse_number(value: &str) -> Result<u64, String> {
+    match value.trim() {
+        "true" => Ok(1),
+        "false" => Ok(0),
+        other => other
+            .parse()
+            .map_err(|_| format!("Значение «{other}» не подходит для числа в реестре.")),
+    }
+}
+
+/// Файлы папки, подходящие под шаблоны, через файловую систему `PluginApi`.
+/// Отсутствующая папка означает, что удалять нечего; ссылки на папки не обходятся.
+fn matching_files(
+    api: &PluginApi,
+    directory: &Path,
+    patterns: &[String],
+    recursive: bool,
+) -> Result<Vec<PathBuf>, String> {
+    let mut files = Vec::new();
+    if !api.exists(directory) {
+        return Ok(files);
+    }
+    let entries = api
+        .read_dir(directory)
+        .map_err(|e| format!("Не удалось прочитать папку {}: {e}", directory.display()))?;
+    for path in entries.into_iter().flatten() {
+        let Ok(info) = api.metadata(&path) else {
+            continue;
+        };
+        if info.is_dir {
+            if recursive {
+                files.extend(matching_files(api, &path, patterns, recursive)?);
+            }
+            continue;
+        }
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        if patterns
+            .iter()
+            .any(|pattern| wildcard_match(&pattern.to_lowercase(), &name))
+        {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+/// Сопоставление с `*` (любая последовательность) и `?` (один символ).
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, n));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            n = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+impl Plugin for DeclarativePlugin {
+    fn meta(&self) -> PluginMeta {
+        self.meta.clone()
+    }
+
+    fn run(&self, api: &PluginApi, settings: &Value, logger: &mut Logger) -> Result<(), String> {
+        for step in &self.steps {
+            if let Some(key) = &step.when
+                && !is_enabled(settings.get(key))
+            {
+                continue;
+            }
+            if api.is_cancelled() {
+                return Err("Запуск отменён.".to_string());
+            }
+            let title = step.title.clone().unwrap_or_else(|| step.action.describe());
+            logger.section(render(&title, settings, api));
+            logger.indent();
+            let result = step.action.execute(api, settings, logger);
+            logger.outdent();
+            result?;
+        }
+        Ok(())
+    }
+}
+
+fn is_enabled(value: Option<&Value>) -> bool {
+    match value {
+        None | Some(Value::Null) | Some(Value::Bool(false)) => false,
+        Some(Value::String(value)) => !value.is_empty(),
+        Some(Value::Number(value)) => value.as_f64() != Some(0.0),
+        Some(_) => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::fake::Fakes;
+    use serde_json::json;
+
+    const HEADER: &str = r#"
+id = "sample"
+name = "Пример"
+description = ""
+category = "Тест"
+capabilities = [{ kind = "registry_write", keys = ["Software\\Vendor"] }]
+
+[[settings]]
+key = "enabled"
+label = "Включить"
+kind = "boolean"
+required = false
+default = true
+"#;
+
+    fn check(steps: &str) -> Result<(), String> {
+        check_definition(&format!("{HEADER}{steps}"))
+    }
+
+    fn check_definition(source: &str) -> Result<(), String> {
+        let definition: Definition = toml::from_str(source).map_err(|e| e.to_string())?;
+        validate(&definition)
+    }
+
+    fn set_value(value: &str) -> String {
+        format!(
+            "[[steps]]\naction = \"set_registry_value\"\nkey = \"Software\\\\Vendor\"\nname = \"Flag\"\nkind = \"dword\"\nvalue = \"{value}\"\n"
+        )
+    }
+
+    #[test]
+    fn accepts_known_placeholders() {
+        assert_eq!(check(&set_value("{{enabled}}")), Ok(()));
+        assert_eq!(check(&set_value("{{ temp_dir }}{{data_dir}}")), Ok(()));
+    }
+
+    #[test]
+    fn rejects_unknown_and_unclosed_placeholders() {
+        let error = check(&set_value("{{missing}}")).unwrap_err();
+        assert_eq!(error, "Шаг 1: неизвестная подстановка «{{missing}}».");
+
+        let error = check(&set_value("{{enabled")).unwrap_err();
+        assert!(error.starts_with("Незакрытая подстановка"), "{error}");
+
+        let title = format!("{}title = \"{{{{nope}}}}\"\n", set_value("1"));
+        assert!(check(&title).unwrap_err().contains("«{{nope}}»"));
+    }
+
+    #[test]
+    fn rejects_invalid_definitions() {
+        let no_steps = check_definition(HEADER).unwrap_err();
+        assert!(no_steps.contains("steps"), "{no_steps}");
+        assert_eq!(
+            check_definition(&format!("steps = []\n{HEADER}")),
+            Err("В плагине нет ни одного шага.".to_string())
+        );
+
+        let bad_id = format!(
+            "{}{}",
+            HEADER.replace("id = \"sample\"", "id = \"Sample-1\""),
+            set_value("1")
+        );
+        assert!(
+            check_definition(&bad_id)
+                .unwrap_err()
+                .starts_with("Некорректный id")
+        );
+
+        let duplicate = format!(
+            "{HEADER}[[settings]]\nkey = \"enabled\"\nlabel = \"Ещё раз\"\nkind = \"boolean\"\nrequired = false\ndefault = false\n{}",
+            set_value("1")
+        );
+        assert_eq!(
+            check_definition(&duplicate),
+            Err("Настройка «enabled» объявлена дважды.".to_string())
+        );
+
+        let unknown_when = format!("{}when = \"other\"\n", set_value("1"));
+        assert_eq!(
+            check(&unknown_when),
+            Err("Шаг 1: условие ссылается на неизвестную настройку «other».".to_string())
+        );
+
+        let undeclared = "[[steps]]\naction = \"write_file\"\npath = \"{{temp_dir}}\\\\a.txt\"\ncontent = \"\"\n";
+        assert_eq!(
+            check(undeclared),
+            Err("Шаг 1: для «{{temp_dir}}\\a.txt» нужно разрешение fs_write.".to_string())
+        );
+    }
+
+    #[test]
+    fn render_substitutes_settings_and_keeps_unclosed_text() {
+        let fakes = Fakes::new();
+        let plugin = DeclarativePlugin {
+            meta: toml::from_str(HEADER).unwrap(),
+            steps: Vec::new(),
+        };
+        let api = fakes.api(&plugin);
+        let settings = json!({ "name": "мир", "count": 3, "empty": null });
+
+        assert_eq!(
+            render("{{ name }}: {{count}}{{empty}}{{absent}}", &settings, &api),
+            "мир: 3"
+        );
+        assert_eq!(
+            render("{{temp_dir}}", &settings, &api),
+            fakes.environment().temp_dir.display().to_string()
+        );
+        assert_eq!(render("a {{name", &settings, &api), "a {{name");
+    }
+
+    #[test]
+    fn wildcards_match_whole_names() {
+        assert!(wildcard_match("*", ""));
+        assert!(wildcard_match("*", "any.log"));
+        assert!(wildcard_match("*.log", "a.b.log"));
+        assert!(!wildcard_match("*.log", "a.log.txt"));
+        assert!(wildcard_match("log*", "log"));
+        assert!(wildcard_match("log**", "logfile"));
+        assert!(wildcard_match("?.tmp", "a.tmp"));
+        assert!(!wildcard_match("?.tmp", ".tmp"));
+        assert!(!wildcard_match("?.tmp", "ab.tmp"));
+        assert!(wildcard_match("a*b?c", "axxbyc"));
+        assert!(!wildcard_match("a*b?c", "axxbc"));
+    }
+
+    fn delete_logs(recursive: bool) -> DeclarativePlugin {
+        let source = format!(
+            "{}[[steps]]\naction = \"delete_files\"\ndirectory = \"{{{{temp_dir}}}}\"\npatterns = [\"*.Log\"]\nrecursive = {recursive}\n",
+            HEADER.replace(
+                "kind = \"registry_write\", keys = [\"Software\\\\Vendor\"]",
+                "kind = \"fs_delete\", scopes = [\"%TEMP%\"]"
+            )
+        );
+        let definition: Definition = toml::from_str(&source).unwrap();
+        validate(&definition).unwrap();
+        DeclarativePlugin {
+            meta: definition.meta,
+            steps: definition.steps,
+        }
+    }
+
+    fn logs_fakes() -> Fakes {
+        let fakes = Fakes::new();
+        fakes.fs.add_file(fakes.path("temp/Report.LOG"), "");
+        fakes.fs.add_file(fakes.path("temp/keep.txt"), "");
+        fakes.fs.add_file(fakes.path("temp/nested/old.log"), "");
+        fakes
+    }
+
+    #[test]
+    fn delete_files_matches_patterns_ignoring_case() {
+        for recursive in [false, true] {
+            let fakes = logs_fakes();
+            let plugin = delete_logs(recursive);
+            let result = plugin.run(&fakes.api(&plugin), &json!({}), &mut Logger::new());
+            assert_eq!(result, Ok(()));
+            assert!(!fakes.fs.contains(fakes.path("temp/Report.LOG")));
+            assert!(fakes.fs.contains(fakes.path("temp/keep.txt")));
+            assert_eq!(
+                fakes.fs.contains(fakes.path("temp/nested/old.log")),
+                !recursive
+            );
+        }
+    }
+
+    #[test]
+    fn failed_delete_fails_the_step() {
+        let fakes = logs_fakes();
+        fakes.fs.lock_path(fakes.path("temp/nested/old.log"));
+        let plugin = delete_logs(true);
+        let error = plugin
+            .run(&fakes.api(&plugin), &json!({}), &mut Logger::new())
+            .unwrap_err();
+        assert!(
+            error.starts_with("Не удалось удалить файлов: 1."),
+            "{error}"
+        );
+        assert!(!fakes.fs.contains(fakes.path("temp/Report.LOG")));
+    }
+}
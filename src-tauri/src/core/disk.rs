@@ -0,0 +1,49 @@
+use std::path::Path;
+
+/// Свободное место на томе, где лежит `path`, в байтах (доступное текущему пользователю).
+#[cfg(windows)]
+pub fn free_space(path: &Path) -> Result<u64, String> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut available = 0u64;
+    // SAFETY: `wide` — строка UTF-16 с нулём в конце, живущая до конца вызова;
+    // `available` — действительный `u64`, остальные выходные параметры необязательны.
+    let ok = unsafe {
+        GetDiskFreeSpaceExW(
+            wide.as_ptr(),
+            &mut available,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        )
+    };
+    if ok == 0 {
+        return Err(format!(
+            "Не удалось узнать свободное место на {}: {}",
+            path.display(),
+            std::io::Error::last_os_error()
+        ));
+    }
+    Ok(available)
+}
+
+#[cfg(unix)]
+pub fn free_space(path: &Path) -> Result<u64, String> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| format!("Некорректный путь: {}", path.display()))?;
+    // SAFETY: `statvfs` — структура из целых чисел, для неё нулевые байты допустимы.
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: `c_path` — строка с нулём в конце, `stat` доступна для записи на время вызова.
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return Err(format!(
+            "Не удалось узнать свободное место на {}: {}",
+            path.display(),
+            std::io::Error::last_os_error()
+        ));
+    }
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
@@ -0,0 +1,357 @@
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use crate::core::api::format_bytes;
+use crate::core::backend::{self, Backends};
+use crate::core::disk;
+use crate::core::environment::Environment;
+use crate::core::hive::RegistryKey;
+use crate::core::host;
+use crate::core::platform::{Platform, is_supported};
+use crate::core::plugin::Plugin;
+use crate::core::post_action::pending_reboots;
+use crate::core::registry::all_plugins;
+use crate::core::settings::PluginMeta;
+
+/// Меньше — предупреждение: обновления и очистка могут не поместиться.
+const LOW_DISK_SPACE: u64 = 1024 * 1024 * 1024;
+/// Меньше — ошибка: запись настроек и истории может не пройти.
+const CRITICAL_DISK_SPACE: u64 = 100 * 1024 * 1024;
+const PROBE_FILE: &str = ".doctor-probe";
+const REGISTRY_PROBES: [&str; 2] = [r"HKCU\Software", r"HKLM\Software"];
+
+/// Порядок важен: итог отчёта — самый тяжёлый статус.
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Ok,
+    Skipped,
+    Warning,
+    Error,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct DoctorCheck {
+    /// Стабильный идентификатор: `tool:ipconfig`, `data_dir`, `disk:temp`.
+    pub id: String,
+    pub title: String,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct DoctorReport {
+    pub status: CheckStatus,
+    pub checks: Vec<DoctorCheck>,
+}
+
+/// Проверка окружения на настоящей системе для всех плагинов.
+pub fn doctor(env: &Environment) -> DoctorReport {
+    diagnose(
+        env,
+        &Backends::default(),
+        &all_plugins(env),
+        disk::free_space,
+    )
+}
+
+/// Проверки программ, прав, папки данных, реестра, свободного места и ожидающей
+/// перезагрузки. Система берётся из `backends`, поэтому отчёт можно построить и на
+/// подделках из `core::fake`.
+pub fn diagnose(
+    env: &Environment,
+    backends: &Backends,
+    plugins: &[Box<dyn Plugin>],
+    free_space: impl Fn(&Path) -> Result<u64, String>,
+) -> DoctorReport {
+    let plugins: Vec<&dyn Plugin> = plugins
+        .iter()
+        .map(Box::as_ref)
+        .filter(|plugin| plugin.load_error().is_none() && is_supported(&plugin.meta().platforms))
+        .collect();
+
+    let mut checks = check_tools(backends, &plugins);
+    checks.push(check_permissions(backends, &plugins));
+    checks.push(check_data_dir(env, backends));
+    checks.push(check_registry(backends));
+    checks.extend(check_disk_space(env, free_space));
+    checks.push(check_pending_reboot(env));
+
+    let status = checks
+        .iter()
+        .map(|check| check.status)
+        .max()
+        .unwrap_or(CheckStatus::Ok);
+    DoctorReport { status, checks }
+}
+
+fn check(
+    id: impl Into<String>,
+    title: impl Into<String>,
+) -> impl Fn(CheckStatus, String) -> DoctorCheck {
+    let id = id.into();
+    let title = title.into();
+    move |status, detail| DoctorCheck {
+        id: id.clone(),
+        title: title.clone(),
+        status,
+        detail,
+    }
+}
+
+fn check_tools(backends: &Backends, plugins: &[&dyn Plugin]) -> Vec<DoctorCheck> {
+    let mut users: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for plugin in plugins {
+        let meta = plugin.meta();
+        for tool in meta.tools {
+            users.entry(tool).or_default().push(meta.name.clone());
+        }
+    }
+
+    users
+        .into_iter()
+        .map(|(tool, names)| {
+            let result = check(format!("tool:{tool}"), format!("Программа {tool}"));
+            match backend::which(&tool, &*backends.env, &*backends.fs) {
+                Some(path) => result(CheckStatus::Ok, path.display().to_string()),
+                None => result(
+                    CheckStatus::Error,
+                    format!("Не найдена. Нужна плагинам: {}", names.join(", ")),
+                ),
+            }
+        })
+        .collect()
+}
+
+fn check_permissions(backends: &Backends, plugins: &[&dyn Plugin]) -> DoctorCheck {
+    let result = check("elevation", "Права администратора");
+    if backends.env.is_elevated() {
+        return result(
+            CheckStatus::Ok,
+            "Приложение запущено от администратора.".to_string(),
+        );
+    }
+    let admin: Vec<String> = plugins
+        .iter()
+        .map(|plugin| plugin.meta())
+        .filter(PluginMeta::may_require_admin)
+        .map(|meta| meta.name)
+        .collect();
+    if admin.is_empty() {
+        return result(
+            CheckStatus::Ok,
+            "Нет прав администратора, но они и не нужны.".to_string(),
+        );
+    }
+    let detail = if host::can_elevate() {
+        format!(
+            "Нет прав администратора. Попросят повышения: {}",
+            admin.join(", ")
+        )
+    } else {
+        format!(
+            "Нет прав администратора, а повысить их из приложения нельзя. Запустите его от \
+             имени администратора для: {}",
+            admin.join(", ")
+        )
+    };
+    result(CheckStatus::Warning, detail)
+}
+
+fn check_data_dir(env: &Environment, backends: &Backends) -> DoctorCheck {
+    let result = check("data_dir", "Папка данных");
+    let Some(data_dir) = &env.data_dir else {
+        return result(
+            CheckStatus::Error,
+            "Папка данных не определена.".to_string(),
+        );
+    };
+    let probe = data_dir.join(PROBE_FILE);
+    let written = backends
+        .fs
+        .create_dir_all(data_dir)
+        .and_then(|_| backends.fs.write(&probe, b"ok"))
+        .and_then(|_| backends.fs.remove_file(&probe));
+    match written {
+        Ok(()) => result(CheckStatus::Ok, data_dir.display().to_string()),
+        Err(err) => result(
+            CheckStatus::Error,
+            format!("Нет доступа на запись в {}: {err}", data_dir.display()),
+        ),
+    }
+}
+
+fn check_registry(backends: &Backends) -> DoctorCheck {
+    let result = check("registry", "Реестр");
+    if Platform::current() != Platform::Windows {
+        return result(
+            CheckStatus::Skipped,
+            format!("Реестра нет на {}.", Platform::current().label()),
+        );
+    }
+    let failures: Vec<String> = REGISTRY_PROBES
+        .iter()
+        .filter_map(|probe| {
+            let key = RegistryKey::from(*probe);
+            match backends.registry.subkeys(&key) {
+                Ok(_) => None,
+                Err(err) => Some(format!("{key}: {err}")),
+            }
+        })
+        .collect();
+    if failures.is_empty() {
+        result(
+            CheckStatus::Ok,
+            format!("Читаются {}.", REGISTRY_PROBES.join(" и ")),
+        )
+    } else {
+        result(CheckStatus::Error, failures.join("; "))
+    }
+}
+
+fn check_pending_reboot(env: &Environment) -> DoctorCheck {
+    let result = check("pending_reboot", "Перезагрузка");
+    let pending = pending_reboots(env);
+    if pending.is_empty() {
+        return result(CheckStatus::Ok, "Не требуется.".to_string());
+    }
+    let reasons: Vec<&str> = pending.iter().map(|entry| entry.reason.as_str()).collect();
+    result(
+        CheckStatus::Warning,
+        format!("Изменения ждут перезагрузки: {}", reasons.join("; ")),
+    )
+}
+
+fn check_disk_space(
+    env: &Environment,
+    free_space: impl Fn(&Path) -> Result<u64, String>,
+) -> Vec<DoctorCheck> {
+    let mut targets: Vec<(&str, &str, PathBuf)> = Vec::new();
+    if let Some(data_dir) = &env.data_dir {
+        targets.push(("disk:data", "Место для данных", data_dir.clone()));
+    }
+    targets.push((
+        "disk:temp",
+        "Место для временных файлов",
+        env.temp_dir.clone(),
+    ));
+
+    targets
+        .into_iter()
+        .map(|(id, title, path)| {
+            let result = check(id, title);
+            match free_space(&path) {
+                Ok(free) => {
+                    let status = if free < CRITICAL_DISK_SPACE {
+                        CheckStatus::Error
+                    } else if free < LOW_DISK_SPACE {
+                        CheckStatus::Warning
+                    } else {
+                        CheckStatus::Ok
+                    };
+                    result(
+                        status,
+                        format!("Свободно {} на {}", format_bytes(free), path.display()),
+                    )
+                }
+                Err(err) => result(CheckStatus::Warning, err),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::PluginApi;
+    use crate::core::capability::Capability;
+    use crate::core::fake::Fakes;
+    use crate::core::logger::Logger;
+    use serde_json::Value;
+
+    struct NeedsTools;
+
+    impl Plugin for NeedsTools {
+        fn meta(&self) -> PluginMeta {
+            PluginMeta {
+                id: "needs_tools".to_string(),
+                name: "Нужны программы".to_string(),
+                description: String::new(),
+                category: "Тест".to_string(),
+                settings: Vec::new(),
+                capabilities: vec![Capability::RequiresAdmin],
+                platforms: Vec::new(),
+                timeout_secs: None,
+                tools: vec!["present".to_string(), "absent".to_string()],
+            }
+        }
+
+        fn run(
+            &self,
+            _api: &PluginApi,
+            _settings: &Value,
+            _logger: &mut Logger,
+        ) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    fn status(report: &DoctorReport, id: &str) -> Option<CheckStatus> {
+        report
+            .checks
+            .iter()
+            .find(|check| check.id == id)
+            .map(|check| check.status)
+    }
+
+    #[test]
+    fn reports_missing_tools_permissions_and_low_disk() {
+        let fakes = Fakes::new();
+        let bin = fakes.path("bin");
+        fakes.fs.add_file(
+            bin.join(if cfg!(windows) {
+                "present.exe"
+            } else {
+                "present"
+            }),
+            "",
+        );
+        fakes.env.set_var("PATH", bin.display().to_string());
+        let plugins: Vec<Box<dyn Plugin>> = vec![Box::new(NeedsTools)];
+
+        let report = diagnose(&fakes.environment(), &fakes.backends(), &plugins, |_| {
+            Ok(50 * 1024 * 1024)
+        });
+
+        assert_eq!(status(&report, "tool:present"), Some(CheckStatus::Ok));
+        assert_eq!(status(&report, "tool:absent"), Some(CheckStatus::Error));
+        assert_eq!(status(&report, "elevation"), Some(CheckStatus::Warning));
+        assert_eq!(status(&report, "data_dir"), Some(CheckStatus::Ok));
+        assert_eq!(status(&report, "disk:temp"), Some(CheckStatus::Error));
+        assert_eq!(report.status, CheckStatus::Error);
+        assert!(!fakes.fs.contains(fakes.path("data").join(PROBE_FILE)));
+    }
+
+    #[test]
+    fn healthy_environment_is_ok() {
+        let fakes = Fakes::new();
+        fakes.env.set_elevated(true);
+        for probe in REGISTRY_PROBES {
+            fakes.registry.add_key(probe);
+        }
+
+        let report = diagnose(&fakes.environment(), &fakes.backends(), &[], |_| {
+            Ok(LOW_DISK_SPACE)
+        });
+
+        assert!(
+            report
+                .checks
+                .iter()
+                .all(|check| check.status <= CheckStatus::Skipped)
+        );
+    }
+}
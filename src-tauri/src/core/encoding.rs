@@ -0,0 +1,74 @@
+use encoding_rs::{IBM866, WINDOWS_1251};
+use serde::{Deserialize, Serialize};
+
+/// Кодировка вывода консольных программ. Русская Windows пишет в консоль в CP866
+/// (`ipconfig`, `netsh`), часть программ — в CP1251 или UTF-8.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputEncoding {
+    #[default]
+    Auto,
+    Utf8,
+    Cp866,
+    Cp1251,
+}
+
+impl OutputEncoding {
+    pub fn decode(self, bytes: &[u8]) -> String {
+        match self {
+            OutputEncoding::Auto => Self::detect(bytes).decode(bytes),
+            OutputEncoding::Utf8 => String::from_utf8_lossy(bytes).into_owned(),
+            OutputEncoding::Cp866 => IBM866.decode_without_bom_handling(bytes).0.into_owned(),
+            OutputEncoding::Cp1251 => WINDOWS_1251
+                .decode_without_bom_handling(bytes)
+                .0
+                .into_owned(),
+        }
+    }
+
+    /// UTF-8, если байты в ней корректны. Иначе из CP866 и CP1251 выбирается та, в которой
+    /// получается больше русских букв: в чужой кодировке половина букв становится
+    /// псевдографикой и знаками.
+    pub fn detect(bytes: &[u8]) -> Self {
+        if std::str::from_utf8(bytes).is_ok() {
+            return OutputEncoding::Utf8;
+        }
+        let letters = |encoding: OutputEncoding| {
+            encoding
+                .decode(bytes)
+                .chars()
+                .filter(|c| ('А'..='я').contains(c))
+                .count()
+        };
+        if letters(OutputEncoding::Cp1251) > letters(OutputEncoding::Cp866) {
+            OutputEncoding::Cp1251
+        } else {
+            OutputEncoding::Cp866
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "Настройка протокола IP для Windows\r\nОчистка кэша сопоставителя DNS.";
+
+    #[test]
+    fn detects_console_code_pages() {
+        for (encoding, bytes) in [
+            (OutputEncoding::Cp866, IBM866.encode(TEXT).0),
+            (OutputEncoding::Cp1251, WINDOWS_1251.encode(TEXT).0),
+            (OutputEncoding::Utf8, TEXT.as_bytes().into()),
+        ] {
+            assert_eq!(OutputEncoding::detect(&bytes), encoding);
+            assert_eq!(OutputEncoding::Auto.decode(&bytes), TEXT);
+        }
+    }
+
+    #[test]
+    fn explicit_encoding_is_not_guessed() {
+        let bytes = WINDOWS_1251.encode("тест").0;
+        assert_ne!(OutputEncoding::Cp866.decode(&bytes), "тест");
+    }
+}
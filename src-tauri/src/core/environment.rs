@@ -0,0 +1,130 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+use crate::core::paths;
+
+const DEFAULT_LOCALE: &str = "ru-RU";
+
+/// Всё, что ядру нужно знать об окружении: без него `PluginApi` не собрать.
+/// GUI заполняет его из `AppHandle`, CLI и хост — из переменных окружения.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Environment {
+    /// Папка данных приложения (история, настройки). `None` — данные не сохраняются.
+    pub data_dir: Option<PathBuf>,
+    pub temp_dir: PathBuf,
+    /// Язык в виде BCP 47, например `ru-RU`.
+    pub locale: String,
+    /// Домашняя папка пользователя. `PathGuard` не даёт удалить или перезаписать её целиком.
+    #[serde(default)]
+    pub home_dir: Option<PathBuf>,
+    /// Системные папки, защищённые так же, как домашняя.
+    #[serde(default)]
+    pub system_dirs: Vec<PathBuf>,
+    /// Загружать только встроенные плагины. Так работает хост с повышенными правами:
+    /// папку данных может изменить любой процесс пользователя.
+    #[serde(default)]
+    pub builtin_only: bool,
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self {
+            data_dir: None,
+            temp_dir: std::env::temp_dir(),
+            locale: DEFAULT_LOCALE.to_string(),
+            home_dir: detect_home_dir(),
+            system_dirs: detect_system_dirs(),
+            builtin_only: false,
+        }
+    }
+}
+
+impl Environment {
+    /// Окружение текущего процесса: стандартная папка данных, системная временная папка, язык из `LANG`.
+    pub fn detect() -> Self {
+        Self {
+            data_dir: paths::app_data_dir(),
+            locale: detect_locale().unwrap_or_else(|| DEFAULT_LOCALE.to_string()),
+            ..Self::default()
+        }
+    }
+
+    pub fn with_data_dir(mut self, data_dir: Option<PathBuf>) -> Self {
+        self.data_dir = data_dir;
+        self
+    }
+}
+
+/// `ru_RU.UTF-8` → `ru-RU`. `C` и `POSIX` языка не задают.
+fn detect_locale() -> Option<String> {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .map(|value| {
+            let value = value.split(['.', '@']).next().unwrap_or_default();
+            value.replace('_', "-")
+        })
+        .find(|value| !value.is_empty() && value != "C" && value != "POSIX")
+}
+
+fn detect_home_dir() -> Option<PathBuf> {
+    let var = if cfg!(windows) { "USERPROFILE" } else { "HOME" };
+    std::env::var_os(var)
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+}
+
+fn detect_system_dirs() -> Vec<PathBuf> {
+    if cfg!(windows) {
+        let mut dirs = Vec::new();
+        for var in [
+            "SystemRoot",
+            "windir",
+            "ProgramFiles",
+            "ProgramFiles(x86)",
+            "ProgramW6432",
+            "ProgramData",
+            "PUBLIC",
+        ] {
+            if let Some(value) = std::env::var_os(var).map(PathBuf::from)
+                && value.is_absolute()
+            {
+                dirs.push(value);
+            }
+        }
+        if let Some(root) = dirs.first().cloned() {
+            dirs.push(root.join("System32"));
+            dirs.push(root.join("SysWOW64"));
+        }
+        dirs.push(PathBuf::from("C:\\Windows"));
+        dirs.push(PathBuf::from("C:\\Users"));
+        dirs
+    } else {
+        [
+            "/bin",
+            "/boot",
+            "/dev",
+            "/etc",
+            "/home",
+            "/lib",
+            "/lib64",
+            "/opt",
+            "/proc",
+            "/root",
+            "/run",
+            "/sbin",
+            "/srv",
+            "/sys",
+            "/tmp",
+            "/usr",
+            "/var",
+            "/Applications",
+            "/Library",
+            "/System",
+            "/Users",
+        ]
+        .iter()
+        .map(PathBuf::from)
+        .collect()
+    }
+}
@@ -0,0 +1,66 @@
+use serde::{Serialize, Serializer, ser::SerializeStruct};
+use std::fmt;
+
+use crate::core::platform::{Platform, unsupported_reason};
+
+/// Ошибки ядра, которые UI различает по `kind`.
+#[derive(Debug, Clone)]
+pub enum CoreError {
+    PluginNotFound {
+        id: String,
+    },
+    UnsupportedPlatform {
+        id: String,
+        platforms: Vec<Platform>,
+    },
+    MissingTools {
+        id: String,
+        tools: Vec<String>,
+    },
+    Plugin(String),
+}
+
+impl CoreError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            CoreError::PluginNotFound { .. } => "plugin_not_found",
+            CoreError::UnsupportedPlatform { .. } => "unsupported_platform",
+            CoreError::MissingTools { .. } => "missing_tools",
+            CoreError::Plugin(_) => "plugin",
+        }
+    }
+}
+
+impl fmt::Display for CoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CoreError::PluginNotFound { id } => write!(f, "Плагин не найден: {id}"),
+            CoreError::UnsupportedPlatform { id, platforms } => write!(
+                f,
+                "Плагин {id} не поддерживается: {}",
+                unsupported_reason(platforms).unwrap_or_default()
+            ),
+            CoreError::MissingTools { id, tools } => write!(
+                f,
+                "Плагину {id} нужны программы, которых нет в системе: {}",
+                tools.join(", ")
+            ),
+            CoreError::Plugin(message) => f.write_str(message),
+        }
+    }
+}
+
+impl From<String> for CoreError {
+    fn from(message: String) -> Self {
+        CoreError::Plugin(message)
+    }
+}
+
+impl Serialize for CoreError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("CoreError", 2)?;
+        state.serialize_field("kind", self.kind())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.end()
+    }
+}
@@ -0,0 +1,550 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex, MutexGuard,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+use crate::core::api::{CancelToken, PluginApi};
+use crate::core::backend::{
+    Backends, CommandOptions, CommandOutput, FileInfo, FileSystem, LineBuffer, LineHandler,
+    OutputStream, ProcessRunner, Registry, SystemEnvironment, command_line,
+};
+use crate::core::environment::Environment;
+use crate::core::hive::{Hive, RegistryKey, RegistryValue, RegistryView};
+use crate::core::plugin::Plugin;
+
+/// Корень поддельной файловой системы: абсолютный путь на любой ОС.
+const FAKE_ROOT: &str = if cfg!(windows) { "C:\\fake" } else { "/fake" };
+
+/// Подделки всех систем `PluginApi` для тестов плагинов. Копии разделяют состояние,
+/// поэтому после запуска можно проверить, что плагин записал и что запустил.
+#[derive(Clone, Default)]
+pub struct Fakes {
+    pub fs: Arc<MemoryFileSystem>,
+    pub registry: Arc<MemoryRegistry>,
+    pub processes: Arc<ScriptedProcessRunner>,
+    pub env: Arc<MemoryEnvironment>,
+}
+
+impl Fakes {
+    /// Пустые подделки с уже созданными папками данных и временных файлов.
+    pub fn new() -> Self {
+        let fakes = Self::default();
+        let env = fakes.environment();
+        fakes.fs.add_dir(&env.temp_dir);
+        if let Some(data_dir) = &env.data_dir {
+            fakes.fs.add_dir(data_dir);
+        }
+        fakes
+    }
+
+    pub fn backends(&self) -> Backends {
+        Backends {
+            fs: self.fs.clone(),
+            registry: self.registry.clone(),
+            processes: self.processes.clone(),
+            env: self.env.clone(),
+        }
+    }
+
+    /// Папки данных, временных файлов, домашняя и системная внутри поддельной файловой системы.
+    pub fn environment(&self) -> Environment {
+        let root = PathBuf::from(FAKE_ROOT);
+        Environment {
+            data_dir: Some(root.join("data")),
+            temp_dir: root.join("temp"),
+            locale: "ru-RU".to_string(),
+            home_dir: Some(root.join("home")),
+            system_dirs: vec![root.join("Windows")],
+            ..Environment::default()
+        }
+    }
+
+    /// `PluginApi` с разрешениями плагина поверх подделок.
+    pub fn api(&self, plugin: &dyn Plugin) -> PluginApi {
+        PluginApi::with_backends(
+            &self.environment(),
+            plugin.meta().capabilities,
+            self.backends(),
+        )
+    }
+
+    /// Путь внутри поддельной файловой системы: `fakes.path("Windows/Temp")`.
+    pub fn path(&self, relative: &str) -> PathBuf {
+        relative
+            .split(['/', '\\'])
+            .filter(|part| !part.is_empty())
+            .fold(PathBuf::from(FAKE_ROOT), |path, part| path.join(part))
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+enum Node {
+    Dir,
+    File(Vec<u8>),
+}
+
+/// Дерево папок и файлов в памяти.
+#[derive(Default)]
+pub struct MemoryFileSystem {
+    nodes: Mutex<BTreeMap<PathBuf, Node>>,
+    locked: Mutex<HashSet<PathBuf>>,
+}
+
+impl MemoryFileSystem {
+    /// Создаёт файл вместе с недостающими папками.
+    pub fn add_file(&self, path: impl AsRef<Path>, contents: impl Into<Vec<u8>>) {
+        let path = path.as_ref();
+        let mut nodes = lock(&self.nodes);
+        add_ancestors(&mut nodes, path);
+        nodes.insert(path.to_path_buf(), Node::File(contents.into()));
+    }
+
+    pub fn add_dir(&self, path: impl AsRef<Path>) {
+        let path = path.as_ref();
+        let mut nodes = lock(&self.nodes);
+        add_ancestors(&mut nodes, path);
+        nodes.insert(path.to_path_buf(), Node::Dir);
+    }
+
+    /// Файл или папка, которые нельзя удалить, как будто их держит другой процесс.
+    pub fn lock_path(&self, path: impl AsRef<Path>) {
+        lock(&self.locked).insert(path.as_ref().to_path_buf());
+    }
+
+    pub fn contents(&self, path: impl AsRef<Path>) -> Option<Vec<u8>> {
+        match lock(&self.nodes).get(path.as_ref()) {
+            Some(Node::File(contents)) => Some(contents.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn contains(&self, path: impl AsRef<Path>) -> bool {
+        lock(&self.nodes).contains_key(path.as_ref())
+    }
+
+    fn check_unlocked(&self, path: &Path) -> io::Result<()> {
+        if lock(&self.locked)
+            .iter()
+            .any(|locked| locked.starts_with(path))
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "The process cannot access the file because it is being used by another process. \
+                 (os error 32)",
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn add_ancestors(nodes: &mut BTreeMap<PathBuf, Node>, path: &Path) {
+    for ancestor in path.ancestors().skip(1) {
+        if ancestor.as_os_str().is_empty() {
+            break;
+        }
+        nodes.entry(ancestor.to_path_buf()).or_insert(Node::Dir);
+    }
+}
+
+fn not_found(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("{}: не найден (os error 2)", path.display()),
+    )
+}
+
+impl FileSystem for MemoryFileSystem {
+    fn exists(&self, path: &Path) -> bool {
+        self.contains(path)
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<io::Result<PathBuf>>> {
+        let nodes = lock(&self.nodes);
+        match nodes.get(path) {
+            Some(Node::Dir) => Ok(nodes
+                .keys()
+                .filter(|child| child.parent() == Some(path))
+                .map(|child| Ok(child.clone()))
+                .collect()),
+            Some(Node::File(_)) => Err(io::Error::other(format!(
+                "{}: это файл, а не папка",
+                path.display()
+            ))),
+            None => Err(not_found(path)),
+        }
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<FileInfo> {
+        match lock(&self.nodes).get(path) {
+            Some(Node::Dir) => Ok(FileInfo {
+                is_dir: true,
+                len: 0,
+            }),
+            Some(Node::File(contents)) => Ok(FileInfo {
+                is_dir: false,
+                len: contents.len() as u64,
+            }),
+            None => Err(not_found(path)),
+        }
+    }
+
+    fn write(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        let mut nodes = lock(&self.nodes);
+        match path.parent().map(|parent| nodes.get(parent)) {
+            Some(Some(Node::Dir)) => {}
+            _ => return Err(not_found(path)),
+        }
+        if let Some(Node::Dir) = nodes.get(path) {
+            return Err(io::Error::other(format!("{}: это папка", path.display())));
+        }
+        nodes.insert(path.to_path_buf(), Node::File(contents.to_vec()));
+        Ok(())
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        if let Some(Node::File(_)) = lock(&self.nodes).get(path) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{}: уже есть файл с таким именем", path.display()),
+            ));
+        }
+        self.add_dir(path);
+        Ok(())
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        self.check_unlocked(path)?;
+        let mut nodes = lock(&self.nodes);
+        match nodes.get(path) {
+            Some(Node::File(_)) => {
+                nodes.remove(path);
+                Ok(())
+            }
+            Some(Node::Dir) => Err(io::Error::other(format!("{}: это папка", path.display()))),
+            None => Err(not_found(path)),
+        }
+    }
+
+    fn remove_dir_all(&self, path: &Path) -> io::Result<()> {
+        self.check_unlocked(path)?;
+        let mut nodes = lock(&self.nodes);
+        match nodes.get(path) {
+            Some(Node::Dir) => {
+                nodes.retain(|node, _| !node.starts_with(path));
+                Ok(())
+            }
+            Some(Node::File(_)) => Err(io::Error::other(format!(
+                "{}: это файл, а не папка",
+                path.display()
+            ))),
+            None => Err(not_found(path)),
+        }
+    }
+}
+
+/// Реестр в памяти. Имена ключей и значений, как и в Windows, без учёта регистра.
+/// 32-битный вид хранится отдельно, `Native` и `Bits64` совпадают.
+#[derive(Default)]
+pub struct MemoryRegistry {
+    keys: Mutex<BTreeMap<KeyId, FakeKey>>,
+}
+
+type KeyId = (Hive, bool, String);
+
+#[derive(Default)]
+struct FakeKey {
+    /// Путь в исходном регистре букв, для перечисления подключей.
+    path: String,
+    values: BTreeMap<String, (String, RegistryValue)>,
+}
+
+fn key_id(key: &RegistryKey) -> KeyId {
+    (
+        key.hive,
+        key.view == RegistryView::Bits32,
+        key.path.to_lowercase(),
+    )
+}
+
+fn key_not_found() -> String {
+    "Ошибка открытия регистра: ключ не найден".to_string()
+}
+
+impl MemoryRegistry {
+    pub fn add_key(&self, key: impl Into<RegistryKey>) {
+        add_key(&mut lock(&self.keys), &key.into());
+    }
+
+    /// Записывает значение, создавая ключ при необходимости.
+    pub fn set_value(&self, key: impl Into<RegistryKey>, name: &str, value: RegistryValue) {
+        let key = key.into();
+        let mut keys = lock(&self.keys);
+        add_key(&mut keys, &key);
+        if let Some(fake) = keys.get_mut(&key_id(&key)) {
+            fake.values
+                .insert(name.to_lowercase(), (name.to_string(), value));
+        }
+    }
+
+    pub fn value(&self, key: impl Into<RegistryKey>, name: &str) -> Option<RegistryValue> {
+        lock(&self.keys)
+            .get(&key_id(&key.into()))
+            .and_then(|fake| fake.values.get(&name.to_lowercase()))
+            .map(|(_, value)| value.clone())
+    }
+
+    pub fn has_key(&self, key: impl Into<RegistryKey>) -> bool {
+        let key = key.into();
+        key.path.is_empty() || lock(&self.keys).contains_key(&key_id(&key))
+    }
+}
+
+fn add_key(keys: &mut BTreeMap<KeyId, FakeKey>, key: &RegistryKey) {
+    let mut path = RegistryKey::new(key.hive, "").with_view(key.view);
+    for part in key.path.split('\\').filter(|part| !part.is_empty()) {
+        path = path.join(part);
+        keys.entry(key_id(&path)).or_insert_with(|| FakeKey {
+            path: path.path.clone(),
+            values: BTreeMap::new(),
+        });
+    }
+}
+
+impl Registry for MemoryRegistry {
+    fn get_value(&self, key: &RegistryKey, name: &str) -> Result<RegistryValue, String> {
+        let keys = lock(&self.keys);
+        let fake = keys.get(&key_id(key)).ok_or_else(key_not_found)?;
+        fake.values
+            .get(&name.to_lowercase())
+            .map(|(_, value)| value.clone())
+            .ok_or_else(|| "Ошибка прочтения значения в регистре: значение не найдено".to_string())
+    }
+
+    fn set_value(
+        &self,
+        key: &RegistryKey,
+        name: &str,
+        value: &RegistryValue,
+    ) -> Result<(), String> {
+        let mut keys = lock(&self.keys);
+        let fake = keys.get_mut(&key_id(key)).ok_or_else(key_not_found)?;
+        fake.values
+            .insert(name.to_lowercase(), (name.to_string(), value.clone()));
+        Ok(())
+    }
+
+    fn delete_value(&self, key: &RegistryKey, name: &str) -> Result<(), String> {
+        if let Some(fake) = lock(&self.keys).get_mut(&key_id(key)) {
+            fake.values.remove(&name.to_lowercase());
+        }
+        Ok(())
+    }
+
+    fn key_exists(&self, key: &RegistryKey) -> Result<bool, String> {
+        Ok(self.has_key(key))
+    }
+
+    fn create_key(&self, key: &RegistryKey) -> Result<(), String> {
+        self.add_key(key);
+        Ok(())
+    }
+
+    fn delete_key(&self, key: &RegistryKey) -> Result<(), String> {
+        if key.path.is_empty() {
+            return Err(format!("Нельзя удалить раздел реестра целиком: {key}"));
+        }
+        let (hive, bits32, path) = key_id(key);
+        let mut keys = lock(&self.keys);
+        if keys.remove(&(hive, bits32, path.clone())).is_none() {
+            return Err("Ошибка удаления ключа реестра: ключ не найден".to_string());
+        }
+        let prefix = format!("{path}\\");
+        keys.retain(|(other_hive, other_bits32, other), _| {
+            *other_hive != hive || *other_bits32 != bits32 || !other.starts_with(&prefix)
+        });
+        Ok(())
+    }
+
+    fn subkeys(&self, key: &RegistryKey) -> Result<Vec<String>, String> {
+        if !self.has_key(key) {
+            return Err(key_not_found());
+        }
+        let parent = key_id(key);
+        Ok(lock(&self.keys)
+            .iter()
+            .filter(|((hive, bits32, _), fake)| {
+                let child = RegistryKey::new(*hive, &fake.path).with_view(key.view);
+                (*hive, *bits32) == (parent.0, parent.1)
+                    && child.parent().map(|parent| parent.path.to_lowercase())
+                        == Some(parent.2.clone())
+            })
+            .map(|(_, fake)| RegistryKey::new(key.hive, &fake.path).name().to_string())
+            .collect())
+    }
+
+    fn values(&self, key: &RegistryKey) -> Result<Vec<(String, RegistryValue)>, String> {
+        let keys = lock(&self.keys);
+        let fake = keys.get(&key_id(key)).ok_or_else(key_not_found)?;
+        Ok(fake.values.values().cloned().collect())
+    }
+}
+
+/// Программы с заранее заданным выводом. Незаданная команда «не запускается».
+#[derive(Default)]
+pub struct ScriptedProcessRunner {
+    outputs: Mutex<HashMap<String, CommandOutput>>,
+    calls: Mutex<Vec<String>>,
+    options: Mutex<Vec<CommandOptions>>,
+}
+
+impl ScriptedProcessRunner {
+    /// Что вернёт `command_line` — программа и аргументы через пробел: `ipconfig /flushdns`.
+    /// Вывод задаётся строкой или байтами, например в CP866.
+    pub fn respond(
+        &self,
+        command_line: &str,
+        code: i32,
+        stdout: impl Into<Vec<u8>>,
+        stderr: impl Into<Vec<u8>>,
+    ) {
+        lock(&self.outputs).insert(
+            command_line.to_string(),
+            CommandOutput {
+                code: Some(code),
+                stdout: stdout.into(),
+                stderr: stderr.into(),
+                duration: Duration::ZERO,
+            },
+        );
+    }
+
+    /// Все запуски по порядку, включая `spawn`.
+    pub fn calls(&self) -> Vec<String> {
+        lock(&self.calls).clone()
+    }
+
+    /// Параметры запусков через `run` по порядку.
+    pub fn options(&self) -> Vec<CommandOptions> {
+        lock(&self.options).clone()
+    }
+}
+
+impl ProcessRunner for ScriptedProcessRunner {
+    fn run(
+        &self,
+        program: &str,
+        args: &[&str],
+        options: &CommandOptions,
+        cancel: &CancelToken,
+        on_line: &mut LineHandler,
+    ) -> Result<CommandOutput, String> {
+        let line = command_line(program, args);
+        lock(&self.calls).push(line.clone());
+        lock(&self.options).push(options.clone());
+        if cancel.is_cancelled() {
+            return Err(format!("Команда прервана: {line}"));
+        }
+        let scripted = lock(&self.outputs)
+            .get(&line)
+            .cloned()
+            .ok_or_else(|| format!("Не удалось запустить {program}: команда не задана в тесте"))?;
+        let mut output = CommandOutput {
+            code: scripted.code,
+            ..CommandOutput::default()
+        };
+        let mut lines = LineBuffer::default();
+        lines.push(&mut output, OutputStream::Stdout, &scripted.stdout, on_line);
+        lines.push(&mut output, OutputStream::Stderr, &scripted.stderr, on_line);
+        lines.finish(&output, on_line);
+        Ok(output)
+    }
+
+    fn spawn(&self, program: &str, args: &[&str]) -> Result<(), String> {
+        lock(&self.calls).push(command_line(program, args));
+        Ok(())
+    }
+}
+
+/// Переменные окружения без учёта регистра имён, как в Windows.
+#[derive(Default)]
+pub struct MemoryEnvironment {
+    vars: Mutex<HashMap<String, String>>,
+    elevated: AtomicBool,
+}
+
+impl MemoryEnvironment {
+    pub fn set_var(&self, name: &str, value: impl Into<String>) {
+        lock(&self.vars).insert(name.to_lowercase(), value.into());
+    }
+
+    pub fn set_elevated(&self, elevated: bool) {
+        self.elevated.store(elevated, Ordering::SeqCst);
+    }
+}
+
+impl SystemEnvironment for MemoryEnvironment {
+    fn var(&self, name: &str) -> Option<String> {
+        lock(&self.vars).get(&name.to_lowercase()).cloned()
+    }
+
+    fn is_elevated(&self) -> bool {
+        self.elevated.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registry_enumerates_direct_children_in_original_case() {
+        let registry = MemoryRegistry::default();
+        registry.set_value(r"HKLM\Software\Vendor\App", "Path", RegistryValue::Dword(1));
+        registry.add_key(r"HKLM\Software\Other");
+        let software = RegistryKey::new(Hive::LocalMachine, "software");
+
+        assert_eq!(
+            registry.subkeys(&software),
+            Ok(vec!["Other".to_string(), "Vendor".to_string()])
+        );
+        assert_eq!(
+            registry.values(&software.join(r"vendor\app")),
+            Ok(vec![("Path".to_string(), RegistryValue::Dword(1))])
+        );
+        assert!(registry.subkeys(&software.join("Missing")).is_err());
+    }
+
+    #[test]
+    fn registry_keeps_32_bit_view_apart() {
+        let registry = MemoryRegistry::default();
+        let key = RegistryKey::new(Hive::LocalMachine, r"Software\Vendor");
+        registry.add_key(key.clone().with_view(RegistryView::Bits32));
+
+        assert!(!registry.has_key(&key));
+        assert!(!registry.has_key(key.clone().with_view(RegistryView::Bits64)));
+        assert!(registry.has_key(key.with_view(RegistryView::Bits32)));
+    }
+
+    #[test]
+    fn deleting_key_removes_subkeys() {
+        let registry = MemoryRegistry::default();
+        registry.add_key(r"Software\Vendor\App\Cache");
+        registry.add_key(r"Software\VendorX");
+
+        assert_eq!(registry.delete_key(&r"Software\Vendor".into()), Ok(()));
+        assert!(!registry.has_key(r"Software\Vendor\App"));
+        assert!(registry.has_key(r"Software\VendorX"));
+        assert!(registry.delete_key(&"HKCU".into()).is_err());
+    }
+}
@@ -0,0 +1,519 @@
+use serde::Serialize;
+use std::{
+    fmt, fs,
+    path::{Component, Path, PathBuf},
+};
+
+use crate::core::environment::Environment;
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum GuardErrorKind {
+    RelativePath,
+    FilesystemRoot,
+    HomeDirectory,
+    SystemDirectory,
+    AppDataDirectory,
+    OutsideAllowedRoots,
+}
+
+impl GuardErrorKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            GuardErrorKind::RelativePath => "относительный путь",
+            GuardErrorKind::FilesystemRoot => "корень файловой системы",
+            GuardErrorKind::HomeDirectory => "домашняя папка пользователя",
+            GuardErrorKind::SystemDirectory => "системная папка",
+            GuardErrorKind::AppDataDirectory => "данные приложения",
+            GuardErrorKind::OutsideAllowedRoots => "вне разрешённых папок плагина",
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum GuardOperation {
+    Delete,
+    Write,
+}
+
+impl GuardOperation {
+    pub fn label(self) -> &'static str {
+        match self {
+            GuardOperation::Delete => "Удаление заблокировано",
+            GuardOperation::Write => "Запись заблокирована",
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct GuardError {
+    pub operation: GuardOperation,
+    pub kind: GuardErrorKind,
+    pub path: PathBuf,
+}
+
+impl fmt::Display for GuardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({}): {}",
+            self.operation.label(),
+            self.kind.label(),
+            self.path.display()
+        )
+    }
+}
+
+/// Проверяет пути перед удалением: корни, домашняя и системные папки,
+/// данные приложения и всё, что лежит вне объявленных плагином папок.
+#[derive(Clone)]
+pub struct PathGuard {
+    protected: Vec<(GuardErrorKind, PathBuf)>,
+    app_data_dir: Option<PathBuf>,
+    allowed_roots: Vec<PathBuf>,
+}
+
+impl PathGuard {
+    /// Защищённые папки и данные приложения берутся из `env`.
+    pub fn new(env: &Environment) -> Self {
+        let mut protected = Vec::new();
+        if let Some(home) = &env.home_dir {
+            protected.push((GuardErrorKind::HomeDirectory, normalize(home)));
+        }
+        for dir in &env.system_dirs {
+            protected.push((GuardErrorKind::SystemDirectory, normalize(dir)));
+        }
+
+        Self {
+            protected,
+            app_data_dir: env.data_dir.as_deref().map(normalize),
+            allowed_roots: Vec::new(),
+        }
+    }
+
+    pub fn set_allowed_roots(&mut self, roots: Vec<PathBuf>) {
+        self.allowed_roots = roots
+            .into_iter()
+            .filter(|root| root.is_absolute() && !is_fs_root(root))
+            .map(|root| normalize(&root))
+            .collect();
+    }
+
+    pub fn check_delete(&self, path: &Path) -> Result<(), GuardError> {
+        let target = self.check_protected(path, GuardOperation::Delete)?;
+        let inside_allowed = self
+            .allowed_roots
+            .iter()
+            .any(|root| is_within(&target, root) && !same_path(&target, root));
+        if !inside_allowed {
+            return Err(GuardError {
+                operation: GuardOperation::Delete,
+                kind: GuardErrorKind::OutsideAllowedRoots,
+                path: path.to_path_buf(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Те же защищённые пути, что у удаления. Куда плагину можно писать, решает
+    /// `Capability::FsWrite`.
+    pub fn check_write(&self, path: &Path) -> Result<(), GuardError> {
+        self.check_protected(path, GuardOperation::Write)
+            .map(|_| ())
+    }
+
+    /// Нормализованный путь, если он не относительный, не корень, не данные приложения
+    /// и не содержит домашнюю или системную папку.
+    fn check_protected(
+        &self,
+        path: &Path,
+        operation: GuardOperation,
+    ) -> Result<PathBuf, GuardError> {
+        let blocked = |kind| GuardError {
+            operation,
+            kind,
+            path: path.to_path_buf(),
+        };
+
+        if !path.is_absolute() {
+            return Err(blocked(GuardErrorKind::RelativePath));
+        }
+
+        let target = normalize(path);
+        if is_fs_root(&target) {
+            return Err(blocked(GuardErrorKind::FilesystemRoot));
+        }
+
+        if let Some(app_data) = &self.app_data_dir
+            && (is_within(&target, app_data) || is_within(app_data, &target))
+        {
+            return Err(blocked(GuardErrorKind::AppDataDirectory));
+        }
+
+        for (kind, dir) in &self.protected {
+            if is_within(dir, &target) {
+                return Err(blocked(*kind));
+            }
+        }
+
+        Ok(target)
+    }
+}
+
+/// Канонизирует родителя (сам путь может быть симлинком, который удаляется без перехода по нему).
+pub(crate) fn normalize(path: &Path) -> PathBuf {
+    let lexical = lexical_normalize(path);
+    match (lexical.parent(), lexical.file_name()) {
+        (Some(parent), Some(name)) => match fs::canonicalize(parent) {
+            Ok(parent) => parent.join(name),
+            Err(_) => lexical,
+        },
+        _ => fs::canonicalize(&lexical).unwrap_or(lexical),
+    }
+}
+
+fn lexical_normalize(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                result.pop();
+            }
+            other => result.push(other.as_os_str()),
+        }
+    }
+    result
+}
+
+fn is_fs_root(path: &Path) -> bool {
+    !path
+        .components()
+        .any(|component| matches!(component, Component::Normal(_)))
+}
+
+fn components_key(path: &Path) -> Vec<String> {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Prefix(prefix) => Some(prefix_key(prefix.as_os_str())),
+            Component::Normal(part) => Some(part.to_string_lossy().to_string()),
+            _ => None,
+        })
+        .map(|part| {
+            if cfg!(windows) {
+                part.to_lowercase()
+            } else {
+                part
+            }
+        })
+        .collect()
+}
+
+/// `\\?\C:` и `C:` должны совпадать, иначе канонизированные пути не сравнить с переменными окружения.
+fn prefix_key(prefix: &std::ffi::OsStr) -> String {
+    let value = prefix.to_string_lossy();
+    value.trim_start_matches("\\\\?\\").to_string()
+}
+
+pub(crate) fn is_within(path: &Path, base: &Path) -> bool {
+    let path = components_key(path);
+    let base = components_key(base);
+    path.len() >= base.len() && path[..base.len()] == base[..]
+}
+
+pub(crate) fn same_path(a: &Path, b: &Path) -> bool {
+    components_key(a) == components_key(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("lamerhelper-guard-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn blocked(guard: &PathGuard, path: &Path) -> Option<GuardErrorKind> {
+        guard.check_delete(path).err().map(|err| err.kind)
+    }
+
+    /// Охрана с заданными защищёнными папками вместо папок текущей системы.
+    fn guard_with(
+        app_data: Option<PathBuf>,
+        home: Option<PathBuf>,
+        system: Vec<PathBuf>,
+    ) -> PathGuard {
+        PathGuard::new(&Environment {
+            data_dir: app_data,
+            home_dir: home,
+            system_dirs: system,
+            ..Environment::default()
+        })
+    }
+
+    /// Охрана с одной разрешённой папкой `allowed` внутри `dir`.
+    fn guard_for(dir: &Path, app_data: Option<PathBuf>) -> PathGuard {
+        fs::create_dir_all(dir.join("allowed")).unwrap();
+        let mut guard = guard_with(app_data, None, Vec::new());
+        guard.set_allowed_roots(vec![dir.join("allowed")]);
+        guard
+    }
+
+    #[test]
+    fn relative_paths_are_blocked() {
+        let dir = scratch_dir("relative");
+        let guard = guard_for(&dir, None);
+
+        for path in ["allowed/file.tmp", "./file.tmp", "../file.tmp"] {
+            assert_eq!(
+                blocked(&guard, Path::new(path)),
+                Some(GuardErrorKind::RelativePath)
+            );
+        }
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn filesystem_roots_are_blocked() {
+        let guard = guard_with(None, None, Vec::new());
+        let root = if cfg!(windows) { r"C:\" } else { "/" };
+
+        assert_eq!(
+            blocked(&guard, Path::new(root)),
+            Some(GuardErrorKind::FilesystemRoot)
+        );
+        let dotted = if cfg!(windows) {
+            r"C:\Temp\.."
+        } else {
+            "/tmp/.."
+        };
+        assert_eq!(
+            blocked(&guard, Path::new(dotted)),
+            Some(GuardErrorKind::FilesystemRoot)
+        );
+    }
+
+    #[test]
+    fn home_and_system_directories_are_blocked() {
+        let dir = scratch_dir("protected");
+        let home = dir.join("home").join("user");
+        let system = dir.join("system").join("etc");
+        let guard = guard_with(None, Some(home.clone()), vec![system.clone()]);
+
+        assert_eq!(blocked(&guard, &home), Some(GuardErrorKind::HomeDirectory));
+        assert_eq!(
+            blocked(&guard, &dir.join("home")),
+            Some(GuardErrorKind::HomeDirectory)
+        );
+        assert_eq!(
+            blocked(&guard, &system),
+            Some(GuardErrorKind::SystemDirectory)
+        );
+        assert_eq!(
+            blocked(&guard, &dir.join("system")),
+            Some(GuardErrorKind::SystemDirectory)
+        );
+        // Внутри защищённых папок решают разрешённые папки плагина, а запись — `FsWrite`.
+        assert!(guard.check_write(&home.join("report.txt")).is_ok());
+        assert!(guard.check_write(&system.join("hosts")).is_ok());
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn detected_directories_are_blocked() {
+        let env = Environment::default();
+        let guard = PathGuard::new(&env);
+        if let Some(home) = &env.home_dir {
+            assert_eq!(blocked(&guard, home), Some(GuardErrorKind::HomeDirectory));
+        }
+        assert!(!env.system_dirs.is_empty());
+        for dir in env.system_dirs.iter().filter(|dir| !is_fs_root(dir)) {
+            let kind = blocked(&guard, dir);
+            assert!(
+                matches!(
+                    kind,
+                    Some(GuardErrorKind::SystemDirectory | GuardErrorKind::HomeDirectory)
+                ),
+                "{} → {kind:?}",
+                dir.display()
+            );
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn ancestors_of_system_directories_are_blocked() {
+        let guard = guard_with(None, None, vec![PathBuf::from("/etc/ssh")]);
+
+        assert_eq!(
+            blocked(&guard, Path::new("/etc")),
+            Some(GuardErrorKind::SystemDirectory)
+        );
+        assert_eq!(
+            blocked(&guard, Path::new("/usr/share/../../etc")),
+            Some(GuardErrorKind::SystemDirectory)
+        );
+    }
+
+    #[test]
+    fn app_data_is_blocked_as_ancestor_and_descendant() {
+        let dir = scratch_dir("app-data");
+        let app_data = dir.join("allowed").join("data");
+        fs::create_dir_all(&app_data).unwrap();
+        let guard = guard_for(&dir, Some(app_data.clone()));
+
+        assert_eq!(
+            blocked(&guard, &app_data),
+            Some(GuardErrorKind::AppDataDirectory)
+        );
+        assert_eq!(
+            blocked(&guard, &app_data.join("history.json")),
+            Some(GuardErrorKind::AppDataDirectory)
+        );
+        assert_eq!(
+            blocked(&guard, &dir.join("allowed")),
+            Some(GuardErrorKind::AppDataDirectory)
+        );
+        assert!(
+            guard
+                .check_delete(&dir.join("allowed").join("cache"))
+                .is_ok()
+        );
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn parent_segments_cannot_escape_allowed_root() {
+        let dir = scratch_dir("escape");
+        fs::create_dir_all(dir.join("outside")).unwrap();
+        let guard = guard_for(&dir, None);
+
+        assert!(
+            guard
+                .check_delete(&dir.join("allowed").join("file.tmp"))
+                .is_ok()
+        );
+        assert_eq!(
+            blocked(&guard, &dir.join("allowed").join("..").join("outside")),
+            Some(GuardErrorKind::OutsideAllowedRoots)
+        );
+        assert_eq!(
+            blocked(
+                &guard,
+                &dir.join("allowed").join("sub").join("..").join("..")
+            ),
+            Some(GuardErrorKind::OutsideAllowedRoots)
+        );
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn allowed_root_itself_is_blocked() {
+        let dir = scratch_dir("root-itself");
+        let guard = guard_for(&dir, None);
+
+        assert_eq!(
+            blocked(&guard, &dir.join("allowed")),
+            Some(GuardErrorKind::OutsideAllowedRoots)
+        );
+        assert_eq!(
+            blocked(&guard, &dir.join("allowed").join("sub").join("..")),
+            Some(GuardErrorKind::OutsideAllowedRoots)
+        );
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn writes_share_protected_paths_but_not_allowed_roots() {
+        let dir = scratch_dir("write");
+        let app_data = dir.join("data");
+        let guard = guard_for(&dir, Some(app_data.clone()));
+
+        let err = guard
+            .check_write(&app_data.join("config.json"))
+            .unwrap_err();
+        assert_eq!(err.operation, GuardOperation::Write);
+        assert_eq!(err.kind, GuardErrorKind::AppDataDirectory);
+        assert_eq!(
+            guard.check_write(Path::new("report.txt")).unwrap_err().kind,
+            GuardErrorKind::RelativePath
+        );
+        assert!(
+            guard
+                .check_write(&dir.join("outside").join("report.txt"))
+                .is_ok()
+        );
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn roots_outside_absolute_paths_are_ignored() {
+        let mut guard = guard_with(None, None, Vec::new());
+        let root = if cfg!(windows) { r"C:\" } else { "/" };
+        guard.set_allowed_roots(vec![PathBuf::from("relative"), PathBuf::from(root)]);
+
+        assert!(guard.allowed_roots.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinked_parent_is_resolved() {
+        let dir = scratch_dir("symlink");
+        fs::create_dir_all(dir.join("outside")).unwrap();
+        let guard = guard_for(&dir, None);
+        let link = dir.join("allowed").join("link");
+        std::os::unix::fs::symlink(dir.join("outside"), &link).unwrap();
+
+        assert_eq!(
+            blocked(&guard, &link.join("file.tmp")),
+            Some(GuardErrorKind::OutsideAllowedRoots)
+        );
+        // Сама ссылка удаляется без перехода по ней.
+        assert!(guard.check_delete(&link).is_ok());
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn verbatim_prefix_matches_plain_drive() {
+        assert_eq!(prefix_key(std::ffi::OsStr::new(r"\\?\C:")), "C:");
+        assert_eq!(prefix_key(std::ffi::OsStr::new("C:")), "C:");
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn windows_paths_compare_case_insensitively() {
+        assert!(is_within(
+            Path::new(r"\\?\C:\Users\Test\AppData\Local\Temp\x"),
+            Path::new(r"c:\users\test\appdata\local\temp"),
+        ));
+        assert!(same_path(
+            Path::new(r"C:\Windows\System32"),
+            Path::new(r"c:\WINDOWS\system32"),
+        ));
+
+        let guard = guard_with(None, None, vec![PathBuf::from(r"C:\Windows")]);
+        assert_eq!(
+            blocked(&guard, Path::new(r"c:\WINDOWS")),
+            Some(GuardErrorKind::SystemDirectory)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unix_paths_are_case_sensitive() {
+        assert!(!same_path(
+            Path::new("/Data/Cache"),
+            Path::new("/data/cache")
+        ));
+        assert!(is_within(
+            Path::new("/data/cache/x"),
+            Path::new("/data/cache")
+        ));
+    }
+}
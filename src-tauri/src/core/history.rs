@@ -0,0 +1,77 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const HISTORY_FILE: &str = "history.jsonl";
+const HISTORY_LIMIT: usize = 500;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct HistoryEntry {
+    pub plugin_id: String,
+    pub plugin_name: String,
+    /// Unix-время начала запуска в секундах.
+    pub started_at: u64,
+    pub duration_ms: u64,
+    pub ok: bool,
+    pub message: String,
+    #[serde(default)]
+    pub panicked: bool,
+    #[serde(default)]
+    pub timed_out: bool,
+    #[serde(default)]
+    pub host_crashed: bool,
+}
+
+pub fn now_unix() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+fn history_path(data_dir: &Path) -> PathBuf {
+    data_dir.join(HISTORY_FILE)
+}
+
+pub fn record(data_dir: &Path, entry: &HistoryEntry) -> Result<(), String> {
+    fs::create_dir_all(data_dir).map_err(|e| format!("Ошибка создания папки истории: {e}"))?;
+    let line = serde_json::to_string(entry).map_err(|e| format!("Ошибка записи истории: {e}"))?;
+    let path = history_path(data_dir);
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .map_err(|e| format!("Ошибка открытия истории: {e}"))?;
+    writeln!(file, "{line}").map_err(|e| format!("Ошибка записи истории: {e}"))?;
+    drop(file);
+
+    let entries = load(data_dir);
+    if entries.len() > HISTORY_LIMIT {
+        let keep = &entries[entries.len() - HISTORY_LIMIT..];
+        let mut content = String::new();
+        for entry in keep {
+            if let Ok(line) = serde_json::to_string(entry) {
+                content.push_str(&line);
+                content.push('\n');
+            }
+        }
+        fs::write(&path, content).map_err(|e| format!("Ошибка записи истории: {e}"))?;
+    }
+    Ok(())
+}
+
+/// Записи от старых к новым. Повреждённые строки пропускаются.
+pub fn load(data_dir: &Path) -> Vec<HistoryEntry> {
+    let content = match fs::read_to_string(history_path(data_dir)) {
+        Ok(content) => content,
+        Err(_) => return Vec::new(),
+    };
+    content
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
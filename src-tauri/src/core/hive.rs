@@ -0,0 +1,379 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+pub const REG_NONE: u32 = 0;
+pub const REG_SZ: u32 = 1;
+pub const REG_EXPAND_SZ: u32 = 2;
+pub const REG_BINARY: u32 = 3;
+pub const REG_DWORD: u32 = 4;
+pub const REG_MULTI_SZ: u32 = 7;
+pub const REG_QWORD: u32 = 11;
+
+/// Корневой раздел реестра.
+#[derive(
+    Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default,
+)]
+pub enum Hive {
+    #[serde(rename = "HKCR", alias = "HKEY_CLASSES_ROOT")]
+    ClassesRoot,
+    #[default]
+    #[serde(rename = "HKCU", alias = "HKEY_CURRENT_USER")]
+    CurrentUser,
+    #[serde(rename = "HKLM", alias = "HKEY_LOCAL_MACHINE")]
+    LocalMachine,
+    #[serde(rename = "HKU", alias = "HKEY_USERS")]
+    Users,
+    #[serde(rename = "HKCC", alias = "HKEY_CURRENT_CONFIG")]
+    CurrentConfig,
+}
+
+impl Hive {
+    pub const ALL: [Hive; 5] = [
+        Hive::ClassesRoot,
+        Hive::CurrentUser,
+        Hive::LocalMachine,
+        Hive::Users,
+        Hive::CurrentConfig,
+    ];
+
+    pub fn short_name(self) -> &'static str {
+        match self {
+            Hive::ClassesRoot => "HKCR",
+            Hive::CurrentUser => "HKCU",
+            Hive::LocalMachine => "HKLM",
+            Hive::Users => "HKU",
+            Hive::CurrentConfig => "HKCC",
+        }
+    }
+
+    pub fn full_name(self) -> &'static str {
+        match self {
+            Hive::ClassesRoot => "HKEY_CLASSES_ROOT",
+            Hive::CurrentUser => "HKEY_CURRENT_USER",
+            Hive::LocalMachine => "HKEY_LOCAL_MACHINE",
+            Hive::Users => "HKEY_USERS",
+            Hive::CurrentConfig => "HKEY_CURRENT_CONFIG",
+        }
+    }
+
+    /// `HKLM` или `HKEY_LOCAL_MACHINE` в любом регистре.
+    pub fn parse(name: &str) -> Option<Hive> {
+        Hive::ALL.into_iter().find(|hive| {
+            name.eq_ignore_ascii_case(hive.short_name())
+                || name.eq_ignore_ascii_case(hive.full_name())
+        })
+    }
+}
+
+/// Какую половину реестра видеть на 64-битной Windows. У 32-битной `Bits32` своя копия
+/// `HKLM\Software` (`WOW6432Node`), `Native` — вид текущего процесса.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub enum RegistryView {
+    #[default]
+    #[serde(rename = "native")]
+    Native,
+    #[serde(rename = "32")]
+    Bits32,
+    #[serde(rename = "64")]
+    Bits64,
+}
+
+/// Ключ реестра: раздел, путь внутри него и вид (32/64 бит).
+///
+/// Из строки собирается так: `HKLM\Software\Vendor` — ключ в HKLM,
+/// `Software\Vendor` без раздела — в HKCU.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct RegistryKey {
+    pub hive: Hive,
+    pub path: String,
+    #[serde(default)]
+    pub view: RegistryView,
+}
+
+impl RegistryKey {
+    pub fn new(hive: Hive, path: &str) -> Self {
+        Self {
+            hive,
+            path: path.trim_matches('\\').to_string(),
+            view: RegistryView::Native,
+        }
+    }
+
+    pub fn parse(text: &str) -> Self {
+        let text = text.trim().trim_matches('\\');
+        let (first, rest) = text.split_once('\\').unwrap_or((text, ""));
+        match Hive::parse(first) {
+            Some(hive) => Self::new(hive, rest),
+            None => Self::new(Hive::CurrentUser, text),
+        }
+    }
+
+    pub fn with_view(mut self, view: RegistryView) -> Self {
+        self.view = view;
+        self
+    }
+
+    pub fn join(&self, child: &str) -> Self {
+        let child = child.trim_matches('\\');
+        let path = match (self.path.is_empty(), child.is_empty()) {
+            (_, true) => self.path.clone(),
+            (true, false) => child.to_string(),
+            (false, false) => format!("{}\\{child}", self.path),
+        };
+        Self {
+            hive: self.hive,
+            path,
+            view: self.view,
+        }
+    }
+
+    /// Родитель ключа; у корня раздела родителя нет.
+    pub fn parent(&self) -> Option<Self> {
+        if self.path.is_empty() {
+            return None;
+        }
+        let path = self.path.rsplit_once('\\').map_or("", |(parent, _)| parent);
+        Some(Self::new(self.hive, path).with_view(self.view))
+    }
+
+    /// Последний компонент пути.
+    pub fn name(&self) -> &str {
+        self.path.rsplit('\\').next().unwrap_or_default()
+    }
+
+    /// Совпадает с `scope` или лежит внутри него. Регистр букв и вид не учитываются.
+    pub fn is_within(&self, scope: &RegistryKey) -> bool {
+        if self.hive != scope.hive {
+            return false;
+        }
+        let key = self.path.to_lowercase();
+        let scope = scope.path.to_lowercase();
+        scope.is_empty() || key == scope || key.starts_with(&format!("{scope}\\"))
+    }
+}
+
+impl fmt::Display for RegistryKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            f.write_str(self.hive.short_name())
+        } else {
+            write!(f, "{}\\{}", self.hive.short_name(), self.path)
+        }
+    }
+}
+
+impl From<&str> for RegistryKey {
+    fn from(text: &str) -> Self {
+        Self::parse(text)
+    }
+}
+
+impl From<&String> for RegistryKey {
+    fn from(text: &String) -> Self {
+        Self::parse(text)
+    }
+}
+
+impl From<String> for RegistryKey {
+    fn from(text: String) -> Self {
+        Self::parse(&text)
+    }
+}
+
+impl From<&RegistryKey> for RegistryKey {
+    fn from(key: &RegistryKey) -> Self {
+        key.clone()
+    }
+}
+
+/// Значение реестра с типом.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum RegistryValue {
+    /// `REG_SZ`
+    String(String),
+    /// `REG_EXPAND_SZ`: строка с `%VAR%`, которые раскрывает читающая программа.
+    ExpandString(String),
+    /// `REG_MULTI_SZ`
+    MultiString(Vec<String>),
+    /// `REG_DWORD`
+    Dword(u32),
+    /// `REG_QWORD`
+    Qword(u64),
+    /// `REG_BINARY`
+    Binary(Vec<u8>),
+    /// Остальные типы (`REG_NONE`, `REG_LINK`, ...) как есть.
+    Other { kind: u32, data: Vec<u8> },
+}
+
+impl RegistryValue {
+    /// Номер типа `REG_*`.
+    pub fn kind(&self) -> u32 {
+        match self {
+            RegistryValue::String(_) => REG_SZ,
+            RegistryValue::ExpandString(_) => REG_EXPAND_SZ,
+            RegistryValue::MultiString(_) => REG_MULTI_SZ,
+            RegistryValue::Dword(_) => REG_DWORD,
+            RegistryValue::Qword(_) => REG_QWORD,
+            RegistryValue::Binary(_) => REG_BINARY,
+            RegistryValue::Other { kind, .. } => *kind,
+        }
+    }
+
+    /// Название типа для журнала: `REG_DWORD`.
+    pub fn kind_name(&self) -> String {
+        match self.kind() {
+            REG_NONE => "REG_NONE".to_string(),
+            REG_SZ => "REG_SZ".to_string(),
+            REG_EXPAND_SZ => "REG_EXPAND_SZ".to_string(),
+            REG_BINARY => "REG_BINARY".to_string(),
+            REG_DWORD => "REG_DWORD".to_string(),
+            REG_MULTI_SZ => "REG_MULTI_SZ".to_string(),
+            REG_QWORD => "REG_QWORD".to_string(),
+            other => format!("тип {other}"),
+        }
+    }
+
+    /// Данные в том виде, в каком их хранит Windows: строки в UTF-16LE с завершающим нулём,
+    /// числа в little-endian.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            RegistryValue::String(text) | RegistryValue::ExpandString(text) => {
+                utf16_bytes(text.encode_utf16().chain([0]))
+            }
+            RegistryValue::MultiString(lines) => utf16_bytes(
+                lines
+                    .iter()
+                    .flat_map(|line| line.encode_utf16().chain([0]))
+                    .chain([0]),
+            ),
+            RegistryValue::Dword(value) => value.to_le_bytes().to_vec(),
+            RegistryValue::Qword(value) => value.to_le_bytes().to_vec(),
+            RegistryValue::Binary(data) | RegistryValue::Other { data, .. } => data.clone(),
+        }
+    }
+
+    /// Обратное к `to_bytes`. Данные неподходящей длины сохраняются как `Other`.
+    pub fn from_bytes(kind: u32, data: &[u8]) -> Self {
+        let other = || RegistryValue::Other {
+            kind,
+            data: data.to_vec(),
+        };
+        match kind {
+            REG_SZ | REG_EXPAND_SZ => {
+                let text = utf16_text(data);
+                let text = text.split('\0').next().unwrap_or_default().to_string();
+                if kind == REG_SZ {
+                    RegistryValue::String(text)
+                } else {
+                    RegistryValue::ExpandString(text)
+                }
+            }
+            REG_MULTI_SZ => {
+                let text = utf16_text(data);
+                let text = text.trim_end_matches('\0');
+                let lines = if text.is_empty() {
+                    Vec::new()
+                } else {
+                    text.split('\0').map(str::to_string).collect()
+                };
+                RegistryValue::MultiString(lines)
+            }
+            REG_DWORD => match <[u8; 4]>::try_from(data) {
+                Ok(bytes) => RegistryValue::Dword(u32::from_le_bytes(bytes)),
+                Err(_) => other(),
+            },
+            REG_QWORD => match <[u8; 8]>::try_from(data) {
+                Ok(bytes) => RegistryValue::Qword(u64::from_le_bytes(bytes)),
+                Err(_) => other(),
+            },
+            REG_BINARY => RegistryValue::Binary(data.to_vec()),
+            _ => other(),
+        }
+    }
+}
+
+impl fmt::Display for RegistryValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegistryValue::String(text) | RegistryValue::ExpandString(text) => f.write_str(text),
+            RegistryValue::MultiString(lines) => f.write_str(&lines.join("; ")),
+            RegistryValue::Dword(value) => write!(f, "{value} (0x{value:08x})"),
+            RegistryValue::Qword(value) => write!(f, "{value} (0x{value:016x})"),
+            RegistryValue::Binary(data) | RegistryValue::Other { data, .. } => {
+                let bytes: Vec<String> = data.iter().map(|byte| format!("{byte:02x}")).collect();
+                f.write_str(&bytes.join(" "))
+            }
+        }
+    }
+}
+
+fn utf16_bytes(units: impl Iterator<Item = u16>) -> Vec<u8> {
+    units.flat_map(u16::to_le_bytes).collect()
+}
+
+fn utf16_text(data: &[u8]) -> String {
+    let units: Vec<u16> = data
+        .chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_without_hive_is_in_current_user() {
+        let key = RegistryKey::parse(r"Software\Vendor\");
+        assert_eq!(key.hive, Hive::CurrentUser);
+        assert_eq!(key.path, r"Software\Vendor");
+        let key = RegistryKey::parse(r"HKEY_LOCAL_MACHINE\Software");
+        assert_eq!(key.to_string(), r"HKLM\Software");
+        assert_eq!(RegistryKey::parse("hklm").path, "");
+    }
+
+    #[test]
+    fn scope_matches_whole_path_components() {
+        let scope = RegistryKey::parse(r"HKLM\Software\Vendor");
+        assert!(RegistryKey::parse(r"HKLM\software\vendor\App").is_within(&scope));
+        assert!(!RegistryKey::parse(r"HKLM\Software\VendorX").is_within(&scope));
+        assert!(!RegistryKey::parse(r"Software\Vendor").is_within(&scope));
+        assert!(RegistryKey::parse(r"HKLM\System").is_within(&RegistryKey::parse("HKLM")));
+    }
+
+    #[test]
+    fn values_round_trip_through_bytes() {
+        let values = [
+            RegistryValue::String("Привет".to_string()),
+            RegistryValue::ExpandString(r"%SystemRoot%\Temp".to_string()),
+            RegistryValue::MultiString(vec!["a".to_string(), "b".to_string()]),
+            RegistryValue::MultiString(Vec::new()),
+            RegistryValue::Dword(0xdead_beef),
+            RegistryValue::Qword(u64::MAX - 1),
+            RegistryValue::Binary(vec![0, 1, 254]),
+        ];
+        for value in values {
+            assert_eq!(
+                RegistryValue::from_bytes(value.kind(), &value.to_bytes()),
+                value
+            );
+        }
+        assert_eq!(
+            RegistryValue::String("a".to_string()).to_bytes(),
+            [b'a', 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn wrong_length_number_is_kept_as_is() {
+        assert_eq!(
+            RegistryValue::from_bytes(REG_DWORD, &[1, 2]),
+            RegistryValue::Other {
+                kind: REG_DWORD,
+                data: vec![1, 2]
+            }
+        );
+    }
+}
@@ -0,0 +1,755 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    ffi::OsString,
+    io::{self, BufRead, BufReader, Read, Write},
+    path::PathBuf,
+    process::{Child, Command, Stdio},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+        mpsc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::core::api::CancelToken;
+use crate::core::environment::Environment;
+use crate::core::error::CoreError;
+use crate::core::history;
+use crate::core::logger::{LogEntry, Logger, RunResult};
+use crate::core::plugin::{
+    Execution, PluginPreview, execute_plugin, plugin_defaults, preview_plugin,
+};
+use crate::core::privilege::is_elevated;
+
+/// Аргумент, с которым основной бинарник запускается как процесс-хост плагинов.
+pub const HOST_FLAG: &str = "--plugin-host";
+pub const DATA_DIR_FLAG: &str = "--data-dir";
+pub const TEMP_DIR_FLAG: &str = "--temp-dir";
+pub const LOCALE_FLAG: &str = "--locale";
+pub const HOME_DIR_FLAG: &str = "--home-dir";
+/// Повторяется для каждой системной папки.
+pub const SYSTEM_DIR_FLAG: &str = "--system-dir";
+pub const PROTOCOL_VERSION: u32 = 1;
+/// Как часто `HostClient::run` проверяет токен отмены.
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Запрос GUI к хосту. Одна JSON-строка на запрос.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum HostRequest {
+    Defaults { id: String },
+    Preview { id: String },
+    Run { id: String, settings: Value },
+    Cancel,
+    Shutdown,
+}
+
+/// Ответ хоста. Во время `Run` приходят `Log`, затем `Finished` или `Error`.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum HostResponse {
+    Ready { version: u32 },
+    Log { entry: LogEntry },
+    Defaults { value: Option<Value> },
+    Preview { preview: Option<PluginPreview> },
+    Finished { execution: Execution },
+    Error { kind: String, message: String },
+}
+
+impl HostResponse {
+    fn from_error(err: CoreError) -> Self {
+        HostResponse::Error {
+            kind: err.kind().to_string(),
+            message: err.to_string(),
+        }
+    }
+}
+
+type SharedOut = Arc<Mutex<Box<dyn Write + Send>>>;
+/// Канал запросов к хосту: stdin дочернего процесса или, в тестах, конец канала в памяти.
+type SharedIn = Arc<Mutex<Box<dyn Write + Send>>>;
+
+fn send(out: &SharedOut, response: &HostResponse) {
+    if let (Ok(line), Ok(mut out)) = (serde_json::to_string(response), out.lock()) {
+        let _ = writeln!(out, "{line}");
+        let _ = out.flush();
+    }
+}
+
+/// Окружение хоста из аргументов. Передаётся явно: pkexec и sudo сбрасывают переменные окружения.
+/// Хост с повышенными правами загружает только встроенные плагины: папку данных ему
+/// передаёт процесс пользователя, и её содержимому root доверять не может.
+pub fn environment_from_args(args: impl IntoIterator<Item = OsString>) -> Environment {
+    let mut env = Environment::detect().with_data_dir(None);
+    env.builtin_only = is_elevated();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let flags = [
+            DATA_DIR_FLAG,
+            TEMP_DIR_FLAG,
+            LOCALE_FLAG,
+            HOME_DIR_FLAG,
+            SYSTEM_DIR_FLAG,
+        ];
+        let Some(value) = flags
+            .iter()
+            .any(|flag| arg == *flag)
+            .then(|| args.next())
+            .flatten()
+        else {
+            continue;
+        };
+        if arg == DATA_DIR_FLAG {
+            env.data_dir = Some(PathBuf::from(value));
+        } else if arg == TEMP_DIR_FLAG {
+            env.temp_dir = PathBuf::from(value);
+        } else if arg == HOME_DIR_FLAG {
+            env.home_dir = Some(PathBuf::from(value));
+        } else if arg == SYSTEM_DIR_FLAG {
+            // Системные папки хоста остаются защищёнными, папки пользователя добавляются к ним.
+            env.system_dirs.push(PathBuf::from(value));
+        } else {
+            env.locale = value.to_string_lossy().to_string();
+        }
+    }
+    env
+}
+
+fn environment_args(env: &Environment) -> Vec<OsString> {
+    let mut args = Vec::new();
+    if let Some(dir) = &env.data_dir {
+        args.push(DATA_DIR_FLAG.into());
+        args.push(dir.into());
+    }
+    args.push(TEMP_DIR_FLAG.into());
+    args.push(env.temp_dir.clone().into());
+    args.push(LOCALE_FLAG.into());
+    args.push(env.locale.clone().into());
+    if let Some(dir) = &env.home_dir {
+        args.push(HOME_DIR_FLAG.into());
+        args.push(dir.into());
+    }
+    for dir in &env.system_dirs {
+        args.push(SYSTEM_DIR_FLAG.into());
+        args.push(dir.into());
+    }
+    args
+}
+
+/// Цикл процесса-хоста: читает запросы из stdin, пишет ответы в stdout.
+pub fn serve(env: Environment) -> i32 {
+    serve_io(env, BufReader::new(io::stdin()), io::stdout())
+}
+
+/// Цикл хоста над произвольными потоками: по JSON-строке на запрос и на ответ.
+/// `Cancel` обрабатывается сразу, даже пока выполняется `Run`.
+fn serve_io(
+    env: Environment,
+    input: impl BufRead + Send + 'static,
+    output: impl Write + Send + 'static,
+) -> i32 {
+    let out: SharedOut = Arc::new(Mutex::new(Box::new(output)));
+    let cancel = Arc::new(Mutex::new(CancelToken::default()));
+    let (tx, rx) = mpsc::channel::<HostRequest>();
+
+    {
+        let cancel = Arc::clone(&cancel);
+        thread::spawn(move || {
+            for line in input.lines() {
+                let Ok(line) = line else { break };
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match serde_json::from_str::<HostRequest>(&line) {
+                    Ok(HostRequest::Cancel) => {
+                        if let Ok(token) = cancel.lock() {
+                            token.cancel();
+                        }
+                    }
+                    Ok(request) => {
+                        if tx.send(request).is_err() {
+                            break;
+                        }
+                    }
+                    Err(_) => continue,
+                }
+            }
+            let _ = tx.send(HostRequest::Shutdown);
+        });
+    }
+
+    send(
+        &out,
+        &HostResponse::Ready {
+            version: PROTOCOL_VERSION,
+        },
+    );
+
+    while let Ok(request) = rx.recv() {
+        let response = match request {
+            HostRequest::Defaults { id } => match plugin_defaults(&env, &id) {
+                Ok(value) => HostResponse::Defaults { value },
+                Err(err) => HostResponse::from_error(err),
+            },
+            HostRequest::Preview { id } => match preview_plugin(&env, &id) {
+                Ok(preview) => HostResponse::Preview { preview },
+                Err(err) => HostResponse::from_error(err),
+            },
+            HostRequest::Run { id, settings } => {
+                let token = CancelToken::default();
+                if let Ok(mut current) = cancel.lock() {
+                    *current = token.clone();
+                }
+                let log_out = Arc::clone(&out);
+                let logger = Logger::with_sink(move |entry| {
+                    send(
+                        &log_out,
+                        &HostResponse::Log {
+                            entry: entry.clone(),
+                        },
+                    )
+                });
+                match execute_plugin(&env, &id, settings, logger, token) {
+                    Ok(execution) => HostResponse::Finished { execution },
+                    Err(err) => HostResponse::from_error(err),
+                }
+            }
+            HostRequest::Cancel => continue,
+            HostRequest::Shutdown => break,
+        };
+        send(&out, &response);
+    }
+
+    0
+}
+
+/// Дочерний процесс-хост со стороны GUI.
+pub struct HostClient {
+    /// `None`, если хост подключён без процесса (в тестах).
+    child: Option<Child>,
+    stdin: SharedIn,
+    stdout: BufReader<Box<dyn Read + Send>>,
+}
+
+/// Отменяет запуск из другого потока, пока `HostClient::run` ждёт ответа.
+struct HostCanceller {
+    stdin: SharedIn,
+}
+
+impl HostCanceller {
+    fn cancel(&self) -> Result<(), String> {
+        write_request(&self.stdin, &HostRequest::Cancel)
+    }
+
+    /// Передаёт хосту отмену `cancel`, пока не выставлен `finished`.
+    fn watch(self, cancel: CancelToken, finished: Arc<AtomicBool>) -> thread::JoinHandle<()> {
+        thread::spawn(move || {
+            while !finished.load(Ordering::SeqCst) {
+                if cancel.is_cancelled() {
+                    let _ = self.cancel();
+                    return;
+                }
+                thread::sleep(CANCEL_POLL_INTERVAL);
+            }
+        })
+    }
+}
+
+impl HostClient {
+    /// Запускает текущий бинарник в режиме хоста. С `elevated` — через pkexec/sudo.
+    pub fn spawn(env: &Environment, elevated: bool) -> Result<Self, CoreError> {
+        let exe = std::env::current_exe()
+            .map_err(|e| CoreError::Plugin(format!("Не удалось найти исполняемый файл: {e}")))?;
+
+        let mut command = if elevated {
+            elevated_command(&exe)?
+        } else {
+            Command::new(&exe)
+        };
+        if !elevated {
+            command.arg(HOST_FLAG);
+        }
+        command.args(environment_args(env));
+
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .map_err(|e| CoreError::Plugin(format!("Не удалось запустить хост плагинов: {e}")))?;
+
+        let stdin = child.stdin.take().ok_or_else(host_closed)?;
+        let stdout = child.stdout.take().ok_or_else(host_closed)?;
+        Self::connect(Some(child), stdin, stdout)
+    }
+
+    /// Ждёт от хоста `Ready` с поддерживаемой версией протокола.
+    fn connect(
+        child: Option<Child>,
+        stdin: impl Write + Send + 'static,
+        stdout: impl Read + Send + 'static,
+    ) -> Result<Self, CoreError> {
+        let mut client = Self {
+            child,
+            stdin: Arc::new(Mutex::new(Box::new(stdin))),
+            stdout: BufReader::new(Box::new(stdout)),
+        };
+
+        match client.read_response()? {
+            HostResponse::Ready { version } if version == PROTOCOL_VERSION => Ok(client),
+            HostResponse::Ready { version } => Err(CoreError::Plugin(format!(
+                "Несовместимая версия хоста плагинов: {version}"
+            ))),
+            _ => Err(protocol_error()),
+        }
+    }
+
+    fn canceller(&self) -> HostCanceller {
+        HostCanceller {
+            stdin: Arc::clone(&self.stdin),
+        }
+    }
+
+    pub fn defaults(&mut self, id: &str) -> Result<Option<Value>, CoreError> {
+        self.request(&HostRequest::Defaults { id: id.to_string() })?;
+        match self.read_response()? {
+            HostResponse::Defaults { value } => Ok(value),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    pub fn preview(&mut self, id: &str) -> Result<Option<PluginPreview>, CoreError> {
+        self.request(&HostRequest::Preview { id: id.to_string() })?;
+        match self.read_response()? {
+            HostResponse::Preview { preview } => Ok(preview),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    /// Запускает плагин в хосте. Записи лога попадают в `logger` по мере поступления,
+    /// как при локальном `execute_plugin`; если хост вернул ошибку или завершился
+    /// посреди запуска, они не теряются и возвращаются в неудачном `RunResult`.
+    /// Отмена `cancel` передаётся хосту.
+    pub fn run(
+        &mut self,
+        id: &str,
+        settings: Value,
+        mut logger: Logger,
+        cancel: &CancelToken,
+    ) -> Result<Execution, CoreError> {
+        self.request(&HostRequest::Run {
+            id: id.to_string(),
+            settings,
+        })?;
+        let started_at = history::now_unix();
+        let started = Instant::now();
+        let finished = Arc::new(AtomicBool::new(false));
+        let watcher = self
+            .canceller()
+            .watch(cancel.clone(), Arc::clone(&finished));
+
+        let mut streamed = false;
+        let outcome = loop {
+            match self.read_response() {
+                Ok(HostResponse::Log { entry }) => {
+                    streamed = true;
+                    logger.append(vec![entry]);
+                }
+                Ok(HostResponse::Finished { execution }) => break Ok(execution),
+                Ok(other) => break Err((unexpected(other), false)),
+                Err(err) => break Err((err, true)),
+            }
+        };
+        finished.store(true, Ordering::SeqCst);
+        let _ = watcher.join();
+
+        let (err, host_crashed) = match outcome {
+            Ok(execution) => return Ok(execution),
+            // Ошибка до начала запуска (плагин не найден, не хватает утилит) — как у `execute_plugin`.
+            Err((err, false)) if !streamed => return Err(err),
+            Err(failure) => failure,
+        };
+        let message = err.to_string();
+        logger.error(message.clone());
+        Ok(Execution {
+            plugin_id: id.to_string(),
+            plugin_name: id.to_string(),
+            started_at,
+            duration_ms: started.elapsed().as_millis() as u64,
+            panicked: false,
+            timed_out: false,
+            host_crashed,
+            result: RunResult {
+                ok: false,
+                message,
+                logs: logger.into_logs(),
+                steps: Vec::new(),
+                post_actions: Vec::new(),
+            },
+        })
+    }
+
+    fn request(&self, request: &HostRequest) -> Result<(), CoreError> {
+        write_request(&self.stdin, request).map_err(CoreError::Plugin)
+    }
+
+    fn read_response(&mut self) -> Result<HostResponse, CoreError> {
+        loop {
+            let mut line = String::new();
+            let read = self
+                .stdout
+                .read_line(&mut line)
+                .map_err(|e| CoreError::Plugin(format!("Ошибка чтения ответа хоста: {e}")))?;
+            if read == 0 {
+                return Err(host_closed());
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+            return serde_json::from_str(&line)
+                .map_err(|e| CoreError::Plugin(format!("Некорректный ответ хоста: {e}")));
+        }
+    }
+}
+
+impl Drop for HostClient {
+    fn drop(&mut self) {
+        let _ = write_request(&self.stdin, &HostRequest::Shutdown);
+        let Some(child) = &mut self.child else {
+            return;
+        };
+        if !matches!(child.try_wait(), Ok(Some(_))) {
+            let _ = child.kill();
+        }
+        let _ = child.wait();
+    }
+}
+
+fn write_request(
+    stdin: &Mutex<Box<dyn Write + Send>>,
+    request: &HostRequest,
+) -> Result<(), String> {
+    let line = serde_json::to_string(request).map_err(|e| format!("Ошибка запроса: {e}"))?;
+    let mut stdin = stdin
+        .lock()
+        .map_err(|_| "Канал хоста недоступен.".to_string())?;
+    writeln!(stdin, "{line}")
+        .and_then(|_| stdin.flush())
+        .map_err(|e| format!("Ошибка отправки запроса хосту: {e}"))
+}
+
+fn host_closed() -> CoreError {
+    CoreError::Plugin("Хост плагинов неожиданно завершился.".to_string())
+}
+
+fn protocol_error() -> CoreError {
+    CoreError::Plugin("Хост плагинов ответил не по протоколу.".to_string())
+}
+
+fn unexpected(response: HostResponse) -> CoreError {
+    match response {
+        HostResponse::Error { message, .. } => CoreError::Plugin(message),
+        _ => protocol_error(),
+    }
+}
+
+/// pkexec спросит пароль в собственном окне. sudo без терминала пароль не спросит,
+/// поэтому годится, только если настроен без пароля: это проверяет `sudo -n true`.
+#[cfg(unix)]
+fn elevation_tool() -> Option<&'static str> {
+    if which("pkexec") {
+        return Some("pkexec");
+    }
+    let sudo_ready = which("sudo")
+        && Command::new("sudo")
+            .args(["-n", "true"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success());
+    sudo_ready.then_some("sudo")
+}
+
+#[cfg(unix)]
+fn elevated_command(exe: &std::path::Path) -> Result<Command, CoreError> {
+    let tool = elevation_tool().ok_or_else(|| {
+        CoreError::Plugin(
+            "Для запуска с правами root нужен pkexec или sudo без пароля.".to_string(),
+        )
+    })?;
+    let mut command = Command::new(tool);
+    if tool == "sudo" {
+        command.arg("-n");
+    }
+    command.arg(exe).arg(HOST_FLAG);
+    Ok(command)
+}
+
+#[cfg(not(unix))]
+fn elevated_command(_exe: &std::path::Path) -> Result<Command, CoreError> {
+    Err(CoreError::Plugin(
+        "Запуск хоста с повышенными правами на Windows не поддерживается. Запустите LamerHelper от имени администратора.".to_string(),
+    ))
+}
+
+/// Можно ли запустить хост с повышенными правами. Если нельзя, плагин запускается
+/// как обычно и сам сообщает, каких прав не хватает.
+#[cfg(unix)]
+pub fn can_elevate() -> bool {
+    elevation_tool().is_some()
+}
+/// UAC (runas) не позволяет перенаправить stdin/stdout запущенного процесса.
+#[cfg(not(unix))]
+pub fn can_elevate() -> bool {
+    false
+}
+
+#[cfg(unix)]
+fn which(tool: &str) -> bool {
+    std::env::var_os("PATH")
+        .map(|paths| std::env::split_paths(&paths).any(|dir| dir.join(tool).is_file()))
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::config::{self, AppConfig};
+    use std::{fs, io::pipe};
+
+    /// Пишет в журнал и крутится, пока запуск не отменят.
+    const WAIT_SCRIPT: &str = r#"
+fn meta() {
+    #{ id: "host_wait", name: "Ожидание", description: "", category: "Тест" }
+}
+
+fn defaults() {
+    #{ rounds: 3 }
+}
+
+fn run(settings) {
+    info("начало");
+    loop {}
+}
+"#;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("lamerhelper-host-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Папка данных с внешним плагином `host_wait`, которому разрешено загружаться.
+    fn environment(name: &str) -> Environment {
+        let dir = scratch_dir(name);
+        fs::create_dir_all(dir.join("plugins")).unwrap();
+        fs::write(dir.join("plugins/host_wait.rhai"), WAIT_SCRIPT).unwrap();
+        let mut app_config = AppConfig::default();
+        app_config.packages.allow_unsigned = true;
+        config::save(&dir, &app_config).unwrap();
+        Environment::default().with_data_dir(Some(dir))
+    }
+
+    /// `serve_io` в отдельном потоке, соединённый с клиентом каналами в памяти.
+    fn connect(env: Environment) -> HostClient {
+        let (host_in, client_out) = pipe().unwrap();
+        let (client_in, host_out) = pipe().unwrap();
+        thread::spawn(move || serve_io(env, BufReader::new(host_in), host_out));
+        HostClient::connect(None, client_out, client_in).unwrap()
+    }
+
+    /// Поддельный хост: отвечает заготовленными строками на первый запрос и закрывает канал.
+    fn scripted_host(lines: Vec<String>) -> HostClient {
+        let (host_in, client_out) = pipe().unwrap();
+        let (client_in, mut host_out) = pipe().unwrap();
+        let ready = serde_json::to_string(&HostResponse::Ready {
+            version: PROTOCOL_VERSION,
+        })
+        .unwrap();
+        writeln!(host_out, "{ready}").unwrap();
+        thread::spawn(move || {
+            let mut request = String::new();
+            let _ = BufReader::new(host_in).read_line(&mut request);
+            for line in lines {
+                let _ = writeln!(host_out, "{line}");
+            }
+        });
+        HostClient::connect(None, client_out, client_in).unwrap()
+    }
+
+    fn log_line(message: &str) -> String {
+        let mut logger = Logger::new();
+        logger.info(message);
+        let entry = logger.into_logs().remove(0);
+        serde_json::to_string(&HostResponse::Log { entry }).unwrap()
+    }
+
+    fn error_line(message: &str) -> String {
+        serde_json::to_string(&HostResponse::Error {
+            kind: "plugin".to_string(),
+            message: message.to_string(),
+        })
+        .unwrap()
+    }
+
+    fn messages(logs: &[LogEntry]) -> Vec<&str> {
+        logs.iter().map(|entry| entry.message.as_str()).collect()
+    }
+
+    #[test]
+    fn serve_answers_json_lines_and_skips_garbage() {
+        let input = [
+            "",
+            "не json",
+            r#"{"type":"defaults","id":"host_wait"}"#,
+            r#"{"type":"preview","id":"missing"}"#,
+        ]
+        .join("\n");
+        let (mut output, host_out) = pipe().unwrap();
+        let env = environment("serve");
+        let code = serve_io(env, io::Cursor::new(input), host_out);
+        assert_eq!(code, 0);
+
+        let mut text = String::new();
+        output.read_to_string(&mut text).unwrap();
+        let responses: Vec<Value> = text
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(responses.len(), 3, "{text}");
+        assert_eq!(responses[0]["type"], "ready");
+        assert_eq!(responses[0]["version"], PROTOCOL_VERSION);
+        assert_eq!(responses[1]["type"], "defaults");
+        assert_eq!(responses[1]["value"]["rounds"], 3);
+        assert_eq!(responses[2]["type"], "error");
+        assert_eq!(responses[2]["kind"], "plugin_not_found");
+    }
+
+    #[test]
+    fn client_runs_and_cancels_through_the_host() {
+        let mut client = connect(environment("cancel"));
+        assert_eq!(
+            client.defaults("host_wait").unwrap(),
+            Some(serde_json::json!({ "rounds": 3 }))
+        );
+        assert!(client.preview("host_wait").unwrap().is_none());
+        assert!(client.defaults("missing").is_err());
+
+        // Отмена приходит, когда хост уже прислал запись, сделанную скриптом.
+        let cancel = CancelToken::default();
+        let token = cancel.clone();
+        let logger = Logger::with_sink(move |entry| {
+            if entry.message == "начало" {
+                token.cancel();
+            }
+        });
+        let execution = client
+            .run("host_wait", Value::Null, logger, &cancel)
+            .unwrap();
+        assert!(!execution.result.ok);
+        assert!(!execution.host_crashed);
+        assert_eq!(execution.result.message, "Отменено.");
+        assert!(messages(&execution.result.logs).contains(&"начало"));
+
+        // После отмены хост принимает следующие запросы.
+        let err = client
+            .run(
+                "missing",
+                Value::Null,
+                Logger::new(),
+                &CancelToken::default(),
+            )
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("missing"), "{err}");
+    }
+
+    #[test]
+    fn host_crash_keeps_streamed_logs() {
+        let mut client = scripted_host(vec![log_line("шаг 1")]);
+        let execution = client
+            .run("any", Value::Null, Logger::new(), &CancelToken::default())
+            .unwrap();
+        assert!(execution.host_crashed);
+        assert!(!execution.result.ok);
+        assert_eq!(
+            messages(&execution.result.logs),
+            ["шаг 1", "Хост плагинов неожиданно завершился."]
+        );
+    }
+
+    #[test]
+    fn host_error_after_logs_is_a_failed_run() {
+        let mut client = scripted_host(vec![log_line("шаг 1"), error_line("сломалось")]);
+        let execution = client
+            .run("any", Value::Null, Logger::new(), &CancelToken::default())
+            .unwrap();
+        assert!(!execution.host_crashed);
+        assert_eq!(execution.result.message, "сломалось");
+        assert_eq!(messages(&execution.result.logs), ["шаг 1", "сломалось"]);
+
+        let mut client = scripted_host(vec![error_line("не найден")]);
+        let err = client
+            .run("any", Value::Null, Logger::new(), &CancelToken::default())
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "не найден");
+    }
+
+    #[test]
+    fn rejects_other_protocol_versions() {
+        let (_host_in, client_out) = pipe().unwrap();
+        let (client_in, mut host_out) = pipe().unwrap();
+        writeln!(host_out, r#"{{"type":"ready","version":99}}"#).unwrap();
+        let err = HostClient::connect(None, client_out, client_in)
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "Несовместимая версия хоста плагинов: 99");
+    }
+
+    #[test]
+    fn builtin_only_host_ignores_the_plugins_folder() {
+        let mut env = environment("builtin");
+        env.builtin_only = true;
+        let mut client = connect(env);
+        assert!(client.defaults("host_wait").is_err());
+
+        let env = environment_from_args(
+            ["--plugin-host", "--data-dir", "/data", "--locale", "en-US"].map(OsString::from),
+        );
+        assert_eq!(env.data_dir, Some(PathBuf::from("/data")));
+        assert_eq!(env.locale, "en-US");
+        assert_eq!(env.builtin_only, is_elevated());
+    }
+
+    #[test]
+    fn protected_dirs_reach_the_host_through_arguments() {
+        let env = Environment {
+            data_dir: Some(PathBuf::from("/data")),
+            temp_dir: PathBuf::from("/scratch"),
+            home_dir: Some(PathBuf::from("/home/user")),
+            system_dirs: vec![PathBuf::from("/srv/vendor")],
+            ..Environment::default()
+        };
+        let mut args = vec![OsString::from(HOST_FLAG)];
+        args.extend(environment_args(&env));
+        let host_env = environment_from_args(args);
+
+        assert_eq!(host_env.data_dir, env.data_dir);
+        assert_eq!(host_env.temp_dir, env.temp_dir);
+        assert_eq!(host_env.home_dir, env.home_dir);
+        assert!(host_env.system_dirs.contains(&PathBuf::from("/srv/vendor")));
+        assert!(
+            Environment::detect()
+                .system_dirs
+                .iter()
+                .all(|dir| host_env.system_dirs.contains(dir))
+        );
+    }
+}
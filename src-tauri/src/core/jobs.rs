@@ -0,0 +1,234 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use crate::core::api::CancelToken;
+use crate::core::environment::Environment;
+use crate::core::error::CoreError;
+use crate::core::logger::{Logger, RunResult};
+use crate::core::plugin::{execute_plugin, record_execution};
+use crate::core::recipe::{Recipe, run_recipe};
+
+/// Сколько завершённых заданий помнить для `status`.
+const FINISHED_LIMIT: usize = 100;
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    Running,
+    Finished,
+    Failed,
+}
+
+#[derive(Serialize, Clone)]
+pub struct JobStatus {
+    pub id: u64,
+    /// Пустой у заданий-рецептов.
+    pub plugin_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recipe_id: Option<String>,
+    pub state: JobState,
+    pub started_at: u64,
+    /// Есть у завершённых заданий; `ok` внутри показывает, успешен ли запуск.
+    pub result: Option<RunResult>,
+    /// Ошибка до запуска плагина: не найден, не поддерживается и т.д.
+    pub error: Option<CoreError>,
+}
+
+struct Job {
+    status: JobStatus,
+    cancel: CancelToken,
+}
+
+/// Фоновые запуски плагинов с доступом к статусу по номеру задания.
+#[derive(Clone, Default)]
+pub struct Jobs {
+    inner: Arc<Mutex<JobsState>>,
+}
+
+#[derive(Default)]
+struct JobsState {
+    next_id: u64,
+    jobs: BTreeMap<u64, Job>,
+}
+
+impl Jobs {
+    pub fn start(&self, env: &Environment, plugin_id: &str, settings: Value) -> u64 {
+        let env = env.clone();
+        let id = plugin_id.to_string();
+        self.launch(plugin_id, None, move |cancel| {
+            let execution = execute_plugin(&env, &id, settings, Logger::new(), cancel)?;
+            record_execution(&env, &execution);
+            Ok(execution.result)
+        })
+    }
+
+    /// Рецепт выполняется одним заданием; шаги запускаются в текущем процессе.
+    pub fn start_recipe(&self, env: &Environment, recipe: Recipe) -> u64 {
+        let env = env.clone();
+        let recipe_id = recipe.id.clone();
+        self.launch("", Some(recipe_id), move |cancel| {
+            Ok(run_recipe(&env, &recipe, &cancel, |step, step_cancel| {
+                execute_plugin(
+                    &env,
+                    &step.plugin_id,
+                    step.settings.clone(),
+                    Logger::new(),
+                    step_cancel.clone(),
+                )
+            }))
+        })
+    }
+
+    fn launch(
+        &self,
+        plugin_id: &str,
+        recipe_id: Option<String>,
+        run: impl FnOnce(CancelToken) -> Result<RunResult, CoreError> + Send + 'static,
+    ) -> u64 {
+        let cancel = CancelToken::default();
+        let id = {
+            let mut state = self.lock();
+            state.next_id += 1;
+            let id = state.next_id;
+            let status = JobStatus {
+                id,
+                plugin_id: plugin_id.to_string(),
+                recipe_id,
+                state: JobState::Running,
+                started_at: crate::core::history::now_unix(),
+                result: None,
+                error: None,
+            };
+            state.jobs.insert(
+                id,
+                Job {
+                    status,
+                    cancel: cancel.clone(),
+                },
+            );
+            id
+        };
+
+        let jobs = self.clone();
+        thread::spawn(move || {
+            let outcome = run(cancel);
+            jobs.finish(id, outcome);
+        });
+        id
+    }
+
+    pub fn status(&self, id: u64) -> Option<JobStatus> {
+        self.lock().jobs.get(&id).map(|job| job.status.clone())
+    }
+
+    /// `false`, если задание не найдено или уже завершилось.
+    pub fn cancel(&self, id: u64) -> bool {
+        match self.lock().jobs.get(&id) {
+            Some(job) if job.status.state == JobState::Running => {
+                job.cancel.cancel();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn finish(&self, id: u64, outcome: Result<RunResult, CoreError>) {
+        let mut state = self.lock();
+        if let Some(job) = state.jobs.get_mut(&id) {
+            match outcome {
+                Ok(result) => {
+                    job.status.state = if result.ok {
+                        JobState::Finished
+                    } else {
+                        JobState::Failed
+                    };
+                    job.status.result = Some(result);
+                }
+                Err(err) => {
+                    job.status.state = JobState::Failed;
+                    job.status.error = Some(err);
+                }
+            }
+        }
+
+        let finished: Vec<u64> = state
+            .jobs
+            .iter()
+            .filter(|(_, job)| job.status.state != JobState::Running)
+            .map(|(id, _)| *id)
+            .collect();
+        let excess = finished.len().saturating_sub(FINISHED_LIMIT);
+        for id in &finished[..excess] {
+            state.jobs.remove(id);
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, JobsState> {
+        self.inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    fn wait(jobs: &Jobs, id: u64) -> JobStatus {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        loop {
+            let status = jobs.status(id).unwrap();
+            if status.state != JobState::Running || Instant::now() > deadline {
+                return status;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn error_before_run_fails_the_job() {
+        let jobs = Jobs::default();
+        let id = jobs.start(&Environment::default(), "no_such_plugin", Value::Null);
+        let status = wait(&jobs, id);
+        assert!(status.state == JobState::Failed);
+        assert!(status.result.is_none());
+        assert!(matches!(
+            status.error,
+            Some(CoreError::PluginNotFound { .. })
+        ));
+        assert!(!jobs.cancel(id));
+        assert!(jobs.status(id + 1).is_none());
+    }
+
+    #[test]
+    fn only_recent_finished_jobs_are_kept() {
+        let jobs = Jobs::default();
+        let total = FINISHED_LIMIT + 5;
+        let mut last = 0;
+        for _ in 0..total {
+            last = jobs.launch("sample", None, |_| {
+                Ok(RunResult {
+                    ok: true,
+                    message: String::new(),
+                    logs: Vec::new(),
+                    steps: Vec::new(),
+                    post_actions: Vec::new(),
+                })
+            });
+        }
+
+        // Заданий станет ровно FINISHED_LIMIT, только когда завершатся все.
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while jobs.lock().jobs.len() > FINISHED_LIMIT && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(jobs.lock().jobs.len(), FINISHED_LIMIT);
+        assert!(jobs.status(last).unwrap().state == JobState::Finished);
+    }
+}
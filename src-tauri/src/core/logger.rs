@@ -1,6 +1,10 @@
-﻿use serde::Serialize;
+﻿use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Clone)]
+use crate::core::post_action::PostActionRequest;
+use crate::core::recipe::StepResult;
+use crate::core::watchdog::Progress;
+
+#[derive(Serialize, Deserialize, Clone)]
 #[serde(rename_all = "snake_case")]
 pub enum LogLevel {
     Info,
@@ -9,7 +13,7 @@ pub enum LogLevel {
     Success,
 }
 
-#[derive(Serialize, Clone)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct LogEntry {
     pub level: LogLevel,
     pub message: String,
@@ -17,16 +21,33 @@ pub struct LogEntry {
     pub indent: u8,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct RunResult {
     pub ok: bool,
     pub message: String,
     pub logs: Vec<LogEntry>,
+    /// Итоги шагов рецепта; у запуска одного плагина пусто.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub steps: Vec<StepResult>,
+    /// Действия, которые нужны, чтобы изменения применились. Выполняются только после
+    /// подтверждения пользователем.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub post_actions: Vec<PostActionRequest>,
 }
 
+type LogSink = Box<dyn FnMut(&LogEntry) + Send>;
+
 pub struct Logger {
     logs: Vec<LogEntry>,
     indent: u8,
+    sink: Option<LogSink>,
+    progress: Option<Progress>,
+}
+
+impl Default for Logger {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Logger {
@@ -34,6 +55,29 @@ impl Logger {
         Self {
             logs: Vec::new(),
             indent: 0,
+            sink: None,
+            progress: None,
+        }
+    }
+
+    /// Логгер с уже накопленными записями, например, когда поток плагина не вернул свой.
+    pub fn from_logs(logs: Vec<LogEntry>) -> Self {
+        Self {
+            logs,
+            ..Self::new()
+        }
+    }
+
+    /// Дублирует записи и заголовки разделов в `Progress`, который видит сторожевой таймер.
+    pub fn track(&mut self, progress: Progress) {
+        self.progress = Some(progress);
+    }
+
+    /// Логгер, который дополнительно отдаёт каждую запись наружу по мере появления.
+    pub fn with_sink(sink: impl FnMut(&LogEntry) + Send + 'static) -> Self {
+        Self {
+            sink: Some(Box::new(sink)),
+            ..Self::new()
         }
     }
 
@@ -54,6 +98,10 @@ impl Logger {
     }
 
     pub fn section(&mut self, title: impl Into<String>) {
+        let title = title.into();
+        if let Some(progress) = &self.progress {
+            progress.set_step(Some(title.clone()));
+        }
         self.push(LogLevel::Info, title, None);
     }
 
@@ -86,13 +134,39 @@ impl Logger {
         self.with_indent(f);
     }
 
+    pub fn push_detail(
+        &mut self,
+        level: LogLevel,
+        message: impl Into<String>,
+        detail: Option<String>,
+    ) {
+        self.push(level, message, detail);
+    }
+
+    /// Добавляет записи другого журнала, сдвигая их на текущий отступ.
+    pub fn append(&mut self, entries: Vec<LogEntry>) {
+        let base = self.indent;
+        for entry in entries {
+            self.indent = base.saturating_add(entry.indent);
+            self.push(entry.level, entry.message, entry.detail);
+        }
+        self.indent = base;
+    }
+
     fn push(&mut self, level: LogLevel, message: impl Into<String>, detail: Option<String>) {
-        self.logs.push(LogEntry {
+        let entry = LogEntry {
             level,
             message: message.into(),
             detail,
             indent: self.indent,
-        });
+        };
+        if let Some(sink) = self.sink.as_mut() {
+            sink(&entry);
+        }
+        if let Some(progress) = &self.progress {
+            progress.record(&entry);
+        }
+        self.logs.push(entry);
     }
 
     pub fn into_logs(self) -> Vec<LogEntry> {
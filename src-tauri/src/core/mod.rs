@@ -1,15 +1,68 @@
 pub mod api;
+pub mod backend;
+#[cfg(any(feature = "wasm-plugins", feature = "native-plugins"))]
+pub mod bridge;
+pub mod capability;
+pub mod config;
+#[cfg(any(test, feature = "test-support"))]
+pub mod conformance;
+pub mod control;
+pub mod cron;
+pub mod declarative;
+pub mod disk;
+pub mod doctor;
+pub mod encoding;
+pub mod environment;
+pub mod error;
+#[cfg(any(test, feature = "test-support"))]
+pub mod fake;
+pub mod guard;
+pub mod history;
+pub mod hive;
+pub mod host;
+pub mod jobs;
 pub mod logger;
+#[cfg(feature = "native-plugins")]
+pub mod native;
+pub mod package;
+pub mod panic;
+pub mod paths;
+pub mod platform;
 pub mod plugin;
+pub mod post_action;
+pub mod privilege;
+pub mod recipe;
+pub mod regfile;
 pub mod registry;
+pub mod scheduler;
+pub mod script;
 pub mod settings;
+pub mod store;
+#[cfg(feature = "wasm-plugins")]
+pub mod wasm;
+pub mod watchdog;
 
-pub use api::PluginApi;
+pub use api::{CancelToken, CommandResult, PluginApi};
+#[allow(unused_imports)]
+pub use backend::CommandOptions;
+#[allow(unused_imports)]
+pub use capability::Capability;
+pub use environment::Environment;
+pub use error::CoreError;
+#[allow(unused_imports)]
+pub use guard::{GuardError, GuardErrorKind};
 #[allow(unused_imports)]
 pub use logger::{LogEntry, LogLevel, Logger, RunResult};
-pub use plugin::{Plugin, PluginPreview, list_plugins, preview_plugin, run_plugin};
+#[allow(unused_imports)]
+pub use platform::Platform;
+pub use plugin::{
+    Plugin, PluginPreview, execute_plugin, list_plugins, preview_plugin, record_execution,
+    requires_elevation, validate_plugin_settings,
+};
+#[allow(unused_imports)]
+pub use post_action::PostAction;
 #[allow(unused_imports)]
 pub use settings::{
     PluginInfo, PluginMeta, SettingField, SettingKind, SettingOption, SettingUi,
-    defaults_from_fields, merge_settings, validate_settings,
+    defaults_from_fields, merge_settings, round_trip_settings, validate_settings,
 };
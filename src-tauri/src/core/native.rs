@@ -0,0 +1,532 @@
+use libloading::Library;
+use serde::Deserialize;
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    env::consts::DLL_EXTENSION,
+    ffi::c_void,
+    fs,
+    path::{Path, PathBuf},
+    ptr, slice,
+    sync::{Arc, Mutex, OnceLock},
+    time::SystemTime,
+};
+
+use crate::core::api::PluginApi;
+use crate::core::bridge::{self, log_level};
+use crate::core::declarative::validate_meta;
+use crate::core::logger::Logger;
+use crate::core::plugin::{Plugin, PluginPreview};
+use crate::core::registry::Unavailable;
+use crate::core::settings::PluginMeta;
+
+/// Версия ABI, которую понимает приложение. Увеличивается при любом изменении
+/// `LhBytes`, `LhHost` или `LhPlugin`.
+pub const ABI_VERSION: u32 = 1;
+const VERSION_SYMBOL: &[u8] = b"lh_abi_version";
+const ENTRY_SYMBOL: &[u8] = b"lh_plugin";
+
+/// Байты, переданные через границу библиотеки. Память освобождает та сторона,
+/// которая её выделила: ответы плагина — `LhPlugin::free`, ответы хоста — `LhHost::free`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct LhBytes {
+    pub ptr: *mut u8,
+    pub len: usize,
+}
+
+/// Функции хоста, которые получает плагин. `context` передаётся в них как есть.
+#[repr(C)]
+pub struct LhHost {
+    pub context: *mut c_void,
+    /// Уровень (0 — info, 1 — warn, 2 — error, 3 — success), сообщение и подробности.
+    pub log: extern "C" fn(*mut c_void, i32, LhBytes, LhBytes),
+    pub section: extern "C" fn(*mut c_void, LhBytes),
+    pub indent: extern "C" fn(*mut c_void),
+    pub outdent: extern "C" fn(*mut c_void),
+    pub is_cancelled: extern "C" fn(*mut c_void) -> bool,
+    /// JSON-запрос к `PluginApi` в том же формате, что у WebAssembly-плагинов.
+    pub call: extern "C" fn(*mut c_void, LhBytes) -> LhBytes,
+    pub free: extern "C" fn(LhBytes),
+}
+
+/// Таблица функций, которую возвращает `lh_plugin`. Все строки — JSON в UTF-8.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct LhPlugin {
+    pub meta: extern "C" fn() -> LhBytes,
+    /// Пустой ответ означает `None`.
+    pub defaults: Option<extern "C" fn(*const LhHost) -> LhBytes>,
+    pub preview: Option<extern "C" fn(*const LhHost) -> LhBytes>,
+    /// Получает настройки; пустой ответ — успех, иначе текст ошибки.
+    pub run: extern "C" fn(*const LhHost, LhBytes) -> LhBytes,
+    pub free: extern "C" fn(LhBytes),
+}
+
+type Loaded = Result<(Arc<Library>, LhPlugin, PluginMeta), String>;
+
+/// Загруженные библиотеки по пути. Выгрузить библиотеку, пока её функции могут
+/// выполняться, нельзя, поэтому изменённый файл загружается заново рядом со старым.
+static LIBRARIES: OnceLock<Mutex<HashMap<PathBuf, (SystemTime, Loaded)>>> = OnceLock::new();
+
+/// Библиотеки плагинов (`.dll`, `.so`, `.dylib`) из списка файлов вместе с их именами.
+/// Библиотека другой версии ABI становится `Unavailable` с объяснением.
+pub fn load_files(paths: &[PathBuf]) -> Vec<(String, Box<dyn Plugin>)> {
+    let paths = paths
+        .iter()
+        .filter(|path| path.extension().is_some_and(|ext| ext == DLL_EXTENSION));
+
+    let mut cache = LIBRARIES
+        .get_or_init(Mutex::default)
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    paths
+        .map(|path| {
+            let file = path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let modified = fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+            let cached = cache
+                .get(path)
+                .filter(|(cached_at, _)| *cached_at == modified);
+            let loaded = match cached {
+                Some((_, loaded)) => loaded.clone(),
+                None => {
+                    let loaded = open(path);
+                    cache.insert(path.clone(), (modified, loaded.clone()));
+                    loaded
+                }
+            };
+            let plugin: Box<dyn Plugin> = match loaded {
+                Ok((library, vtable, meta)) => Box::new(NativePlugin {
+                    vtable,
+                    meta,
+                    _library: library,
+                }),
+                Err(reason) => Box::new(Unavailable::new(&file, None, reason)),
+            };
+            (file, plugin)
+        })
+        .collect()
+}
+
+fn open(path: &Path) -> Loaded {
+    // SAFETY: загрузка выполняет код инициализации библиотеки. Сюда попадают только
+    // файлы, которые пропустил `Trust::check` в `registry::plugin_files`: из пакетов
+    // с совпавшей контрольной суммой или при включённом `allow_unsigned`. Хост
+    // с повышенными правами внешние файлы не загружает вовсе.
+    let library = unsafe { Library::new(path) }
+        .map_err(|e| format!("Не удалось загрузить библиотеку: {e}"))?;
+
+    // SAFETY: сигнатура `lh_abi_version` не меняется между версиями ABI.
+    let version = unsafe { library.get::<extern "C" fn() -> u32>(VERSION_SYMBOL) }
+        .map(|version| *version)
+        .map_err(|_| "Библиотека не является плагином: нет функции lh_abi_version.".to_string())?;
+    // SAFETY: адрес `lh_plugin` только читается; вызывает её `handshake` после проверки версии.
+    let entry = unsafe { library.get::<extern "C" fn() -> *const LhPlugin>(ENTRY_SYMBOL) }
+        .map(|entry| *entry)
+        .ok();
+    let (vtable, meta) = handshake(version, entry)?;
+    Ok((Arc::new(library), vtable, meta))
+}
+
+/// Проверка версии ABI и чтение метаданных. `lh_plugin` вызывается, только если
+/// версия совпала: у другой версии таблица функций может быть устроена иначе.
+fn handshake(
+    version: extern "C" fn() -> u32,
+    entry: Option<extern "C" fn() -> *const LhPlugin>,
+) -> Result<(LhPlugin, PluginMeta), String> {
+    let version = version();
+    if version != ABI_VERSION {
+        return Err(format!(
+            "Плагин собран для ABI версии {version}, приложение поддерживает версию \
+             {ABI_VERSION}. Пересоберите плагин."
+        ));
+    }
+    let entry = entry.ok_or_else(|| "В библиотеке нет функции lh_plugin.".to_string())?;
+    let vtable = entry();
+    if vtable.is_null() {
+        return Err("lh_plugin вернула пустой указатель.".to_string());
+    }
+    // SAFETY: версия совпала, значит `lh_plugin` возвращает `LhPlugin` этой версии,
+    // который живёт столько же, сколько библиотека.
+    let vtable = unsafe { *vtable };
+
+    let meta = take(&vtable, (vtable.meta)());
+    let meta: PluginMeta = serde_json::from_slice(&meta)
+        .map_err(|e| format!("Плагин вернул некорректные метаданные: {e}"))?;
+    validate_meta(&meta)?;
+    Ok((vtable, meta))
+}
+
+/// Копирует ответ плагина и возвращает память плагину.
+fn take(vtable: &LhPlugin, bytes: LhBytes) -> Vec<u8> {
+    if bytes.ptr.is_null() {
+        return Vec::new();
+    }
+    // SAFETY: по ABI плагин возвращает `len` байт по `ptr`, пока не вызван `free`.
+    let copy = unsafe { read(bytes) };
+    (vtable.free)(bytes);
+    copy
+}
+
+/// # Safety
+/// `bytes` должен указывать на `len` доступных для чтения байт или быть пустым.
+unsafe fn read(bytes: LhBytes) -> Vec<u8> {
+    if bytes.ptr.is_null() || bytes.len == 0 {
+        return Vec::new();
+    }
+    // SAFETY: гарантирует вызывающий.
+    unsafe { slice::from_raw_parts(bytes.ptr, bytes.len) }.to_vec()
+}
+
+/// Плагин из динамической библиотеки. Код библиотеки выполняется в процессе
+/// приложения: отмену он должен проверять сам через `is_cancelled`.
+pub struct NativePlugin {
+    vtable: LhPlugin,
+    meta: PluginMeta,
+    _library: Arc<Library>,
+}
+
+impl NativePlugin {
+    fn call(
+        &self,
+        api: &PluginApi,
+        logger: &mut Logger,
+        function: impl FnOnce(&LhHost) -> LhBytes,
+    ) -> Vec<u8> {
+        let mut context = HostContext { api, logger };
+        let host = LhHost {
+            context: (&mut context as *mut HostContext).cast(),
+            log: host_log,
+            section: host_section,
+            indent: host_indent,
+            outdent: host_outdent,
+            is_cancelled: host_is_cancelled,
+            call: host_call,
+            free: host_free,
+        };
+        let result = function(&host);
+        take(&self.vtable, result)
+    }
+
+    /// Необязательная функция плагина, которая возвращает JSON или пустой ответ.
+    fn optional_json<T: for<'de> Deserialize<'de>>(
+        &self,
+        api: &PluginApi,
+        function: Option<extern "C" fn(*const LhHost) -> LhBytes>,
+        name: &str,
+    ) -> Result<Option<T>, String> {
+        let Some(function) = function else {
+            return Ok(None);
+        };
+        let bytes = self.call(api, &mut Logger::new(), |host| function(host));
+        if bytes.is_empty() {
+            return Ok(None);
+        }
+        serde_json::from_slice(&bytes)
+            .map(Some)
+            .map_err(|e| format!("Плагин вернул некорректный ответ {name}: {e}"))
+    }
+}
+
+impl Plugin for NativePlugin {
+    fn meta(&self) -> PluginMeta {
+        self.meta.clone()
+    }
+
+    fn defaults(&self, api: &PluginApi) -> Option<Value> {
+        self.optional_json(api, self.vtable.defaults, "defaults")
+            .ok()
+            .flatten()
+    }
+
+    fn preview(&self, api: &PluginApi) -> Result<Option<PluginPreview>, String> {
+        self.optional_json(api, self.vtable.preview, "preview")
+    }
+
+    fn run(&self, api: &PluginApi, settings: &Value, logger: &mut Logger) -> Result<(), String> {
+        let mut input = serde_json::to_vec(settings).map_err(|e| e.to_string())?;
+        let settings = LhBytes {
+            ptr: input.as_mut_ptr(),
+            len: input.len(),
+        };
+        let bytes = self.call(api, logger, |host| (self.vtable.run)(host, settings));
+        if bytes.is_empty() {
+            Ok(())
+        } else {
+            Err(String::from_utf8_lossy(&bytes).into_owned())
+        }
+    }
+}
+
+/// То, на что указывает `LhHost::context` во время одного вызова плагина.
+struct HostContext<'a> {
+    api: &'a PluginApi,
+    logger: &'a mut Logger,
+}
+
+/// # Safety
+/// `context` — указатель из `LhHost`, который действителен до конца вызова плагина.
+unsafe fn context<'a>(context: *mut c_void) -> &'a mut HostContext<'a> {
+    // SAFETY: гарантирует вызывающий.
+    unsafe { &mut *context.cast::<HostContext<'a>>() }
+}
+
+fn text(bytes: LhBytes) -> String {
+    // SAFETY: строки, которые плагин передаёт хосту, действительны на время вызова.
+    String::from_utf8_lossy(&unsafe { read(bytes) }).into_owned()
+}
+
+extern "C" fn host_log(context_ptr: *mut c_void, level: i32, message: LhBytes, detail: LhBytes) {
+    // SAFETY: плагин вызывает функции хоста только внутри вызова, получившего `LhHost`.
+    let context = unsafe { context(context_ptr) };
+    let detail = Some(text(detail)).filter(|detail| !detail.is_empty());
+    context
+        .logger
+        .push_detail(log_level(level), text(message), detail);
+}
+
+extern "C" fn host_section(context_ptr: *mut c_void, title: LhBytes) {
+    // SAFETY: см. `host_log`.
+    let context = unsafe { context(context_ptr) };
+    context.logger.section(text(title));
+}
+
+extern "C" fn host_indent(context_ptr: *mut c_void) {
+    // SAFETY: см. `host_log`.
+    unsafe { context(context_ptr) }.logger.indent();
+}
+
+extern "C" fn host_outdent(context_ptr: *mut c_void) {
+    // SAFETY: см. `host_log`.
+    unsafe { context(context_ptr) }.logger.outdent();
+}
+
+extern "C" fn host_is_cancelled(context_ptr: *mut c_void) -> bool {
+    // SAFETY: см. `host_log`.
+    unsafe { context(context_ptr) }.api.is_cancelled()
+}
+
+extern "C" fn host_call(context_ptr: *mut c_void, request: LhBytes) -> LhBytes {
+    // SAFETY: см. `host_log`.
+    let context = unsafe { context(context_ptr) };
+    // SAFETY: запрос действителен на время вызова.
+    let request = unsafe { read(request) };
+    let response = bridge::respond(Some(context.api), &request).into_boxed_slice();
+    let len = response.len();
+    LhBytes {
+        ptr: Box::into_raw(response).cast(),
+        len,
+    }
+}
+
+extern "C" fn host_free(bytes: LhBytes) {
+    if bytes.ptr.is_null() {
+        return;
+    }
+    // SAFETY: `bytes` получен из `host_call`, где память выделена как `Box<[u8]>` длины `len`.
+    drop(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(bytes.ptr, bytes.len)) });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::config::{self, AppConfig};
+    use crate::core::environment::Environment;
+    use crate::core::fake::Fakes;
+    use crate::core::logger::LogLevel;
+    use crate::core::plugin::list_plugins;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("lamerhelper-native-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Байты, выделенные «плагином» так же, как их выделяет хост.
+    fn plugin_bytes(bytes: &[u8]) -> LhBytes {
+        let bytes: Box<[u8]> = bytes.into();
+        let len = bytes.len();
+        LhBytes {
+            ptr: Box::into_raw(bytes).cast(),
+            len,
+        }
+    }
+
+    fn borrowed(bytes: &[u8]) -> LhBytes {
+        LhBytes {
+            ptr: bytes.as_ptr().cast_mut(),
+            len: bytes.len(),
+        }
+    }
+
+    extern "C" fn current_version() -> u32 {
+        ABI_VERSION
+    }
+
+    extern "C" fn next_version() -> u32 {
+        ABI_VERSION + 1
+    }
+
+    extern "C" fn sample_meta() -> LhBytes {
+        let meta = json!({ "id": "native_sample", "name": "Пример", "description": "", "category": "Тест" });
+        plugin_bytes(meta.to_string().as_bytes())
+    }
+
+    extern "C" fn empty_id_meta() -> LhBytes {
+        let meta = json!({ "id": "", "name": "Пример", "description": "", "category": "Тест" });
+        plugin_bytes(meta.to_string().as_bytes())
+    }
+
+    /// Пишет в журнал и пробует записать в реестр без объявленного разрешения;
+    /// ответ хоста возвращает как текст ошибки.
+    extern "C" fn sample_run(host: *const LhHost, _settings: LhBytes) -> LhBytes {
+        // SAFETY: хост передаёт действительный `LhHost` на время вызова.
+        let host = unsafe { &*host };
+        (host.log)(
+            host.context,
+            3,
+            borrowed("запуск".as_bytes()),
+            borrowed(b""),
+        );
+        let request = json!({
+            "op": "set_registry_string",
+            "key": "Software\\Vendor",
+            "name": "Mode",
+            "value": "1",
+        })
+        .to_string();
+        let response = (host.call)(host.context, borrowed(request.as_bytes()));
+        // SAFETY: ответ хоста действителен до вызова `free`.
+        let copy = unsafe { read(response) };
+        (host.free)(response);
+        plugin_bytes(&copy)
+    }
+
+    static ENTRY_CALLED: AtomicBool = AtomicBool::new(false);
+
+    static SAMPLE: LhPlugin = LhPlugin {
+        meta: sample_meta,
+        defaults: None,
+        preview: None,
+        run: sample_run,
+        free: host_free,
+    };
+
+    static EMPTY_ID: LhPlugin = LhPlugin {
+        meta: empty_id_meta,
+        ..SAMPLE
+    };
+
+    extern "C" fn sample_entry() -> *const LhPlugin {
+        ENTRY_CALLED.store(true, Ordering::SeqCst);
+        &SAMPLE
+    }
+
+    extern "C" fn empty_id_entry() -> *const LhPlugin {
+        &EMPTY_ID
+    }
+
+    extern "C" fn null_entry() -> *const LhPlugin {
+        ptr::null()
+    }
+
+    /// Сама программа тестов вместо библиотеки плагина: функции плагина выше
+    /// живут в ней.
+    fn this_program() -> Arc<Library> {
+        #[cfg(unix)]
+        let library = libloading::os::unix::Library::this();
+        #[cfg(windows)]
+        let library = libloading::os::windows::Library::this().unwrap();
+        Arc::new(library.into())
+    }
+
+    #[test]
+    fn other_abi_version_is_refused_before_lh_plugin() {
+        ENTRY_CALLED.store(false, Ordering::SeqCst);
+        let reason = handshake(next_version, Some(sample_entry)).err().unwrap();
+        assert_eq!(
+            reason,
+            format!(
+                "Плагин собран для ABI версии {}, приложение поддерживает версию {ABI_VERSION}. \
+                 Пересоберите плагин.",
+                ABI_VERSION + 1
+            )
+        );
+        assert!(!ENTRY_CALLED.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn broken_entry_or_meta_is_refused() {
+        let reason = |entry| handshake(current_version, entry).err().unwrap();
+        assert_eq!(reason(None), "В библиотеке нет функции lh_plugin.");
+        assert_eq!(
+            reason(Some(null_entry)),
+            "lh_plugin вернула пустой указатель."
+        );
+        assert!(reason(Some(empty_id_entry)).starts_with("Некорректный id «»"));
+    }
+
+    #[test]
+    fn handshake_gives_a_plugin_that_reaches_the_host() {
+        let (vtable, meta) = handshake(current_version, Some(sample_entry)).unwrap();
+        assert_eq!(meta.id, "native_sample");
+        let plugin = NativePlugin {
+            vtable,
+            meta,
+            _library: this_program(),
+        };
+
+        let fakes = Fakes::new();
+        let mut logger = Logger::new();
+        let reason = plugin
+            .run(&fakes.api(&plugin), &json!({}), &mut logger)
+            .unwrap_err();
+        let response: Value = serde_json::from_str(&reason).unwrap();
+        let error = response["error"].as_str().unwrap();
+        assert!(
+            error.starts_with("Операция не объявлена плагином"),
+            "{error}"
+        );
+        assert!(!fakes.registry.has_key("HKCU\\Software\\Vendor"));
+
+        let logs = logger.into_logs();
+        assert!(
+            logs.iter()
+                .any(|entry| entry.message == "запуск" && matches!(entry.level, LogLevel::Success))
+        );
+    }
+
+    #[test]
+    fn unloadable_library_is_listed_with_the_reason() {
+        let dir = scratch_dir("list");
+        let plugins = dir.join("plugins");
+        fs::create_dir_all(&plugins).unwrap();
+        let file = format!("broken.{DLL_EXTENSION}");
+        fs::write(plugins.join(&file), b"not a library").unwrap();
+        let mut app_config = AppConfig::default();
+        app_config.packages.allow_unsigned = true;
+        config::save(&dir, &app_config).unwrap();
+
+        let env = Environment::default().with_data_dir(Some(dir.clone()));
+        let info = list_plugins(&env)
+            .into_iter()
+            .find(|info| info.id == file)
+            .unwrap();
+        assert!(!info.supported);
+        let reason = info.unsupported_reason.unwrap();
+        assert!(
+            reason.starts_with("Не удалось загрузить библиотеку:"),
+            "{reason}"
+        );
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,765 @@
+use ed25519_dalek::{Signature, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::core::capability::Capability;
+use crate::core::config::{self, PackagesConfig, TrustedKey};
+use crate::core::environment::Environment;
+use crate::core::error::CoreError;
+use crate::core::history::now_unix;
+use crate::core::registry::{load_plugins, plugins_dir};
+use crate::core::store;
+
+const INSTALLED_FILE: &str = "packages.json";
+const MANIFEST_FILE: &str = "manifest.json";
+/// Подпись Ed25519 байтов `manifest.json`, 64 байта в hex.
+const SIGNATURE_FILE: &str = "manifest.json.sig";
+/// Файлы, которые умеют загружать загрузчики внешних плагинов.
+const PLUGIN_EXTENSIONS: [&str; 7] = ["toml", "json", "rhai", "wasm", "dll", "so", "dylib"];
+
+/// Описание пакета. Подпись покрывает манифест, а манифест — файл плагина
+/// через контрольную сумму.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PackageManifest {
+    pub id: String,
+    pub version: String,
+    pub author: String,
+    #[serde(default)]
+    pub description: String,
+    /// Разрешения, которые пользователь подтверждает при установке. Плагин
+    /// не может объявить ничего сверх них.
+    pub capabilities: Vec<Capability>,
+    /// Имя файла плагина в папке пакета.
+    pub file: String,
+    /// SHA-256 файла плагина в hex.
+    pub checksum: String,
+}
+
+/// `index.json` каталога: папки пакетов относительно самого файла.
+#[derive(Deserialize)]
+struct CatalogIndex {
+    packages: Vec<CatalogRef>,
+}
+
+#[derive(Deserialize)]
+struct CatalogRef {
+    path: String,
+}
+
+/// Пакет из каталога вместе с итогом проверки. Пакет с `error` установить нельзя.
+#[derive(Serialize, Clone)]
+pub struct CatalogEntry {
+    pub path: String,
+    pub manifest: Option<PackageManifest>,
+    /// Имя доверенного ключа, которым подписан пакет.
+    pub signer: Option<String>,
+    pub installed_version: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct InstalledPackage {
+    pub id: String,
+    pub version: String,
+    pub author: String,
+    pub signer: String,
+    /// Имя файла в папке `plugins`.
+    pub file: String,
+    /// SHA-256 файла при установке: изменённый потом файл не загружается.
+    #[serde(default)]
+    pub checksum: String,
+    pub capabilities: Vec<Capability>,
+    pub installed_at: u64,
+}
+
+#[derive(Clone, Copy)]
+enum Mode {
+    Install,
+    Update,
+}
+
+/// Проверенный пакет, готовый к установке.
+struct Verified {
+    manifest: PackageManifest,
+    signer: String,
+    payload: Vec<u8>,
+}
+
+/// Какие файлы папки `plugins` можно загружать: установленные из пакетов, пока их
+/// контрольная сумма совпадает с записанной при установке. С `allow_unsigned` в
+/// настройках — любые. Файлы, которые не похожи на плагины, не проверяются.
+pub struct Trust {
+    allow_unsigned: bool,
+    /// Имя файла → `id` пакета и SHA-256 файла.
+    installed: HashMap<String, (String, String)>,
+}
+
+impl Trust {
+    pub fn load(env: &Environment) -> Self {
+        let allow_unsigned = env
+            .data_dir
+            .as_deref()
+            .is_some_and(|data_dir| config::load(data_dir).packages.allow_unsigned);
+        let installed = list_installed(env)
+            .into_iter()
+            .map(|package| (package.file, (package.id, package.checksum)))
+            .collect();
+        Self {
+            allow_unsigned,
+            installed,
+        }
+    }
+
+    /// Файл пакета, который проверяется при установке, ещё до записи в `packages.json`.
+    fn with_file(mut self, id: &str, file: &str, checksum: &str) -> Self {
+        self.installed
+            .insert(file.to_string(), (id.to_string(), checksum.to_string()));
+        self
+    }
+
+    pub fn check(&self, path: &Path) -> Result<(), String> {
+        let is_plugin = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| PLUGIN_EXTENSIONS.contains(&ext));
+        if self.allow_unsigned || !is_plugin {
+            return Ok(());
+        }
+        let file = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let Some((id, checksum)) = self.installed.get(&file) else {
+            return Err(
+                "Плагин не установлен из подписанного пакета. Чтобы загружать свои плагины, \
+                 включите allow_unsigned в разделе packages файла config.json."
+                    .to_string(),
+            );
+        };
+        let bytes =
+            fs::read(path).map_err(|e| format!("Не удалось прочитать {}: {e}", path.display()))?;
+        if checksum.is_empty() || !sha256_hex(&bytes).eq_ignore_ascii_case(checksum) {
+            return Err(format!(
+                "Файл изменён после установки пакета «{id}». Переустановите пакет."
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+fn installed_path(data_dir: &Path) -> PathBuf {
+    data_dir.join(INSTALLED_FILE)
+}
+
+fn require_data_dir(env: &Environment) -> Result<&Path, CoreError> {
+    env.data_dir.as_deref().ok_or_else(|| {
+        CoreError::Plugin("Папка данных недоступна: пакеты не устанавливаются.".into())
+    })
+}
+
+pub fn list_installed(env: &Environment) -> Vec<InstalledPackage> {
+    match env.data_dir.as_deref() {
+        Some(data_dir) => store::load(&installed_path(data_dir)),
+        None => Vec::new(),
+    }
+}
+
+/// Все пакеты каталога из настроек. Неподписанные и повреждённые пакеты тоже
+/// попадают в список — с описанием ошибки.
+pub fn list_catalog(env: &Environment) -> Result<Vec<CatalogEntry>, CoreError> {
+    let data_dir = require_data_dir(env)?;
+    let config = config::load(data_dir).packages;
+    let installed = list_installed(env);
+    let entries = catalog_dirs(&config)?
+        .into_iter()
+        .map(|(path, dir)| match verify(&dir, &config.trusted_keys) {
+            Ok(verified) => CatalogEntry {
+                path,
+                installed_version: installed
+                    .iter()
+                    .find(|package| package.id == verified.manifest.id)
+                    .map(|package| package.version.clone()),
+                manifest: Some(verified.manifest),
+                signer: Some(verified.signer),
+                error: None,
+            },
+            Err(err) => CatalogEntry {
+                path,
+                manifest: read_manifest(&dir).ok(),
+                signer: None,
+                installed_version: None,
+                error: Some(err),
+            },
+        })
+        .collect();
+    Ok(entries)
+}
+
+/// Устанавливает пакет, если он ещё не установлен. `approved` — разрешения,
+/// которые пользователь видел и подтвердил: они должны совпасть с манифестом.
+pub fn install_package(
+    env: &Environment,
+    id: &str,
+    approved: &[Capability],
+) -> Result<InstalledPackage, CoreError> {
+    deploy(env, id, approved, Mode::Install)
+}
+
+/// Заменяет установленный пакет более новой версией из каталога.
+pub fn update_package(
+    env: &Environment,
+    id: &str,
+    approved: &[Capability],
+) -> Result<InstalledPackage, CoreError> {
+    deploy(env, id, approved, Mode::Update)
+}
+
+pub fn uninstall_package(env: &Environment, id: &str) -> Result<(), CoreError> {
+    let data_dir = require_data_dir(env)?;
+    let plugins =
+        plugins_dir(env).ok_or_else(|| CoreError::Plugin("Папка плагинов недоступна.".into()))?;
+    store::update(
+        &installed_path(data_dir),
+        |installed: &mut Vec<InstalledPackage>| {
+            let index = installed
+                .iter()
+                .position(|package| package.id == id)
+                .ok_or_else(|| CoreError::Plugin(format!("Пакет не установлен: {id}")))?;
+            remove_plugin_file(&plugins.join(&installed[index].file))?;
+            installed.remove(index);
+            Ok(())
+        },
+    )
+}
+
+fn deploy(
+    env: &Environment,
+    id: &str,
+    approved: &[Capability],
+    mode: Mode,
+) -> Result<InstalledPackage, CoreError> {
+    let data_dir = require_data_dir(env)?;
+    let plugins =
+        plugins_dir(env).ok_or_else(|| CoreError::Plugin("Папка плагинов недоступна.".into()))?;
+    let config = config::load(data_dir).packages;
+    let Verified {
+        manifest,
+        signer,
+        payload,
+    } = find_package(&config, id)?;
+    if manifest.capabilities != approved {
+        return Err(CoreError::Plugin(
+            "Разрешения пакета изменились после проверки. Просмотрите их ещё раз.".into(),
+        ));
+    }
+
+    store::update(
+        &installed_path(data_dir),
+        |installed: &mut Vec<InstalledPackage>| {
+            let previous = installed.iter().position(|package| package.id == id);
+            match (mode, previous) {
+                (Mode::Install, Some(_)) => {
+                    return Err(CoreError::Plugin(format!(
+                        "Пакет «{id}» уже установлен, используйте обновление."
+                    )));
+                }
+                (Mode::Update, None) => {
+                    return Err(CoreError::Plugin(format!("Пакет не установлен: {id}")));
+                }
+                (Mode::Update, Some(index))
+                    if compare_versions(&manifest.version, &installed[index].version)
+                        != Ordering::Greater =>
+                {
+                    return Err(CoreError::Plugin(format!(
+                        "Установлена версия {}, в каталоге нет более новой.",
+                        installed[index].version
+                    )));
+                }
+                _ => {}
+            }
+
+            let target = plugins.join(&manifest.file);
+            let owned = previous.is_some_and(|index| installed[index].file == manifest.file);
+            if target.exists() && !owned {
+                return Err(CoreError::Plugin(format!(
+                    "В папке плагинов уже есть файл «{}», который не относится к этому пакету.",
+                    manifest.file
+                )));
+            }
+            let backup = fs::read(&target).ok();
+            fs::create_dir_all(&plugins)
+                .map_err(|e| format!("Ошибка создания папки плагинов: {e}"))?;
+            fs::write(&target, &payload)
+                .map_err(|e| format!("Не удалось записать {}: {e}", target.display()))?;
+
+            let checksum = sha256_hex(&payload);
+            if let Err(err) = check_installed_plugin(env, &manifest, &checksum) {
+                // Возвращаем папку плагинов в прежнее состояние.
+                let _ = match &backup {
+                    Some(bytes) => fs::write(&target, bytes),
+                    None => fs::remove_file(&target),
+                };
+                return Err(CoreError::Plugin(err));
+            }
+
+            let package = InstalledPackage {
+                id: manifest.id.clone(),
+                version: manifest.version.clone(),
+                author: manifest.author.clone(),
+                signer: signer.clone(),
+                file: manifest.file.clone(),
+                checksum,
+                capabilities: manifest.capabilities.clone(),
+                installed_at: now_unix(),
+            };
+            if let Some(index) = previous {
+                let old = std::mem::replace(&mut installed[index], package.clone());
+                if old.file != package.file {
+                    remove_plugin_file(&plugins.join(&old.file))?;
+                }
+            } else {
+                installed.push(package.clone());
+            }
+            Ok(package)
+        },
+    )
+}
+
+/// Установленный файл должен загрузиться как плагин с тем же `id` и не объявлять
+/// разрешений, которых нет в манифесте.
+fn check_installed_plugin(
+    env: &Environment,
+    manifest: &PackageManifest,
+    checksum: &str,
+) -> Result<(), String> {
+    let trust = Trust::load(env).with_file(&manifest.id, &manifest.file, checksum);
+    let plugin = load_plugins(env, &trust)
+        .into_iter()
+        .find(|plugin| plugin.meta().id == manifest.id)
+        .ok_or_else(|| format!("Файл пакета не загрузился как плагин «{}».", manifest.id))?;
+    if let Some(reason) = plugin.load_error() {
+        return Err(format!("Плагин из пакета не загружается: {reason}"));
+    }
+    let extra: Vec<String> = plugin
+        .meta()
+        .capabilities
+        .iter()
+        .filter(|capability| !manifest.capabilities.contains(capability))
+        .map(|capability| format!("{capability:?}"))
+        .collect();
+    if !extra.is_empty() {
+        return Err(format!(
+            "Плагин объявляет разрешения, которых нет в манифесте: {}",
+            extra.join(", ")
+        ));
+    }
+    Ok(())
+}
+
+fn remove_plugin_file(path: &Path) -> Result<(), String> {
+    match fs::remove_file(path) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(format!("Не удалось удалить {}: {e}", path.display())),
+    }
+}
+
+/// Папки пакетов из `index.json` вместе с путями, как они записаны в индексе.
+fn catalog_dirs(config: &PackagesConfig) -> Result<Vec<(String, PathBuf)>, CoreError> {
+    if config.catalog.trim().is_empty() {
+        return Err(CoreError::Plugin(
+            "Каталог пакетов не указан в настройках.".into(),
+        ));
+    }
+    let index_path = Path::new(config.catalog.trim());
+    let content = fs::read_to_string(index_path)
+        .map_err(|e| format!("Не удалось прочитать каталог {}: {e}", index_path.display()))?;
+    let index: CatalogIndex = serde_json::from_str(&content)
+        .map_err(|e| format!("Ошибка в каталоге {}: {e}", index_path.display()))?;
+    let root = index_path.parent().unwrap_or(Path::new(""));
+    Ok(index
+        .packages
+        .into_iter()
+        .map(|package| {
+            let dir = root.join(&package.path);
+            (package.path, dir)
+        })
+        .collect())
+}
+
+/// Самая новая проверенная версия пакета в каталоге.
+fn find_package(config: &PackagesConfig, id: &str) -> Result<Verified, CoreError> {
+    let mut last_error = None;
+    let mut best: Option<Verified> = None;
+    for (_, dir) in catalog_dirs(config)? {
+        if !read_manifest(&dir).is_ok_and(|manifest| manifest.id == id) {
+            continue;
+        }
+        match verify(&dir, &config.trusted_keys) {
+            Ok(verified) => {
+                let newer = best.as_ref().is_none_or(|best| {
+                    compare_versions(&verified.manifest.version, &best.manifest.version)
+                        == Ordering::Greater
+                });
+                if newer {
+                    best = Some(verified);
+                }
+            }
+            Err(err) => last_error = Some(err),
+        }
+    }
+    best.ok_or_else(|| match last_error {
+        Some(err) => CoreError::Plugin(format!("Пакет «{id}» не прошёл проверку: {err}")),
+        None => CoreError::Plugin(format!("Пакета «{id}» нет в каталоге.")),
+    })
+}
+
+fn read_manifest(dir: &Path) -> Result<PackageManifest, String> {
+    let bytes = fs::read(dir.join(MANIFEST_FILE))
+        .map_err(|e| format!("Не удалось прочитать {MANIFEST_FILE}: {e}"))?;
+    serde_json::from_slice(&bytes).map_err(|e| format!("Ошибка в {MANIFEST_FILE}: {e}"))
+}
+
+/// Проверяет подпись манифеста доверенным ключом и контрольную сумму файла плагина.
+fn verify(dir: &Path, trusted_keys: &[TrustedKey]) -> Result<Verified, String> {
+    let manifest_bytes = fs::read(dir.join(MANIFEST_FILE))
+        .map_err(|e| format!("Не удалось прочитать {MANIFEST_FILE}: {e}"))?;
+    let signature = fs::read_to_string(dir.join(SIGNATURE_FILE))
+        .map_err(|_| "Пакет не подписан.".to_string())?;
+    let signature: [u8; 64] = decode_hex(&signature).ok_or("Подпись пакета повреждена.")?;
+    let signature = Signature::from_bytes(&signature);
+
+    let signer = trusted_keys
+        .iter()
+        .find(|key| {
+            decode_hex::<32>(&key.public_key)
+                .and_then(|bytes| VerifyingKey::from_bytes(&bytes).ok())
+                .is_some_and(|public| public.verify_strict(&manifest_bytes, &signature).is_ok())
+        })
+        .map(|key| key.name.clone())
+        .ok_or("Пакет подписан ключом, которого нет в списке доверенных.")?;
+
+    let manifest: PackageManifest = serde_json::from_slice(&manifest_bytes)
+        .map_err(|e| format!("Ошибка в {MANIFEST_FILE}: {e}"))?;
+    validate_manifest(&manifest)?;
+    let payload = fs::read(dir.join(&manifest.file))
+        .map_err(|e| format!("Не удалось прочитать {}: {e}", manifest.file))?;
+    let checksum = sha256_hex(&payload);
+    if !checksum.eq_ignore_ascii_case(manifest.checksum.trim()) {
+        return Err(format!(
+            "Контрольная сумма {} не совпадает с манифестом.",
+            manifest.file
+        ));
+    }
+    Ok(Verified {
+        manifest,
+        signer,
+        payload,
+    })
+}
+
+fn validate_manifest(manifest: &PackageManifest) -> Result<(), String> {
+    if manifest.id.trim().is_empty() || manifest.version.trim().is_empty() {
+        return Err("В манифесте не указан id или версия.".to_string());
+    }
+    let plain_name = Path::new(&manifest.file)
+        .file_name()
+        .is_some_and(|name| name == manifest.file.as_str());
+    let known_extension = Path::new(&manifest.file)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| PLUGIN_EXTENSIONS.contains(&ext));
+    if !plain_name || manifest.file.starts_with('.') || !known_extension {
+        return Err(format!(
+            "Недопустимое имя файла плагина: «{}».",
+            manifest.file
+        ));
+    }
+    Ok(())
+}
+
+fn decode_hex<const N: usize>(text: &str) -> Option<[u8; N]> {
+    hex::decode(text.trim()).ok()?.try_into().ok()
+}
+
+/// Сравнение версий вида `1.2.10` по числовым частям; недостающие части — нули.
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    let parts = |version: &str| -> Vec<u64> {
+        version
+            .trim()
+            .split('.')
+            .map(|part| part.parse().unwrap_or(0))
+            .collect()
+    };
+    let (a, b) = (parts(a), parts(b));
+    (0..a.len().max(b.len()))
+        .map(|i| {
+            let left = a.get(i).copied().unwrap_or(0);
+            left.cmp(&b.get(i).copied().unwrap_or(0))
+        })
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::config::AppConfig;
+    use crate::core::registry::all_plugins;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    const KEY: [u8; 32] = [7; 32];
+    const PLUGIN: &str = r#"
+id = "disk_report"
+name = "Отчёт о диске"
+description = ""
+category = "Тест"
+capabilities = [{ kind = "fs_write", scopes = ["%TEMP%"] }]
+
+[[steps]]
+action = "write_file"
+path = "{{temp_dir}}/report.txt"
+content = "ok"
+"#;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("lamerhelper-package-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn trusted(key: [u8; 32]) -> Vec<TrustedKey> {
+        vec![TrustedKey {
+            name: "Отдел ИТ".to_string(),
+            public_key: hex::encode(SigningKey::from_bytes(&key).verifying_key().as_bytes()),
+        }]
+    }
+
+    fn manifest(version: &str, capabilities: Vec<Capability>) -> PackageManifest {
+        PackageManifest {
+            id: "disk_report".to_string(),
+            version: version.to_string(),
+            author: "ИТ".to_string(),
+            description: String::new(),
+            capabilities,
+            file: "disk_report.toml".to_string(),
+            checksum: sha256_hex(PLUGIN.as_bytes()),
+        }
+    }
+
+    fn fs_write() -> Vec<Capability> {
+        vec![Capability::FsWrite {
+            scopes: vec!["%TEMP%".to_string()],
+        }]
+    }
+
+    /// Папка пакета: файл плагина, манифест и его подпись ключом `KEY`.
+    fn write_package(dir: &Path, manifest: &PackageManifest, plugin: &str) {
+        fs::create_dir_all(dir).unwrap();
+        fs::write(dir.join(&manifest.file), plugin).unwrap();
+        let bytes = serde_json::to_vec(manifest).unwrap();
+        let signature = SigningKey::from_bytes(&KEY).sign(&bytes);
+        fs::write(dir.join(MANIFEST_FILE), &bytes).unwrap();
+        fs::write(dir.join(SIGNATURE_FILE), hex::encode(signature.to_bytes())).unwrap();
+    }
+
+    /// Папка данных с каталогом из одного пакета `package` и доверенным ключом `KEY`.
+    fn environment(root: &Path, allow_unsigned: bool) -> Environment {
+        let data_dir = root.join("data");
+        let catalog = root.join("catalog");
+        fs::create_dir_all(&catalog).unwrap();
+        fs::write(
+            catalog.join("index.json"),
+            r#"{"packages": [{"path": "package"}]}"#,
+        )
+        .unwrap();
+        let mut config = AppConfig::default();
+        config.packages.catalog = catalog.join("index.json").display().to_string();
+        config.packages.trusted_keys = trusted(KEY);
+        config.packages.allow_unsigned = allow_unsigned;
+        config::save(&data_dir, &config).unwrap();
+        Environment {
+            data_dir: Some(data_dir),
+            temp_dir: root.join("temp"),
+            locale: "ru-RU".to_string(),
+            ..Environment::default()
+        }
+    }
+
+    /// `Some(None)` — плагин загружен, `Some(Some(причина))` — виден заглушкой.
+    fn plugin_error(env: &Environment, id: &str) -> Option<Option<String>> {
+        all_plugins(env)
+            .into_iter()
+            .find(|plugin| plugin.meta().id == id)
+            .map(|plugin| plugin.load_error())
+    }
+
+    #[test]
+    fn verify_accepts_signed_package() {
+        let dir = scratch_dir("verify-ok");
+        write_package(&dir, &manifest("1.0", fs_write()), PLUGIN);
+
+        let verified = verify(&dir, &trusted(KEY)).unwrap_or_else(|err| panic!("{err}"));
+        assert_eq!(verified.signer, "Отдел ИТ");
+        assert_eq!(verified.payload, PLUGIN.as_bytes());
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn verify_rejects_unknown_key_tampering_and_checksum() {
+        let dir = scratch_dir("verify-bad");
+        write_package(&dir, &manifest("1.0", fs_write()), PLUGIN);
+
+        assert_eq!(
+            verify(&dir, &trusted([8; 32])).err().as_deref(),
+            Some("Пакет подписан ключом, которого нет в списке доверенных.")
+        );
+
+        let signed = fs::read_to_string(dir.join(MANIFEST_FILE)).unwrap();
+        fs::write(
+            dir.join(MANIFEST_FILE),
+            signed.replace("\"capabilities\":[", "\"capabilities\":[\"network\","),
+        )
+        .unwrap();
+        assert_eq!(
+            verify(&dir, &trusted(KEY)).err().as_deref(),
+            Some("Пакет подписан ключом, которого нет в списке доверенных.")
+        );
+
+        write_package(&dir, &manifest("1.0", fs_write()), PLUGIN);
+        fs::write(dir.join("disk_report.toml"), format!("{PLUGIN}\n# изменён")).unwrap();
+        assert_eq!(
+            verify(&dir, &trusted(KEY)).err().as_deref(),
+            Some("Контрольная сумма disk_report.toml не совпадает с манифестом.")
+        );
+
+        fs::remove_file(dir.join(SIGNATURE_FILE)).unwrap();
+        assert_eq!(
+            verify(&dir, &trusted(KEY)).err().as_deref(),
+            Some("Пакет не подписан.")
+        );
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn manifest_file_must_be_a_plain_plugin_name() {
+        for file in [
+            "../disk_report.toml",
+            "..\\disk_report.toml",
+            "sub/disk_report.toml",
+            "/etc/disk_report.toml",
+            ".hidden.toml",
+            "disk_report.exe",
+            "disk_report",
+        ] {
+            let manifest = PackageManifest {
+                file: file.to_string(),
+                ..manifest("1.0", Vec::new())
+            };
+            assert!(validate_manifest(&manifest).is_err(), "{file}");
+        }
+        assert!(validate_manifest(&manifest("1.0", Vec::new())).is_ok());
+        let unnamed = PackageManifest {
+            version: " ".to_string(),
+            ..manifest("1.0", Vec::new())
+        };
+        assert!(validate_manifest(&unnamed).is_err());
+    }
+
+    #[test]
+    fn versions_compare_numerically() {
+        assert_eq!(compare_versions("1.2.10", "1.2.9"), Ordering::Greater);
+        assert_eq!(compare_versions("1.2", "1.2.0"), Ordering::Equal);
+        assert_eq!(compare_versions("1.9", "1.10"), Ordering::Less);
+        assert_eq!(compare_versions(" 2 ", "1.99.99"), Ordering::Greater);
+        assert_eq!(compare_versions("1.x", "1.0"), Ordering::Equal);
+    }
+
+    #[test]
+    fn failed_check_rolls_back_the_plugin_file() {
+        let root = scratch_dir("rollback");
+        let env = environment(&root, false);
+        let plugins = plugins_dir(&env).unwrap();
+        // Манифест не разрешает fs_write, которое объявляет сам плагин.
+        write_package(
+            &root.join("catalog/package"),
+            &manifest("1.0", Vec::new()),
+            PLUGIN,
+        );
+
+        let error = install_package(&env, "disk_report", &[]).err().unwrap();
+        assert!(error.to_string().contains("FsWrite"), "{error}");
+        assert!(!plugins.join("disk_report.toml").exists());
+        assert!(list_installed(&env).is_empty());
+
+        write_package(
+            &root.join("catalog/package"),
+            &manifest("1.0", fs_write()),
+            PLUGIN,
+        );
+        install_package(&env, "disk_report", &fs_write()).unwrap();
+        let changed = PLUGIN.replace("Отчёт о диске", "Отчёт 2");
+        let broken = PackageManifest {
+            checksum: sha256_hex(changed.as_bytes()),
+            ..manifest("2.0", Vec::new())
+        };
+        write_package(&root.join("catalog/package"), &broken, &changed);
+
+        assert!(update_package(&env, "disk_report", &[]).is_err());
+        assert_eq!(
+            fs::read_to_string(plugins.join("disk_report.toml")).unwrap(),
+            PLUGIN
+        );
+        assert_eq!(list_installed(&env)[0].version, "1.0");
+        let _ = fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn only_installed_unchanged_files_are_loaded() {
+        let root = scratch_dir("trust");
+        let env = environment(&root, false);
+        let plugins = plugins_dir(&env).unwrap();
+        write_package(
+            &root.join("catalog/package"),
+            &manifest("1.0", fs_write()),
+            PLUGIN,
+        );
+
+        install_package(&env, "disk_report", &fs_write()).unwrap();
+        assert_eq!(plugin_error(&env, "disk_report"), Some(None));
+
+        fs::write(
+            plugins.join("disk_report.toml"),
+            format!("{PLUGIN}\n# изменён"),
+        )
+        .unwrap();
+        let changed = plugin_error(&env, "disk_report.toml").flatten().unwrap();
+        assert!(changed.starts_with("Файл изменён"), "{changed}");
+
+        fs::write(
+            plugins.join("own.toml"),
+            PLUGIN.replace("disk_report", "own"),
+        )
+        .unwrap();
+        fs::write(plugins.join("notes.txt"), "не плагин").unwrap();
+        let unsigned = plugin_error(&env, "own.toml").flatten().unwrap();
+        assert!(unsigned.contains("allow_unsigned"), "{unsigned}");
+        assert_eq!(plugin_error(&env, "notes.txt"), None);
+
+        let env = environment(&root, true);
+        assert_eq!(plugin_error(&env, "own"), Some(None));
+        let _ = fs::remove_dir_all(root);
+    }
+}
@@ -0,0 +1,68 @@
+use std::{
+    cell::RefCell,
+    panic::{self, AssertUnwindSafe},
+    sync::Once,
+};
+
+#[derive(Clone, Debug)]
+pub struct PanicReport {
+    pub message: String,
+    pub location: Option<String>,
+}
+
+impl PanicReport {
+    pub fn summary(&self) -> String {
+        match &self.location {
+            Some(location) => format!("Плагин аварийно завершился: {} ({location})", self.message),
+            None => format!("Плагин аварийно завершился: {}", self.message),
+        }
+    }
+}
+
+thread_local! {
+    static LAST_PANIC: RefCell<Option<PanicReport>> = const { RefCell::new(None) };
+}
+
+static HOOK: Once = Once::new();
+
+/// Хук запоминает сообщение и место паники, затем передаёт управление стандартному.
+fn install_hook() {
+    HOOK.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            let report = PanicReport {
+                message: payload_message(info.payload()),
+                location: info
+                    .location()
+                    .map(|location| format!("{}:{}", location.file(), location.line())),
+            };
+            LAST_PANIC.with(|last| *last.borrow_mut() = Some(report));
+            previous(info);
+        }));
+    });
+}
+
+/// Выполняет `f`, превращая панику в `PanicReport` вместо размотки через вызывающий код.
+pub fn catch<R>(f: impl FnOnce() -> R) -> Result<R, PanicReport> {
+    install_hook();
+    LAST_PANIC.with(|last| last.borrow_mut().take());
+
+    panic::catch_unwind(AssertUnwindSafe(f)).map_err(|payload| {
+        LAST_PANIC
+            .with(|last| last.borrow_mut().take())
+            .unwrap_or_else(|| PanicReport {
+                message: payload_message(payload.as_ref()),
+                location: None,
+            })
+    })
+}
+
+fn payload_message(payload: &(dyn std::any::Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "неизвестная ошибка".to_string()
+    }
+}
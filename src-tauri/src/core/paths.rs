@@ -0,0 +1,25 @@
+use std::path::PathBuf;
+
+/// Совпадает с `identifier` в `tauri.conf.json`: GUI и CLI должны видеть одну папку данных.
+pub const APP_IDENTIFIER: &str = "darkfated.lamerhelper";
+
+/// Папка данных приложения без `AppHandle` — там же, где её ищет Tauri.
+pub fn app_data_dir() -> Option<PathBuf> {
+    base_data_dir().map(|dir| dir.join(APP_IDENTIFIER))
+}
+
+fn base_data_dir() -> Option<PathBuf> {
+    if cfg!(windows) {
+        env_dir("APPDATA")
+    } else if cfg!(target_os = "macos") {
+        env_dir("HOME").map(|home| home.join("Library").join("Application Support"))
+    } else {
+        env_dir("XDG_DATA_HOME").or_else(|| env_dir("HOME").map(|home| home.join(".local/share")))
+    }
+}
+
+fn env_dir(var: &str) -> Option<PathBuf> {
+    std::env::var_os(var)
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+}
@@ -0,0 +1,50 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Platform {
+    Windows,
+    Linux,
+    Macos,
+}
+
+impl Platform {
+    pub fn current() -> Self {
+        if cfg!(windows) {
+            Platform::Windows
+        } else if cfg!(target_os = "macos") {
+            Platform::Macos
+        } else {
+            Platform::Linux
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Platform::Windows => "Windows",
+            Platform::Linux => "Linux",
+            Platform::Macos => "macOS",
+        }
+    }
+}
+
+/// Пустой список означает, что плагин работает везде.
+pub fn is_supported(platforms: &[Platform]) -> bool {
+    platforms.is_empty() || platforms.contains(&Platform::current())
+}
+
+pub fn unsupported_reason(platforms: &[Platform]) -> Option<String> {
+    if is_supported(platforms) {
+        return None;
+    }
+    let supported: Vec<&str> = platforms.iter().map(|platform| platform.label()).collect();
+    Some(format!(
+        "Доступно только на {}, текущая система: {}.",
+        supported.join(", "),
+        Platform::current().label()
+    ))
+}
+
+pub fn unsupported_feature(feature: &str) -> String {
+    format!("{feature} недоступен на {}.", Platform::current().label())
+}
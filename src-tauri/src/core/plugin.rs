@@ -1,13 +1,26 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use tauri::AppHandle;
+use std::{sync::Arc, time::Instant};
 
-use crate::core::api::PluginApi;
-use crate::core::logger::{Logger, RunResult};
+use crate::core::api::{CancelToken, PluginApi};
+use crate::core::capability::Capability;
+use crate::core::environment::Environment;
+use crate::core::error::CoreError;
+use crate::core::history::{self, HistoryEntry};
+use crate::core::logger::{LogLevel, Logger, RunResult};
+use crate::core::panic;
+use crate::core::platform::is_supported;
+use crate::core::post_action::{self, PostActionRequest};
+use crate::core::privilege::{elevation_hint, is_elevated};
 use crate::core::registry::all_plugins;
 use crate::core::settings::{
-    PluginInfo, PluginMeta, defaults_from_fields, merge_settings, validate_settings,
+    PluginInfo, PluginMeta, admin_fields, defaults_from_fields, merge_settings, validate_settings,
 };
+use crate::core::watchdog::{self, Outcome};
+
+const ADMIN_REQUIRED: &str = "Требуются права администратора:";
+const ADMIN_ONLY_PLUGIN: &str = "Плагин работает только с повышенными правами.";
+const BLOCKED_OPERATIONS: &str = "Заблокированные операции:";
 
 pub trait Plugin: Send + Sync {
     fn meta(&self) -> PluginMeta;
@@ -18,95 +31,431 @@ pub trait Plugin: Send + Sync {
         Ok(None)
     }
     fn run(&self, api: &PluginApi, settings: &Value, logger: &mut Logger) -> Result<(), String>;
+    /// Настройки после чтения в тип, с которым работает `run`, и записи обратно.
+    /// По ним проверки плагинов сверяют тип с объявленными полями. `None` — плагин
+    /// читает `Value` сам.
+    fn settings_round_trip(&self, _settings: &Value) -> Option<Result<Value, String>> {
+        None
+    }
+    /// Причина, по которой внешний плагин не может работать. Такой плагин виден
+    /// в списке, но не запускается.
+    fn load_error(&self) -> Option<String> {
+        None
+    }
 }
 
-pub fn list_plugins(app: AppHandle) -> Vec<PluginInfo> {
-    let api = PluginApi::new(app);
-    let mut plugins: Vec<PluginInfo> = all_plugins()
+pub fn list_plugins(env: &Environment) -> Vec<PluginInfo> {
+    let mut plugins: Vec<PluginInfo> = all_plugins(env)
         .into_iter()
         .map(|plugin| {
             let meta = plugin.meta();
+            let api = PluginApi::new(env, meta.capabilities.clone());
             let mut info = PluginInfo::from_meta(meta);
-            if let Some(defaults) = plugin.defaults(&api) {
+            if let Some(reason) = plugin.load_error() {
+                info.supported = false;
+                info.unsupported_reason = Some(reason);
+            }
+            if !info.supported {
+                return info;
+            }
+            info.missing_tools = missing_tools(&api, &info.tools);
+            if let Ok(Some(defaults)) = panic::catch(|| plugin.defaults(&api)) {
                 info.defaults = merge_settings(&defaults, &info.defaults);
             }
             info
         })
         .collect();
-    plugins.sort_by(|a, b| a.name.to_lowercase().cmp(&b.name.to_lowercase()));
+    plugins.sort_by_key(|plugin| plugin.name.to_lowercase());
     plugins
 }
 
-#[derive(Serialize, Clone)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct PluginPreview {
     pub title: String,
     pub value: String,
     pub note: Option<String>,
 }
 
-pub fn preview_plugin(app: AppHandle, id: &str) -> Result<Option<PluginPreview>, String> {
-    let plugin = all_plugins()
+/// Итог одного запуска вместе с данными для истории.
+#[derive(Serialize, Deserialize)]
+pub struct Execution {
+    pub plugin_id: String,
+    pub plugin_name: String,
+    pub started_at: u64,
+    pub duration_ms: u64,
+    pub panicked: bool,
+    #[serde(default)]
+    pub timed_out: bool,
+    /// Хост плагинов завершился или ответил ошибкой, не доведя запуск до конца.
+    #[serde(default)]
+    pub host_crashed: bool,
+    pub result: RunResult,
+}
+
+/// Чем закончился `Plugin::run`, помимо самого `RunResult`.
+#[derive(Clone, Copy, PartialEq)]
+enum Termination {
+    Normal,
+    Panicked,
+    TimedOut,
+}
+
+fn find_plugin(env: &Environment, id: &str) -> Result<Box<dyn Plugin>, CoreError> {
+    all_plugins(env)
         .into_iter()
         .find(|plugin| plugin.meta().id == id)
-        .ok_or_else(|| format!("Плагин не найден: {id}"))?;
-    let api = PluginApi::new(app);
-    plugin.preview(&api)
+        .ok_or_else(|| CoreError::PluginNotFound { id: id.to_string() })
+}
+
+fn ensure_supported(plugin: &dyn Plugin, meta: &PluginMeta) -> Result<(), CoreError> {
+    if let Some(reason) = plugin.load_error() {
+        return Err(CoreError::Plugin(reason));
+    }
+    if is_supported(&meta.platforms) {
+        Ok(())
+    } else {
+        Err(CoreError::UnsupportedPlatform {
+            id: meta.id.clone(),
+            platforms: meta.platforms.clone(),
+        })
+    }
+}
+
+/// Программы из `tools`, которые `PluginApi::which` не нашёл.
+pub fn missing_tools(api: &PluginApi, tools: &[String]) -> Vec<String> {
+    tools
+        .iter()
+        .filter(|tool| api.which(tool).is_none())
+        .cloned()
+        .collect()
+}
+
+pub fn plugin_defaults(env: &Environment, id: &str) -> Result<Option<Value>, CoreError> {
+    let plugin = find_plugin(env, id)?;
+    let meta = plugin.meta();
+    ensure_supported(plugin.as_ref(), &meta)?;
+    let api = PluginApi::new(env, meta.capabilities);
+    catch_defaults(plugin.as_ref(), &api)
 }
 
-pub fn run_plugin(app: AppHandle, id: &str, settings: Value) -> Result<RunResult, String> {
-    let plugin = all_plugins()
+fn catch_defaults(plugin: &dyn Plugin, api: &PluginApi) -> Result<Option<Value>, CoreError> {
+    panic::catch(|| plugin.defaults(api)).map_err(|report| CoreError::Plugin(report.summary()))
+}
+
+pub fn preview_plugin(env: &Environment, id: &str) -> Result<Option<PluginPreview>, CoreError> {
+    let plugin = find_plugin(env, id)?;
+    let meta = plugin.meta();
+    ensure_supported(plugin.as_ref(), &meta)?;
+    let api = PluginApi::new(env, meta.capabilities);
+    catch_preview(plugin.as_ref(), &api)
+}
+
+fn catch_preview(plugin: &dyn Plugin, api: &PluginApi) -> Result<Option<PluginPreview>, CoreError> {
+    match panic::catch(|| plugin.preview(api)) {
+        Ok(result) => Ok(result?),
+        Err(report) => Err(CoreError::Plugin(report.summary())),
+    }
+}
+
+/// Ошибки настроек с учётом значений по умолчанию — та же проверка, что перед запуском.
+pub fn validate_plugin_settings(
+    env: &Environment,
+    id: &str,
+    settings: &Value,
+) -> Result<Vec<String>, CoreError> {
+    let plugin = find_plugin(env, id)?;
+    let meta = plugin.meta();
+    let defaults = effective_defaults(env, plugin.as_ref(), &meta);
+    Ok(validate_settings(
+        &merge_settings(settings, &defaults),
+        &meta.settings,
+    ))
+}
+
+/// Значения полей по умолчанию, поверх них — текущие значения из `Plugin::defaults`,
+/// как перед запуском.
+fn effective_defaults(env: &Environment, plugin: &dyn Plugin, meta: &PluginMeta) -> Value {
+    let api = PluginApi::new(env, meta.capabilities.clone());
+    let defaults = defaults_from_fields(&meta.settings);
+    match panic::catch(|| plugin.defaults(&api)) {
+        Ok(Some(extra_defaults)) => merge_settings(&extra_defaults, &defaults),
+        _ => defaults,
+    }
+}
+
+/// Нужны ли для запуска с такими настройками права, которых у процесса нет.
+pub fn requires_elevation(
+    env: &Environment,
+    id: &str,
+    settings: &Value,
+) -> Result<bool, CoreError> {
+    if is_elevated() {
+        return Ok(false);
+    }
+    Ok(!admin_requirements(env, id, settings)?.is_empty())
+}
+
+/// Что в запуске с такими настройками требует прав администратора, независимо от прав
+/// процесса: названия включённых полей или название плагина, если права нужны ему целиком.
+pub fn admin_requirements(
+    env: &Environment,
+    id: &str,
+    settings: &Value,
+) -> Result<Vec<String>, CoreError> {
+    let plugin = find_plugin(env, id)?;
+    let meta = plugin.meta();
+    if meta.capabilities.contains(&Capability::RequiresAdmin) {
+        return Ok(vec![meta.name]);
+    }
+    let merged = merge_settings(settings, &effective_defaults(env, plugin.as_ref(), &meta));
+    Ok(admin_fields(&merged, &meta.settings)
         .into_iter()
-        .find(|plugin| plugin.meta().id == id)
-        .ok_or_else(|| format!("Плагин не найден: {id}"))?;
+        .map(|field| field.label.clone())
+        .collect())
+}
 
+/// Запуск без записи в историю: используется процессом-хостом, историю ведёт GUI.
+pub fn execute_plugin(
+    env: &Environment,
+    id: &str,
+    settings: Value,
+    logger: Logger,
+    cancel: CancelToken,
+) -> Result<Execution, CoreError> {
+    let plugin = find_plugin(env, id)?;
+    execute_found(env, Arc::from(plugin), settings, logger, cancel)
+}
+
+fn execute_found(
+    env: &Environment,
+    plugin: Arc<dyn Plugin>,
+    settings: Value,
+    logger: Logger,
+    cancel: CancelToken,
+) -> Result<Execution, CoreError> {
     let meta = plugin.meta();
-    let api = PluginApi::new(app);
+    ensure_supported(plugin.as_ref(), &meta)?;
+
+    let mut api = PluginApi::new(env, meta.capabilities.clone());
+    let missing = missing_tools(&api, &meta.tools);
+    if !missing.is_empty() {
+        return Err(CoreError::MissingTools {
+            id: meta.id,
+            tools: missing,
+        });
+    }
+    api.set_cancel_token(cancel);
+    let started_at = history::now_unix();
+    let started = Instant::now();
+    let (result, termination) = execute(plugin, &meta, Arc::new(api), settings, logger);
+
+    Ok(Execution {
+        plugin_id: meta.id,
+        plugin_name: meta.name,
+        started_at,
+        duration_ms: started.elapsed().as_millis() as u64,
+        panicked: termination == Termination::Panicked,
+        timed_out: termination == Termination::TimedOut,
+        host_crashed: false,
+        result,
+    })
+}
+
+/// Записывает запуск в историю, если у окружения есть папка данных.
+pub fn record_execution(env: &Environment, execution: &Execution) {
+    let Some(data_dir) = &env.data_dir else {
+        return;
+    };
+    let entry = HistoryEntry {
+        plugin_id: execution.plugin_id.clone(),
+        plugin_name: execution.plugin_name.clone(),
+        started_at: execution.started_at,
+        duration_ms: execution.duration_ms,
+        ok: execution.result.ok,
+        message: execution.result.message.clone(),
+        panicked: execution.panicked,
+        timed_out: execution.timed_out,
+        host_crashed: execution.host_crashed,
+    };
+    let _ = history::record(data_dir, &entry);
+    let _ = post_action::remember(
+        data_dir,
+        &execution.plugin_id,
+        &execution.result.post_actions,
+    );
+}
+
+fn execute(
+    plugin: Arc<dyn Plugin>,
+    meta: &PluginMeta,
+    api: Arc<PluginApi>,
+    settings: Value,
+    mut logger: Logger,
+) -> (RunResult, Termination) {
+    let progress = api.progress();
+    logger.track(progress.clone());
+
     let mut defaults = defaults_from_fields(&meta.settings);
-    if let Some(extra_defaults) = plugin.defaults(&api) {
-        defaults = merge_settings(&extra_defaults, &defaults);
+    match panic::catch(|| plugin.defaults(&api)) {
+        Ok(Some(extra_defaults)) => defaults = merge_settings(&extra_defaults, &defaults),
+        Ok(None) => {}
+        Err(report) => logger.warn(format!(
+            "Не удалось получить текущие значения: {}",
+            report.summary()
+        )),
     }
     let merged_settings = merge_settings(&settings, &defaults);
 
-    let mut logger = Logger::new();
     logger.section(format!("Плагин: {}", meta.name));
     if !meta.description.is_empty() {
         logger.info(meta.description.clone());
     }
 
-    log_settings(&mut logger, &meta, &merged_settings);
+    log_settings(&mut logger, meta, &merged_settings);
 
     let validation_errors = validate_settings(&merged_settings, &meta.settings);
     if !validation_errors.is_empty() {
         for err in validation_errors {
             logger.error(err);
         }
-        return Ok(RunResult {
+        let result = RunResult {
             ok: false,
             message: "Некорректные настройки.".to_string(),
             logs: logger.into_logs(),
-        });
+            steps: Vec::new(),
+            post_actions: Vec::new(),
+        };
+        return (result, Termination::Normal);
+    }
+
+    if !api.is_elevated() {
+        let plugin_requires_admin = meta.capabilities.contains(&Capability::RequiresAdmin);
+        let fields = admin_fields(&merged_settings, &meta.settings);
+        if plugin_requires_admin || !fields.is_empty() {
+            logger.group(ADMIN_REQUIRED, |logger| {
+                if plugin_requires_admin {
+                    logger.error(ADMIN_ONLY_PLUGIN);
+                }
+                for field in &fields {
+                    logger.error(field.label.clone());
+                }
+                logger.info(elevation_hint());
+            });
+            let result = RunResult {
+                ok: false,
+                message: "Недостаточно прав.".to_string(),
+                logs: logger.into_logs(),
+                steps: Vec::new(),
+                post_actions: Vec::new(),
+            };
+            return (result, Termination::Normal);
+        }
     }
 
     logger.info("Запуск...".to_string());
+    progress.set_step(None);
 
-    let result = plugin.run(&api, &merged_settings, &mut logger);
+    let timeout = watchdog::timeout_for(meta.timeout_secs);
+    let (mut logger, outcome) = watchdog::run(
+        plugin,
+        Arc::clone(&api),
+        merged_settings,
+        logger,
+        progress,
+        timeout,
+    );
+    log_blocked(&mut logger, &api);
 
-    let (ok, message) = match result {
-        Ok(()) => {
+    let (ok, message, termination) = match outcome {
+        Outcome::TimedOut { step } => {
+            let secs = timeout.map(|timeout| timeout.as_secs()).unwrap_or_default();
+            let step = step.unwrap_or_else(|| "основная работа плагина".to_string());
+            logger.push_detail(
+                LogLevel::Error,
+                format!("Превышено время выполнения ({secs} с)."),
+                Some(format!("Выполнялось: {step}")),
+            );
+            let message = format!("Превышено время выполнения ({secs} с) на шаге: {step}");
+            (false, message, Termination::TimedOut)
+        }
+        Outcome::Finished(_) if api.is_cancelled() => {
+            logger.warn("Выполнение отменено.".to_string());
+            (false, "Отменено.".to_string(), Termination::Normal)
+        }
+        Outcome::Finished(Ok(Ok(()))) => {
             logger.success("Завершено успешно.".to_string());
-            (true, "Готово.".to_string())
+            (true, "Готово.".to_string(), Termination::Normal)
         }
-        Err(err) => {
+        Outcome::Finished(Ok(Err(err))) => {
             logger.error(err.clone());
-            (false, err)
+            (false, err, Termination::Normal)
+        }
+        Outcome::Finished(Err(report)) => {
+            logger.push_detail(
+                LogLevel::Error,
+                format!("Паника: {}", report.message),
+                report.location.clone(),
+            );
+            (false, report.summary(), Termination::Panicked)
         }
     };
 
-    Ok(RunResult {
+    let post_actions = collect_post_actions(&mut logger, meta, &api);
+    let result = RunResult {
         ok,
         message,
         logs: logger.into_logs(),
-    })
+        steps: Vec::new(),
+        post_actions,
+    };
+    (result, termination)
+}
+
+/// Объединяет запросы плагина и подписывает причины его названием, чтобы в рецепте
+/// было видно, кто что попросил.
+fn collect_post_actions(
+    logger: &mut Logger,
+    meta: &PluginMeta,
+    api: &PluginApi,
+) -> Vec<PostActionRequest> {
+    let requests = post_action::merge(api.take_post_actions().into_iter().map(|request| {
+        PostActionRequest {
+            action: request.action,
+            reasons: request
+                .reasons
+                .into_iter()
+                .map(|reason| format!("{}: {reason}", meta.name))
+                .collect(),
+        }
+    }));
+    log_post_actions(logger, &requests);
+    requests
+}
+
+pub(crate) fn log_post_actions(logger: &mut Logger, requests: &[PostActionRequest]) {
+    for request in requests {
+        logger.push_detail(
+            LogLevel::Warn,
+            format!(
+                "Чтобы изменения применились, нужно {}.",
+                request.action.label()
+            ),
+            Some(request.reasons.join("\n")),
+        );
+    }
+}
+
+fn log_blocked(logger: &mut Logger, api: &PluginApi) {
+    let blocked = api.take_blocked();
+    if blocked.is_empty() {
+        return;
+    }
+
+    logger.group(BLOCKED_OPERATIONS, |logger| {
+        for err in blocked {
+            logger.error(err.to_string());
+        }
+    });
 }
 
 fn log_settings(logger: &mut Logger, meta: &PluginMeta, settings: &Value) {
@@ -137,3 +486,154 @@ fn format_value(value: &Value) -> String {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, path::PathBuf};
+
+    #[derive(Clone, Copy, PartialEq)]
+    enum PanicIn {
+        Defaults,
+        Preview,
+        Run,
+    }
+
+    /// Паникует в выбранном методе; `run` перед паникой успевает записать в журнал.
+    struct Panicking(PanicIn);
+
+    impl Plugin for Panicking {
+        fn meta(&self) -> PluginMeta {
+            PluginMeta {
+                id: "panicking".to_string(),
+                name: "Паника".to_string(),
+                description: String::new(),
+                category: "Тест".to_string(),
+                settings: Vec::new(),
+                capabilities: Vec::new(),
+                platforms: Vec::new(),
+                timeout_secs: None,
+                tools: Vec::new(),
+            }
+        }
+
+        fn defaults(&self, _api: &PluginApi) -> Option<Value> {
+            if self.0 == PanicIn::Defaults {
+                panic!("defaults сломался");
+            }
+            None
+        }
+
+        fn preview(&self, _api: &PluginApi) -> Result<Option<PluginPreview>, String> {
+            if self.0 == PanicIn::Preview {
+                panic!("preview сломался");
+            }
+            Ok(None)
+        }
+
+        fn run(
+            &self,
+            _api: &PluginApi,
+            _settings: &Value,
+            logger: &mut Logger,
+        ) -> Result<(), String> {
+            logger.info("шаг 1");
+            if self.0 == PanicIn::Run {
+                let index = 7;
+                panic!("run сломался на {index}");
+            }
+            Ok(())
+        }
+    }
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("lamerhelper-plugin-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn execute_panicking(env: &Environment, at: PanicIn) -> Execution {
+        execute_found(
+            env,
+            Arc::new(Panicking(at)),
+            Value::Null,
+            Logger::new(),
+            CancelToken::default(),
+        )
+        .unwrap()
+    }
+
+    fn messages(result: &RunResult) -> Vec<&str> {
+        result
+            .logs
+            .iter()
+            .map(|entry| entry.message.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn panic_in_run_keeps_logs_message_and_location() {
+        let execution = execute_panicking(&Environment::default(), PanicIn::Run);
+        assert!(execution.panicked);
+        assert!(!execution.result.ok);
+        assert!(execution.result.message.contains("run сломался на 7"));
+        assert!(execution.result.message.contains("plugin.rs:"));
+
+        let messages = messages(&execution.result);
+        let step = messages
+            .iter()
+            .position(|message| *message == "шаг 1")
+            .unwrap();
+        let panic = messages
+            .iter()
+            .position(|message| *message == "Паника: run сломался на 7")
+            .unwrap();
+        assert!(step < panic);
+        let location = execution.result.logs[panic].detail.as_deref().unwrap();
+        assert!(location.starts_with(file!()), "{location}");
+    }
+
+    #[test]
+    fn panic_in_defaults_or_preview_is_an_error() {
+        let api = PluginApi::new(&Environment::default(), Vec::new());
+        let err = catch_defaults(&Panicking(PanicIn::Defaults), &api).unwrap_err();
+        assert!(err.to_string().contains("defaults сломался"), "{err}");
+        let err = catch_preview(&Panicking(PanicIn::Preview), &api)
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("preview сломался"), "{err}");
+        assert!(
+            catch_preview(&Panicking(PanicIn::Run), &api)
+                .unwrap()
+                .is_none()
+        );
+
+        // Перед запуском паника в defaults только предупреждение: плагин всё равно работает.
+        let execution = execute_panicking(&Environment::default(), PanicIn::Defaults);
+        assert!(execution.result.ok);
+        assert!(!execution.panicked);
+        assert!(execution.result.logs.iter().any(|entry| {
+            entry
+                .message
+                .starts_with("Не удалось получить текущие значения")
+                && entry.message.contains("defaults сломался")
+        }));
+    }
+
+    #[test]
+    fn panicked_run_is_recorded_in_history() {
+        let dir = scratch_dir("history");
+        let env = Environment::default().with_data_dir(Some(dir.clone()));
+        let execution = execute_panicking(&env, PanicIn::Run);
+        record_execution(&env, &execution);
+
+        let entries = history::load(&dir);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].plugin_id, "panicking");
+        assert!(entries[0].panicked && !entries[0].ok);
+        assert_eq!(entries[0].message, execution.result.message);
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,271 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::core::api::CancelToken;
+use crate::core::backend::{Backends, CommandOptions};
+use crate::core::environment::Environment;
+use crate::core::error::CoreError;
+use crate::core::history::now_unix;
+use crate::core::platform::Platform;
+use crate::core::store;
+
+const PENDING_REBOOT_FILE: &str = "pending_reboot.json";
+
+/// Что нужно сделать после запуска, чтобы изменения применились. Порядок важен: более
+/// сильное действие заменяет слабые — после перезагрузки перезапускать Проводник не нужно.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum PostAction {
+    RestartShell,
+    Logoff,
+    Reboot,
+}
+
+impl PostAction {
+    pub fn label(self) -> &'static str {
+        match self {
+            PostAction::RestartShell => "перезапустить Проводник",
+            PostAction::Logoff => "выйти из системы и войти снова",
+            PostAction::Reboot => "перезагрузить компьютер",
+        }
+    }
+}
+
+/// Действие и причины, по которым его попросили плагины.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct PostActionRequest {
+    pub action: PostAction,
+    pub reasons: Vec<String>,
+}
+
+/// Перезагрузка, которую попросил плагин, но которой ещё не было.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct PendingReboot {
+    pub plugin_id: String,
+    pub reason: String,
+    /// Unix-время запроса в секундах.
+    pub requested_at: u64,
+}
+
+/// Сводит запросы к одному, самому сильному действию со всеми причинами без повторов.
+pub fn merge(requests: impl IntoIterator<Item = PostActionRequest>) -> Vec<PostActionRequest> {
+    let mut merged: Option<PostActionRequest> = None;
+    for request in requests {
+        let target = merged.get_or_insert_with(|| PostActionRequest {
+            action: request.action,
+            reasons: Vec::new(),
+        });
+        target.action = target.action.max(request.action);
+        for reason in request.reasons {
+            if !target.reasons.contains(&reason) {
+                target.reasons.push(reason);
+            }
+        }
+    }
+    merged.into_iter().collect()
+}
+
+fn pending_reboot_path(data_dir: &Path) -> PathBuf {
+    data_dir.join(PENDING_REBOOT_FILE)
+}
+
+/// Запоминает запросы перезагрузки плагина, чтобы напоминать о них и после перезапуска
+/// приложения. Повторный запрос с той же причиной только обновляет время.
+pub fn remember(
+    data_dir: &Path,
+    plugin_id: &str,
+    requests: &[PostActionRequest],
+) -> Result<(), CoreError> {
+    let reasons: Vec<&String> = requests
+        .iter()
+        .filter(|request| request.action == PostAction::Reboot)
+        .flat_map(|request| &request.reasons)
+        .collect();
+    if reasons.is_empty() {
+        return Ok(());
+    }
+    let requested_at = now_unix();
+    store::update(
+        &pending_reboot_path(data_dir),
+        |pending: &mut Vec<PendingReboot>| {
+            for reason in reasons {
+                pending.retain(|entry| entry.plugin_id != plugin_id || &entry.reason != reason);
+                pending.push(PendingReboot {
+                    plugin_id: plugin_id.to_string(),
+                    reason: reason.clone(),
+                    requested_at,
+                });
+            }
+            Ok(())
+        },
+    )
+}
+
+/// Перезагрузки, которых ещё не было. Запросы, сделанные до последней загрузки системы,
+/// выполнены и удаляются из списка.
+pub fn pending_reboots(env: &Environment) -> Vec<PendingReboot> {
+    let Some(data_dir) = &env.data_dir else {
+        return Vec::new();
+    };
+    let path = pending_reboot_path(data_dir);
+    let pending: Vec<PendingReboot> = store::load(&path);
+    let Some(booted_at) = boot_time() else {
+        return pending;
+    };
+    if pending.iter().all(|entry| entry.requested_at >= booted_at) {
+        return pending;
+    }
+    let mut current = Vec::new();
+    let _ = store::update(&path, |pending: &mut Vec<PendingReboot>| {
+        pending.retain(|entry| entry.requested_at >= booted_at);
+        current = pending.clone();
+        Ok(())
+    });
+    current
+}
+
+/// Unix-время последней загрузки системы.
+#[cfg(windows)]
+fn boot_time() -> Option<u64> {
+    use windows_sys::Win32::System::SystemInformation::GetTickCount64;
+
+    // SAFETY: функция без аргументов только читает системный счётчик времени.
+    let uptime = unsafe { GetTickCount64() } / 1000;
+    now_unix().checked_sub(uptime)
+}
+
+#[cfg(target_os = "linux")]
+fn boot_time() -> Option<u64> {
+    let uptime = std::fs::read_to_string("/proc/uptime").ok()?;
+    let seconds: f64 = uptime.split_whitespace().next()?.parse().ok()?;
+    now_unix().checked_sub(seconds as u64)
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
+fn boot_time() -> Option<u64> {
+    None
+}
+
+/// Выполняет самое сильное из запрошенных действий. Вызывается только после того, как
+/// пользователь подтвердил его.
+pub fn confirm(requests: &[PostActionRequest]) -> Result<Option<PostAction>, CoreError> {
+    let Some(request) = merge(requests.iter().cloned()).pop() else {
+        return Ok(None);
+    };
+    if Platform::current() != Platform::Windows {
+        return Err(CoreError::Plugin(format!(
+            "Действие «{}» доступно только в Windows.",
+            request.action.label()
+        )));
+    }
+    perform(
+        request.action,
+        &Backends::default(),
+        &CancelToken::default(),
+    )?;
+    Ok(Some(request.action))
+}
+
+pub fn perform(
+    action: PostAction,
+    backends: &Backends,
+    cancel: &CancelToken,
+) -> Result<(), String> {
+    match action {
+        PostAction::RestartShell => restart_shell(backends, cancel),
+        PostAction::Logoff => shutdown(backends, cancel, &["/l"]),
+        PostAction::Reboot => shutdown(backends, cancel, &["/r", "/t", "0"]),
+    }
+}
+
+/// Останавливает `explorer.exe` и запускает его заново.
+pub fn restart_shell(backends: &Backends, cancel: &CancelToken) -> Result<(), String> {
+    let processes = &backends.processes;
+    let (args, options) = (["/F", "/IM", "explorer.exe"], CommandOptions::default());
+    let output = processes
+        .run("taskkill", &args, &options, cancel, &mut |_, _| {})
+        .map_err(|e| format!("Ошибка остановки Explorer: {e}"))?;
+    if !output.success() {
+        return Err(format!(
+            "Ошибка остановки Explorer ({})",
+            output.status_text()
+        ));
+    }
+    processes
+        .spawn("explorer.exe", &[])
+        .map_err(|e| format!("Ошибка запуска Explorer: {e}"))
+}
+
+fn shutdown(backends: &Backends, cancel: &CancelToken, args: &[&str]) -> Result<(), String> {
+    let output = backends
+        .processes
+        .run(
+            "shutdown",
+            args,
+            &CommandOptions::default(),
+            cancel,
+            &mut |_, _| {},
+        )
+        .map_err(|e| format!("Ошибка запуска shutdown: {e}"))?;
+    if output.success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "shutdown завершилась с ошибкой ({})",
+            output.status_text()
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::fake::Fakes;
+
+    fn request(action: PostAction, reason: &str) -> PostActionRequest {
+        PostActionRequest {
+            action,
+            reasons: vec![reason.to_string()],
+        }
+    }
+
+    #[test]
+    fn strongest_action_absorbs_weaker_ones() {
+        let merged = merge([
+            request(PostAction::RestartShell, "меню"),
+            request(PostAction::Reboot, "курсор"),
+            request(PostAction::RestartShell, "меню"),
+        ]);
+
+        assert_eq!(
+            merged,
+            [PostActionRequest {
+                action: PostAction::Reboot,
+                reasons: vec!["меню".to_string(), "курсор".to_string()],
+            }]
+        );
+        assert!(merge([]).is_empty());
+    }
+
+    #[test]
+    fn actions_run_windows_commands() {
+        let fakes = Fakes::new();
+        fakes
+            .processes
+            .respond("taskkill /F /IM explorer.exe", 0, "", "");
+        fakes.processes.respond("shutdown /r /t 0", 0, "", "");
+        let cancel = CancelToken::default();
+
+        perform(PostAction::RestartShell, &fakes.backends(), &cancel).unwrap();
+        perform(PostAction::Reboot, &fakes.backends(), &cancel).unwrap();
+
+        assert_eq!(
+            fakes.processes.calls(),
+            [
+                "taskkill /F /IM explorer.exe",
+                "explorer.exe",
+                "shutdown /r /t 0"
+            ]
+        );
+    }
+}
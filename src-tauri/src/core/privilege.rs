@@ -0,0 +1,47 @@
+/// Запущен ли процесс с правами администратора (Windows) или от root (Linux/macOS).
+#[cfg(windows)]
+pub fn is_elevated() -> bool {
+    use windows_sys::Win32::Foundation::{CloseHandle, HANDLE};
+    use windows_sys::Win32::Security::{
+        GetTokenInformation, TOKEN_ELEVATION, TOKEN_QUERY, TokenElevation,
+    };
+    use windows_sys::Win32::System::Threading::{GetCurrentProcess, OpenProcessToken};
+
+    let mut token: HANDLE = std::ptr::null_mut();
+    // SAFETY: токен текущего процесса открывается только на чтение и закрывается ниже.
+    unsafe {
+        if OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token) == 0 {
+            return false;
+        }
+        let mut elevation = TOKEN_ELEVATION { TokenIsElevated: 0 };
+        let mut size = 0u32;
+        let ok = GetTokenInformation(
+            token,
+            TokenElevation,
+            &mut elevation as *mut _ as *mut _,
+            std::mem::size_of::<TOKEN_ELEVATION>() as u32,
+            &mut size,
+        );
+        CloseHandle(token);
+        ok != 0 && elevation.TokenIsElevated != 0
+    }
+}
+
+#[cfg(unix)]
+pub fn is_elevated() -> bool {
+    // SAFETY: geteuid не имеет побочных эффектов.
+    unsafe { libc::geteuid() == 0 }
+}
+
+#[cfg(not(any(windows, unix)))]
+pub fn is_elevated() -> bool {
+    false
+}
+
+pub fn elevation_hint() -> &'static str {
+    if cfg!(windows) {
+        "Запустите LamerHelper от имени администратора."
+    } else {
+        "Запустите LamerHelper от root (например, через sudo)."
+    }
+}
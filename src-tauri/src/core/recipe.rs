@@ -0,0 +1,385 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::core::api::CancelToken;
+use crate::core::environment::Environment;
+use crate::core::error::CoreError;
+use crate::core::logger::{LogLevel, Logger, RunResult};
+use crate::core::plugin::{
+    Execution, log_post_actions, record_execution, validate_plugin_settings,
+};
+use crate::core::post_action;
+use crate::core::registry::all_plugins;
+use crate::core::store;
+
+const RECIPES_FILE: &str = "recipes.json";
+
+/// Когда выполнять шаг в зависимости от итога предыдущего выполненного шага.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum StepCondition {
+    #[default]
+    Always,
+    PreviousSucceeded,
+    PreviousFailed,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RecipeStep {
+    pub plugin_id: String,
+    #[serde(default)]
+    pub settings: Value,
+    /// Ошибка шага не останавливает рецепт.
+    #[serde(default)]
+    pub continue_on_error: bool,
+    #[serde(default)]
+    pub condition: StepCondition,
+}
+
+/// Цепочка плагинов, которая запускается как один запуск.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Recipe {
+    /// Пустой у нового рецепта: присваивается при сохранении.
+    #[serde(default)]
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub steps: Vec<RecipeStep>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum StepStatus {
+    Succeeded,
+    Failed,
+    Skipped,
+}
+
+/// Итог одного шага в общем `RunResult` рецепта.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct StepResult {
+    pub plugin_id: String,
+    pub plugin_name: String,
+    pub status: StepStatus,
+    pub message: String,
+}
+
+fn recipes_path(data_dir: &Path) -> PathBuf {
+    data_dir.join(RECIPES_FILE)
+}
+
+fn require_data_dir(env: &Environment) -> Result<&Path, CoreError> {
+    env.data_dir
+        .as_deref()
+        .ok_or_else(|| CoreError::Plugin("Папка данных недоступна: рецепты не сохраняются.".into()))
+}
+
+pub fn list_recipes(env: &Environment) -> Vec<Recipe> {
+    match env.data_dir.as_deref() {
+        Some(data_dir) => store::load(&recipes_path(data_dir)),
+        None => Vec::new(),
+    }
+}
+
+pub fn find_recipe(env: &Environment, id: &str) -> Result<Recipe, CoreError> {
+    list_recipes(env)
+        .into_iter()
+        .find(|recipe| recipe.id == id)
+        .ok_or_else(|| CoreError::Plugin(format!("Рецепт не найден: {id}")))
+}
+
+/// Создаёт рецепт (пустой `id`) или заменяет существующий с тем же `id`.
+pub fn save_recipe(env: &Environment, mut recipe: Recipe) -> Result<Recipe, CoreError> {
+    let data_dir = require_data_dir(env)?;
+    validate_recipe(env, &recipe)?;
+
+    store::update(&recipes_path(data_dir), |recipes: &mut Vec<Recipe>| {
+        if recipe.id.is_empty() {
+            let ids = recipes.iter().map(|existing| existing.id.as_str());
+            recipe.id = store::next_id("recipe", ids);
+            recipes.push(recipe.clone());
+            return Ok(recipe);
+        }
+        let existing = recipes
+            .iter_mut()
+            .find(|existing| existing.id == recipe.id)
+            .ok_or_else(|| CoreError::Plugin(format!("Рецепт не найден: {}", recipe.id)))?;
+        *existing = recipe.clone();
+        Ok(recipe)
+    })
+}
+
+pub fn delete_recipe(env: &Environment, id: &str) -> Result<(), CoreError> {
+    let data_dir = require_data_dir(env)?;
+    store::update(&recipes_path(data_dir), |recipes: &mut Vec<Recipe>| {
+        let before = recipes.len();
+        recipes.retain(|recipe| recipe.id != id);
+        if recipes.len() == before {
+            return Err(CoreError::Plugin(format!("Рецепт не найден: {id}")));
+        }
+        Ok(())
+    })
+}
+
+/// Добавляет рецепт из файла как новый: идентификатор из файла не используется.
+pub fn import_recipe(env: &Environment, path: &Path) -> Result<Recipe, CoreError> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("Не удалось прочитать {}: {e}", path.display()))?;
+    let mut recipe: Recipe = serde_json::from_str(&content)
+        .map_err(|e| format!("Файл {} не является рецептом: {e}", path.display()))?;
+    recipe.id.clear();
+    save_recipe(env, recipe)
+}
+
+pub fn export_recipe(env: &Environment, id: &str, path: &Path) -> Result<(), CoreError> {
+    let recipe = find_recipe(env, id)?;
+    let content =
+        serde_json::to_string_pretty(&recipe).map_err(|e| format!("Ошибка записи рецепта: {e}"))?;
+    fs::write(path, content)
+        .map_err(|e| CoreError::Plugin(format!("Не удалось записать {}: {e}", path.display())))
+}
+
+/// Название плагина для шагов, которые не запускались; неизвестный плагин — по `id`.
+fn plugin_name(env: &Environment, id: &str) -> String {
+    all_plugins(env)
+        .into_iter()
+        .map(|plugin| plugin.meta())
+        .find(|meta| meta.id == id)
+        .map_or_else(|| id.to_string(), |meta| meta.name)
+}
+
+fn validate_recipe(env: &Environment, recipe: &Recipe) -> Result<(), CoreError> {
+    if recipe.name.trim().is_empty() {
+        return Err(CoreError::Plugin("Укажите название рецепта.".into()));
+    }
+    if recipe.steps.is_empty() {
+        return Err(CoreError::Plugin("В рецепте нет ни одного шага.".into()));
+    }
+    for (index, step) in recipe.steps.iter().enumerate() {
+        let errors = validate_plugin_settings(env, &step.plugin_id, &step.settings)?;
+        if !errors.is_empty() {
+            return Err(CoreError::Plugin(format!(
+                "Шаг {}: {}",
+                index + 1,
+                errors.join("; ")
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Выполняет шаги по порядку и собирает общий `RunResult`: журнал каждого шага
+/// вложен под его заголовком. `run_step` решает, где выполнить плагин (в процессе
+/// или в хосте); каждый выполненный шаг записывается в историю. Шаг получает свой
+/// токен отмены, дочерний к `cancel`: таймаут шага не отменяет следующие шаги.
+pub fn run_recipe(
+    env: &Environment,
+    recipe: &Recipe,
+    cancel: &CancelToken,
+    mut run_step: impl FnMut(&RecipeStep, &CancelToken) -> Result<Execution, CoreError>,
+) -> RunResult {
+    let mut logger = Logger::new();
+    let mut steps = Vec::with_capacity(recipe.steps.len());
+    let mut previous_ok = true;
+    let mut stopped: Option<String> = None;
+    let mut post_actions = Vec::new();
+    let total = recipe.steps.len();
+
+    logger.section(format!("Рецепт: {}", recipe.name));
+    if !recipe.description.is_empty() {
+        logger.info(recipe.description.clone());
+    }
+
+    for (index, step) in recipe.steps.iter().enumerate() {
+        let number = index + 1;
+        let skip_reason = if let Some(reason) = &stopped {
+            Some(reason.clone())
+        } else if cancel.is_cancelled() {
+            Some("запуск отменён".to_string())
+        } else {
+            match step.condition {
+                StepCondition::PreviousSucceeded if !previous_ok => {
+                    Some("предыдущий шаг завершился ошибкой".to_string())
+                }
+                StepCondition::PreviousFailed if previous_ok => {
+                    Some("предыдущий шаг выполнен успешно".to_string())
+                }
+                _ => None,
+            }
+        };
+
+        if let Some(reason) = skip_reason {
+            let plugin_name = plugin_name(env, &step.plugin_id);
+            logger.push_detail(
+                LogLevel::Warn,
+                format!("Шаг {number}/{total}: {plugin_name} — пропущен"),
+                Some(reason.clone()),
+            );
+            steps.push(StepResult {
+                plugin_id: step.plugin_id.clone(),
+                plugin_name,
+                status: StepStatus::Skipped,
+                message: reason,
+            });
+            continue;
+        }
+
+        let (plugin_name, ok, message) = match run_step(step, &cancel.child()) {
+            Ok(execution) => {
+                record_execution(env, &execution);
+                post_actions.extend(execution.result.post_actions);
+                logger.section(format!("Шаг {number}/{total}: {}", execution.plugin_name));
+                logger.with_indent(|logger| logger.append(execution.result.logs));
+                (
+                    execution.plugin_name,
+                    execution.result.ok,
+                    execution.result.message,
+                )
+            }
+            Err(err) => {
+                let plugin_name = plugin_name(env, &step.plugin_id);
+                logger.section(format!("Шаг {number}/{total}: {plugin_name}"));
+                logger.with_indent(|logger| logger.error(err.to_string()));
+                (plugin_name, false, err.to_string())
+            }
+        };
+
+        previous_ok = ok;
+        if !ok && !step.continue_on_error {
+            stopped = Some(format!("рецепт остановлен на шаге {number}"));
+        }
+        steps.push(StepResult {
+            plugin_id: step.plugin_id.clone(),
+            plugin_name,
+            status: if ok {
+                StepStatus::Succeeded
+            } else {
+                StepStatus::Failed
+            },
+            message,
+        });
+    }
+
+    let succeeded = steps
+        .iter()
+        .filter(|step| step.status == StepStatus::Succeeded)
+        .count();
+    let (ok, message) = if cancel.is_cancelled() {
+        logger.warn("Рецепт отменён.");
+        (false, "Отменено.".to_string())
+    } else if let Some(reason) = stopped {
+        logger.error(format!("Рецепт не выполнен: {reason}."));
+        (false, format!("Остановлено: {reason}."))
+    } else {
+        logger.success(format!(
+            "Рецепт выполнен: успешно {succeeded} из {total} шагов."
+        ));
+        (true, "Готово.".to_string())
+    };
+
+    let post_actions = post_action::merge(post_actions);
+    log_post_actions(&mut logger, &post_actions);
+
+    RunResult {
+        ok,
+        message,
+        logs: logger.into_logs(),
+        steps,
+        post_actions,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recipe(plugin_ids: &[&str]) -> Recipe {
+        Recipe {
+            id: "recipe-1".to_string(),
+            name: "Тест".to_string(),
+            description: String::new(),
+            steps: plugin_ids
+                .iter()
+                .map(|id| RecipeStep {
+                    plugin_id: id.to_string(),
+                    settings: Value::Null,
+                    continue_on_error: true,
+                    condition: StepCondition::Always,
+                })
+                .collect(),
+        }
+    }
+
+    fn failed(id: &str) -> Execution {
+        Execution {
+            plugin_id: id.to_string(),
+            plugin_name: id.to_string(),
+            started_at: 0,
+            duration_ms: 0,
+            panicked: false,
+            timed_out: true,
+            host_crashed: false,
+            result: RunResult {
+                ok: false,
+                message: "Превышено время выполнения.".to_string(),
+                logs: Vec::new(),
+                steps: Vec::new(),
+                post_actions: Vec::new(),
+            },
+        }
+    }
+
+    #[test]
+    fn step_timeout_does_not_cancel_next_steps() {
+        let env = Environment::detect().with_data_dir(None);
+        let cancel = CancelToken::default();
+        let mut started = Vec::new();
+
+        let result = run_recipe(
+            &env,
+            &recipe(&["first", "second"]),
+            &cancel,
+            |step, token| {
+                assert!(!token.is_cancelled());
+                // Так сторожевой таймер останавливает шаг.
+                token.cancel();
+                started.push(step.plugin_id.clone());
+                Ok(failed(&step.plugin_id))
+            },
+        );
+
+        assert_eq!(started, ["first", "second"]);
+        assert!(!cancel.is_cancelled());
+        assert!(
+            result
+                .steps
+                .iter()
+                .all(|step| step.status == StepStatus::Failed)
+        );
+    }
+
+    #[test]
+    fn cancelling_recipe_skips_remaining_steps() {
+        let env = Environment::detect().with_data_dir(None);
+        let cancel = CancelToken::default();
+
+        let result = run_recipe(
+            &env,
+            &recipe(&["first", "second"]),
+            &cancel,
+            |step, token| {
+                cancel.cancel();
+                assert!(token.is_cancelled());
+                Ok(failed(&step.plugin_id))
+            },
+        );
+
+        assert_eq!(result.message, "Отменено.");
+        assert!(result.steps[1].status == StepStatus::Skipped);
+    }
+}
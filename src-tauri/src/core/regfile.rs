@@ -0,0 +1,788 @@
+use encoding_rs::WINDOWS_1251;
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    path::Path,
+    sync::{Mutex, MutexGuard},
+};
+
+use crate::core::backend::Registry;
+use crate::core::error::CoreError;
+use crate::core::hive::{
+    Hive, REG_EXPAND_SZ, REG_MULTI_SZ, REG_SZ, RegistryKey, RegistryValue, RegistryView,
+};
+
+const HEADER_REGEDIT4: &str = "REGEDIT4";
+const HEADER_REGEDIT5: &str = "Windows Registry Editor Version 5.00";
+/// Ширина строки, после которой hex-данные переносятся, как у regedit.
+const LINE_WIDTH: usize = 80;
+
+/// Версия формата `.reg`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum RegFormat {
+    /// `REGEDIT4`: файл в кодировке ANSI (windows-1251), строки в hex — тоже.
+    Regedit4,
+    /// `Windows Registry Editor Version 5.00`: UTF-16LE с BOM.
+    #[default]
+    Regedit5,
+}
+
+/// Раздел файла: `[ключ]` со значениями или `[-ключ]` — удаление ключа с подключами.
+#[derive(Clone, PartialEq, Debug)]
+pub struct RegSection {
+    pub key: RegistryKey,
+    pub delete: bool,
+    /// Пустое имя — значение по умолчанию (`@`), `None` — удаление значения (`"name"=-`).
+    pub values: Vec<(String, Option<RegistryValue>)>,
+}
+
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct RegFile {
+    pub format: RegFormat,
+    pub sections: Vec<RegSection>,
+}
+
+impl RegFile {
+    /// Разбирает содержимое файла: UTF-16LE (с BOM или без), UTF-8 или ANSI.
+    pub fn decode(bytes: &[u8]) -> Result<Self, String> {
+        let text = if let Some(rest) = bytes.strip_prefix(&[0xFF, 0xFE]) {
+            utf16_text(rest)
+        } else if let Some(rest) = bytes.strip_prefix(b"\xEF\xBB\xBF") {
+            String::from_utf8_lossy(rest).into_owned()
+        } else if bytes.get(1) == Some(&0) {
+            utf16_text(bytes)
+        } else {
+            match std::str::from_utf8(bytes) {
+                Ok(text) => text.to_string(),
+                Err(_) => WINDOWS_1251
+                    .decode_without_bom_handling(bytes)
+                    .0
+                    .into_owned(),
+            }
+        };
+        Self::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut lines = logical_lines(text)
+            .into_iter()
+            .filter(|(_, line)| !line.trim().is_empty());
+        let format = match lines.next() {
+            Some((_, line)) if line.trim() == HEADER_REGEDIT5 => RegFormat::Regedit5,
+            Some((_, line)) if line.trim() == HEADER_REGEDIT4 => RegFormat::Regedit4,
+            Some((_, line)) => {
+                return Err(format!(
+                    "Это не файл реестра: первая строка «{}».",
+                    line.trim()
+                ));
+            }
+            None => return Err("Файл реестра пуст.".to_string()),
+        };
+
+        let mut sections: Vec<RegSection> = Vec::new();
+        for (number, line) in lines {
+            let line = line.trim();
+            if line.starts_with(';') {
+                continue;
+            }
+            let at = |error: String| format!("Строка {number}: {error}");
+            if let Some(inner) = line.strip_prefix('[') {
+                let inner = inner
+                    .strip_suffix(']')
+                    .ok_or_else(|| at("нет закрывающей скобки ].".to_string()))?;
+                let (delete, path) = match inner.strip_prefix('-') {
+                    Some(path) => (true, path),
+                    None => (false, inner),
+                };
+                sections.push(RegSection {
+                    key: parse_key(path).map_err(at)?,
+                    delete,
+                    values: Vec::new(),
+                });
+                continue;
+            }
+            let section = sections
+                .last_mut()
+                .ok_or_else(|| at("значение до первого ключа.".to_string()))?;
+            let (name, value) = parse_value(line, format).map_err(at)?;
+            // Как и regedit, значения в разделе удаляемого ключа пропускаются.
+            if !section.delete {
+                section.values.push((name, value));
+            }
+        }
+        Ok(Self { format, sections })
+    }
+
+    /// Текст файла с переводами строк Windows.
+    pub fn to_text(&self) -> String {
+        let mut output = String::new();
+        output.push_str(match self.format {
+            RegFormat::Regedit4 => HEADER_REGEDIT4,
+            RegFormat::Regedit5 => HEADER_REGEDIT5,
+        });
+        output.push_str("\r\n");
+        for section in &self.sections {
+            let mark = if section.delete { "-" } else { "" };
+            output.push_str(&format!("\r\n[{mark}{}]\r\n", key_name(&section.key)));
+            for (name, value) in &section.values {
+                let name = if name.is_empty() {
+                    "@".to_string()
+                } else {
+                    quote(name)
+                };
+                output.push_str(&self.value_line(&name, value.as_ref()));
+                output.push_str("\r\n");
+            }
+        }
+        output.push_str("\r\n");
+        output
+    }
+
+    /// Байты файла: UTF-16LE с BOM для версии 5.00, ANSI для REGEDIT4.
+    pub fn encode(&self) -> Vec<u8> {
+        let text = self.to_text();
+        match self.format {
+            RegFormat::Regedit4 => WINDOWS_1251.encode(&text).0.into_owned(),
+            RegFormat::Regedit5 => [0xFF, 0xFE]
+                .into_iter()
+                .chain(text.encode_utf16().flat_map(u16::to_le_bytes))
+                .collect(),
+        }
+    }
+
+    /// В `.reg` нет понятия вида реестра: он задаётся при применении.
+    pub fn with_view(mut self, view: RegistryView) -> Self {
+        for section in &mut self.sections {
+            section.key.view = view;
+        }
+        self
+    }
+
+    fn value_line(&self, name: &str, value: Option<&RegistryValue>) -> String {
+        match value {
+            None => format!("{name}=-"),
+            Some(RegistryValue::String(text)) if !text.contains(['\r', '\n', '\0']) => {
+                format!("{name}={}", quote(text))
+            }
+            Some(RegistryValue::Dword(value)) => format!("{name}=dword:{value:08x}"),
+            Some(RegistryValue::Binary(data)) => hex_line(&format!("{name}=hex:"), data),
+            Some(value) => {
+                let data = match self.format {
+                    RegFormat::Regedit4 => ansi_bytes(value).unwrap_or_else(|| value.to_bytes()),
+                    RegFormat::Regedit5 => value.to_bytes(),
+                };
+                hex_line(&format!("{name}=hex({:x}):", value.kind()), &data)
+            }
+        }
+    }
+}
+
+/// Что меняется в реестре при применении файла.
+#[derive(Serialize, Clone, PartialEq, Debug)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RegChange {
+    CreateKey {
+        key: RegistryKey,
+    },
+    DeleteKey {
+        key: RegistryKey,
+    },
+    SetValue {
+        key: RegistryKey,
+        name: String,
+        old: Option<RegistryValue>,
+        new: RegistryValue,
+    },
+    DeleteValue {
+        key: RegistryKey,
+        name: String,
+        old: RegistryValue,
+    },
+}
+
+impl fmt::Display for RegChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value_name = |name: &str| {
+            if name.is_empty() {
+                "(по умолчанию)".to_string()
+            } else {
+                name.to_string()
+            }
+        };
+        match self {
+            RegChange::CreateKey { key } => write!(f, "Создание ключа {key}"),
+            RegChange::DeleteKey { key } => write!(f, "Удаление ключа {key}"),
+            RegChange::SetValue {
+                key,
+                name,
+                old: Some(old),
+                new,
+            } => write!(f, "{key}: {} = {new} (было {old})", value_name(name)),
+            RegChange::SetValue {
+                key,
+                name,
+                old: None,
+                new,
+            } => write!(f, "{key}: {} = {new}", value_name(name)),
+            RegChange::DeleteValue { key, name, .. } => {
+                write!(f, "{key}: удаление {}", value_name(name))
+            }
+        }
+    }
+}
+
+/// Ключ со всеми подключами и значениями в виде файла версии 5.00.
+pub fn export(registry: &dyn Registry, key: &RegistryKey) -> Result<RegFile, String> {
+    if !registry.key_exists(key)? {
+        return Err(format!("Ключ {key} не найден."));
+    }
+    let mut file = RegFile::default();
+    export_section(registry, key, &mut file.sections)?;
+    Ok(file)
+}
+
+fn export_section(
+    registry: &dyn Registry,
+    key: &RegistryKey,
+    sections: &mut Vec<RegSection>,
+) -> Result<(), String> {
+    let values = registry
+        .values(key)?
+        .into_iter()
+        .map(|(name, value)| (name, Some(value)))
+        .collect();
+    sections.push(RegSection {
+        key: key.clone(),
+        delete: false,
+        values,
+    });
+    let mut subkeys = registry.subkeys(key)?;
+    subkeys.sort_by_key(|name| name.to_lowercase());
+    for name in subkeys {
+        export_section(registry, &key.join(&name), sections)?;
+    }
+    Ok(())
+}
+
+/// Применяет файл и возвращает то, что действительно изменилось: совпадающие значения
+/// не перезаписываются.
+pub fn apply(registry: &dyn Registry, file: &RegFile) -> Result<Vec<RegChange>, String> {
+    let mut changes = Vec::new();
+    for section in &file.sections {
+        let key = &section.key;
+        let exists = registry.key_exists(key)?;
+        if section.delete {
+            if exists {
+                registry.delete_key(key)?;
+                changes.push(RegChange::DeleteKey { key: key.clone() });
+            }
+            continue;
+        }
+        if !exists {
+            registry.create_key(key)?;
+            changes.push(RegChange::CreateKey { key: key.clone() });
+        }
+        let mut current = registry.values(key)?;
+        for (name, value) in &section.values {
+            let position = current
+                .iter()
+                .position(|(existing, _)| existing.to_lowercase() == name.to_lowercase());
+            let old = position.map(|index| current.remove(index).1);
+            match (value, old) {
+                (Some(new), Some(old)) if *new == old => current.push((name.clone(), old)),
+                (Some(new), old) => {
+                    registry.set_value(key, name, new)?;
+                    current.push((name.clone(), new.clone()));
+                    changes.push(RegChange::SetValue {
+                        key: key.clone(),
+                        name: name.clone(),
+                        old,
+                        new: new.clone(),
+                    });
+                }
+                (None, Some(old)) => {
+                    registry.delete_value(key, name)?;
+                    changes.push(RegChange::DeleteValue {
+                        key: key.clone(),
+                        name: name.clone(),
+                        old,
+                    });
+                }
+                (None, None) => {}
+            }
+        }
+    }
+    Ok(changes)
+}
+
+/// Что изменит `apply`, без записи в реестр: затронутые ключи копируются в память,
+/// и файл применяется к копии.
+pub fn preview(registry: &dyn Registry, file: &RegFile) -> Result<Vec<RegChange>, String> {
+    let copy = Snapshot::default();
+    for section in &file.sections {
+        let key = &section.key;
+        if registry.key_exists(key)? {
+            copy.keys().insert(snapshot_key(key), registry.values(key)?);
+        }
+    }
+    apply(&copy, file)
+}
+
+/// Копия ключей из файла для `preview`. Умеет только то, что нужно `apply`.
+#[derive(Default)]
+struct Snapshot(Mutex<BTreeMap<SnapshotKey, Vec<(String, RegistryValue)>>>);
+
+/// Раздел, вид (32-битный или нет) и путь в нижнем регистре.
+type SnapshotKey = (Hive, bool, String);
+
+fn snapshot_key(key: &RegistryKey) -> SnapshotKey {
+    (
+        key.hive,
+        key.view == RegistryView::Bits32,
+        key.path.to_lowercase(),
+    )
+}
+
+impl Snapshot {
+    fn keys(&self) -> MutexGuard<'_, BTreeMap<SnapshotKey, Vec<(String, RegistryValue)>>> {
+        self.0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Registry for Snapshot {
+    fn get_value(&self, key: &RegistryKey, name: &str) -> Result<RegistryValue, String> {
+        self.values(key)?
+            .into_iter()
+            .find(|(existing, _)| existing.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value)
+            .ok_or_else(|| format!("Значение {name} не найдено."))
+    }
+
+    fn set_value(
+        &self,
+        key: &RegistryKey,
+        name: &str,
+        value: &RegistryValue,
+    ) -> Result<(), String> {
+        let mut keys = self.keys();
+        let values = keys
+            .get_mut(&snapshot_key(key))
+            .ok_or_else(|| format!("Ключ {key} не найден."))?;
+        values.retain(|(existing, _)| existing.to_lowercase() != name.to_lowercase());
+        values.push((name.to_string(), value.clone()));
+        Ok(())
+    }
+
+    fn delete_value(&self, key: &RegistryKey, name: &str) -> Result<(), String> {
+        if let Some(values) = self.keys().get_mut(&snapshot_key(key)) {
+            values.retain(|(existing, _)| existing.to_lowercase() != name.to_lowercase());
+        }
+        Ok(())
+    }
+
+    fn key_exists(&self, key: &RegistryKey) -> Result<bool, String> {
+        Ok(self.keys().contains_key(&snapshot_key(key)))
+    }
+
+    fn create_key(&self, key: &RegistryKey) -> Result<(), String> {
+        self.keys().entry(snapshot_key(key)).or_default();
+        Ok(())
+    }
+
+    fn delete_key(&self, key: &RegistryKey) -> Result<(), String> {
+        let (hive, bits32, path) = snapshot_key(key);
+        let prefix = format!("{path}\\");
+        self.keys().retain(|(other_hive, other_bits32, other), _| {
+            *other_hive != hive
+                || *other_bits32 != bits32
+                || (*other != path && !other.starts_with(&prefix))
+        });
+        Ok(())
+    }
+
+    fn subkeys(&self, key: &RegistryKey) -> Result<Vec<String>, String> {
+        let (hive, bits32, path) = snapshot_key(key);
+        let prefix = format!("{path}\\");
+        Ok(self
+            .keys()
+            .keys()
+            .filter(|(other_hive, other_bits32, _)| *other_hive == hive && *other_bits32 == bits32)
+            .filter_map(|(_, _, other)| other.strip_prefix(&prefix))
+            .filter(|rest| !rest.contains('\\'))
+            .map(str::to_string)
+            .collect())
+    }
+
+    fn values(&self, key: &RegistryKey) -> Result<Vec<(String, RegistryValue)>, String> {
+        self.keys()
+            .get(&snapshot_key(key))
+            .cloned()
+            .ok_or_else(|| format!("Ключ {key} не найден."))
+    }
+}
+
+pub fn read_file(path: &Path) -> Result<RegFile, CoreError> {
+    let bytes =
+        fs::read(path).map_err(|e| format!("Не удалось прочитать {}: {e}", path.display()))?;
+    RegFile::decode(&bytes)
+        .map_err(|e| CoreError::Plugin(format!("Ошибка в файле {}: {e}", path.display())))
+}
+
+pub fn export_to_file(
+    registry: &dyn Registry,
+    key: &RegistryKey,
+    path: &Path,
+) -> Result<(), CoreError> {
+    let file = export(registry, key)?;
+    fs::write(path, file.encode())
+        .map_err(|e| CoreError::Plugin(format!("Не удалось записать {}: {e}", path.display())))
+}
+
+pub fn preview_file(
+    registry: &dyn Registry,
+    path: &Path,
+    view: RegistryView,
+) -> Result<Vec<RegChange>, CoreError> {
+    let file = read_file(path)?.with_view(view);
+    Ok(preview(registry, &file)?)
+}
+
+pub fn apply_file(
+    registry: &dyn Registry,
+    path: &Path,
+    view: RegistryView,
+) -> Result<Vec<RegChange>, CoreError> {
+    let file = read_file(path)?.with_view(view);
+    Ok(apply(registry, &file)?)
+}
+
+/// Строки файла с номерами; строки, оканчивающиеся на `\`, склеиваются со следующими.
+fn logical_lines(text: &str) -> Vec<(usize, String)> {
+    let mut lines = Vec::new();
+    let mut pending: Option<(usize, String)> = None;
+    for (index, line) in text.lines().enumerate() {
+        let (number, mut joined) = match pending.take() {
+            Some((number, start)) => (number, start + line.trim_start()),
+            None => (index + 1, line.to_string()),
+        };
+        if joined.trim_end().ends_with('\\') {
+            joined.truncate(joined.trim_end().len() - 1);
+            pending = Some((number, joined));
+        } else {
+            lines.push((number, joined));
+        }
+    }
+    lines.extend(pending);
+    lines
+}
+
+/// В файле раздел всегда указан явно: `HKEY_CURRENT_USER\Software\...`.
+fn parse_key(text: &str) -> Result<RegistryKey, String> {
+    let text = text.trim();
+    let root = text.split('\\').next().unwrap_or_default();
+    if Hive::parse(root).is_none() {
+        return Err(format!("неизвестный раздел реестра «{root}»."));
+    }
+    Ok(RegistryKey::parse(text))
+}
+
+fn key_name(key: &RegistryKey) -> String {
+    if key.path.is_empty() {
+        key.hive.full_name().to_string()
+    } else {
+        format!("{}\\{}", key.hive.full_name(), key.path)
+    }
+}
+
+fn parse_value(line: &str, format: RegFormat) -> Result<(String, Option<RegistryValue>), String> {
+    let (name, rest) = if let Some(rest) = line.strip_prefix('@') {
+        (String::new(), rest)
+    } else if let Some(rest) = line.strip_prefix('"') {
+        unquote(rest)?
+    } else {
+        return Err(format!("непонятная строка «{line}»."));
+    };
+    let data = rest
+        .trim_start()
+        .strip_prefix('=')
+        .ok_or_else(|| format!("нет знака = после имени «{name}»."))?
+        .trim();
+
+    let value = if data == "-" {
+        None
+    } else if let Some(rest) = data.strip_prefix('"') {
+        let (text, tail) = unquote(rest)?;
+        if !tail.trim().is_empty() {
+            return Err(format!("лишние символы после строки: «{}».", tail.trim()));
+        }
+        Some(RegistryValue::String(text))
+    } else if let Some(number) = data.strip_prefix("dword:") {
+        let number = u32::from_str_radix(number.trim(), 16)
+            .map_err(|_| format!("некорректное значение dword «{number}»."))?;
+        Some(RegistryValue::Dword(number))
+    } else if let Some(bytes) = data.strip_prefix("hex:") {
+        Some(RegistryValue::Binary(parse_hex(bytes)?))
+    } else if let Some(rest) = data.strip_prefix("hex(") {
+        let (kind, bytes) = rest
+            .split_once("):")
+            .ok_or_else(|| format!("некорректный тип в «{data}»."))?;
+        let kind = u32::from_str_radix(kind.trim(), 16)
+            .map_err(|_| format!("некорректный тип «{kind}»."))?;
+        let bytes = parse_hex(bytes)?;
+        Some(match format {
+            RegFormat::Regedit4 if matches!(kind, REG_SZ | REG_EXPAND_SZ | REG_MULTI_SZ) => {
+                let text = WINDOWS_1251.decode_without_bom_handling(&bytes).0;
+                let utf16: Vec<u8> = text.encode_utf16().flat_map(u16::to_le_bytes).collect();
+                RegistryValue::from_bytes(kind, &utf16)
+            }
+            _ => RegistryValue::from_bytes(kind, &bytes),
+        })
+    } else {
+        return Err(format!("неизвестный формат данных «{data}»."));
+    };
+    Ok((name, value))
+}
+
+fn parse_hex(text: &str) -> Result<Vec<u8>, String> {
+    text.split(',')
+        .map(str::trim)
+        .filter(|byte| !byte.is_empty())
+        .map(|byte| {
+            u8::from_str_radix(byte, 16).map_err(|_| format!("некорректный байт «{byte}»."))
+        })
+        .collect()
+}
+
+/// Строка после открывающей кавычки: текст без экранирования и остаток после закрывающей.
+fn unquote(text: &str) -> Result<(String, &str), String> {
+    let mut result = String::new();
+    let mut chars = text.char_indices();
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '"' => return Ok((result, &text[index + 1..])),
+            '\\' => match chars.next() {
+                Some((_, escaped)) => result.push(escaped),
+                None => break,
+            },
+            c => result.push(c),
+        }
+    }
+    Err("нет закрывающей кавычки.".to_string())
+}
+
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn hex_line(prefix: &str, data: &[u8]) -> String {
+    let mut output = prefix.to_string();
+    let mut width = prefix.chars().count();
+    for (index, byte) in data.iter().enumerate() {
+        let chunk = if index + 1 == data.len() {
+            format!("{byte:02x}")
+        } else {
+            format!("{byte:02x},")
+        };
+        if width + chunk.len() >= LINE_WIDTH - 1 {
+            output.push_str("\\\r\n  ");
+            width = 2;
+        }
+        output.push_str(&chunk);
+        width += chunk.len();
+    }
+    output
+}
+
+/// Строковые значения в REGEDIT4 хранятся однобайтовыми строками.
+fn ansi_bytes(value: &RegistryValue) -> Option<Vec<u8>> {
+    let text = match value {
+        RegistryValue::String(text) | RegistryValue::ExpandString(text) => format!("{text}\0"),
+        RegistryValue::MultiString(lines) => {
+            lines
+                .iter()
+                .map(|line| format!("{line}\0"))
+                .collect::<String>()
+                + "\0"
+        }
+        _ => return None,
+    };
+    Some(WINDOWS_1251.encode(&text).0.into_owned())
+}
+
+fn utf16_text(data: &[u8]) -> String {
+    let units: Vec<u16> = data
+        .chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::fake::MemoryRegistry;
+
+    const SAMPLE: &str = "Windows Registry Editor Version 5.00\r\n\
+        \r\n\
+        ; комментарий\r\n\
+        [-HKEY_CURRENT_USER\\Software\\Old]\r\n\
+        \"Ignored\"=\"x\"\r\n\
+        \r\n\
+        [HKEY_LOCAL_MACHINE\\Software\\Vendor]\r\n\
+        @=\"По умолчанию\"\r\n\
+        \"Path\"=\"C:\\\\Program Files\\\\\\\"App\\\"\"\r\n\
+        \"Count\"=dword:0000002a\r\n\
+        \"Big\"=hex(b):01,00,00,00,00,00,00,00\r\n\
+        \"Data\"=hex:de,ad,\\\r\n\
+        \x20 be,ef\r\n\
+        \"Expand\"=hex(2):25,00,54,00,25,00,00,00\r\n\
+        \"Lines\"=hex(7):61,00,00,00,62,00,00,00,00,00\r\n\
+        \"Gone\"=-\r\n";
+
+    #[test]
+    fn parses_all_value_kinds() {
+        let file = RegFile::parse(SAMPLE).unwrap();
+        assert_eq!(file.format, RegFormat::Regedit5);
+        assert_eq!(file.sections.len(), 2);
+        assert!(file.sections[0].delete);
+        assert!(file.sections[0].values.is_empty());
+
+        let section = &file.sections[1];
+        assert_eq!(section.key, RegistryKey::parse(r"HKLM\Software\Vendor"));
+        let string = |text: &str| Some(RegistryValue::String(text.to_string()));
+        assert_eq!(
+            section.values,
+            [
+                (String::new(), string("По умолчанию")),
+                ("Path".to_string(), string(r#"C:\Program Files\"App""#)),
+                ("Count".to_string(), Some(RegistryValue::Dword(42))),
+                ("Big".to_string(), Some(RegistryValue::Qword(1))),
+                (
+                    "Data".to_string(),
+                    Some(RegistryValue::Binary(vec![0xde, 0xad, 0xbe, 0xef]))
+                ),
+                (
+                    "Expand".to_string(),
+                    Some(RegistryValue::ExpandString("%T%".to_string()))
+                ),
+                (
+                    "Lines".to_string(),
+                    Some(RegistryValue::MultiString(vec![
+                        "a".to_string(),
+                        "b".to_string()
+                    ]))
+                ),
+                ("Gone".to_string(), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn round_trips_through_both_formats() {
+        let mut file = RegFile::parse(SAMPLE).unwrap();
+        file.sections[1].values.push((
+            "Long".to_string(),
+            Some(RegistryValue::Binary((0..=255).collect())),
+        ));
+        for format in [RegFormat::Regedit5, RegFormat::Regedit4] {
+            file.format = format;
+            let bytes = file.encode();
+            assert_eq!(RegFile::decode(&bytes).unwrap(), file);
+        }
+        assert!(file.to_text().lines().all(|line| line.len() <= LINE_WIDTH));
+    }
+
+    #[test]
+    fn regedit4_strings_are_ansi() {
+        let text = "REGEDIT4\n\n[HKEY_CURRENT_USER\\Env]\n\"Path\"=hex(2):25,f2,25,00\n";
+        let file = RegFile::parse(text).unwrap();
+        assert_eq!(
+            file.sections[0].values[0].1,
+            Some(RegistryValue::ExpandString("%т%".to_string()))
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_header_and_root() {
+        assert!(RegFile::parse("[HKEY_CURRENT_USER\\A]").is_err());
+        let error = RegFile::parse("REGEDIT4\n[Software\\A]").unwrap_err();
+        assert!(error.starts_with("Строка 2"), "{error}");
+    }
+
+    #[test]
+    fn preview_reports_changes_without_writing() {
+        let registry = MemoryRegistry::default();
+        registry.set_value(r"HKLM\Software\Vendor", "Count", RegistryValue::Dword(42));
+        registry.set_value(r"HKLM\Software\Vendor", "Gone", RegistryValue::Dword(1));
+        registry.add_key(r"Software\Old\Child");
+        let file = RegFile::parse(SAMPLE).unwrap();
+
+        let planned = preview(&registry, &file).unwrap();
+        assert!(registry.has_key(r"Software\Old\Child"));
+        assert_eq!(
+            registry.value(r"HKLM\Software\Vendor", "Gone"),
+            Some(RegistryValue::Dword(1))
+        );
+
+        let applied = apply(&registry, &file).unwrap();
+        assert_eq!(planned, applied);
+        assert_eq!(
+            applied[0],
+            RegChange::DeleteKey {
+                key: RegistryKey::parse(r"Software\Old")
+            }
+        );
+        assert!(!applied.iter().any(|change| matches!(
+            change,
+            RegChange::SetValue { name, .. } if name == "Count"
+        )));
+        assert!(!registry.has_key(r"Software\Old"));
+        assert_eq!(registry.value(r"HKLM\Software\Vendor", "Gone"), None);
+        assert!(apply(&registry, &file).unwrap().is_empty());
+    }
+
+    #[test]
+    fn preview_follows_deleted_parent_and_recreated_child() {
+        let registry = MemoryRegistry::default();
+        registry.set_value(r"Software\App\Child", "Old", RegistryValue::Dword(1));
+        let text = "Windows Registry Editor Version 5.00\n\
+            [HKEY_CURRENT_USER\\Software\\App\\Child]\n\
+            \"old\"=dword:00000001\n\
+            [-HKEY_CURRENT_USER\\Software\\App]\n\
+            [HKEY_CURRENT_USER\\Software\\App\\Child]\n\
+            \"New\"=dword:00000002\n";
+        let file = RegFile::parse(text).unwrap();
+
+        let planned = preview(&registry, &file).unwrap();
+        assert!(registry.value(r"Software\App\Child", "New").is_none());
+        assert_eq!(planned, apply(&registry, &file).unwrap());
+        assert_eq!(planned.len(), 3);
+    }
+
+    #[test]
+    fn export_includes_subkeys() {
+        let registry = MemoryRegistry::default();
+        registry.set_value(
+            r"Software\App",
+            "Name",
+            RegistryValue::String("a".to_string()),
+        );
+        registry.set_value(r"Software\App\Colors", "Red", RegistryValue::Dword(1));
+
+        let file = export(&registry, &RegistryKey::parse(r"Software\App")).unwrap();
+        let text = file.to_text();
+        assert!(
+            text.contains("[HKEY_CURRENT_USER\\Software\\App\\Colors]\r\n\"Red\"=dword:00000001")
+        );
+
+        let copy = MemoryRegistry::default();
+        apply(&copy, &RegFile::decode(&file.encode()).unwrap()).unwrap();
+        assert_eq!(
+            copy.value(r"Software\App\Colors", "Red"),
+            Some(RegistryValue::Dword(1))
+        );
+    }
+}
@@ -1,3 +1,151 @@
-﻿use crate::core::plugin::Plugin;
+use serde_json::Value;
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::core::api::PluginApi;
+use crate::core::declarative;
+use crate::core::environment::Environment;
+use crate::core::logger::Logger;
+use crate::core::package::Trust;
+use crate::core::plugin::Plugin;
+use crate::core::script;
+use crate::core::settings::PluginMeta;
 
 include!(concat!(env!("OUT_DIR"), "/plugins.generated.rs"));
+
+const PLUGINS_DIR: &str = "plugins";
+
+/// Папка внешних плагинов в данных приложения.
+pub fn plugins_dir(env: &Environment) -> Option<PathBuf> {
+    env.data_dir.as_ref().map(|dir| dir.join(PLUGINS_DIR))
+}
+
+/// Встроенные плагины и плагины из папки `plugins`. Внешний плагин не может
+/// занять `id` уже зарегистрированного: вместо него в списке будет заглушка.
+/// Файлы, которым `Trust` не доверяет, не загружаются и тоже видны заглушками.
+/// С `Environment::builtin_only` папка `plugins` не читается.
+pub fn all_plugins(env: &Environment) -> Vec<Box<dyn Plugin>> {
+    load_plugins(env, &Trust::load(env))
+}
+
+pub(crate) fn load_plugins(env: &Environment, trust: &Trust) -> Vec<Box<dyn Plugin>> {
+    let mut plugins = builtin_plugins();
+    let mut ids: HashSet<String> = plugins.iter().map(|plugin| plugin.meta().id).collect();
+
+    let Some(dir) = plugins_dir(env).filter(|_| !env.builtin_only) else {
+        return plugins;
+    };
+    let (paths, refused) = plugin_files(&dir, trust);
+    let external = declarative::load_files(&paths)
+        .into_iter()
+        .chain(script::load_files(&paths));
+    #[cfg(feature = "wasm-plugins")]
+    let external = external.chain(crate::core::wasm::load_files(&paths));
+    #[cfg(feature = "native-plugins")]
+    let external = external.chain(crate::core::native::load_files(&paths));
+    for (file, plugin) in external {
+        let meta = plugin.meta();
+        if ids.insert(meta.id.clone()) {
+            plugins.push(plugin);
+        } else {
+            let reason = format!("Плагин с id «{}» уже зарегистрирован.", meta.id);
+            plugins.push(Box::new(Unavailable::new(&file, Some(meta), reason)));
+        }
+    }
+    for (file, reason) in refused {
+        plugins.push(Box::new(Unavailable::new(&file, None, reason)));
+    }
+    plugins
+}
+
+/// Встроен ли плагин в приложение. Только такие запускаются с повышенными правами.
+pub fn is_builtin(id: &str) -> bool {
+    builtin_plugins()
+        .iter()
+        .any(|plugin| plugin.meta().id == id)
+}
+
+/// Файлы папки плагинов по порядку имён: те, что можно загружать, и отклонённые
+/// с причиной. Файлы других видов загрузчики пропустят сами.
+fn plugin_files(dir: &Path, trust: &Trust) -> (Vec<PathBuf>, Vec<(String, String)>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return (Vec::new(), Vec::new());
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .collect();
+    paths.sort();
+
+    let mut allowed = Vec::new();
+    let mut refused = Vec::new();
+    for path in paths {
+        match trust.check(&path) {
+            Ok(()) => allowed.push(path),
+            Err(reason) => {
+                let file = path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                refused.push((file, reason));
+            }
+        }
+    }
+    (allowed, refused)
+}
+
+/// Внешний плагин, который не удалось загрузить. Идентификатором служит имя файла,
+/// поэтому он не пересекается с настоящими плагинами.
+pub struct Unavailable {
+    meta: PluginMeta,
+    reason: String,
+}
+
+impl Unavailable {
+    pub fn new(file: &str, meta: Option<PluginMeta>, reason: String) -> Self {
+        let meta = match meta {
+            Some(meta) => PluginMeta {
+                id: file.to_string(),
+                ..meta
+            },
+            None => PluginMeta {
+                id: file.to_string(),
+                name: file.to_string(),
+                description: String::new(),
+                category: "Внешние".to_string(),
+                settings: Vec::new(),
+                capabilities: Vec::new(),
+                platforms: Vec::new(),
+                timeout_secs: None,
+                tools: Vec::new(),
+            },
+        };
+        Self { meta, reason }
+    }
+}
+
+impl Plugin for Unavailable {
+    fn meta(&self) -> PluginMeta {
+        self.meta.clone()
+    }
+
+    fn run(&self, _api: &PluginApi, _settings: &Value, _logger: &mut Logger) -> Result<(), String> {
+        Err(self.reason.clone())
+    }
+
+    fn load_error(&self) -> Option<String> {
+        Some(self.reason.clone())
+    }
+}
+
+#[cfg(test)]
+mod conformance_tests {
+    include!(concat!(
+        env!("OUT_DIR"),
+        "/plugins_conformance.generated.rs"
+    ));
+}
@@ -0,0 +1,413 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+use crate::core::cron::CronExpr;
+use crate::core::disk;
+use crate::core::environment::Environment;
+use crate::core::error::CoreError;
+use crate::core::history::now_unix;
+use crate::core::jobs::{JobState, Jobs};
+use crate::core::plugin::{admin_requirements, validate_plugin_settings};
+use crate::core::store;
+
+const SCHEDULES_FILE: &str = "schedules.json";
+/// Как часто планировщик проверяет расписания.
+const TICK: Duration = Duration::from_secs(30);
+/// Пока места мало, расписание по свободному месту повторяется не чаще этого интервала.
+const LOW_DISK_COOLDOWN_SECS: u64 = 6 * 60 * 60;
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Trigger {
+    /// Выражение из пяти полей, см. `CronExpr`.
+    Cron {
+        expression: String,
+    },
+    Interval {
+        minutes: u64,
+    },
+    AppStart,
+    /// Срабатывает, когда на томе с `path` остаётся меньше `min_free_mb` мегабайт.
+    LowDiskSpace {
+        path: String,
+        min_free_mb: u64,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Schedule {
+    /// Пустой у нового расписания: присваивается при сохранении.
+    #[serde(default)]
+    pub id: String,
+    pub name: String,
+    pub plugin_id: String,
+    /// Сохранённые настройки плагина; недостающие поля берутся по умолчанию.
+    #[serde(default)]
+    pub settings: Value,
+    pub trigger: Trigger,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub created_at: u64,
+    /// Unix-время последнего запуска по расписанию.
+    #[serde(default)]
+    pub last_run: Option<u64>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// Расписание вместе с временем следующего запуска.
+#[derive(Serialize, Clone)]
+pub struct ScheduleInfo {
+    #[serde(flatten)]
+    pub schedule: Schedule,
+    /// `None` у выключенных и событийных расписаний (запуск приложения, свободное место).
+    pub next_run: Option<u64>,
+}
+
+impl ScheduleInfo {
+    fn new(schedule: Schedule) -> Self {
+        Self {
+            next_run: next_run(&schedule),
+            schedule,
+        }
+    }
+}
+
+/// Ближайший плановый запуск. Если он уже в прошлом (приложение было закрыто),
+/// планировщик выполнит расписание на следующей проверке.
+pub fn next_run(schedule: &Schedule) -> Option<u64> {
+    if !schedule.enabled {
+        return None;
+    }
+    let since = schedule.last_run.unwrap_or(schedule.created_at);
+    match &schedule.trigger {
+        Trigger::Cron { expression } => CronExpr::parse(expression).ok()?.next_after(since),
+        Trigger::Interval { minutes } => Some(since.saturating_add(minutes.saturating_mul(60))),
+        Trigger::AppStart | Trigger::LowDiskSpace { .. } => None,
+    }
+}
+
+fn schedules_path(data_dir: &Path) -> PathBuf {
+    data_dir.join(SCHEDULES_FILE)
+}
+
+/// Повреждённый или отсутствующий файл даёт пустой список.
+pub fn load(data_dir: &Path) -> Vec<Schedule> {
+    store::load(&schedules_path(data_dir))
+}
+
+fn require_data_dir(env: &Environment) -> Result<&Path, CoreError> {
+    env.data_dir.as_deref().ok_or_else(|| {
+        CoreError::Plugin("Папка данных недоступна: расписания не сохраняются.".into())
+    })
+}
+
+pub fn list_schedules(env: &Environment) -> Vec<ScheduleInfo> {
+    let Some(data_dir) = env.data_dir.as_deref() else {
+        return Vec::new();
+    };
+    load(data_dir).into_iter().map(ScheduleInfo::new).collect()
+}
+
+/// Создаёт расписание (пустой `id`) или заменяет существующее с тем же `id`.
+pub fn save_schedule(env: &Environment, mut schedule: Schedule) -> Result<ScheduleInfo, CoreError> {
+    let data_dir = require_data_dir(env)?;
+    validate_schedule(env, &schedule)?;
+
+    store::update(
+        &schedules_path(data_dir),
+        |schedules: &mut Vec<Schedule>| {
+            if schedule.id.is_empty() {
+                let ids = schedules.iter().map(|existing| existing.id.as_str());
+                schedule.id = store::next_id("schedule", ids);
+                schedule.created_at = now_unix();
+                schedule.last_run = None;
+                schedules.push(schedule.clone());
+                return Ok(ScheduleInfo::new(schedule));
+            }
+
+            let existing = schedules
+                .iter_mut()
+                .find(|existing| existing.id == schedule.id)
+                .ok_or_else(|| {
+                    CoreError::Plugin(format!("Расписание не найдено: {}", schedule.id))
+                })?;
+            schedule.created_at = existing.created_at;
+            if schedule.trigger != existing.trigger {
+                // Новое условие отсчитывается с момента изменения.
+                schedule.created_at = now_unix();
+                schedule.last_run = None;
+            } else {
+                schedule.last_run = existing.last_run;
+            }
+            *existing = schedule.clone();
+            Ok(ScheduleInfo::new(schedule))
+        },
+    )
+}
+
+pub fn delete_schedule(env: &Environment, id: &str) -> Result<(), CoreError> {
+    let data_dir = require_data_dir(env)?;
+    store::update(
+        &schedules_path(data_dir),
+        |schedules: &mut Vec<Schedule>| {
+            let before = schedules.len();
+            schedules.retain(|schedule| schedule.id != id);
+            if schedules.len() == before {
+                return Err(CoreError::Plugin(format!("Расписание не найдено: {id}")));
+            }
+            Ok(())
+        },
+    )
+}
+
+fn validate_schedule(env: &Environment, schedule: &Schedule) -> Result<(), CoreError> {
+    if schedule.name.trim().is_empty() {
+        return Err(CoreError::Plugin("Укажите название расписания.".into()));
+    }
+    match &schedule.trigger {
+        Trigger::Cron { expression } => {
+            CronExpr::parse(expression)
+                .map_err(|e| CoreError::Plugin(format!("Некорректное выражение cron: {e}")))?;
+        }
+        Trigger::Interval { minutes: 0 } => {
+            return Err(CoreError::Plugin(
+                "Интервал должен быть не меньше минуты.".into(),
+            ));
+        }
+        Trigger::LowDiskSpace { path, .. } if path.trim().is_empty() => {
+            return Err(CoreError::Plugin(
+                "Укажите диск или папку для проверки места.".into(),
+            ));
+        }
+        _ => {}
+    }
+
+    let errors = validate_plugin_settings(env, &schedule.plugin_id, &schedule.settings)?;
+    if !errors.is_empty() {
+        return Err(CoreError::Plugin(errors.join("\n")));
+    }
+
+    // Запуски по расписанию идут в процессе приложения и права не запрашивают.
+    let admin = admin_requirements(env, &schedule.plugin_id, &schedule.settings)?;
+    if !admin.is_empty() {
+        return Err(CoreError::Plugin(format!(
+            "По расписанию плагины запускаются без прав администратора, а они нужны: {}. \
+             Отключите эти настройки или запускайте плагин вручную.",
+            admin.join(", ")
+        )));
+    }
+    Ok(())
+}
+
+/// Запускает фоновый поток: сначала расписания «при запуске», затем проверка каждые `TICK`.
+/// Запуски идут через `Jobs`, поэтому попадают в историю и видны в API управления.
+pub fn start(env: Environment, jobs: Jobs) {
+    if env.data_dir.is_none() {
+        return;
+    }
+    thread::spawn(move || {
+        let mut scheduler = Scheduler {
+            env,
+            jobs,
+            running: HashMap::new(),
+        };
+        scheduler.tick(true);
+        loop {
+            thread::sleep(TICK);
+            scheduler.tick(false);
+        }
+    });
+}
+
+struct Scheduler {
+    env: Environment,
+    jobs: Jobs,
+    /// Задание, запущенное по расписанию: пока оно идёт, расписание не запускается снова.
+    running: HashMap<String, u64>,
+}
+
+impl Scheduler {
+    fn tick(&mut self, app_start: bool) {
+        let Some(data_dir) = self.env.data_dir.clone() else {
+            return;
+        };
+        let path = schedules_path(&data_dir);
+        let now = now_unix();
+        let due: Vec<Schedule> = load(&data_dir)
+            .into_iter()
+            .filter(|schedule| schedule.enabled && !self.is_running(&schedule.id))
+            .filter(|schedule| is_due(schedule, now, app_start))
+            .collect();
+
+        for schedule in due {
+            let marked = store::update(&path, |schedules: &mut Vec<Schedule>| {
+                let stored = schedules.iter_mut().find(|stored| stored.id == schedule.id);
+                Ok(stored.map(|stored| stored.last_run = Some(now)).is_some())
+            });
+            // Расписание удалили между чтением и запуском, или файл не записать:
+            // без отметки о запуске оно выполнялось бы на каждой проверке.
+            if !matches!(marked, Ok(true)) {
+                continue;
+            }
+            let job_id = self
+                .jobs
+                .start(&self.env, &schedule.plugin_id, schedule.settings);
+            self.running.insert(schedule.id, job_id);
+        }
+    }
+
+    fn is_running(&mut self, schedule_id: &str) -> bool {
+        let Some(job_id) = self.running.get(schedule_id) else {
+            return false;
+        };
+        let running = self
+            .jobs
+            .status(*job_id)
+            .is_some_and(|status| status.state == JobState::Running);
+        if !running {
+            self.running.remove(schedule_id);
+        }
+        running
+    }
+}
+
+fn is_due(schedule: &Schedule, now: u64, app_start: bool) -> bool {
+    match &schedule.trigger {
+        Trigger::AppStart => app_start,
+        Trigger::LowDiskSpace { path, min_free_mb } => {
+            let cooled_down = schedule
+                .last_run
+                .is_none_or(|last| now.saturating_sub(last) >= LOW_DISK_COOLDOWN_SECS);
+            cooled_down
+                && disk::free_space(Path::new(path))
+                    .is_ok_and(|free| free < min_free_mb.saturating_mul(1024 * 1024))
+        }
+        Trigger::Cron { .. } | Trigger::Interval { .. } => {
+            next_run(schedule).is_some_and(|next| next <= now)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schedule(trigger: Trigger) -> Schedule {
+        Schedule {
+            id: "schedule-1".to_string(),
+            name: "Очистка".to_string(),
+            plugin_id: "temp_cleaner".to_string(),
+            settings: Value::Null,
+            trigger,
+            enabled: true,
+            created_at: 1_000,
+            last_run: None,
+        }
+    }
+
+    #[test]
+    fn interval_counts_from_last_run_or_creation() {
+        let mut every_hour = schedule(Trigger::Interval { minutes: 60 });
+        assert_eq!(next_run(&every_hour), Some(4_600));
+        assert!(!is_due(&every_hour, 4_599, false));
+        assert!(is_due(&every_hour, 4_600, false));
+
+        every_hour.last_run = Some(10_000);
+        assert_eq!(next_run(&every_hour), Some(13_600));
+        assert!(!is_due(&every_hour, 10_001, false));
+    }
+
+    #[test]
+    fn cron_uses_next_match_after_last_run() {
+        let mut hourly = schedule(Trigger::Cron {
+            expression: "@hourly".to_string(),
+        });
+        hourly.last_run = Some(1_800_000_000);
+        let next = next_run(&hourly).unwrap();
+        assert_eq!(
+            Some(next),
+            CronExpr::parse("@hourly")
+                .unwrap()
+                .next_after(1_800_000_000)
+        );
+        assert!(!is_due(&hourly, next - 1, false));
+        assert!(is_due(&hourly, next, false));
+
+        hourly.trigger = Trigger::Cron {
+            expression: "не cron".to_string(),
+        };
+        assert_eq!(next_run(&hourly), None);
+        assert!(!is_due(&hourly, u64::MAX, false));
+    }
+
+    #[test]
+    fn disabled_and_event_schedules_have_no_next_run() {
+        let mut disabled = schedule(Trigger::Interval { minutes: 1 });
+        disabled.enabled = false;
+        assert_eq!(next_run(&disabled), None);
+
+        let app_start = schedule(Trigger::AppStart);
+        assert_eq!(next_run(&app_start), None);
+        assert!(is_due(&app_start, 0, true));
+        assert!(!is_due(&app_start, u64::MAX, false));
+    }
+
+    #[test]
+    fn low_disk_space_waits_for_cooldown() {
+        let mut low_disk = schedule(Trigger::LowDiskSpace {
+            path: std::env::temp_dir().display().to_string(),
+            min_free_mb: u64::MAX,
+        });
+        assert_eq!(next_run(&low_disk), None);
+        assert!(is_due(&low_disk, 100_000, false));
+
+        low_disk.last_run = Some(100_000);
+        assert!(!is_due(
+            &low_disk,
+            100_000 + LOW_DISK_COOLDOWN_SECS - 1,
+            false
+        ));
+        assert!(is_due(&low_disk, 100_000 + LOW_DISK_COOLDOWN_SECS, false));
+
+        low_disk.trigger = Trigger::LowDiskSpace {
+            path: std::env::temp_dir().display().to_string(),
+            min_free_mb: 0,
+        };
+        assert!(!is_due(&low_disk, u64::MAX, false));
+    }
+
+    #[cfg(feature = "plugin-temp-cleaner")]
+    #[test]
+    fn plugins_needing_admin_cannot_be_scheduled() {
+        let dir = std::env::temp_dir().join(format!(
+            "lamerhelper-scheduler-{}-admin",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        let env = Environment::default().with_data_dir(Some(dir.clone()));
+        let mut cleanup = schedule(Trigger::Interval { minutes: 60 });
+        cleanup.id = String::new();
+
+        cleanup.settings = serde_json::json!({ "system_temp": true });
+        let Err(CoreError::Plugin(error)) = save_schedule(&env, cleanup.clone()) else {
+            panic!("расписание с системными папками сохранено");
+        };
+        assert!(error.contains("Временные файлы системы"), "{error}");
+        assert!(list_schedules(&env).is_empty());
+
+        cleanup.settings = serde_json::json!({ "user_temp": true });
+        assert!(save_schedule(&env, cleanup).is_ok());
+        assert_eq!(list_schedules(&env).len(), 1);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,633 @@
+use rhai::module_resolvers::DummyModuleResolver;
+use rhai::{AST, Array, Dynamic, Engine, EvalAltResult, FnPtr, Map, NativeCallContext, Scope};
+use serde::Deserialize;
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock},
+    time::SystemTime,
+};
+
+use crate::core::api::PluginApi;
+use crate::core::backend::CommandOptions;
+use crate::core::declarative::validate_meta;
+use crate::core::hive::RegistryValue;
+use crate::core::logger::Logger;
+use crate::core::plugin::{Plugin, PluginPreview};
+use crate::core::post_action::PostAction;
+use crate::core::registry::Unavailable;
+use crate::core::settings::PluginMeta;
+
+/// Лимит операций для `meta`, `defaults` и `preview`: список плагинов не должен
+/// зависать из-за скрипта. Сам `run` ограничен только `timeout_secs`.
+const QUICK_CALL_OPERATIONS: u64 = 1_000_000;
+/// Глубина вложенности выражений (верхний уровень и функции). У Rhai она
+/// различается в отладочной и релизной сборке, поэтому задаётся явно.
+const MAX_EXPR_DEPTHS: (usize, usize) = (64, 32);
+
+/// Третий аргумент `run_command` в скрипте: поля `CommandOptions` и `stream` — писать
+/// вывод в журнал по мере появления.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct ScriptCommandOptions {
+    #[serde(flatten)]
+    options: CommandOptions,
+    stream: bool,
+}
+
+/// Разобранные скрипты по пути; перечитываются при изменении файла.
+type ScriptCache = HashMap<PathBuf, (SystemTime, Result<(AST, PluginMeta), String>)>;
+
+static SCRIPTS: OnceLock<Mutex<ScriptCache>> = OnceLock::new();
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+/// Скрипты `*.rhai` из списка файлов вместе с их именами. Скрипт с ошибкой
+/// становится `Unavailable` с номером строки в описании.
+pub fn load_files(paths: &[PathBuf]) -> Vec<(String, Box<dyn Plugin>)> {
+    let paths = paths
+        .iter()
+        .filter(|path| path.extension().is_some_and(|ext| ext == "rhai"));
+
+    let mut cache = SCRIPTS
+        .get_or_init(Mutex::default)
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    paths
+        .map(|path| {
+            let file = path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let modified = fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+            let cached = cache
+                .get(path)
+                .filter(|(cached_at, _)| *cached_at == modified);
+            let loaded = match cached {
+                Some((_, loaded)) => loaded.clone(),
+                None => {
+                    let loaded = compile(path);
+                    cache.insert(path.clone(), (modified, loaded.clone()));
+                    loaded
+                }
+            };
+            let plugin: Box<dyn Plugin> = match loaded {
+                Ok((ast, meta)) => Box::new(ScriptPlugin { ast, meta }),
+                Err(reason) => Box::new(Unavailable::new(&file, None, reason)),
+            };
+            (file, plugin)
+        })
+        .collect()
+}
+
+fn compile(path: &Path) -> Result<(AST, PluginMeta), String> {
+    let source = fs::read_to_string(path)
+        .map_err(|e| format!("Не удалось прочитать {}: {e}", path.display()))?;
+    let host = Host::new(None, Logger::new());
+    let mut engine = host.engine();
+    engine.set_max_operations(QUICK_CALL_OPERATIONS);
+    let ast = engine.compile(source).map_err(|e| describe(e.into()))?;
+    let meta: Dynamic = engine
+        .call_fn(&mut Scope::new(), &ast, "meta", ())
+        .map_err(|e| describe(*e))?;
+    let meta: PluginMeta = rhai::serde::from_dynamic::<Value>(&meta)
+        .and_then(|meta| serde_json::from_value(meta).map_err(|e| e.to_string().into()))
+        .map_err(|e| format!("Функция meta вернула некорректные метаданные: {e}"))?;
+    validate_meta(&meta)?;
+    if !has_function(&ast, "run") {
+        return Err("В скрипте нет функции run(settings).".to_string());
+    }
+    Ok((ast, meta))
+}
+
+fn has_function(ast: &AST, name: &str) -> bool {
+    ast.iter_functions().any(|function| function.name == name)
+}
+
+/// Текст ошибки скрипта с номером строки. Ошибка во вложенной функции
+/// разворачивается до места, где она возникла.
+fn describe(mut err: EvalAltResult) -> String {
+    while let EvalAltResult::ErrorInFunctionCall(_, _, inner, _) = err {
+        err = *inner;
+    }
+    match err {
+        EvalAltResult::ErrorTerminated(..) => return "Выполнение скрипта прервано.".to_string(),
+        EvalAltResult::ErrorTooManyOperations(..) => {
+            return "Скрипт превысил лимит вычислений.".to_string();
+        }
+        _ => {}
+    }
+    let position = err.take_position();
+    let message = match err {
+        EvalAltResult::ErrorRuntime(value, _) => value.to_string(),
+        err => err.to_string(),
+    };
+    match position.line() {
+        Some(line) => format!("Строка {line}: {message}"),
+        None => message,
+    }
+}
+
+/// Плагин на Rhai: скрипт определяет `meta()` и `run(settings)`, а также
+/// необязательные `defaults()` и `preview()`. Доступные функции описаны в README.
+pub struct ScriptPlugin {
+    ast: AST,
+    meta: PluginMeta,
+}
+
+impl ScriptPlugin {
+    /// Необязательная функция скрипта, результат которой читается как JSON.
+    fn optional_json<T: for<'de> Deserialize<'de>>(
+        &self,
+        api: &PluginApi,
+        function: &str,
+    ) -> Result<Option<T>, String> {
+        if !has_function(&self.ast, function) {
+            return Ok(None);
+        }
+        let host = Host::new(Some(api.clone()), Logger::new());
+        let mut engine = host.engine();
+        engine.set_max_operations(QUICK_CALL_OPERATIONS);
+        let result: Dynamic = engine
+            .call_fn(&mut Scope::new(), &self.ast, function, ())
+            .map_err(|e| describe(*e))?;
+        if result.is_unit() {
+            return Ok(None);
+        }
+        rhai::serde::from_dynamic::<Value>(&result)
+            .map_err(|e| e.to_string())
+            .and_then(|value| serde_json::from_value(value).map_err(|e| e.to_string()))
+            .map(Some)
+            .map_err(|e| format!("Функция {function} вернула некорректный ответ: {e}"))
+    }
+}
+
+impl Plugin for ScriptPlugin {
+    fn meta(&self) -> PluginMeta {
+        self.meta.clone()
+    }
+
+    fn defaults(&self, api: &PluginApi) -> Option<Value> {
+        self.optional_json(api, "defaults").ok().flatten()
+    }
+
+    fn preview(&self, api: &PluginApi) -> Result<Option<PluginPreview>, String> {
+        self.optional_json(api, "preview")
+    }
+
+    fn run(&self, api: &PluginApi, settings: &Value, logger: &mut Logger) -> Result<(), String> {
+        let settings = rhai::serde::to_dynamic(settings).map_err(|e| e.to_string())?;
+        // Журнал на время запуска принадлежит скрипту, чтобы записи сразу
+        // уходили в прогресс и поток событий, а потом возвращается обратно.
+        let host = Host::new(Some(api.clone()), std::mem::take(logger));
+        let cancel = api.clone();
+        let mut engine = host.engine();
+        engine.on_progress(move |_| cancel.is_cancelled().then_some(Dynamic::UNIT));
+        let result = engine.call_fn::<Dynamic>(&mut Scope::new(), &self.ast, "run", (settings,));
+        drop(engine);
+        *logger = host.take_logger();
+        result.map(|_| ()).map_err(|e| describe(*e))
+    }
+}
+
+/// Общее состояние функций, зарегистрированных в движке: `PluginApi` (нет при
+/// чтении метаданных) и журнал запуска.
+#[derive(Clone)]
+struct Host {
+    api: Option<PluginApi>,
+    logger: Arc<Mutex<Logger>>,
+}
+
+impl Host {
+    fn new(api: Option<PluginApi>, logger: Logger) -> Self {
+        Self {
+            api,
+            logger: Arc::new(Mutex::new(logger)),
+        }
+    }
+
+    fn take_logger(&self) -> Logger {
+        std::mem::take(&mut *self.lock_logger())
+    }
+
+    fn lock_logger(&self) -> std::sync::MutexGuard<'_, Logger> {
+        self.logger
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn log(&self, write: impl FnOnce(&mut Logger)) {
+        write(&mut self.lock_logger());
+    }
+
+    fn run_command(
+        &self,
+        program: &str,
+        args: Array,
+        options: ScriptCommandOptions,
+    ) -> ScriptResult<Map> {
+        let args: Vec<String> = args.iter().map(ToString::to_string).collect();
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        let api = self.api()?;
+        let output = if options.stream {
+            let mut logger = self.lock_logger();
+            api.run_command_with(program, &args, &options.options, Some(&mut logger))?
+        } else {
+            api.run_command_with(program, &args, &options.options, None)?
+        };
+        let mut result = Map::new();
+        result.insert("code".into(), output.code.map_or(-1, i64::from).into());
+        result.insert("success".into(), output.success().into());
+        result.insert("stdout".into(), output.stdout.into());
+        result.insert("stderr".into(), output.stderr.into());
+        let duration = i64::try_from(output.duration.as_millis()).unwrap_or(i64::MAX);
+        result.insert("duration_ms".into(), duration.into());
+        Ok(result)
+    }
+
+    fn api(&self) -> ScriptResult<&PluginApi> {
+        self.api
+            .as_ref()
+            .ok_or_else(|| "API недоступно при чтении метаданных.".into())
+    }
+
+    /// Движок с функциями журнала, файлов, реестра и запуска команд. `import` отключён:
+    /// иначе скрипт мог бы подключить любой файл с диска в обход проверки пакетов.
+    fn engine(&self) -> Engine {
+        let mut engine = Engine::new();
+        engine.set_max_expr_depths(MAX_EXPR_DEPTHS.0, MAX_EXPR_DEPTHS.1);
+        engine.set_module_resolver(DummyModuleResolver::new());
+        let host = self.clone();
+        engine.on_print(move |text| host.log(|logger| logger.info(text)));
+        let host = self.clone();
+        engine.on_debug(move |text, _, _| host.log(|logger| logger.info(text)));
+        self.register_logger(&mut engine);
+        self.register_fs(&mut engine);
+        self.register_registry(&mut engine);
+        self.register_system(&mut engine);
+        engine
+    }
+
+    fn register_logger(&self, engine: &mut Engine) {
+        let host = self.clone();
+        engine.register_fn("info", move |message: &str| {
+            host.log(|logger| logger.info(message))
+        });
+        let host = self.clone();
+        engine.register_fn("warn", move |message: &str| {
+            host.log(|logger| logger.warn(message))
+        });
+        let host = self.clone();
+        engine.register_fn("error", move |message: &str| {
+            host.log(|logger| logger.error(message))
+        });
+        let host = self.clone();
+        engine.register_fn("success", move |message: &str| {
+            host.log(|logger| logger.success(message))
+        });
+        let host = self.clone();
+        engine.register_fn("section", move |title: &str| {
+            host.log(|logger| logger.section(title))
+        });
+        let host = self.clone();
+        engine.register_fn("kv", move |label: &str, value: Dynamic| {
+            host.log(|logger| logger.kv(label, value.to_string()))
+        });
+        let host = self.clone();
+        engine.register_fn(
+            "group",
+            move |context: NativeCallContext, title: &str, body: FnPtr| -> ScriptResult<()> {
+                host.log(|logger| {
+                    logger.section(title);
+                    logger.indent();
+                });
+                let result = body.call_within_context::<Dynamic>(&context, ());
+                host.log(Logger::outdent);
+                result.map(|_| ())
+            },
+        );
+    }
+
+    fn register_fs(&self, engine: &mut Engine) {
+        let host = self.clone();
+        engine.register_fn("temp_dir", move || -> ScriptResult<String> {
+            Ok(host.api()?.temp_dir().display().to_string())
+        });
+        let host = self.clone();
+        engine.register_fn("data_dir", move || -> ScriptResult<String> {
+            Ok(host.api()?.app_data_dir()?.display().to_string())
+        });
+        let host = self.clone();
+        engine.register_fn(
+            "write_file",
+            move |path: &str, content: &str| -> ScriptResult<()> {
+                Ok(host
+                    .api()?
+                    .write_file(Path::new(path), content.as_bytes())?)
+            },
+        );
+        let host = self.clone();
+        engine.register_fn("create_dir_all", move |path: &str| -> ScriptResult<()> {
+            Ok(host.api()?.create_dir_all(Path::new(path))?)
+        });
+        let host = self.clone();
+        engine.register_fn("remove_file", move |path: &str| -> ScriptResult<()> {
+            Ok(host.api()?.remove_file(Path::new(path))?)
+        });
+        let host = self.clone();
+        engine.register_fn("remove_dir_all", move |path: &str| -> ScriptResult<()> {
+            Ok(host.api()?.remove_dir_all(Path::new(path))?)
+        });
+    }
+
+    fn register_registry(&self, engine: &mut Engine) {
+        let host = self.clone();
+        engine.register_fn(
+            "get_registry_string",
+            move |key: &str, name: &str| -> ScriptResult<String> {
+                Ok(host.api()?.get_registry_string(key, name)?)
+            },
+        );
+        let host = self.clone();
+        engine.register_fn(
+            "set_registry_string",
+            move |key: &str, name: &str, value: &str| -> ScriptResult<()> {
+                Ok(host.api()?.set_registry_string(key, name, value)?)
+            },
+        );
+        let host = self.clone();
+        engine.register_fn(
+            "set_registry_dword",
+            move |key: &str, name: &str, value: i64| -> ScriptResult<()> {
+                let value = u32::try_from(value)
+                    .map_err(|_| format!("Значение {value} не подходит для DWORD."))?;
+                Ok(host.api()?.set_registry_dword(key, name, value)?)
+            },
+        );
+        let host = self.clone();
+        engine.register_fn(
+            "delete_registry_value",
+            move |key: &str, name: &str| -> ScriptResult<()> {
+                Ok(host.api()?.delete_registry_value(key, name)?)
+            },
+        );
+        let host = self.clone();
+        engine.register_fn(
+            "registry_key_exists",
+            move |key: &str| -> ScriptResult<bool> { Ok(host.api()?.registry_key_exists(key)?) },
+        );
+        let host = self.clone();
+        engine.register_fn(
+            "create_registry_key",
+            move |key: &str| -> ScriptResult<()> { Ok(host.api()?.create_registry_key(key)?) },
+        );
+        let host = self.clone();
+        engine.register_fn(
+            "delete_registry_key",
+            move |key: &str| -> ScriptResult<()> { Ok(host.api()?.delete_registry_key(key)?) },
+        );
+        let host = self.clone();
+        engine.register_fn(
+            "get_registry_value",
+            move |key: &str, name: &str| -> ScriptResult<Dynamic> {
+                rhai::serde::to_dynamic(host.api()?.get_registry_value(key, name)?)
+            },
+        );
+        let host = self.clone();
+        engine.register_fn(
+            "set_registry_value",
+            move |key: &str, name: &str, value: Map| -> ScriptResult<()> {
+                let value: RegistryValue = rhai::serde::from_dynamic(&value.into())?;
+                Ok(host.api()?.set_registry_value(key, name, value)?)
+            },
+        );
+        let host = self.clone();
+        engine.register_fn(
+            "registry_subkeys",
+            move |key: &str| -> ScriptResult<Array> {
+                let subkeys = host.api()?.registry_subkeys(key)?;
+                Ok(subkeys.into_iter().map(Dynamic::from).collect())
+            },
+        );
+        let host = self.clone();
+        engine.register_fn("registry_values", move |key: &str| -> ScriptResult<Array> {
+            let mut values = Array::new();
+            for (name, value) in host.api()?.registry_values(key)? {
+                let mut entry = Map::new();
+                entry.insert("name".into(), name.into());
+                entry.insert("value".into(), rhai::serde::to_dynamic(value)?);
+                values.push(entry.into());
+            }
+            Ok(values)
+        });
+    }
+
+    fn register_system(&self, engine: &mut Engine) {
+        let host = self.clone();
+        engine.register_fn(
+            "run_command",
+            move |program: &str, args: Array| -> ScriptResult<Map> {
+                host.run_command(program, args, ScriptCommandOptions::default())
+            },
+        );
+        let host = self.clone();
+        engine.register_fn(
+            "run_command",
+            move |program: &str, args: Array, options: Map| -> ScriptResult<Map> {
+                let options = rhai::serde::from_dynamic(&options.into())?;
+                host.run_command(program, args, options)
+            },
+        );
+        let host = self.clone();
+        engine.register_fn("is_cancelled", move || -> ScriptResult<bool> {
+            Ok(host.api()?.is_cancelled())
+        });
+        let host = self.clone();
+        engine.register_fn("is_elevated", move || -> ScriptResult<bool> {
+            Ok(host.api()?.is_elevated())
+        });
+        let host = self.clone();
+        engine.register_fn("locale", move || -> ScriptResult<String> {
+            Ok(host.api()?.locale().to_string())
+        });
+        let host = self.clone();
+        engine.register_fn(
+            "request_post_action",
+            move |action: &str, reason: &str| -> ScriptResult<()> {
+                let action: PostAction = serde_json::from_value(action.into())
+                    .map_err(|_| format!("Неизвестное действие: {action}"))?;
+                Ok(host.api()?.request_post_action(action, reason)?)
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::fake::Fakes;
+    use crate::core::logger::LogEntry;
+    use serde_json::json;
+    use std::time::Duration;
+
+    const META: &str = r#"
+fn meta() {
+    #{
+        id: "script_sample",
+        name: "Пример",
+        description: "",
+        category: "Тест",
+        capabilities: [
+            #{ kind: "fs_write", scopes: ["%TEMP%"] },
+            #{ kind: "registry_read", keys: ["Software\\Vendor"] },
+            #{ kind: "registry_write", keys: ["Software\\Vendor"] },
+        ],
+    }
+}
+"#;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("lamerhelper-script-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Загружает скрипт `META` + `body` тем же путём, что и папка `plugins`.
+    fn load(dir: &Path, body: &str) -> Box<dyn Plugin> {
+        let path = dir.join("sample.rhai");
+        fs::write(&path, format!("{META}{body}")).unwrap();
+        let (_, plugin) = load_files(&[path]).remove(0);
+        plugin
+    }
+
+    fn run(plugin: &dyn Plugin, fakes: &Fakes) -> (Result<(), String>, Vec<LogEntry>) {
+        let mut logger = Logger::new();
+        let result = plugin.run(&fakes.api(plugin), &json!({}), &mut logger);
+        (result, logger.into_logs())
+    }
+
+    #[test]
+    fn errors_carry_line_numbers() {
+        let dir = scratch_dir("errors");
+        let broken = load(&dir, "fn run(settings) {\n    let x = ;\n}\n");
+        let reason = broken.load_error().unwrap();
+        assert!(reason.starts_with("Строка 16:"), "{reason}");
+
+        let plugin = load(
+            &dir,
+            "fn check(value) {\n    throw `плохое значение ${value}`;\n}\n\nfn run(settings) {\n    check(3);\n}\n",
+        );
+        assert!(plugin.load_error().is_none());
+        let (result, _) = run(plugin.as_ref(), &Fakes::new());
+        assert_eq!(result, Err("Строка 16: плохое значение 3".to_string()));
+
+        let no_run = load(&dir, "");
+        assert_eq!(
+            no_run.load_error().as_deref(),
+            Some("В скрипте нет функции run(settings).")
+        );
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn changed_script_is_reloaded() {
+        let dir = scratch_dir("reload");
+        let path = dir.join("sample.rhai");
+        let script = format!("{META}fn run(settings) {{}}\n");
+        fs::write(&path, &script).unwrap();
+        let name = |paths: &[PathBuf]| load_files(paths).remove(0).1.meta().name;
+        assert_eq!(name(std::slice::from_ref(&path)), "Пример");
+
+        fs::write(&path, script.replace("Пример", "Изменённый")).unwrap();
+        // Время изменения сдвигается явно: у файловой системы оно может быть грубым.
+        let file = fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(5))
+            .unwrap();
+        assert_eq!(name(std::slice::from_ref(&path)), "Изменённый");
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn bindings_reach_logger_files_and_registry() {
+        let dir = scratch_dir("bindings");
+        let plugin = load(
+            &dir,
+            r#"
+fn run(settings) {
+    group("Файлы", || {
+        write_file(temp_dir() + "/out.txt", "привет");
+        kv("Записано", 6);
+    });
+    set_registry_string("Software\\Vendor", "Name", "значение");
+    info(get_registry_string("Software\\Vendor", "Name"));
+    print("из print");
+}
+"#,
+        );
+        let fakes = Fakes::new();
+        let (result, logs) = run(plugin.as_ref(), &fakes);
+        assert_eq!(result, Ok(()));
+
+        let written = fakes.fs.contents(fakes.path("temp/out.txt")).unwrap();
+        assert_eq!(String::from_utf8(written).unwrap(), "привет");
+        assert_eq!(
+            fakes.registry.value("Software\\Vendor", "Name"),
+            Some(RegistryValue::String("значение".to_string()))
+        );
+        let entries: Vec<(&str, u8)> = logs
+            .iter()
+            .map(|entry| (entry.message.as_str(), entry.indent))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                ("Файлы", 0),
+                ("Записано", 1),
+                ("значение", 0),
+                ("из print", 0)
+            ]
+        );
+        assert_eq!(logs[1].detail.as_deref(), Some("6"));
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn undeclared_operations_are_refused() {
+        let dir = scratch_dir("refused");
+        let plugin = load(
+            &dir,
+            "fn run(settings) {\n    set_registry_string(\"Software\\\\Other\", \"Name\", \"x\");\n}\n",
+        );
+        let fakes = Fakes::new();
+        let (result, _) = run(plugin.as_ref(), &fakes);
+        let error = result.unwrap_err();
+        assert!(error.starts_with("Строка 16:"), "{error}");
+        assert!(!fakes.registry.has_key("Software\\Other"));
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn import_cannot_reach_the_disk() {
+        let dir = scratch_dir("import");
+        let module = dir.join("secret.rhai");
+        fs::write(&module, "fn value() { 42 }").unwrap();
+        let plugin = load(
+            &dir,
+            &format!(
+                "fn run(settings) {{\n    import \"{}\" as secret;\n    info(secret::value());\n}}\n",
+                module.with_extension("").display()
+            ),
+        );
+        let (result, logs) = run(plugin.as_ref(), &Fakes::new());
+        let error = result.unwrap_err();
+        assert!(error.starts_with("Строка 16:"), "{error}");
+        assert!(logs.is_empty());
+        let _ = fs::remove_dir_all(dir);
+    }
+}
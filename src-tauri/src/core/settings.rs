@@ -1,6 +1,9 @@
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
 use serde_json::{Map, Value};
 
+use crate::core::capability::Capability;
+use crate::core::platform::{Platform, is_supported, unsupported_reason};
+
 #[derive(Serialize, Deserialize, Clone)]
 #[serde(rename_all = "snake_case")]
 pub enum SettingKind {
@@ -35,6 +38,9 @@ pub struct SettingField {
     pub kind: SettingKind,
     pub description: Option<String>,
     pub required: bool,
+    /// Включённое поле требует прав администратора (или root).
+    #[serde(default)]
+    pub requires_admin: bool,
     #[serde(rename = "default")]
     pub default_value: Value,
     pub options: Option<Vec<SettingOption>>,
@@ -47,7 +53,27 @@ pub struct PluginMeta {
     pub name: String,
     pub description: String,
     pub category: String,
+    #[serde(default)]
     pub settings: Vec<SettingField>,
+    #[serde(default)]
+    pub capabilities: Vec<Capability>,
+    /// Пустой список — плагин работает на любой системе.
+    #[serde(default)]
+    pub platforms: Vec<Platform>,
+    /// Лимит времени на `run` в секундах. `None` — значение по умолчанию, `Some(0)` — без лимита.
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+    /// Внешние программы, без которых плагин не работает: `ipconfig`, `explorer.exe`.
+    #[serde(default)]
+    pub tools: Vec<String>,
+}
+
+impl PluginMeta {
+    /// Плагину целиком или хотя бы одному его полю нужны права администратора.
+    pub fn may_require_admin(&self) -> bool {
+        self.capabilities.contains(&Capability::RequiresAdmin)
+            || self.settings.iter().any(|field| field.requires_admin)
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -57,18 +83,36 @@ pub struct PluginInfo {
     pub description: String,
     pub category: String,
     pub settings: Vec<SettingField>,
+    pub capabilities: Vec<Capability>,
+    pub platforms: Vec<Platform>,
+    pub supported: bool,
+    pub unsupported_reason: Option<String>,
+    pub requires_admin: bool,
+    pub tools: Vec<String>,
+    /// Программы из `tools`, которых нет в системе. Заполняет `list_plugins`.
+    pub missing_tools: Vec<String>,
     pub defaults: Value,
 }
 
 impl PluginInfo {
     pub fn from_meta(meta: PluginMeta) -> Self {
         let defaults = defaults_from_fields(&meta.settings);
+        let supported = is_supported(&meta.platforms);
+        let unsupported_reason = unsupported_reason(&meta.platforms);
+        let requires_admin = meta.may_require_admin();
         Self {
             id: meta.id,
             name: meta.name,
             description: meta.description,
             category: meta.category,
             settings: meta.settings,
+            capabilities: meta.capabilities,
+            platforms: meta.platforms,
+            supported,
+            unsupported_reason,
+            requires_admin,
+            tools: meta.tools,
+            missing_tools: Vec::new(),
             defaults,
         }
     }
@@ -82,6 +126,15 @@ pub fn defaults_from_fields(fields: &[SettingField]) -> Value {
     Value::Object(map)
 }
 
+/// `settings`, прочитанные в `T` и записанные обратно: реализация
+/// `Plugin::settings_round_trip` для плагина с типом настроек `T`.
+pub fn round_trip_settings<T: DeserializeOwned + Serialize>(
+    settings: &Value,
+) -> Result<Value, String> {
+    let parsed: T = serde_json::from_value(settings.clone()).map_err(|e| e.to_string())?;
+    serde_json::to_value(parsed).map_err(|e| e.to_string())
+}
+
 pub fn merge_settings(settings: &Value, defaults: &Value) -> Value {
     match (settings, defaults) {
         (Value::Object(values), Value::Object(defaults_map)) => {
@@ -95,6 +148,19 @@ pub fn merge_settings(settings: &Value, defaults: &Value) -> Value {
     }
 }
 
+/// Поля, которые включены в настройках и требуют повышенных прав.
+pub fn admin_fields<'a>(settings: &Value, fields: &'a [SettingField]) -> Vec<&'a SettingField> {
+    fields
+        .iter()
+        .filter(|field| field.requires_admin)
+        .filter(|field| match settings.get(&field.key) {
+            None | Some(Value::Null) | Some(Value::Bool(false)) => false,
+            Some(Value::String(value)) => !value.is_empty(),
+            Some(_) => true,
+        })
+        .collect()
+}
+
 pub fn validate_settings(settings: &Value, fields: &[SettingField]) -> Vec<String> {
     let mut errors = Vec::new();
     let values = match settings.as_object() {
@@ -0,0 +1,146 @@
+use serde::{Serialize, de::DeserializeOwned};
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crate::core::error::CoreError;
+
+/// Списки в папке данных читаются и переписываются и командами, и фоновыми потоками.
+static STORE_LOCK: Mutex<()> = Mutex::new(());
+
+/// Список из JSON-файла. Отсутствующий файл — пустой список. Повреждённый файл тоже даёт
+/// пустой список, но его копия остаётся рядом в `.bak`, а `update` его не перезапишет.
+pub fn load<T: DeserializeOwned>(path: &Path) -> Vec<T> {
+    read(path).unwrap_or_default()
+}
+
+fn read<T: DeserializeOwned>(path: &Path) -> Result<Vec<T>, String> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(format!("Ошибка чтения {}: {err}", path.display())),
+    };
+    serde_json::from_str(&content).map_err(|err| {
+        // Копия делается один раз: повторное чтение не должно затирать первый снимок.
+        let backup = backup_path(path);
+        if !backup.exists() {
+            let _ = fs::copy(path, backup);
+        }
+        format!(
+            "Файл {} повреждён ({err}), его копия сохранена в .bak.",
+            path.display()
+        )
+    })
+}
+
+fn backup_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".bak");
+    PathBuf::from(name)
+}
+
+/// Пишет во временный файл и переименовывает, чтобы оборванная запись не портила список.
+fn save<T: Serialize>(path: &Path, items: &[T]) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("Ошибка создания папки данных: {e}"))?;
+    }
+    let content = serde_json::to_string_pretty(items)
+        .map_err(|e| format!("Ошибка записи {}: {e}", path.display()))?;
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    fs::write(&temp, content)
+        .and_then(|_| fs::rename(&temp, path))
+        .map_err(|e| format!("Ошибка записи {}: {e}", path.display()))
+}
+
+/// Читает список, применяет `change` и сохраняет, если `change` не вернул ошибку.
+/// Если файл не читается или повреждён, возвращает ошибку и ничего не записывает.
+pub fn update<T, R>(
+    path: &Path,
+    change: impl FnOnce(&mut Vec<T>) -> Result<R, CoreError>,
+) -> Result<R, CoreError>
+where
+    T: Serialize + DeserializeOwned,
+{
+    let _lock = STORE_LOCK
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let mut items = read(path)?;
+    let value = change(&mut items)?;
+    save(path, &items)?;
+    Ok(value)
+}
+
+/// Первый свободный идентификатор вида `<prefix>-<n>`.
+pub fn next_id<'a>(prefix: &str, existing: impl Iterator<Item = &'a str> + Clone) -> String {
+    (1..)
+        .map(|n| format!("{prefix}-{n}"))
+        .find(|id| existing.clone().all(|existing| existing != id))
+        .expect("infinite range always yields a free id")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("lamerhelper-store-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn corrupt_file_is_kept_and_not_overwritten() {
+        let dir = scratch_dir("corrupt");
+        let path = dir.join("recipes.json");
+        fs::write(&path, "[\"первый\", \"втор").unwrap();
+
+        assert!(load::<String>(&path).is_empty());
+        let result = update(&path, |items: &mut Vec<String>| {
+            items.push("новый".to_string());
+            Ok(())
+        });
+
+        assert!(result.is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "[\"первый\", \"втор");
+        assert_eq!(
+            fs::read_to_string(backup_path(&path)).unwrap(),
+            "[\"первый\", \"втор"
+        );
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn backup_is_written_once() {
+        let dir = scratch_dir("backup-once");
+        let path = dir.join("recipes.json");
+        fs::write(&path, "[\"первый").unwrap();
+        assert!(load::<String>(&path).is_empty());
+
+        fs::write(&path, "{").unwrap();
+        assert!(load::<String>(&path).is_empty());
+
+        assert_eq!(fs::read_to_string(backup_path(&path)).unwrap(), "[\"первый");
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn missing_file_starts_empty() {
+        let dir = scratch_dir("missing");
+        let path = dir.join("schedules.json");
+
+        update(&path, |items: &mut Vec<String>| {
+            items.push("первый".to_string());
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(load::<String>(&path), ["первый"]);
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,530 @@
+use serde::Deserialize;
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+    thread,
+    time::{Duration, SystemTime},
+};
+use wasmtime::{
+    AsContextMut, Caller, Config, Engine, Extern, Instance, Linker, Module, Store, Trap,
+    UpdateDeadline,
+};
+
+use crate::core::api::PluginApi;
+use crate::core::bridge::{self, log_level};
+use crate::core::declarative::validate_meta;
+use crate::core::logger::Logger;
+use crate::core::plugin::{Plugin, PluginPreview};
+use crate::core::registry::Unavailable;
+use crate::core::settings::PluginMeta;
+
+const HOST_MODULE: &str = "lamerhelper";
+/// Шаг счётчика эпох, по которому модуль проверяет отмену и лимит времени.
+const EPOCH_TICK: Duration = Duration::from_millis(100);
+/// `meta`, `defaults` и `preview` должны уложиться в 2 секунды.
+const QUICK_CALL_TICKS: u64 = 20;
+/// Лимиты инструкций: служебные вызовы и сам запуск.
+const QUICK_CALL_FUEL: u64 = 100_000_000;
+const RUN_FUEL: u64 = 50_000_000_000;
+
+/// Скомпилированные модули по пути; перекомпилируются при изменении файла.
+type ModuleCache = HashMap<PathBuf, (SystemTime, Result<(Module, PluginMeta), String>)>;
+
+static MODULES: OnceLock<Mutex<ModuleCache>> = OnceLock::new();
+
+fn engine() -> &'static Engine {
+    static ENGINE: OnceLock<Engine> = OnceLock::new();
+    ENGINE.get_or_init(|| {
+        let mut config = Config::new();
+        config.consume_fuel(true).epoch_interruption(true);
+        let engine = Engine::new(&config).expect("wasm engine config is valid");
+        let ticker = engine.clone();
+        thread::spawn(move || {
+            loop {
+                thread::sleep(EPOCH_TICK);
+                ticker.increment_epoch();
+            }
+        });
+        engine
+    })
+}
+
+/// Модули `*.wasm` из списка файлов вместе с их именами. Модуль, который не удалось
+/// скомпилировать или который вернул некорректные метаданные, становится `Unavailable`.
+pub fn load_files(paths: &[PathBuf]) -> Vec<(String, Box<dyn Plugin>)> {
+    let paths = paths
+        .iter()
+        .filter(|path| path.extension().is_some_and(|ext| ext == "wasm"));
+
+    let mut cache = MODULES
+        .get_or_init(Mutex::default)
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    paths
+        .map(|path| {
+            let file = path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let modified = fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+            let cached = cache
+                .get(path)
+                .filter(|(cached_at, _)| *cached_at == modified);
+            let loaded = match cached {
+                Some((_, loaded)) => loaded.clone(),
+                None => {
+                    let loaded = compile(path);
+                    cache.insert(path.clone(), (modified, loaded.clone()));
+                    loaded
+                }
+            };
+            let plugin: Box<dyn Plugin> = match loaded {
+                Ok((module, meta)) => Box::new(WasmPlugin { module, meta }),
+                Err(reason) => Box::new(Unavailable::new(&file, None, reason)),
+            };
+            (file, plugin)
+        })
+        .collect()
+}
+
+fn compile(path: &Path) -> Result<(Module, PluginMeta), String> {
+    let module = Module::from_file(engine(), path)
+        .map_err(|e| format!("Не удалось загрузить модуль: {e}"))?;
+    let mut session = Session::start(&module, None, QUICK_CALL_FUEL)?;
+    let bytes = session.call_export("lh_meta", None)?;
+    let meta: PluginMeta = serde_json::from_slice(&bytes)
+        .map_err(|e| format!("Модуль вернул некорректные метаданные: {e}"))?;
+    validate_meta(&meta)?;
+    Ok((module, meta))
+}
+
+/// Модуль WebAssembly с экспортами `lh_meta`, `lh_run` и необязательными `lh_defaults`,
+/// `lh_preview`. Формат обмена с хостом описан в README.
+pub struct WasmPlugin {
+    module: Module,
+    meta: PluginMeta,
+}
+
+impl WasmPlugin {
+    /// Необязательный экспорт, который возвращает JSON или пустую строку.
+    fn optional_json<T: for<'de> Deserialize<'de>>(
+        &self,
+        api: &PluginApi,
+        export: &str,
+    ) -> Result<Option<T>, String> {
+        let mut session = Session::start(&self.module, Some(api.clone()), QUICK_CALL_FUEL)?;
+        if !session.has_export(export) {
+            return Ok(None);
+        }
+        let bytes = session.call_export(export, None)?;
+        if bytes.is_empty() {
+            return Ok(None);
+        }
+        serde_json::from_slice(&bytes)
+            .map(Some)
+            .map_err(|e| format!("Модуль вернул некорректный ответ {export}: {e}"))
+    }
+}
+
+impl Plugin for WasmPlugin {
+    fn meta(&self) -> PluginMeta {
+        self.meta.clone()
+    }
+
+    fn defaults(&self, api: &PluginApi) -> Option<Value> {
+        self.optional_json(api, "lh_defaults").ok().flatten()
+    }
+
+    fn preview(&self, api: &PluginApi) -> Result<Option<PluginPreview>, String> {
+        self.optional_json(api, "lh_preview")
+    }
+
+    fn run(&self, api: &PluginApi, settings: &Value, logger: &mut Logger) -> Result<(), String> {
+        let mut session = Session::start(&self.module, Some(api.clone()), RUN_FUEL)?;
+        session.until_cancelled();
+        let input = serde_json::to_vec(settings).map_err(|e| e.to_string())?;
+        let result = session.call_export("lh_run", Some(&input));
+        logger.append(session.into_logs());
+
+        let bytes = result?;
+        if bytes.is_empty() {
+            Ok(())
+        } else {
+            Err(String::from_utf8_lossy(&bytes).into_owned())
+        }
+    }
+}
+
+/// Состояние хоста внутри `Store`: копия `PluginApi` и журнал вызова.
+struct HostState {
+    api: Option<PluginApi>,
+    logger: Logger,
+}
+
+struct Session {
+    store: Store<HostState>,
+    instance: Instance,
+}
+
+impl Session {
+    fn start(module: &Module, api: Option<PluginApi>, fuel: u64) -> Result<Self, String> {
+        let mut store = Store::new(
+            engine(),
+            HostState {
+                api,
+                logger: Logger::new(),
+            },
+        );
+        store.set_fuel(fuel).map_err(|e| e.to_string())?;
+        store.set_epoch_deadline(QUICK_CALL_TICKS);
+        let instance = linker()?
+            .instantiate(&mut store, module)
+            .map_err(|e| format!("Не удалось создать экземпляр модуля: {e}"))?;
+        Ok(Self { store, instance })
+    }
+
+    /// Для `run`: лимит времени задаёт сторожевой таймер, модуль прерывается при отмене.
+    fn until_cancelled(&mut self) {
+        self.store.set_epoch_deadline(1);
+        self.store.epoch_deadline_callback(|context| {
+            let cancelled = context
+                .data()
+                .api
+                .as_ref()
+                .is_some_and(|api| api.is_cancelled());
+            Ok(if cancelled {
+                UpdateDeadline::Interrupt
+            } else {
+                UpdateDeadline::Continue(1)
+            })
+        });
+    }
+
+    fn has_export(&mut self, name: &str) -> bool {
+        self.instance.get_func(&mut self.store, name).is_some()
+    }
+
+    fn call_export(&mut self, name: &str, input: Option<&[u8]>) -> Result<Vec<u8>, String> {
+        let packed = match input {
+            None => self
+                .instance
+                .get_typed_func::<(), i64>(&mut self.store, name)
+                .map_err(|e| format!("Модуль не экспортирует {name}: {e}"))?
+                .call(&mut self.store, ())
+                .map_err(describe_trap)?,
+            Some(input) => {
+                let (ptr, len) = write_guest(&mut self.store, &self.instance, input)?;
+                self.instance
+                    .get_typed_func::<(i32, i32), i64>(&mut self.store, name)
+                    .map_err(|e| format!("Модуль не экспортирует {name}: {e}"))?
+                    .call(&mut self.store, (ptr, len))
+                    .map_err(describe_trap)?
+            }
+        };
+        let (ptr, len) = unpack(packed);
+        let memory = self
+            .instance
+            .get_memory(&mut self.store, "memory")
+            .ok_or("Модуль не экспортирует memory.")?;
+        read_memory(memory.data(&self.store), ptr, len)
+    }
+
+    fn into_logs(self) -> Vec<crate::core::logger::LogEntry> {
+        self.store.into_data().logger.into_logs()
+    }
+}
+
+fn describe_trap(error: wasmtime::Error) -> String {
+    match error.downcast_ref::<Trap>() {
+        Some(Trap::OutOfFuel) => "Плагин превысил лимит вычислений.".to_string(),
+        Some(Trap::Interrupt) => "Выполнение плагина прервано.".to_string(),
+        _ => format!("Ошибка в модуле: {error}"),
+    }
+}
+
+fn unpack(packed: i64) -> (u32, u32) {
+    ((packed as u64 >> 32) as u32, packed as u32)
+}
+
+fn read_memory(data: &[u8], ptr: u32, len: u32) -> Result<Vec<u8>, String> {
+    let start = ptr as usize;
+    data.get(start..start + len as usize)
+        .map(<[u8]>::to_vec)
+        .ok_or_else(|| "Модуль передал строку за пределами памяти.".to_string())
+}
+
+/// Копирует данные в память модуля через его `lh_alloc`.
+fn write_guest(
+    mut store: impl AsContextMut<Data = HostState>,
+    instance: &Instance,
+    bytes: &[u8],
+) -> Result<(i32, i32), String> {
+    let alloc = instance
+        .get_typed_func::<i32, i32>(&mut store, "lh_alloc")
+        .map_err(|e| format!("Модуль не экспортирует lh_alloc: {e}"))?;
+    let len = bytes.len() as i32;
+    let ptr = alloc.call(&mut store, len).map_err(describe_trap)?;
+    let memory = instance
+        .get_memory(&mut store, "memory")
+        .ok_or("Модуль не экспортирует memory.")?;
+    memory
+        .write(&mut store, ptr as usize, bytes)
+        .map_err(|_| "lh_alloc вернул адрес за пределами памяти.".to_string())?;
+    Ok((ptr, len))
+}
+
+fn linker() -> Result<Linker<HostState>, String> {
+    let mut linker = Linker::new(engine());
+    let define = |result: wasmtime::Result<&mut Linker<HostState>>| {
+        result
+            .map(|_| ())
+            .map_err(|e| format!("Ошибка подготовки API: {e}"))
+    };
+
+    define(linker.func_wrap(
+        HOST_MODULE,
+        "log",
+        |mut caller: Caller<'_, HostState>, level: i32, ptr: i32, len: i32| {
+            let message = caller_string(&mut caller, ptr, len)?;
+            caller
+                .data_mut()
+                .logger
+                .push_detail(log_level(level), message, None);
+            Ok(())
+        },
+    ))?;
+    define(linker.func_wrap(
+        HOST_MODULE,
+        "log_detail",
+        |mut caller: Caller<'_, HostState>,
+         level: i32,
+         ptr: i32,
+         len: i32,
+         dptr: i32,
+         dlen: i32| {
+            let message = caller_string(&mut caller, ptr, len)?;
+            let detail = caller_string(&mut caller, dptr, dlen)?;
+            caller
+                .data_mut()
+                .logger
+                .push_detail(log_level(level), message, Some(detail));
+            Ok(())
+        },
+    ))?;
+    define(linker.func_wrap(
+        HOST_MODULE,
+        "section",
+        |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| {
+            let title = caller_string(&mut caller, ptr, len)?;
+            caller.data_mut().logger.section(title);
+            Ok(())
+        },
+    ))?;
+    define(linker.func_wrap(
+        HOST_MODULE,
+        "indent",
+        |mut caller: Caller<'_, HostState>| {
+            caller.data_mut().logger.indent();
+        },
+    ))?;
+    define(linker.func_wrap(
+        HOST_MODULE,
+        "outdent",
+        |mut caller: Caller<'_, HostState>| {
+            caller.data_mut().logger.outdent();
+        },
+    ))?;
+    define(linker.func_wrap(
+        HOST_MODULE,
+        "is_cancelled",
+        |caller: Caller<'_, HostState>| -> i32 {
+            caller
+                .data()
+                .api
+                .as_ref()
+                .is_some_and(|api| api.is_cancelled())
+                .into()
+        },
+    ))?;
+    define(linker.func_wrap(
+        HOST_MODULE,
+        "call",
+        |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| -> wasmtime::Result<i64> {
+            let request = caller_bytes(&mut caller, ptr, len)?;
+            let bytes = bridge::respond(caller.data().api.as_ref(), &request);
+            let Some(Extern::Func(alloc)) = caller.get_export("lh_alloc") else {
+                return Err(wasmtime::Error::msg("Модуль не экспортирует lh_alloc."));
+            };
+            let ptr = alloc
+                .typed::<i32, i32>(&caller)?
+                .call(&mut caller, bytes.len() as i32)?;
+            let Some(Extern::Memory(memory)) = caller.get_export("memory") else {
+                return Err(wasmtime::Error::msg("Модуль не экспортирует memory."));
+            };
+            memory.write(&mut caller, ptr as usize, &bytes)?;
+            Ok(((ptr as u32 as i64) << 32) | bytes.len() as i64)
+        },
+    ))?;
+    Ok(linker)
+}
+
+fn caller_bytes(
+    caller: &mut Caller<'_, HostState>,
+    ptr: i32,
+    len: i32,
+) -> wasmtime::Result<Vec<u8>> {
+    let Some(Extern::Memory(memory)) = caller.get_export("memory") else {
+        return Err(wasmtime::Error::msg("Модуль не экспортирует memory."));
+    };
+    read_memory(memory.data(&caller), ptr as u32, len as u32).map_err(wasmtime::Error::msg)
+}
+
+fn caller_string(
+    caller: &mut Caller<'_, HostState>,
+    ptr: i32,
+    len: i32,
+) -> wasmtime::Result<String> {
+    Ok(String::from_utf8_lossy(&caller_bytes(caller, ptr, len)?).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::api::CancelToken;
+    use crate::core::fake::Fakes;
+    use serde_json::json;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("lamerhelper-wasm-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn wat_string(text: &str) -> String {
+        text.replace('\\', "\\\\").replace('"', "\\\"")
+    }
+
+    /// Модуль с метаданными `meta` по адресу 0, строкой `data` по адресу 1024
+    /// и телами `lh_meta` и `lh_run` на WAT.
+    fn module(meta: &Value, data: &str, meta_body: &str, run_body: &str) -> String {
+        let meta = meta.to_string();
+        format!(
+            r#"(module
+  (import "lamerhelper" "call" (func $call (param i32 i32) (result i64)))
+  (memory (export "memory") 1)
+  (global $heap (mut i32) (i32.const 4096))
+  (data (i32.const 0) "{}")
+  (data (i32.const 1024) "{}")
+  (func (export "lh_alloc") (param $len i32) (result i32)
+    (local $ptr i32)
+    (local.set $ptr (global.get $heap))
+    (global.set $heap (i32.add (global.get $heap) (local.get $len)))
+    (local.get $ptr))
+  (func (export "lh_meta") (result i64)
+    {meta_body}
+    (i64.const {}))
+  (func (export "lh_run") (param i32 i32) (result i64)
+    {run_body}))"#,
+            wat_string(&meta),
+            wat_string(data),
+            meta.len(),
+        )
+    }
+
+    fn sample_meta() -> Value {
+        json!({ "id": "wasm_sample", "name": "Пример", "description": "", "category": "Тест" })
+    }
+
+    /// Компилирует WAT в `<name>.wasm` и загружает тем же путём, что и папка `plugins`.
+    fn load(dir: &Path, name: &str, wat: &str) -> Box<dyn Plugin> {
+        let path = dir.join(format!("{name}.wasm"));
+        fs::write(&path, wat::parse_str(wat).unwrap()).unwrap();
+        load_files(&[path]).remove(0).1
+    }
+
+    #[test]
+    fn invalid_meta_is_rejected() {
+        let dir = scratch_dir("meta");
+        let mut meta = sample_meta();
+        meta["id"] = json!("");
+        let plugin = load(&dir, "empty_id", &module(&meta, "", "", "(i64.const 0)"));
+        let reason = plugin.load_error().unwrap();
+        assert!(reason.starts_with("Некорректный id «»"), "{reason}");
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn endless_meta_runs_out_of_fuel() {
+        let dir = scratch_dir("fuel");
+        let wat = module(
+            &sample_meta(),
+            "",
+            "(loop $spin (br $spin))",
+            "(i64.const 0)",
+        );
+        let plugin = load(&dir, "spin", &wat);
+        assert_eq!(
+            plugin.load_error().as_deref(),
+            Some("Плагин превысил лимит вычислений.")
+        );
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn cancellation_interrupts_run() {
+        let dir = scratch_dir("cancel");
+        let wat = module(
+            &sample_meta(),
+            "",
+            "",
+            "(loop $spin (br $spin)) (i64.const 0)",
+        );
+        let plugin = load(&dir, "endless", &wat);
+        assert!(plugin.load_error().is_none());
+
+        let fakes = Fakes::new();
+        let mut api = fakes.api(plugin.as_ref());
+        let cancel = CancelToken::default();
+        api.set_cancel_token(cancel.clone());
+        let canceller = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(200));
+            cancel.cancel();
+        });
+        let result = plugin.run(&api, &json!({}), &mut Logger::new());
+        canceller.join().unwrap();
+        assert_eq!(result, Err("Выполнение плагина прервано.".to_string()));
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn host_call_without_capability_is_refused() {
+        let dir = scratch_dir("capability");
+        let fakes = Fakes::new();
+        let target = fakes.path("temp/out.txt");
+        let request = json!({ "op": "write_file", "path": target, "content": "x" }).to_string();
+        // lh_run возвращает ответ хоста как текст ошибки запуска.
+        let run = format!(
+            "(call $call (i32.const 1024) (i32.const {}))",
+            request.len()
+        );
+        let plugin = load(&dir, "writer", &module(&sample_meta(), &request, "", &run));
+        assert!(plugin.load_error().is_none());
+
+        let result = plugin.run(&fakes.api(plugin.as_ref()), &json!({}), &mut Logger::new());
+        let response: Value = serde_json::from_str(&result.unwrap_err()).unwrap();
+        let error = response["error"].as_str().unwrap();
+        assert!(
+            error.starts_with("Операция не объявлена плагином"),
+            "{error}"
+        );
+        assert!(!fakes.fs.contains(&target));
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,277 @@
+use serde_json::Value;
+use std::{
+    sync::{Arc, Mutex, mpsc},
+    thread,
+    time::Duration,
+};
+
+use crate::core::api::PluginApi;
+use crate::core::logger::{LogEntry, Logger};
+use crate::core::panic::{self, PanicReport};
+use crate::core::plugin::Plugin;
+
+/// Лимит для плагинов, которые не указали `timeout_secs`.
+pub const DEFAULT_TIMEOUT_SECS: u64 = 300;
+/// Сколько ждать плагин после отмены, прежде чем бросить его поток.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(5);
+
+/// Что делает плагин прямо сейчас: последний раздел лога или запущенная команда.
+/// Копия лога нужна, если поток плагина так и не вернул `Logger`.
+#[derive(Clone, Default)]
+pub struct Progress(Arc<Mutex<ProgressState>>);
+
+#[derive(Default)]
+struct ProgressState {
+    step: Option<String>,
+    logs: Vec<LogEntry>,
+}
+
+impl Progress {
+    /// Возвращает предыдущий шаг, чтобы его можно было восстановить.
+    pub fn set_step(&self, step: Option<String>) -> Option<String> {
+        match self.0.lock() {
+            Ok(mut state) => std::mem::replace(&mut state.step, step),
+            Err(_) => None,
+        }
+    }
+
+    pub fn step(&self) -> Option<String> {
+        self.0.lock().ok().and_then(|state| state.step.clone())
+    }
+
+    pub fn record(&self, entry: &LogEntry) {
+        if let Ok(mut state) = self.0.lock() {
+            state.logs.push(entry.clone());
+        }
+    }
+
+    fn logs(&self) -> Vec<LogEntry> {
+        self.0
+            .lock()
+            .map(|state| state.logs.clone())
+            .unwrap_or_default()
+    }
+}
+
+pub enum Outcome {
+    Finished(Result<Result<(), String>, PanicReport>),
+    TimedOut { step: Option<String> },
+}
+
+/// `Some(0)` отключает ограничение.
+pub fn timeout_for(timeout_secs: Option<u64>) -> Option<Duration> {
+    match timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS) {
+        0 => None,
+        secs => Some(Duration::from_secs(secs)),
+    }
+}
+
+/// Запускает `Plugin::run` в отдельном потоке. По истечении времени отменяет запуск
+/// (команды `PluginApi` при этом убивают свои процессы) и ждёт плагин ещё немного.
+pub fn run(
+    plugin: Arc<dyn Plugin>,
+    api: Arc<PluginApi>,
+    settings: Value,
+    logger: Logger,
+    progress: Progress,
+    timeout: Option<Duration>,
+) -> (Logger, Outcome) {
+    supervise(
+        plugin,
+        api,
+        settings,
+        logger,
+        progress,
+        timeout,
+        SHUTDOWN_GRACE,
+    )
+}
+
+fn supervise(
+    plugin: Arc<dyn Plugin>,
+    api: Arc<PluginApi>,
+    settings: Value,
+    mut logger: Logger,
+    progress: Progress,
+    timeout: Option<Duration>,
+    grace: Duration,
+) -> (Logger, Outcome) {
+    let Some(timeout) = timeout else {
+        let result = panic::catch(|| plugin.run(&api, &settings, &mut logger));
+        return (logger, Outcome::Finished(result));
+    };
+
+    let (tx, rx) = mpsc::channel();
+    {
+        let api = Arc::clone(&api);
+        thread::spawn(move || {
+            let result = panic::catch(|| plugin.run(&api, &settings, &mut logger));
+            let _ = tx.send((logger, result));
+        });
+    }
+
+    if let Ok((logger, result)) = rx.recv_timeout(timeout) {
+        return (logger, Outcome::Finished(result));
+    }
+
+    let step = progress.step();
+    api.abort();
+    let logger = match rx.recv_timeout(grace) {
+        Ok((logger, _)) => logger,
+        Err(_) => Logger::from_logs(progress.logs()),
+    };
+    (logger, Outcome::TimedOut { step })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::api::CancelToken;
+    use crate::core::fake::Fakes;
+    use crate::core::settings::PluginMeta;
+
+    /// Плагин, чей `run` задаёт тест.
+    struct Scripted(fn(&PluginApi, &mut Logger) -> Result<(), String>);
+
+    impl Plugin for Scripted {
+        fn meta(&self) -> PluginMeta {
+            PluginMeta {
+                id: "scripted".to_string(),
+                name: "Сценарий".to_string(),
+                description: String::new(),
+                category: "Тест".to_string(),
+                settings: Vec::new(),
+                capabilities: Vec::new(),
+                platforms: Vec::new(),
+                timeout_secs: None,
+                tools: Vec::new(),
+            }
+        }
+
+        fn run(
+            &self,
+            api: &PluginApi,
+            _settings: &Value,
+            logger: &mut Logger,
+        ) -> Result<(), String> {
+            (self.0)(api, logger)
+        }
+    }
+
+    fn start(
+        script: fn(&PluginApi, &mut Logger) -> Result<(), String>,
+        cancel: CancelToken,
+        timeout: Option<Duration>,
+        grace: Duration,
+    ) -> (Logger, Outcome) {
+        let plugin = Scripted(script);
+        let mut api = Fakes::new().api(&plugin);
+        api.set_cancel_token(cancel);
+        let progress = Progress::default();
+        let mut logger = Logger::new();
+        logger.track(progress.clone());
+        supervise(
+            Arc::new(plugin),
+            Arc::new(api),
+            Value::Null,
+            logger,
+            progress,
+            timeout,
+            grace,
+        )
+    }
+
+    fn wait_for_cancel(api: &PluginApi, logger: &mut Logger) -> Result<(), String> {
+        logger.section("Ожидание");
+        while !api.is_cancelled() {
+            thread::sleep(Duration::from_millis(5));
+        }
+        logger.warn("Остановлено");
+        Err("Отменено".to_string())
+    }
+
+    fn messages(logger: Logger) -> Vec<String> {
+        logger
+            .into_logs()
+            .into_iter()
+            .map(|entry| entry.message)
+            .collect()
+    }
+
+    #[test]
+    fn zero_timeout_disables_limit() {
+        assert_eq!(timeout_for(Some(0)), None);
+        assert_eq!(timeout_for(Some(7)), Some(Duration::from_secs(7)));
+        assert_eq!(
+            timeout_for(None),
+            Some(Duration::from_secs(DEFAULT_TIMEOUT_SECS))
+        );
+    }
+
+    #[test]
+    fn timeout_aborts_plugin_and_keeps_its_log() {
+        let (logger, outcome) = start(
+            wait_for_cancel,
+            CancelToken::default(),
+            Some(Duration::from_millis(50)),
+            Duration::from_secs(5),
+        );
+
+        assert!(matches!(
+            outcome,
+            Outcome::TimedOut { step: Some(ref step) } if step == "Ожидание"
+        ));
+        assert!(messages(logger).contains(&"Остановлено".to_string()));
+    }
+
+    #[test]
+    fn cancellation_finishes_before_timeout() {
+        let cancel = CancelToken::default();
+        {
+            let cancel = cancel.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(20));
+                cancel.cancel();
+            });
+        }
+
+        let (logger, outcome) = start(
+            wait_for_cancel,
+            cancel,
+            Some(Duration::from_secs(30)),
+            Duration::from_secs(5),
+        );
+
+        assert!(matches!(
+            outcome,
+            Outcome::Finished(Ok(Err(ref message))) if message == "Отменено"
+        ));
+        assert!(messages(logger).contains(&"Остановлено".to_string()));
+    }
+
+    #[test]
+    fn plugin_ignoring_cancel_is_abandoned_after_grace() {
+        fn stubborn(_api: &PluginApi, logger: &mut Logger) -> Result<(), String> {
+            logger.section("Зависание");
+            logger.info("До зависания");
+            thread::sleep(Duration::from_secs(2));
+            logger.info("После зависания");
+            Ok(())
+        }
+
+        let (logger, outcome) = start(
+            stubborn,
+            CancelToken::default(),
+            Some(Duration::from_millis(50)),
+            Duration::from_millis(50),
+        );
+
+        assert!(matches!(
+            outcome,
+            Outcome::TimedOut { step: Some(ref step) } if step == "Зависание"
+        ));
+        let messages = messages(logger);
+        assert!(messages.contains(&"До зависания".to_string()));
+        assert!(!messages.contains(&"После зависания".to_string()));
+    }
+}
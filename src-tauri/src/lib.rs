@@ -1,38 +1,391 @@
-mod core;
+mod cli;
+/// Ядро плагинов без зависимости от окна: его можно подключать как библиотеку
+/// и запускать плагины через `core::execute_plugin` с собственным `Environment`.
+pub mod core;
 
-use core::{PluginInfo, PluginPreview, RunResult};
+use core::backend::OsRegistry;
+use core::capability::Capability;
+use core::doctor::DoctorReport;
+use core::history::HistoryEntry;
+use core::hive::{RegistryKey, RegistryView};
+use core::host::HostClient;
+use core::package::{CatalogEntry, InstalledPackage};
+use core::plugin::Execution;
+use core::post_action::{PendingReboot, PostAction, PostActionRequest};
+use core::recipe::Recipe;
+use core::regfile::RegChange;
+use core::scheduler::{Schedule, ScheduleInfo};
+use core::{CancelToken, CoreError, Environment, Logger, PluginInfo, PluginPreview, RunResult};
 use serde_json::Value;
-use tauri::AppHandle;
+use std::{path::Path, sync::Mutex};
+use tauri::{AppHandle, Manager, State};
+
+/// Всё, что ядру нужно от Tauri, — папка данных приложения.
+fn environment(app: &AppHandle) -> Environment {
+    Environment::detect().with_data_dir(app.path().app_data_dir().ok())
+}
 
 #[tauri::command]
 fn list_plugins(app: AppHandle) -> Vec<PluginInfo> {
-    core::list_plugins(app)
+    core::list_plugins(&environment(&app))
 }
 
 #[tauri::command]
-async fn preview_plugin(app: AppHandle, id: String) -> Result<Option<PluginPreview>, String> {
-    let handle = app.clone();
-    tauri::async_runtime::spawn_blocking(move || core::preview_plugin(handle, &id))
+fn run_history(app: AppHandle) -> Result<Vec<HistoryEntry>, String> {
+    let env = environment(&app);
+    let data_dir = env
+        .data_dir
+        .ok_or_else(|| "Ошибка открытия AppData: папка не определена".to_string())?;
+    let mut entries = core::history::load(&data_dir);
+    entries.reverse();
+    Ok(entries)
+}
+
+#[tauri::command]
+fn is_elevated() -> bool {
+    core::privilege::is_elevated()
+}
+
+#[tauri::command]
+async fn preview_plugin(app: AppHandle, id: String) -> Result<Option<PluginPreview>, CoreError> {
+    let env = environment(&app);
+    tauri::async_runtime::spawn_blocking(move || core::preview_plugin(&env, &id))
         .await
-        .map_err(|e| format!("Не удалось запустить превью задачу: {e}"))?
+        .map_err(|e| CoreError::Plugin(format!("Не удалось запустить превью задачу: {e}")))?
+}
+
+/// Отмена текущего запуска. Токен зарегистрирован на весь запуск, у рецепта — до
+/// последнего шага, поэтому отмена между шагами не теряется. Запуск в хосте следит
+/// за тем же токеном.
+#[derive(Default)]
+struct ActiveRun(Mutex<Option<CancelToken>>);
+
+impl ActiveRun {
+    fn set(&self, cancel: Option<CancelToken>) {
+        if let Ok(mut state) = self.0.lock() {
+            *state = cancel;
+        }
+    }
+
+    fn begin(&self, cancel: &CancelToken) {
+        self.set(Some(cancel.clone()));
+    }
+
+    fn finish(&self) {
+        self.set(None);
+    }
+
+    fn cancel(&self) -> Result<(), String> {
+        let state = self
+            .0
+            .lock()
+            .map_err(|_| "Состояние запуска недоступно.".to_string())?;
+        if let Some(token) = state.as_ref() {
+            token.cancel();
+        }
+        Ok(())
+    }
+}
+
+/// Встроенные плагины, которым нужны права администратора, выполняются в отдельном
+/// повышенном хосте; `isolated` запускает в хосте и обычные плагины. Если повысить
+/// права нельзя, повышенный хост не запустился или плагин внешний, он запускается
+/// как обычно и возвращает «Недостаточно прав».
+fn execute(
+    env: &Environment,
+    id: &str,
+    settings: Value,
+    isolated: bool,
+    cancel: &CancelToken,
+) -> Result<Execution, CoreError> {
+    let elevated = core::requires_elevation(env, id, &settings)?
+        && core::registry::is_builtin(id)
+        && core::host::can_elevate();
+    if !elevated && !isolated {
+        return core::execute_plugin(env, id, settings, Logger::new(), cancel.clone());
+    }
+    let mut host = match HostClient::spawn(env, elevated) {
+        Ok(host) => host,
+        Err(_) if elevated && isolated => HostClient::spawn(env, false)?,
+        Err(_) if elevated => {
+            return core::execute_plugin(env, id, settings, Logger::new(), cancel.clone());
+        }
+        Err(err) => return Err(err),
+    };
+    host.run(id, settings, Logger::new(), cancel)
+}
+
+#[tauri::command]
+async fn run_plugin(
+    app: AppHandle,
+    id: String,
+    settings: Value,
+    isolated: Option<bool>,
+) -> Result<RunResult, CoreError> {
+    let env = environment(&app);
+    let handle = app.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let active = handle.state::<ActiveRun>();
+        let cancel = CancelToken::default();
+        let isolated = isolated.unwrap_or(false);
+        active.begin(&cancel);
+        let execution = execute(&env, &id, settings, isolated, &cancel);
+        active.finish();
+        let execution = execution?;
+        core::record_execution(&env, &execution);
+        Ok(execution.result)
+    })
+    .await
+    .map_err(|e| CoreError::Plugin(format!("Не удалось запустить задачу: {e}")))?
 }
 
+/// Шаги рецепта запускаются так же, как `run_plugin`; `cancel_run` останавливает
+/// текущий шаг, и оставшиеся пропускаются. Таймаут шага останавливает только его.
 #[tauri::command]
-async fn run_plugin(app: AppHandle, id: String, settings: Value) -> Result<RunResult, String> {
+async fn run_recipe(app: AppHandle, id: String) -> Result<RunResult, CoreError> {
+    let env = environment(&app);
     let handle = app.clone();
-    tauri::async_runtime::spawn_blocking(move || core::run_plugin(handle, &id, settings))
+    tauri::async_runtime::spawn_blocking(move || {
+        let recipe = core::recipe::find_recipe(&env, &id)?;
+        let active = handle.state::<ActiveRun>();
+        let cancel = CancelToken::default();
+        active.begin(&cancel);
+        let result = core::recipe::run_recipe(&env, &recipe, &cancel, |step, step_cancel| {
+            execute(
+                &env,
+                &step.plugin_id,
+                step.settings.clone(),
+                false,
+                step_cancel,
+            )
+        });
+        active.finish();
+        Ok(result)
+    })
+    .await
+    .map_err(|e| CoreError::Plugin(format!("Не удалось запустить задачу: {e}")))?
+}
+
+#[tauri::command]
+fn cancel_run(active: State<'_, ActiveRun>) -> Result<(), String> {
+    active.cancel()
+}
+
+#[tauri::command]
+fn list_schedules(app: AppHandle) -> Vec<ScheduleInfo> {
+    core::scheduler::list_schedules(&environment(&app))
+}
+
+/// Создаёт расписание, если `id` пустой, иначе заменяет существующее.
+#[tauri::command]
+fn save_schedule(app: AppHandle, schedule: Schedule) -> Result<ScheduleInfo, CoreError> {
+    core::scheduler::save_schedule(&environment(&app), schedule)
+}
+
+#[tauri::command]
+fn delete_schedule(app: AppHandle, id: String) -> Result<(), CoreError> {
+    core::scheduler::delete_schedule(&environment(&app), &id)
+}
+
+#[tauri::command]
+fn list_recipes(app: AppHandle) -> Vec<Recipe> {
+    core::recipe::list_recipes(&environment(&app))
+}
+
+/// Создаёт рецепт, если `id` пустой, иначе заменяет существующий.
+#[tauri::command]
+fn save_recipe(app: AppHandle, recipe: Recipe) -> Result<Recipe, CoreError> {
+    core::recipe::save_recipe(&environment(&app), recipe)
+}
+
+#[tauri::command]
+fn delete_recipe(app: AppHandle, id: String) -> Result<(), CoreError> {
+    core::recipe::delete_recipe(&environment(&app), &id)
+}
+
+#[tauri::command]
+fn import_recipe(app: AppHandle, path: String) -> Result<Recipe, CoreError> {
+    core::recipe::import_recipe(&environment(&app), Path::new(&path))
+}
+
+#[tauri::command]
+fn export_recipe(app: AppHandle, id: String, path: String) -> Result<(), CoreError> {
+    core::recipe::export_recipe(&environment(&app), &id, Path::new(&path))
+}
+
+#[tauri::command]
+async fn list_package_catalog(app: AppHandle) -> Result<Vec<CatalogEntry>, CoreError> {
+    let env = environment(&app);
+    tauri::async_runtime::spawn_blocking(move || core::package::list_catalog(&env))
+        .await
+        .map_err(|e| CoreError::Plugin(format!("Не удалось прочитать каталог: {e}")))?
+}
+
+#[tauri::command]
+fn list_installed_packages(app: AppHandle) -> Vec<InstalledPackage> {
+    core::package::list_installed(&environment(&app))
+}
+
+/// `capabilities` — разрешения, которые пользователь просмотрел и подтвердил.
+#[tauri::command]
+fn install_package(
+    app: AppHandle,
+    id: String,
+    capabilities: Vec<Capability>,
+) -> Result<InstalledPackage, CoreError> {
+    core::package::install_package(&environment(&app), &id, &capabilities)
+}
+
+#[tauri::command]
+fn update_package(
+    app: AppHandle,
+    id: String,
+    capabilities: Vec<Capability>,
+) -> Result<InstalledPackage, CoreError> {
+    core::package::update_package(&environment(&app), &id, &capabilities)
+}
+
+#[tauri::command]
+fn uninstall_package(app: AppHandle, id: String) -> Result<(), CoreError> {
+    core::package::uninstall_package(&environment(&app), &id)
+}
+
+/// Выгружает ключ со всеми подключами в `.reg` (Windows Registry Editor 5.00).
+#[tauri::command]
+async fn export_registry_key(
+    key: String,
+    view: Option<RegistryView>,
+    path: String,
+) -> Result<(), CoreError> {
+    let key = RegistryKey::parse(&key).with_view(view.unwrap_or_default());
+    tauri::async_runtime::spawn_blocking(move || {
+        core::regfile::export_to_file(&OsRegistry, &key, Path::new(&path))
+    })
+    .await
+    .map_err(|e| CoreError::Plugin(format!("Не удалось выгрузить ключ: {e}")))?
+}
+
+/// Что изменит `apply_reg_file`, без записи в реестр.
+#[tauri::command]
+fn preview_reg_file(path: String, view: Option<RegistryView>) -> Result<Vec<RegChange>, CoreError> {
+    core::regfile::preview_file(&OsRegistry, Path::new(&path), view.unwrap_or_default())
+}
+
+#[tauri::command]
+fn apply_reg_file(path: String, view: Option<RegistryView>) -> Result<Vec<RegChange>, CoreError> {
+    core::regfile::apply_file(&OsRegistry, Path::new(&path), view.unwrap_or_default())
+}
+
+/// Перезагрузки, которые попросили плагины и которых ещё не было.
+#[tauri::command]
+fn pending_reboots(app: AppHandle) -> Vec<PendingReboot> {
+    core::post_action::pending_reboots(&environment(&app))
+}
+
+/// Выполняет действия из `RunResult::post_actions`. UI вызывает команду только после того,
+/// как пользователь подтвердил действие.
+#[tauri::command]
+async fn perform_post_actions(
+    requests: Vec<PostActionRequest>,
+) -> Result<Option<PostAction>, CoreError> {
+    tauri::async_runtime::spawn_blocking(move || core::post_action::confirm(&requests))
+        .await
+        .map_err(|e| CoreError::Plugin(format!("Не удалось выполнить действие: {e}")))?
+}
+
+/// Проверка окружения: программы плагинов, права, папка данных, реестр, место на диске.
+#[tauri::command]
+async fn doctor(app: AppHandle) -> Result<DoctorReport, CoreError> {
+    let env = environment(&app);
+    tauri::async_runtime::spawn_blocking(move || core::doctor::doctor(&env))
         .await
-        .map_err(|e| format!("Не удалось запустить задачу: {e}"))?
+        .map_err(|e| CoreError::Plugin(format!("Не удалось проверить окружение: {e}")))
+}
+
+/// Итог запуска API управления. У GUI-сборки нет консоли, поэтому адрес или ошибку
+/// UI забирает командой `control_api_status`.
+#[derive(Default)]
+struct ControlApiStatus(Mutex<Option<Result<Option<String>, String>>>);
+
+impl ControlApiStatus {
+    fn set(&self, status: Result<Option<String>, String>) {
+        if let Ok(mut current) = self.0.lock() {
+            *current = Some(status);
+        }
+    }
+
+    fn get(&self) -> Result<Option<String>, String> {
+        let current = self
+            .0
+            .lock()
+            .map_err(|_| "Состояние API управления недоступно.".to_string())?;
+        current.clone().unwrap_or(Ok(None))
+    }
+}
+
+/// Адрес API управления или `None`, если он выключен в настройках.
+#[tauri::command]
+fn control_api_status(status: State<'_, ControlApiStatus>) -> Result<Option<String>, String> {
+    status.get()
+}
+
+/// Точка входа процесса-хоста плагинов (`--plugin-host [--data-dir <путь>]`).
+pub fn run_plugin_host() -> i32 {
+    core::host::serve(core::host::environment_from_args(std::env::args_os()))
+}
+
+/// Точка входа `lamerhelper-cli`: работает без окна и `AppHandle`.
+pub fn run_cli(args: Vec<String>) -> i32 {
+    cli::run(args)
+}
+
+pub fn is_plugin_host_invocation() -> bool {
+    std::env::args().any(|arg| arg == core::host::HOST_FLAG)
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
+        .manage(ActiveRun::default())
+        .manage(ControlApiStatus::default())
+        .setup(|app| {
+            let env = environment(app.handle());
+            let jobs = core::jobs::Jobs::default();
+            core::scheduler::start(env.clone(), jobs.clone());
+            let status = core::control::start(env, jobs)
+                .map(|addr| addr.map(|addr| format!("http://{addr}/rpc")));
+            app.state::<ControlApiStatus>().set(status);
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             list_plugins,
+            is_elevated,
+            run_history,
             preview_plugin,
-            run_plugin
+            run_plugin,
+            cancel_run,
+            list_schedules,
+            save_schedule,
+            delete_schedule,
+            list_recipes,
+            save_recipe,
+            delete_recipe,
+            import_recipe,
+            export_recipe,
+            run_recipe,
+            list_package_catalog,
+            list_installed_packages,
+            install_package,
+            update_package,
+            uninstall_package,
+            export_registry_key,
+            preview_reg_file,
+            apply_reg_file,
+            doctor,
+            pending_reboots,
+            perform_post_actions,
+            control_api_status
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
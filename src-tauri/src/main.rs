@@ -2,5 +2,8 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 fn main() {
+    if tauri_app_lib::is_plugin_host_invocation() {
+        std::process::exit(tauri_app_lib::run_plugin_host());
+    }
     tauri_app_lib::run()
 }
@@ -1,5 +1,8 @@
-use crate::core::{Logger, Plugin, PluginApi, PluginMeta, SettingField, SettingKind, SettingUi};
-use serde::Deserialize;
+use crate::core::{
+    Capability, Logger, Platform, Plugin, PluginApi, PluginMeta, PostAction, SettingField,
+    SettingKind, SettingUi, round_trip_settings,
+};
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 
 pub fn plugin() -> Box<dyn Plugin> {
@@ -8,7 +11,9 @@ pub fn plugin() -> Box<dyn Plugin> {
 
 struct ClassicContextMenuPlugin;
 
-#[derive(Deserialize)]
+const CLSID_BASE: &str = "Software\\Classes\\CLSID\\{86ca1aa0-34aa-4e8b-a509-50c905bae2a2}";
+
+#[derive(Serialize, Deserialize)]
 struct ClassicContextSettings {
     #[serde(default = "default_use_classic")]
     use_classic: bool,
@@ -32,6 +37,7 @@ impl Plugin for ClassicContextMenuPlugin {
                     kind: SettingKind::Boolean,
                     description: Some("Создаёт или удаляет ключ реестра, который включает классическое контекстное меню в Windows 11.".to_string()),
                     required: true,
+                    requires_admin: false,
                     default_value: json!(default_use_classic()),
                     options: None,
                     ui: Some(SettingUi {
@@ -40,67 +46,184 @@ impl Plugin for ClassicContextMenuPlugin {
                     }),
                 },
             ],
+            capabilities: vec![
+                Capability::RegistryWrite {
+                    keys: vec![CLSID_BASE.to_string()],
+                },
+                Capability::ShellRestart,
+            ],
+            platforms: vec![Platform::Windows],
+            timeout_secs: None,
+            tools: Vec::new(),
         }
     }
 
     fn defaults(&self, api: &PluginApi) -> Option<serde_json::Value> {
-        let inproc_path = "Software\\Classes\\CLSID\\{86ca1aa0-34aa-4e8b-a509-50c905bae2a2}\\InprocServer32";
+        let inproc_path =
+            "Software\\Classes\\CLSID\\{86ca1aa0-34aa-4e8b-a509-50c905bae2a2}\\InprocServer32";
 
         match api.registry_key_exists(inproc_path) {
-            Ok(true) => Some(json!({"use_classic": true})),
-            Ok(false) => Some(json!({"use_classic": false})),
-            Err(_) => Some(json!({"use_classic": false})),
+            Ok(exists) => Some(json!({"use_classic": exists})),
+            Err(_) => None,
         }
     }
 
+    fn settings_round_trip(
+        &self,
+        settings: &serde_json::Value,
+    ) -> Option<Result<serde_json::Value, String>> {
+        Some(round_trip_settings::<ClassicContextSettings>(settings))
+    }
+
     fn run(
         &self,
         api: &PluginApi,
         settings: &serde_json::Value,
         logger: &mut Logger,
     ) -> Result<(), String> {
-        let settings: ClassicContextSettings = serde_json::from_value(settings.clone()).unwrap_or(ClassicContextSettings {
-            use_classic: default_use_classic(),
-        });
+        let settings: ClassicContextSettings =
+            serde_json::from_value(settings.clone()).unwrap_or(ClassicContextSettings {
+                use_classic: default_use_classic(),
+            });
 
-        let clsid_base = "Software\\Classes\\CLSID\\{86ca1aa0-34aa-4e8b-a509-50c905bae2a2}";
-        let inproc_path = "Software\\Classes\\CLSID\\{86ca1aa0-34aa-4e8b-a509-50c905bae2a2}\\InprocServer32";
+        let inproc_path =
+            "Software\\Classes\\CLSID\\{86ca1aa0-34aa-4e8b-a509-50c905bae2a2}\\InprocServer32";
 
         if settings.use_classic {
             match api.registry_key_exists(inproc_path) {
                 Ok(true) => {
                     logger.info("Классическое меню уже включено.".to_string());
-                    return Ok(());
+                    Ok(())
                 }
                 Ok(false) => {
                     api.create_registry_key(inproc_path)?;
                     api.set_registry_string(inproc_path, "", "")?;
                     logger.info("Классическое меню включено (создан реестр).".to_string());
-                    match api.restart_explorer() {
-                        Ok(_) => logger.success("Explorer перезапущен, изменения применились.".to_string()),
-                        Err(e) => logger.info(format!("Не удалось перезапустить Explorer: {e}. Перезапустите его вручную или выйдите из системы.")),
-                    }
-                    return Ok(());
+                    api.request_post_action(
+                        PostAction::RestartShell,
+                        "включено классическое меню",
+                    )?;
+                    Ok(())
                 }
-                Err(e) => return Err(format!("Ошибка проверки реестра: {e}")),
+                Err(e) => Err(format!("Ошибка проверки реестра: {e}")),
             }
         } else {
-            match api.registry_key_exists(clsid_base) {
+            match api.registry_key_exists(CLSID_BASE) {
                 Ok(true) => {
-                    api.delete_registry_key(clsid_base)?;
+                    api.delete_registry_key(CLSID_BASE)?;
                     logger.info("Классическое меню отключено (удален ключ реестра).".to_string());
-                    match api.restart_explorer() {
-                        Ok(_) => logger.success("Explorer перезапущен, изменения применились.".to_string()),
-                        Err(e) => logger.info(format!("Не удалось перезапустить Explorer: {e}. Перезапустите его вручную или выйдите из системы.")),
-                    }
-                    return Ok(());
+                    api.request_post_action(
+                        PostAction::RestartShell,
+                        "отключено классическое меню",
+                    )?;
+                    Ok(())
                 }
                 Ok(false) => {
                     logger.info("Классическое меню уже отключено.".to_string());
-                    return Ok(());
+                    Ok(())
                 }
-                Err(e) => return Err(format!("Ошибка проверки реестра: {e}")),
+                Err(e) => Err(format!("Ошибка проверки реестра: {e}")),
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::fake::Fakes;
+    use crate::core::hive::RegistryValue;
+    use crate::core::post_action::PostActionRequest;
+
+    const INPROC: &str =
+        "Software\\Classes\\CLSID\\{86ca1aa0-34aa-4e8b-a509-50c905bae2a2}\\InprocServer32";
+
+    struct Run {
+        result: Result<(), String>,
+        messages: Vec<String>,
+        post_actions: Vec<PostActionRequest>,
+    }
+
+    fn run(fakes: &Fakes, use_classic: bool) -> Run {
+        let plugin = ClassicContextMenuPlugin;
+        let api = fakes.api(&plugin);
+        let mut logger = Logger::new();
+        let result = plugin.run(&api, &json!({ "use_classic": use_classic }), &mut logger);
+        Run {
+            result,
+            messages: logger
+                .into_logs()
+                .into_iter()
+                .map(|entry| entry.message)
+                .collect(),
+            post_actions: api.take_post_actions(),
+        }
+    }
+
+    fn shell_restart(reason: &str) -> Vec<PostActionRequest> {
+        vec![PostActionRequest {
+            action: PostAction::RestartShell,
+            reasons: vec![reason.to_string()],
+        }]
+    }
+
+    #[test]
+    fn defaults_follow_registry() {
+        let fakes = Fakes::new();
+        let plugin = ClassicContextMenuPlugin;
+        let api = fakes.api(&plugin);
+        assert_eq!(plugin.defaults(&api), Some(json!({ "use_classic": false })));
+        fakes.registry.add_key(INPROC);
+        assert_eq!(plugin.defaults(&api), Some(json!({ "use_classic": true })));
+    }
+
+    #[test]
+    fn enabling_creates_key_and_requests_shell_restart() {
+        let fakes = Fakes::new();
+
+        let run = run(&fakes, true);
+
+        assert_eq!(run.result, Ok(()));
+        assert_eq!(
+            fakes.registry.value(INPROC, ""),
+            Some(RegistryValue::String(String::new()))
+        );
+        assert_eq!(
+            run.post_actions,
+            shell_restart("включено классическое меню")
+        );
+        assert!(fakes.processes.calls().is_empty());
+    }
+
+    #[test]
+    fn enabling_twice_changes_nothing() {
+        let fakes = Fakes::new();
+        fakes.registry.add_key(INPROC);
+
+        let run = run(&fakes, true);
+
+        assert_eq!(run.result, Ok(()));
+        assert!(
+            run.messages
+                .contains(&"Классическое меню уже включено.".to_string())
+        );
+        assert!(run.post_actions.is_empty());
+    }
+
+    #[test]
+    fn disabling_removes_whole_clsid_key() {
+        let fakes = Fakes::new();
+        fakes.registry.add_key(INPROC);
+
+        let run = run(&fakes, false);
+
+        assert_eq!(run.result, Ok(()));
+        assert!(!fakes.registry.has_key(CLSID_BASE));
+        assert!(!fakes.registry.has_key(INPROC));
+        assert_eq!(
+            run.post_actions,
+            shell_restart("отключено классическое меню")
+        );
+        assert!(fakes.processes.calls().is_empty());
+    }
+}
@@ -1,5 +1,8 @@
-﻿use crate::core::{Logger, Plugin, PluginApi, PluginMeta, SettingField, SettingKind, SettingUi};
-use serde::Deserialize;
+use crate::core::{
+    Capability, Logger, Platform, Plugin, PluginApi, PluginMeta, PostAction, SettingField,
+    SettingKind, SettingUi, round_trip_settings,
+};
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 
 pub fn plugin() -> Box<dyn Plugin> {
@@ -8,7 +11,7 @@ pub fn plugin() -> Box<dyn Plugin> {
 
 struct CursorHighlightPlugin;
 
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize)]
 struct CursorHighlightSettings {
     #[serde(default = "default_hilight_color")]
     hilight_color: String,
@@ -38,6 +41,7 @@ impl Plugin for CursorHighlightPlugin {
                     kind: SettingKind::Color,
                     description: Some("HKCU\\Control Panel\\Colors\\Hilight".to_string()),
                     required: true,
+                    requires_admin: false,
                     default_value: json!(default_hilight_color()),
                     options: None,
                     ui: Some(SettingUi {
@@ -51,6 +55,7 @@ impl Plugin for CursorHighlightPlugin {
                     kind: SettingKind::Color,
                     description: Some("HKCU\\Control Panel\\Colors\\HotTrackingColor".to_string()),
                     required: true,
+                    requires_admin: false,
                     default_value: json!(default_hot_tracking_color()),
                     options: None,
                     ui: Some(SettingUi {
@@ -59,12 +64,17 @@ impl Plugin for CursorHighlightPlugin {
                     }),
                 },
             ],
+            capabilities: vec![Capability::RegistryWrite {
+                keys: vec!["Control Panel\\Colors".to_string()],
+            }],
+            platforms: vec![Platform::Windows],
+            timeout_secs: None,
+            tools: Vec::new(),
         }
     }
 
     fn defaults(&self, api: &PluginApi) -> Option<serde_json::Value> {
-        let hilight = read_registry_color_hex(api, "Hilight")
-            .unwrap_or_else(default_hilight_color);
+        let hilight = read_registry_color_hex(api, "Hilight").unwrap_or_else(default_hilight_color);
 
         let hot_tracking = read_registry_color_hex(api, "HotTrackingColor")
             .unwrap_or_else(default_hot_tracking_color);
@@ -75,6 +85,13 @@ impl Plugin for CursorHighlightPlugin {
         }))
     }
 
+    fn settings_round_trip(
+        &self,
+        settings: &serde_json::Value,
+    ) -> Option<Result<serde_json::Value, String>> {
+        Some(round_trip_settings::<CursorHighlightSettings>(settings))
+    }
+
     fn run(
         &self,
         api: &PluginApi,
@@ -90,36 +107,35 @@ impl Plugin for CursorHighlightPlugin {
         let desired_hilight = normalize_to_hex(&settings.hilight_color)?;
         let desired_hot_tracking = normalize_to_hex(&settings.hot_tracking_color)?;
 
-        apply_color(
-            api,
-            logger,
-            "Hilight",
-            &desired_hilight,
-        )?;
+        let hilight_changed = apply_color(api, logger, "Hilight", &desired_hilight)?;
 
-        apply_color(
-            api,
-            logger,
-            "HotTrackingColor",
-            &desired_hot_tracking,
-        )?;
+        let hot_tracking_changed =
+            apply_color(api, logger, "HotTrackingColor", &desired_hot_tracking)?;
 
-        logger.success("Готово! Для применения перезагрузите компьютер.".to_string());
+        if hilight_changed || hot_tracking_changed {
+            api.request_post_action(PostAction::Reboot, "изменены цвета выделения")?;
+        }
+        logger.success("Готово!".to_string());
         Ok(())
     }
 }
 
+/// `true`, если значение в реестре изменилось.
 fn apply_color(
     api: &PluginApi,
     logger: &mut Logger,
     name: &str,
     desired_hex: &str,
-) -> Result<(), String> {
+) -> Result<bool, String> {
     let current_hex = read_registry_color_hex(api, name);
 
-    if current_hex.as_deref().map(|c| c.eq_ignore_ascii_case(desired_hex)) == Some(true) {
+    if current_hex
+        .as_deref()
+        .map(|c| c.eq_ignore_ascii_case(desired_hex))
+        == Some(true)
+    {
         logger.info(format!("{name} уже установлен: {desired_hex}"));
-        return Ok(());
+        return Ok(false);
     }
 
     let (r, g, b) = parse_color(desired_hex)?;
@@ -127,7 +143,7 @@ fn apply_color(
 
     api.set_registry_string("Control Panel\\Colors", name, &value)?;
     logger.info(format!("{name} установлен: {value}"));
-    Ok(())
+    Ok(true)
 }
 
 fn parse_color(value: &str) -> Result<(u8, u8, u8), String> {
@@ -147,7 +163,7 @@ fn parse_color(value: &str) -> Result<(u8, u8, u8), String> {
 }
 
 fn parse_rgb_string(value: &str) -> Option<(u8, u8, u8)> {
-    let parts: Vec<&str> = value.split(|c| c == ' ' || c == ',').filter(|p| !p.is_empty()).collect();
+    let parts: Vec<&str> = value.split([' ', ',']).filter(|p| !p.is_empty()).collect();
     if parts.len() != 3 {
         return None;
     }
@@ -173,3 +189,130 @@ fn read_registry_color_hex(api: &PluginApi, name: &str) -> Option<String> {
         .and_then(|v| parse_rgb_string(&v))
         .map(|(r, g, b)| rgb_to_hex(r, g, b))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::fake::Fakes;
+    use crate::core::hive::RegistryValue;
+    use crate::core::post_action::PostActionRequest;
+
+    const COLORS: &str = "Control Panel\\Colors";
+
+    fn registry_string(fakes: &Fakes, name: &str) -> Option<String> {
+        match fakes.registry.value(COLORS, name) {
+            Some(RegistryValue::String(value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn defaults_read_current_colors() {
+        let fakes = Fakes::new();
+        fakes.registry.set_value(
+            COLORS,
+            "Hilight",
+            RegistryValue::String("255 0 16".to_string()),
+        );
+        let plugin = CursorHighlightPlugin;
+
+        let defaults = plugin.defaults(&fakes.api(&plugin));
+
+        assert_eq!(
+            defaults,
+            Some(json!({
+                "hilight_color": "#ff0010",
+                "hot_tracking_color": default_hot_tracking_color(),
+            }))
+        );
+    }
+
+    #[test]
+    fn run_writes_colors_as_rgb() {
+        let fakes = Fakes::new();
+        fakes.registry.add_key(COLORS);
+        let plugin = CursorHighlightPlugin;
+        let api = fakes.api(&plugin);
+        let mut logger = Logger::new();
+
+        let result = plugin.run(
+            &api,
+            &json!({ "hilight_color": "#102030", "hot_tracking_color": "1, 2, 3" }),
+            &mut logger,
+        );
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            registry_string(&fakes, "Hilight").as_deref(),
+            Some("16 32 48")
+        );
+        assert_eq!(
+            registry_string(&fakes, "HotTrackingColor").as_deref(),
+            Some("1 2 3")
+        );
+        assert_eq!(
+            api.take_post_actions(),
+            [PostActionRequest {
+                action: PostAction::Reboot,
+                reasons: vec!["изменены цвета выделения".to_string()],
+            }]
+        );
+    }
+
+    #[test]
+    fn unchanged_colors_need_no_reboot() {
+        let fakes = Fakes::new();
+        let current = RegistryValue::String("16 32 48".to_string());
+        fakes.registry.set_value(COLORS, "Hilight", current.clone());
+        fakes
+            .registry
+            .set_value(COLORS, "HotTrackingColor", current);
+        let plugin = CursorHighlightPlugin;
+        let api = fakes.api(&plugin);
+
+        let settings = json!({ "hilight_color": "#102030", "hot_tracking_color": "#102030" });
+        let result = plugin.run(&api, &settings, &mut Logger::new());
+
+        assert_eq!(result, Ok(()));
+        assert!(api.take_post_actions().is_empty());
+    }
+
+    #[test]
+    fn run_skips_colors_that_already_match() {
+        let fakes = Fakes::new();
+        fakes.registry.set_value(
+            COLORS,
+            "Hilight",
+            RegistryValue::String("16 32 48".to_string()),
+        );
+        let plugin = CursorHighlightPlugin;
+        let mut logger = Logger::new();
+
+        let result = plugin.run(
+            &fakes.api(&plugin),
+            &json!({ "hilight_color": "#102030", "hot_tracking_color": "#102030" }),
+            &mut logger,
+        );
+
+        assert_eq!(result, Ok(()));
+        let messages: Vec<String> = logger.into_logs().into_iter().map(|e| e.message).collect();
+        assert!(messages.contains(&"Hilight уже установлен: #102030".to_string()));
+        assert!(messages.contains(&"HotTrackingColor установлен: 16 32 48".to_string()));
+    }
+
+    #[test]
+    fn run_rejects_invalid_color() {
+        let fakes = Fakes::new();
+        fakes.registry.add_key(COLORS);
+        let plugin = CursorHighlightPlugin;
+
+        let result = plugin.run(
+            &fakes.api(&plugin),
+            &json!({ "hilight_color": "#12", "hot_tracking_color": "#102030" }),
+            &mut Logger::new(),
+        );
+
+        assert!(result.is_err());
+        assert_eq!(registry_string(&fakes, "HotTrackingColor"), None);
+    }
+}
@@ -1,7 +1,14 @@
-﻿use crate::core::{Logger, Plugin, PluginApi, PluginMeta, SettingField, SettingKind};
-use serde::Deserialize;
+use crate::core::{
+    Capability, CommandOptions, Logger, Platform, Plugin, PluginApi, PluginMeta, SettingField,
+    SettingKind, round_trip_settings,
+};
+use serde::{Deserialize, Serialize};
 use serde_json::json;
-use std::process::Command;
+
+/// `ipconfig /renew` ждёт ответа DHCP-сервера, но не дольше этого.
+const COMMAND_TIMEOUT_SECS: u64 = 120;
+/// Плагин выполняет до четырёх команд, у каждой свой лимит; общий лимит больше их суммы.
+const PLUGIN_TIMEOUT_SECS: u64 = 4 * COMMAND_TIMEOUT_SECS + 60;
 
 pub fn plugin() -> Box<dyn Plugin> {
     Box::new(NetworkToolsPlugin)
@@ -9,7 +16,7 @@ pub fn plugin() -> Box<dyn Plugin> {
 
 struct NetworkToolsPlugin;
 
-#[derive(Deserialize, Default)]
+#[derive(Serialize, Deserialize, Default)]
 struct NetworkSettings {
     #[serde(default)]
     flush_dns: bool,
@@ -35,6 +42,7 @@ impl Plugin for NetworkToolsPlugin {
                     kind: SettingKind::Boolean,
                     description: Some("Полезно, если сайт не открывается или открывается старый/не тот адрес. Обновляет список адресов сайтов.".to_string()),
                     required: false,
+                    requires_admin: false,
                     default_value: json!(false),
                     options: None,
                     ui: None,
@@ -45,6 +53,7 @@ impl Plugin for NetworkToolsPlugin {
                     kind: SettingKind::Boolean,
                     description: Some("Освобождает текущий DHCP‑адрес и временно отключает IP на адаптере. Полезно при переключении сети или когда нужно сбросить \"зависший\" адрес.".to_string()),
                     required: false,
+                    requires_admin: false,
                     default_value: json!(false),
                     options: None,
                     ui: None,
@@ -55,6 +64,7 @@ impl Plugin for NetworkToolsPlugin {
                     kind: SettingKind::Boolean,
                     description: Some("Запрашивает адрес у DHCP‑сервера. Может вернуть тот же адрес, если аренда ещё действительна. Обычно достаточно для восстановления интернета.".to_string()),
                     required: false,
+                    requires_admin: false,
                     default_value: json!(false),
                     options: None,
                     ui: None,
@@ -65,6 +75,7 @@ impl Plugin for NetworkToolsPlugin {
                     kind: SettingKind::Boolean,
                     description: Some("Используйте, если другие пункты не помогли. Восстанавливает сетевые настройки и может устранить сложные сбои.".to_string()),
                     required: false,
+                    requires_admin: true,
                     default_value: json!(false),
                     options: None,
                     ui: Some(crate::core::SettingUi {
@@ -73,38 +84,50 @@ impl Plugin for NetworkToolsPlugin {
                     }),
                 },
             ],
+            capabilities: vec![
+                Capability::ProcessSpawn {
+                    executables: vec!["ipconfig".to_string(), "netsh".to_string()],
+                },
+                Capability::Network,
+            ],
+            platforms: vec![Platform::Windows],
+            timeout_secs: Some(PLUGIN_TIMEOUT_SECS),
+            tools: vec!["ipconfig".to_string(), "netsh".to_string()],
         }
     }
 
+    fn settings_round_trip(
+        &self,
+        settings: &serde_json::Value,
+    ) -> Option<Result<serde_json::Value, String>> {
+        Some(round_trip_settings::<NetworkSettings>(settings))
+    }
+
     fn run(
         &self,
-        _api: &PluginApi,
+        api: &PluginApi,
         settings: &serde_json::Value,
         logger: &mut Logger,
     ) -> Result<(), String> {
-        if !cfg!(windows) {
-            return Err("Модуль доступен только на Windows.".to_string());
-        }
-
         let settings: NetworkSettings =
             serde_json::from_value(settings.clone()).unwrap_or_default();
         let mut any = false;
 
         if settings.flush_dns {
             any = true;
-            run_cmd(logger, "ipconfig", &["/flushdns"])?;
+            run_cmd(api, logger, "ipconfig", &["/flushdns"])?;
         }
         if settings.release_ip {
             any = true;
-            run_cmd(logger, "ipconfig", &["/release"])?;
+            run_cmd(api, logger, "ipconfig", &["/release"])?;
         }
         if settings.renew_ip {
             any = true;
-            run_cmd(logger, "ipconfig", &["/renew"])?;
+            run_cmd(api, logger, "ipconfig", &["/renew"])?;
         }
         if settings.reset_winsock {
             any = true;
-            run_cmd(logger, "netsh", &["winsock", "reset"])?;
+            run_cmd(api, logger, "netsh", &["winsock", "reset"])?;
             logger.warn("После сброса Winsock может потребоваться перезапуск системы.".to_string());
         }
 
@@ -116,25 +139,121 @@ impl Plugin for NetworkToolsPlugin {
     }
 }
 
-fn run_cmd(logger: &mut Logger, cmd: &str, args: &[&str]) -> Result<(), String> {
+fn run_cmd(api: &PluginApi, logger: &mut Logger, cmd: &str, args: &[&str]) -> Result<(), String> {
     logger.info(format!("Запуск: {} {}", cmd, args.join(" ")));
-    let output = Command::new(cmd)
-        .args(args)
-        .output()
-        .map_err(|e| format!("Не удалось запустить {cmd}: {e}"))?;
-
-    if !output.stdout.is_empty() {
-        let text = String::from_utf8_lossy(&output.stdout);
-        logger.info(text.trim().to_string());
-    }
-    if !output.stderr.is_empty() {
-        let text = String::from_utf8_lossy(&output.stderr);
-        logger.warn(text.trim().to_string());
-    }
+    let options = CommandOptions {
+        timeout_secs: Some(COMMAND_TIMEOUT_SECS),
+        ..CommandOptions::default()
+    };
+    let output = api.run_command_with(cmd, args, &options, Some(logger))?;
 
-    if output.status.success() {
+    if output.success() {
         Ok(())
     } else {
-        Err(format!("Команда {cmd} завершилась с кодом {:?}", output.status.code()))
+        Err(format!(
+            "Команда {cmd} завершилась с ошибкой ({})",
+            output.status_text()
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::fake::Fakes;
+
+    fn run(fakes: &Fakes, settings: serde_json::Value) -> (Result<(), String>, Vec<String>) {
+        let plugin = NetworkToolsPlugin;
+        let mut logger = Logger::new();
+        let result = plugin.run(&fakes.api(&plugin), &settings, &mut logger);
+        let messages = logger
+            .into_logs()
+            .into_iter()
+            .map(|entry| entry.message)
+            .collect();
+        (result, messages)
+    }
+
+    #[test]
+    fn runs_selected_commands_in_order() {
+        let fakes = Fakes::new();
+        fakes
+            .processes
+            .respond("ipconfig /flushdns", 0, "DNS-кэш очищен.\r\n", "");
+        fakes.processes.respond("ipconfig /renew", 0, "", "");
+
+        let (result, messages) = run(&fakes, json!({ "flush_dns": true, "renew_ip": true }));
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            fakes.processes.calls(),
+            ["ipconfig /flushdns", "ipconfig /renew"]
+        );
+        assert!(messages.contains(&"DNS-кэш очищен.".to_string()));
+    }
+
+    #[test]
+    fn failed_command_stops_run() {
+        let fakes = Fakes::new();
+        fakes
+            .processes
+            .respond("ipconfig /release", 1, "", "Нет адаптеров.");
+
+        let (result, messages) = run(&fakes, json!({ "release_ip": true, "renew_ip": true }));
+
+        assert_eq!(
+            result,
+            Err("Команда ipconfig завершилась с ошибкой (код 1)".to_string())
+        );
+        assert_eq!(fakes.processes.calls(), ["ipconfig /release"]);
+        assert!(messages.contains(&"Нет адаптеров.".to_string()));
+    }
+
+    #[test]
+    fn winsock_reset_warns_about_reboot() {
+        let fakes = Fakes::new();
+        fakes.processes.respond("netsh winsock reset", 0, "", "");
+
+        let (result, messages) = run(&fakes, json!({ "reset_winsock": true }));
+
+        assert_eq!(result, Ok(()));
+        assert!(
+            messages
+                .iter()
+                .any(|message| message.contains("перезапуск системы"))
+        );
+    }
+
+    #[test]
+    fn console_output_in_cp866_is_readable() {
+        let fakes = Fakes::new();
+        let (stdout, _, _) = encoding_rs::IBM866.encode("Настройка протокола IP для Windows\r\n");
+        fakes
+            .processes
+            .respond("ipconfig /flushdns", 0, stdout.into_owned(), "");
+
+        let (result, messages) = run(&fakes, json!({ "flush_dns": true }));
+
+        assert_eq!(result, Ok(()));
+        assert!(messages.contains(&"Настройка протокола IP для Windows".to_string()));
+        assert_eq!(
+            fakes.processes.options()[0].timeout_secs,
+            Some(COMMAND_TIMEOUT_SECS)
+        );
+    }
+
+    #[test]
+    fn plugin_timeout_covers_every_command() {
+        assert!(plugin().meta().timeout_secs > Some(4 * COMMAND_TIMEOUT_SECS));
+    }
+
+    #[test]
+    fn nothing_selected_is_an_error() {
+        let fakes = Fakes::new();
+
+        let (result, _) = run(&fakes, json!({}));
+
+        assert_eq!(result, Err("Выберите хотя бы одну операцию.".to_string()));
+        assert!(fakes.processes.calls().is_empty());
     }
 }
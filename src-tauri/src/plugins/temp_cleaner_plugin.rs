@@ -1,10 +1,10 @@
-﻿use crate::core::api::{format_bytes, short_path};
+use crate::core::api::{format_bytes, short_path};
 use crate::core::{
-    Logger, Plugin, PluginApi, PluginMeta, PluginPreview, SettingField, SettingKind,
+    Capability, Logger, Plugin, PluginApi, PluginMeta, PluginPreview, SettingField, SettingKind,
+    round_trip_settings,
 };
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
-use std::fs;
 use std::path::{Path, PathBuf};
 
 pub fn plugin() -> Box<dyn Plugin> {
@@ -13,7 +13,7 @@ pub fn plugin() -> Box<dyn Plugin> {
 
 struct TempCleanerPlugin;
 
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize)]
 struct TempCleanerSettings {
     #[serde(default = "default_true")]
     user_temp: bool,
@@ -59,6 +59,7 @@ impl Plugin for TempCleanerPlugin {
                         "Удаляет кэш и временные файлы, которые создают приложения и система. Но Windows не очищает их, поэтому со временем они могут занимать много места.".to_string(),
                     ),
                     required: false,
+                    requires_admin: false,
                     default_value: json!(true),
                     options: None,
                     ui: None,
@@ -72,6 +73,7 @@ impl Plugin for TempCleanerPlugin {
                             .to_string(),
                     ),
                     required: false,
+                    requires_admin: true,
                     default_value: json!(false),
                     options: None,
                     ui: None,
@@ -85,6 +87,7 @@ impl Plugin for TempCleanerPlugin {
                             .to_string(),
                     ),
                     required: false,
+                    requires_admin: true,
                     default_value: json!(false),
                     options: None,
                     ui: None,
@@ -98,6 +101,7 @@ impl Plugin for TempCleanerPlugin {
                             .to_string(),
                     ),
                     required: false,
+                    requires_admin: false,
                     default_value: json!(false),
                     options: None,
                     ui: None,
@@ -108,11 +112,23 @@ impl Plugin for TempCleanerPlugin {
                     kind: SettingKind::Boolean,
                     description: Some("Показывает, что будет удалено, без изменений.".to_string()),
                     required: false,
+                    requires_admin: false,
                     default_value: json!(false),
                     options: None,
                     ui: None,
                 },
             ],
+            capabilities: vec![Capability::FsDelete {
+                scopes: vec![
+                    "%TEMP%".to_string(),
+                    "%SystemRoot%\\Temp".to_string(),
+                    "%SystemRoot%\\SoftwareDistribution\\Download".to_string(),
+                    "%SystemRoot%\\Minidump".to_string(),
+                ],
+            }],
+            platforms: Vec::new(),
+            timeout_secs: Some(900),
+            tools: Vec::new(),
         }
     }
 
@@ -124,7 +140,7 @@ impl Plugin for TempCleanerPlugin {
         let mut details = Vec::new();
 
         for target in targets {
-            let size = dir_size(&target.path, None, &mut size_errors);
+            let size = dir_size(api, &target.path, None, &mut size_errors);
             total_bytes = total_bytes.saturating_add(size);
             details.push(format!("{}: {}", target.label, format_bytes(size)));
         }
@@ -142,6 +158,13 @@ impl Plugin for TempCleanerPlugin {
         }))
     }
 
+    fn settings_round_trip(
+        &self,
+        settings: &serde_json::Value,
+    ) -> Option<Result<serde_json::Value, String>> {
+        Some(round_trip_settings::<TempCleanerSettings>(settings))
+    }
+
     fn run(
         &self,
         api: &PluginApi,
@@ -160,11 +183,11 @@ impl Plugin for TempCleanerPlugin {
                 continue;
             }
             logger.info(format!("Раздел: {}", target.label));
-            if !target.path.exists() {
+            if !api.exists(&target.path) {
                 logger.warn(format!("Путь не найден: {}", short_path(&target.path, 4)));
                 continue;
             }
-            let entries = match fs::read_dir(&target.path) {
+            let entries = match api.read_dir(&target.path) {
                 Ok(entries) => entries,
                 Err(err) => {
                     size_errors += 1;
@@ -178,16 +201,15 @@ impl Plugin for TempCleanerPlugin {
             };
 
             for entry in entries {
-                let entry = match entry {
-                    Ok(entry) => entry,
+                let path = match entry {
+                    Ok(path) => path,
                     Err(err) => {
                         size_errors += 1;
                         logger.warn(format!("Ошибка чтения элемента: {err}"));
                         continue;
                     }
                 };
-                let path = entry.path();
-                let metadata = match entry.metadata() {
+                let metadata = match api.metadata(&path) {
                     Ok(meta) => meta,
                     Err(err) => {
                         size_errors += 1;
@@ -199,11 +221,11 @@ impl Plugin for TempCleanerPlugin {
                         continue;
                     }
                 };
-                let is_dir = metadata.is_dir();
+                let is_dir = metadata.is_dir;
                 let size = if is_dir {
-                    dir_size(&path, Some(logger), &mut size_errors)
+                    dir_size(api, &path, Some(logger), &mut size_errors)
                 } else {
-                    metadata.len()
+                    metadata.len
                 };
                 items.push(CleanItem {
                     path,
@@ -321,44 +343,46 @@ struct CleanItem {
 }
 
 fn build_targets(api: &PluginApi, settings: &TempCleanerSettings) -> Vec<CleanTarget> {
-    let system_root = std::env::var("SystemRoot").unwrap_or_else(|_| "C:\\Windows".to_string());
+    let system_root = api
+        .env_var("SystemRoot")
+        .unwrap_or_else(|| "C:\\Windows".to_string());
     let system_root = PathBuf::from(system_root);
-    let mut targets = Vec::new();
-
-    targets.push(CleanTarget {
-        tag: "TEMP".to_string(),
-        label: "%TEMP% пользователя".to_string(),
-        path: api.temp_dir(),
-        enabled: settings.user_temp,
-    });
-
-    targets.push(CleanTarget {
-        tag: "SYS".to_string(),
-        label: "Системный TEMP".to_string(),
-        path: system_root.join("Temp"),
-        enabled: settings.system_temp,
-    });
-
-    targets.push(CleanTarget {
-        tag: "UPD".to_string(),
-        label: "Кэш обновлений Windows".to_string(),
-        path: system_root.join("SoftwareDistribution").join("Download"),
-        enabled: settings.update_cache,
-    });
-
-    targets.push(CleanTarget {
-        tag: "DMP".to_string(),
-        label: "Минидампы ошибок".to_string(),
-        path: system_root.join("Minidump"),
-        enabled: settings.minidumps,
-    });
-
-    targets
+    vec![
+        CleanTarget {
+            tag: "TEMP".to_string(),
+            label: "%TEMP% пользователя".to_string(),
+            path: api.temp_dir(),
+            enabled: settings.user_temp,
+        },
+        CleanTarget {
+            tag: "SYS".to_string(),
+            label: "Системный TEMP".to_string(),
+            path: system_root.join("Temp"),
+            enabled: settings.system_temp,
+        },
+        CleanTarget {
+            tag: "UPD".to_string(),
+            label: "Кэш обновлений Windows".to_string(),
+            path: system_root.join("SoftwareDistribution").join("Download"),
+            enabled: settings.update_cache,
+        },
+        CleanTarget {
+            tag: "DMP".to_string(),
+            label: "Минидампы ошибок".to_string(),
+            path: system_root.join("Minidump"),
+            enabled: settings.minidumps,
+        },
+    ]
 }
 
-fn dir_size(path: &Path, mut logger: Option<&mut Logger>, errors: &mut u64) -> u64 {
+fn dir_size(
+    api: &PluginApi,
+    path: &Path,
+    mut logger: Option<&mut Logger>,
+    errors: &mut u64,
+) -> u64 {
     let mut size = 0u64;
-    let entries = match fs::read_dir(path) {
+    let entries = match api.read_dir(path) {
         Ok(entries) => entries,
         Err(err) => {
             *errors += 1;
@@ -374,8 +398,8 @@ fn dir_size(path: &Path, mut logger: Option<&mut Logger>, errors: &mut u64) -> u
     };
 
     for entry in entries {
-        let entry = match entry {
-            Ok(entry) => entry,
+        let path = match entry {
+            Ok(path) => path,
             Err(err) => {
                 *errors += 1;
                 if let Some(logger) = logger.as_deref_mut() {
@@ -384,8 +408,7 @@ fn dir_size(path: &Path, mut logger: Option<&mut Logger>, errors: &mut u64) -> u
                 continue;
             }
         };
-        let path = entry.path();
-        let metadata = match entry.metadata() {
+        let metadata = match api.metadata(&path) {
             Ok(meta) => meta,
             Err(err) => {
                 *errors += 1;
@@ -399,10 +422,10 @@ fn dir_size(path: &Path, mut logger: Option<&mut Logger>, errors: &mut u64) -> u
                 continue;
             }
         };
-        if metadata.is_dir() {
-            size = size.saturating_add(dir_size(&path, logger.as_deref_mut(), errors));
+        if metadata.is_dir {
+            size = size.saturating_add(dir_size(api, &path, logger.as_deref_mut(), errors));
         } else {
-            size = size.saturating_add(metadata.len());
+            size = size.saturating_add(metadata.len);
         }
     }
 
@@ -442,3 +465,123 @@ fn short_error(err: &dyn std::fmt::Display) -> String {
     }
     message.to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::fake::Fakes;
+
+    /// Пользовательский TEMP с файлом на 10 байт и папкой с файлом на 20 байт.
+    fn fakes_with_temp() -> Fakes {
+        let fakes = Fakes::new();
+        let temp = fakes.environment().temp_dir;
+        fakes.fs.add_file(temp.join("setup.log"), vec![0; 10]);
+        fakes
+            .fs
+            .add_file(temp.join("cache").join("blob.bin"), vec![0; 20]);
+        fakes
+    }
+
+    fn run(fakes: &Fakes, settings: serde_json::Value) -> (Result<(), String>, Vec<String>) {
+        let plugin = TempCleanerPlugin;
+        let mut logger = Logger::new();
+        let result = plugin.run(&fakes.api(&plugin), &settings, &mut logger);
+        let messages = logger
+            .into_logs()
+            .into_iter()
+            .map(|entry| entry.message)
+            .collect();
+        (result, messages)
+    }
+
+    #[test]
+    fn preview_sums_user_temp() {
+        let fakes = fakes_with_temp();
+        let plugin = TempCleanerPlugin;
+
+        let preview = plugin.preview(&fakes.api(&plugin)).unwrap().unwrap();
+
+        assert_eq!(preview.value, format_bytes(30));
+    }
+
+    #[test]
+    fn run_empties_user_temp() {
+        let fakes = fakes_with_temp();
+        let temp = fakes.environment().temp_dir;
+
+        let (result, messages) = run(&fakes, json!({ "user_temp": true }));
+
+        assert_eq!(result, Ok(()));
+        assert!(fakes.fs.contains(&temp));
+        assert!(!fakes.fs.contains(temp.join("setup.log")));
+        assert!(!fakes.fs.contains(temp.join("cache")));
+        assert!(messages.contains(&format!("Освобождено: {}", format_bytes(30))));
+    }
+
+    #[test]
+    fn dry_run_keeps_files() {
+        let fakes = fakes_with_temp();
+        let temp = fakes.environment().temp_dir;
+
+        let (result, messages) = run(&fakes, json!({ "user_temp": true, "dry_run": true }));
+
+        assert_eq!(result, Ok(()));
+        assert!(fakes.fs.contains(temp.join("setup.log")));
+        assert!(fakes.fs.contains(temp.join("cache").join("blob.bin")));
+        assert!(messages.contains(&"Итог: файлов 1, папок 1, ошибок 0.".to_string()));
+    }
+
+    #[test]
+    fn locked_file_is_reported_and_others_removed() {
+        let fakes = fakes_with_temp();
+        let temp = fakes.environment().temp_dir;
+        fakes.fs.lock_path(temp.join("setup.log"));
+
+        let (result, messages) = run(&fakes, json!({ "user_temp": true }));
+
+        assert_eq!(
+            result,
+            Err("Часть элементов не удалось обработать: 1".to_string())
+        );
+        assert!(fakes.fs.contains(temp.join("setup.log")));
+        assert!(!fakes.fs.contains(temp.join("cache")));
+        assert!(
+            messages
+                .iter()
+                .any(|message| message.contains("файл занят другим процессом"))
+        );
+    }
+
+    #[test]
+    fn system_folders_follow_system_root() {
+        let fakes = Fakes::new();
+        let windows = fakes.path("Windows");
+        fakes
+            .env
+            .set_var("SystemRoot", windows.to_string_lossy().into_owned());
+        fakes
+            .fs
+            .add_file(windows.join("Temp").join("old.tmp"), vec![0; 5]);
+        fakes
+            .fs
+            .add_file(windows.join("Minidump").join("crash.dmp"), vec![0; 7]);
+
+        let (result, messages) = run(
+            &fakes,
+            json!({ "user_temp": false, "system_temp": true, "update_cache": true }),
+        );
+
+        assert_eq!(result, Ok(()));
+        assert!(!fakes.fs.contains(windows.join("Temp").join("old.tmp")));
+        assert!(
+            fakes
+                .fs
+                .contains(windows.join("Minidump").join("crash.dmp"))
+        );
+        assert!(
+            messages
+                .iter()
+                .any(|message| message.starts_with("Путь не найден"))
+        );
+    }
+}
@@ -1,7 +1,8 @@
 use crate::core::{
     Logger, Plugin, PluginApi, PluginMeta, SettingField, SettingKind, SettingOption, SettingUi,
+    round_trip_settings,
 };
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 
 pub fn plugin() -> Box<dyn Plugin> {
@@ -10,7 +11,7 @@ pub fn plugin() -> Box<dyn Plugin> {
 
 struct TestPlugin;
 
-#[derive(Deserialize, Default)]
+#[derive(Serialize, Deserialize, Default)]
 struct TestSettings {
     #[serde(default)]
     enabled: bool,
@@ -40,6 +41,7 @@ impl Plugin for TestPlugin {
                     kind: SettingKind::Boolean,
                     description: Some("Проверка переключателя.".to_string()),
                     required: false,
+                    requires_admin: false,
                     default_value: json!(true),
                     options: None,
                     ui: None,
@@ -50,6 +52,7 @@ impl Plugin for TestPlugin {
                     kind: SettingKind::Text,
                     description: Some("Обычное текстовое поле.".to_string()),
                     required: true,
+                    requires_admin: false,
                     default_value: json!("darkfated"),
                     options: None,
                     ui: Some(SettingUi {
@@ -63,6 +66,7 @@ impl Plugin for TestPlugin {
                     kind: SettingKind::Text,
                     description: Some("Многострочный текст.".to_string()),
                     required: false,
+                    requires_admin: false,
                     default_value: json!("Например: список задач, пояснения..."),
                     options: None,
                     ui: Some(SettingUi {
@@ -77,6 +81,7 @@ impl Plugin for TestPlugin {
                     kind: SettingKind::Number,
                     description: Some("Числовое поле с min/max/step.".to_string()),
                     required: true,
+                    requires_admin: false,
                     default_value: json!(60),
                     options: None,
                     ui: Some(SettingUi {
@@ -93,6 +98,7 @@ impl Plugin for TestPlugin {
                     kind: SettingKind::Color,
                     description: Some("HEX цвет (#RRGGBB).".to_string()),
                     required: true,
+                    requires_admin: false,
                     default_value: json!("#7ae2ff"),
                     options: None,
                     ui: Some(SettingUi {
@@ -106,6 +112,7 @@ impl Plugin for TestPlugin {
                     kind: SettingKind::Select,
                     description: Some("Выпадающий список вариантов.".to_string()),
                     required: true,
+                    requires_admin: false,
                     default_value: json!("balanced"),
                     options: Some(vec![
                         SettingOption {
@@ -124,9 +131,20 @@ impl Plugin for TestPlugin {
                     ui: None,
                 },
             ],
+            capabilities: Vec::new(),
+            platforms: Vec::new(),
+            timeout_secs: None,
+            tools: Vec::new(),
         }
     }
 
+    fn settings_round_trip(
+        &self,
+        settings: &serde_json::Value,
+    ) -> Option<Result<serde_json::Value, String>> {
+        Some(round_trip_settings::<TestSettings>(settings))
+    }
+
     fn run(
         &self,
         _api: &PluginApi,